@@ -0,0 +1,158 @@
+//! Throughput of the stages of a conversion on a generated document: reading the input bytes of
+//! each `VariationArchive` element, deserializing them, building their VCF records, and
+//! deserializing on the threads of `--jobs`
+//!
+//! ```bash
+//! cargo bench --bench convert
+//! ```
+
+use clinvar::fixture_gen::{self, ConditionSpec, LocationSpec, RcvSpec, RecordSpec, Spec};
+use clinvar::{reader, Assembly, Converter, VariationArchive, VariationArchiveReader};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+const RECORDS: u64 = 5000;
+
+/// Records queued for each thread, as in `clinvar_xml2vcf`
+const CHANNEL_CAPACITY_PER_JOB: usize = 64;
+
+fn spec() -> Spec {
+    Spec {
+        release_date: Some("2024-05-01".to_string()),
+        records: (1..=RECORDS)
+            .map(|i| RecordSpec {
+                variation_id: i,
+                locations: vec![LocationSpec {
+                    assembly: "GRCh38".to_string(),
+                    chr: "1".to_string(),
+                    position: i * 10,
+                    reference: "A".to_string(),
+                    alternate: "G".to_string(),
+                }],
+                rcvs: (0..2)
+                    .map(|j| RcvSpec {
+                        accession: format!("RCV{:09}", i * 2 + j),
+                        conditions: vec![ConditionSpec {
+                            db: "MedGen".to_string(),
+                            id: format!("C{:07}", i % 100 + j),
+                            name: format!("Disease {}", i % 100 + j),
+                        }],
+                        classification: "Pathogenic".to_string(),
+                        ..Default::default()
+                    })
+                    .collect(),
+                ..Default::default()
+            })
+            .collect(),
+    }
+}
+
+/// Input bytes and positions of the `VariationArchive` elements of `xml`
+fn elements(xml: &str) -> Vec<(Vec<u8>, usize)> {
+    let mut reader = VariationArchiveReader::from_reader(xml.as_bytes());
+    let mut elements = Vec::new();
+    while let Some(result) = reader.read_raw() {
+        let (bytes, position) = result.expect("element");
+        elements.push((bytes.to_vec(), position));
+    }
+
+    elements
+}
+
+/// Number of records deserialized on `jobs` threads from the elements read by this thread
+///
+/// The elements are sent through one channel shared by the threads and the records collected
+/// through another, as `--jobs` does before writing them.
+fn deserialize_on(xml: &str, jobs: usize) -> u64 {
+    thread::scope(|scope| {
+        let (element_tx, element_rx) =
+            mpsc::sync_channel::<(Vec<u8>, usize)>(jobs * CHANNEL_CAPACITY_PER_JOB);
+        let element_rx = Arc::new(Mutex::new(element_rx));
+        let (record_tx, record_rx) =
+            mpsc::sync_channel::<VariationArchive>(jobs * CHANNEL_CAPACITY_PER_JOB);
+
+        for _ in 0..jobs {
+            let element_rx = element_rx.clone();
+            let record_tx = record_tx.clone();
+            scope.spawn(move || loop {
+                let received = element_rx.lock().expect("no worker panics").recv();
+                let Ok((bytes, position)) = received else {
+                    break;
+                };
+                let record = reader::deserialize(&bytes, position).expect("record");
+                record_tx.send(record).expect("collector");
+            });
+        }
+        drop(record_tx);
+
+        let collector = scope.spawn(move || record_rx.into_iter().count() as u64);
+
+        let mut reader = VariationArchiveReader::from_reader(xml.as_bytes());
+        while let Some(result) = reader.read_raw() {
+            let (bytes, position) = result.expect("element");
+            element_tx.send((bytes.to_vec(), position)).expect("worker");
+        }
+        drop(element_tx);
+
+        collector.join().expect("the collector does not panic")
+    })
+}
+
+fn convert(c: &mut Criterion) {
+    let xml = fixture_gen::generate(&spec());
+    let elements = elements(&xml);
+    let variants = elements
+        .iter()
+        .map(|(bytes, position)| reader::deserialize(bytes, *position).expect("record"))
+        .collect::<Vec<_>>();
+    let converter = Converter::new(Assembly::GRCh38);
+
+    let mut group = c.benchmark_group("convert");
+    group.throughput(Throughput::Bytes(xml.len() as u64));
+    group.bench_function("parse", |b| {
+        b.iter(|| {
+            let mut reader = VariationArchiveReader::from_reader(xml.as_bytes());
+            let mut count = 0;
+            while let Some(result) = reader.read_raw() {
+                result.expect("element");
+                count += 1;
+            }
+            assert_eq!(count, RECORDS)
+        })
+    });
+    group.bench_function("deserialize", |b| {
+        b.iter(|| {
+            for (bytes, position) in &elements {
+                reader::deserialize(bytes, *position).expect("record");
+            }
+        })
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group("records");
+    group.throughput(Throughput::Elements(RECORDS));
+    group.bench_function("to_vcf_records", |b| {
+        b.iter(|| {
+            for variant in &variants {
+                for record in converter.records(variant) {
+                    record.expect("record");
+                }
+            }
+        })
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group("jobs");
+    group.throughput(Throughput::Bytes(xml.len() as u64));
+    group.sample_size(20);
+    for jobs in [1, 2, 4] {
+        group.bench_with_input(BenchmarkId::from_parameter(jobs), &jobs, |b, x| {
+            b.iter(|| assert_eq!(deserialize_on(&xml, *x), RECORDS))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, convert);
+criterion_main!(benches);
@@ -0,0 +1,71 @@
+//! Reading of the `VariationArchive` elements of a generated document with the buffer capacities
+//! chosen by `--read-buffer-mb` (see `clinvar::io_stats::default_buffer_size`)
+//!
+//! ```bash
+//! cargo bench --bench read_buffer
+//! ```
+
+use clinvar::fixture_gen::{self, LocationSpec, RcvSpec, RecordSpec, Spec};
+use clinvar::io_stats::{DEFAULT_BUFFER_SIZE, LARGE_BUFFER_SIZE};
+use clinvar::VariationArchiveReader;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::Path;
+
+const RECORDS: u64 = 5000;
+
+fn spec() -> Spec {
+    Spec {
+        release_date: Some("2024-05-01".to_string()),
+        records: (1..=RECORDS)
+            .map(|i| RecordSpec {
+                variation_id: i,
+                locations: vec![LocationSpec {
+                    assembly: "GRCh38".to_string(),
+                    chr: "1".to_string(),
+                    position: i * 10,
+                    reference: "A".to_string(),
+                    alternate: "G".to_string(),
+                }],
+                rcvs: vec![RcvSpec {
+                    accession: format!("RCV{:09}", i),
+                    classification: "Pathogenic".to_string(),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            })
+            .collect(),
+    }
+}
+
+/// Number of `VariationArchive` elements read from `path` through a buffer of `capacity` bytes
+fn read(path: &Path, capacity: usize) -> u64 {
+    let file = File::open(path).expect("input");
+    let mut reader = VariationArchiveReader::from_reader(BufReader::with_capacity(capacity, file));
+    let mut count = 0;
+    while let Some(result) = reader.read_raw() {
+        result.expect("element");
+        count += 1;
+    }
+
+    count
+}
+
+fn read_buffer(c: &mut Criterion) {
+    let dir = tempfile::tempdir().expect("temporary directory");
+    let path = dir.path().join("input.xml");
+    fs::write(&path, fixture_gen::generate(&spec())).expect("input");
+
+    let mut group = c.benchmark_group("read_buffer");
+    group.throughput(Throughput::Bytes(fs::metadata(&path).expect("input").len()));
+    for capacity in [DEFAULT_BUFFER_SIZE, LARGE_BUFFER_SIZE] {
+        group.bench_with_input(BenchmarkId::from_parameter(capacity), &capacity, |b, x| {
+            b.iter(|| assert_eq!(read(&path, *x), RECORDS))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, read_buffer);
+criterion_main!(benches);
@@ -0,0 +1,420 @@
+//! Annotation of output records with INFO fields copied from a bgzipped, tabix-indexed VCF
+
+use crate::{bgzf, tabix};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+static REGEX_INFO_HEADER: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r#"\A##INFO=<ID=([^,>]+),Number=([^,>]+),Type=([^,>]+),Description="((?:[^"\\]|\\.)*)""#,
+    )
+    .unwrap()
+});
+
+#[derive(Debug, Clone)]
+pub struct InfoDefinition {
+    pub id: String,
+    pub number: String,
+    pub r#type: String,
+    pub description: String,
+}
+
+pub struct Annotator {
+    path: PathBuf,
+    prefix: String,
+    fields: Vec<InfoDefinition>,
+    index: tabix::Index,
+    reader: bgzf::Reader<BufReader<File>>,
+    contigs: HashMap<String, Option<String>>,
+    line: String,
+}
+
+impl Annotator {
+    /// Create an annotator from `<vcf.gz>:<FIELD>,<FIELD>...[:<PREFIX>]`
+    ///
+    /// The prefix defaults to the upper-cased file name up to the first `.`
+    /// (e.g. `gnomad.genomes.vcf.gz:AF` adds `GNOMAD_AF`).
+    pub fn from_spec(spec: &str) -> io::Result<Self> {
+        let parts = spec.split(':').collect::<Vec<&str>>();
+        if parts.len() < 2 || parts.len() > 3 || parts[1].is_empty() {
+            Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "Invalid annotation: {} (expected <vcf.gz>:<FIELD>,...[:<PREFIX>])",
+                    spec
+                ),
+            ))?
+        }
+
+        let path = Path::new(parts[0]);
+        let fields = parts[1].split(',').map(|x| x.to_string()).collect();
+        let prefix = match parts.get(2) {
+            Some(x) => x.to_string(),
+            None => path
+                .file_name()
+                .and_then(|x| x.to_str())
+                .and_then(|x| x.split('.').next())
+                .unwrap_or_default()
+                .chars()
+                .map(|x| {
+                    if x.is_ascii_alphanumeric() {
+                        x.to_ascii_uppercase()
+                    } else {
+                        '_'
+                    }
+                })
+                .collect(),
+        };
+
+        Self::new(path, fields, prefix)
+    }
+
+    pub fn new<P: AsRef<Path>>(path: P, fields: Vec<String>, prefix: String) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+
+        let mut index_path = path.clone().into_os_string();
+        index_path.push(".tbi");
+        let index_path = PathBuf::from(index_path);
+        if !index_path.exists() {
+            Err(Error::new(
+                ErrorKind::NotFound,
+                format!("{}", index_path.to_string_lossy()),
+            ))?
+        }
+        let index = tabix::Index::from_path(&index_path)?;
+
+        let mut reader = bgzf::Reader::new(BufReader::new(File::open(&path)?));
+        let mut definitions = HashMap::new();
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 || !line.starts_with('#') {
+                break;
+            }
+            if let Some(c) = REGEX_INFO_HEADER.captures(&line) {
+                definitions.insert(
+                    c[1].to_string(),
+                    InfoDefinition {
+                        id: c[1].to_string(),
+                        number: c[2].to_string(),
+                        r#type: c[3].to_string(),
+                        description: c[4].to_string(),
+                    },
+                );
+            }
+        }
+
+        let fields = fields
+            .iter()
+            .map(|x| {
+                definitions.get(x).cloned().ok_or_else(|| {
+                    Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("INFO field {} not found in {}", x, path.to_string_lossy()),
+                    )
+                })
+            })
+            .collect::<io::Result<Vec<InfoDefinition>>>()?;
+
+        Ok(Annotator {
+            path,
+            prefix,
+            fields,
+            index,
+            reader,
+            contigs: HashMap::new(),
+            line,
+        })
+    }
+
+    /// `##INFO` lines of the fields added by this annotator
+    pub fn header(&self) -> Vec<String> {
+        self.fields
+            .iter()
+            .map(|x| {
+                format!(
+                    r#"##INFO=<ID={}_{},Number={},Type={},Description="{} (from {})">"#,
+                    self.prefix,
+                    x.id,
+                    match x.number.as_str() {
+                        "A" | "R" => "1",
+                        n => n,
+                    },
+                    x.r#type,
+                    x.description,
+                    self.path.file_name().unwrap_or_default().to_string_lossy()
+                )
+            })
+            .collect()
+    }
+
+    /// Look up the record matching CHROM/POS/REF/ALT and return `<PREFIX>_<FIELD>=<VALUE>` entries
+    ///
+    /// Misses (no record, no matching allele, or missing values) produce no entries.
+    pub fn annotate(
+        &mut self,
+        chrom: &str,
+        pos: u64,
+        reference: &str,
+        alternate: &str,
+    ) -> io::Result<Vec<String>> {
+        let contig = match self.resolve_contig(chrom) {
+            Some(x) => x,
+            None => return Ok(Vec::new()),
+        };
+
+        let chunks = self.index.query(&contig, pos - 1, pos);
+        let start = match chunks.first() {
+            Some(x) => x.0,
+            None => return Ok(Vec::new()),
+        };
+        self.reader.seek_virtual(start)?;
+
+        loop {
+            self.line.clear();
+            if self.reader.read_line(&mut self.line)? == 0 {
+                return Ok(Vec::new());
+            }
+            if self.line.starts_with('#') {
+                continue;
+            }
+
+            let columns = self
+                .line
+                .trim_end_matches(['\n', '\r'])
+                .splitn(9, '\t')
+                .collect::<Vec<&str>>();
+            if columns.len() < 8 || columns[0] != contig {
+                return Ok(Vec::new());
+            }
+
+            let p = columns[1]
+                .parse::<u64>()
+                .map_err(|e| Error::new(ErrorKind::InvalidData, format!("{}", e)))?;
+            if p > pos {
+                return Ok(Vec::new());
+            }
+            if p < pos || columns[3] != reference {
+                continue;
+            }
+
+            if let Some(i) = columns[4].split(',').position(|x| x == alternate) {
+                return Ok(self.extract(columns[7], i));
+            }
+        }
+    }
+
+    fn extract(&self, info: &str, allele_index: usize) -> Vec<String> {
+        let values = info
+            .split(';')
+            .map(|x| match x.split_once('=') {
+                Some((k, v)) => (k, Some(v)),
+                None => (x, None),
+            })
+            .collect::<HashMap<&str, Option<&str>>>();
+
+        self.fields
+            .iter()
+            .filter_map(|field| {
+                let value = *values.get(field.id.as_str())?;
+                if field.r#type == "Flag" {
+                    return Some(format!("{}_{}", self.prefix, field.id));
+                }
+
+                let value = match field.number.as_str() {
+                    "A" => value?.split(',').nth(allele_index)?,
+                    "R" => value?.split(',').nth(allele_index + 1)?,
+                    _ => value?,
+                };
+                if value == "." {
+                    return None;
+                }
+
+                Some(format!("{}_{}={}", self.prefix, field.id, value))
+            })
+            .collect()
+    }
+
+    /// Find the name of the contig in the annotation file (e.g. `chr17` for `17`, `chrM` for `MT`)
+    fn resolve_contig(&mut self, chrom: &str) -> Option<String> {
+        if let Some(x) = self.contigs.get(chrom) {
+            return x.clone();
+        }
+
+        let plain = chrom.trim_start_matches("chr");
+        let mut candidates = vec![
+            chrom.to_string(),
+            plain.to_string(),
+            format!("chr{}", plain),
+        ];
+        if plain == "MT" || plain == "M" {
+            candidates.extend(["MT", "chrM", "M", "chrMT"].map(|x| x.to_string()));
+        }
+
+        let found = candidates
+            .into_iter()
+            .find(|x| self.index.names.contains(x));
+        self.contigs.insert(chrom.to_string(), found.clone());
+
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tabix::{build_index, IndexFormat};
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    const VCF: &str = "##fileformat=VCFv4.3
+##INFO=<ID=AF,Number=A,Type=Float,Description=\"Allele frequency\">
+##INFO=<ID=AC,Number=A,Type=Integer,Description=\"Allele count\">
+##INFO=<ID=AN,Number=1,Type=Integer,Description=\"Number of alleles\">
+##INFO=<ID=DP,Number=R,Type=Integer,Description=\"Depth of each allele\">
+##INFO=<ID=LCR,Number=0,Type=Flag,Description=\"In a low complexity region\">
+##contig=<ID=chr17>
+##contig=<ID=chrM>
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO
+chr17\t100\t.\tA\tG,T\t.\tPASS\tAF=0.1,0.2;AC=1,2;AN=10;DP=7,1,2;LCR
+chr17\t100\t.\tAC\tA\t.\tPASS\tAF=0.3;AC=3;AN=10;DP=7,3
+chr17\t200\t.\tC\tT\t.\tPASS\tAF=.;AC=0;AN=10;DP=10,0
+chrM\t50\t.\tG\tA\t.\tPASS\tAF=1;AC=10;AN=10;DP=0,10
+";
+
+    /// Bgzipped and tabix-indexed `VCF` named `gnomad.genomes.vcf.gz`
+    fn fixture() -> (TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("gnomad.genomes.vcf.gz");
+        let mut writer = bgzf::Writer::new(File::create(&path).unwrap());
+        writer.write_all(VCF.as_bytes()).unwrap();
+        writer.finish().unwrap();
+        build_index(&path, IndexFormat::Tbi).unwrap();
+
+        (dir, path)
+    }
+
+    fn spec(path: &Path, rest: &str) -> String {
+        format!("{}:{}", path.to_str().unwrap(), rest)
+    }
+
+    #[test]
+    fn spec_names_fields_and_prefix() {
+        let (_dir, path) = fixture();
+
+        let annotator = Annotator::from_spec(&spec(&path, "AF,AN")).unwrap();
+        assert_eq!(annotator.prefix, "GNOMAD");
+        let ids = annotator.fields.iter().map(|x| x.id.as_str());
+        assert_eq!(ids.collect::<Vec<_>>(), vec!["AF", "AN"]);
+
+        let annotator = Annotator::from_spec(&spec(&path, "AF:GNOMAD_G")).unwrap();
+        assert_eq!(annotator.prefix, "GNOMAD_G");
+    }
+
+    #[test]
+    fn invalid_specs_are_rejected() {
+        let (_dir, path) = fixture();
+
+        for rest in ["", "AF:P:Q"] {
+            let e = Annotator::from_spec(&spec(&path, rest)).err().unwrap();
+            assert_eq!(e.kind(), ErrorKind::InvalidInput, "{}", rest);
+        }
+        let e = Annotator::from_spec(path.to_str().unwrap()).err().unwrap();
+        assert_eq!(e.kind(), ErrorKind::InvalidInput);
+
+        let e = Annotator::from_spec(&spec(&path, "AF,XX")).err().unwrap();
+        assert_eq!(e.kind(), ErrorKind::InvalidInput);
+        assert!(e.to_string().contains("INFO field XX not found"));
+
+        fs_remove_index(&path);
+        let e = Annotator::from_spec(&spec(&path, "AF")).err().unwrap();
+        assert_eq!(e.kind(), ErrorKind::NotFound);
+    }
+
+    fn fs_remove_index(path: &Path) {
+        let mut index = path.as_os_str().to_owned();
+        index.push(".tbi");
+        std::fs::remove_file(index).unwrap();
+    }
+
+    #[test]
+    fn header_renames_fields_with_prefix() {
+        let (_dir, path) = fixture();
+        let annotator = Annotator::from_spec(&spec(&path, "AF,AN,DP,LCR:G")).unwrap();
+
+        assert_eq!(
+            annotator.header(),
+            vec![
+                r#"##INFO=<ID=G_AF,Number=1,Type=Float,Description="Allele frequency (from gnomad.genomes.vcf.gz)">"#,
+                r#"##INFO=<ID=G_AN,Number=1,Type=Integer,Description="Number of alleles (from gnomad.genomes.vcf.gz)">"#,
+                r#"##INFO=<ID=G_DP,Number=1,Type=Integer,Description="Depth of each allele (from gnomad.genomes.vcf.gz)">"#,
+                r#"##INFO=<ID=G_LCR,Number=0,Type=Flag,Description="In a low complexity region (from gnomad.genomes.vcf.gz)">"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn values_of_the_matching_allele_are_copied() {
+        let (_dir, path) = fixture();
+        let mut annotator = Annotator::from_spec(&spec(&path, "AF,AC,AN,DP,LCR:G")).unwrap();
+
+        assert_eq!(
+            annotator.annotate("17", 100, "A", "T").unwrap(),
+            vec!["G_AF=0.2", "G_AC=2", "G_AN=10", "G_DP=2", "G_LCR"]
+        );
+        assert_eq!(
+            annotator.annotate("17", 100, "AC", "A").unwrap(),
+            vec!["G_AF=0.3", "G_AC=3", "G_AN=10", "G_DP=3"]
+        );
+        // missing values are left out
+        assert_eq!(
+            annotator.annotate("17", 200, "C", "T").unwrap(),
+            vec!["G_AC=0", "G_AN=10", "G_DP=0"]
+        );
+    }
+
+    #[test]
+    fn only_the_same_chrom_pos_ref_alt_matches() {
+        let (_dir, path) = fixture();
+        let mut annotator = Annotator::from_spec(&spec(&path, "AF")).unwrap();
+
+        for (chrom, pos, reference, alternate) in [
+            ("17", 100, "A", "C"),
+            ("17", 100, "G", "T"),
+            ("17", 101, "A", "T"),
+            ("17", 99, "A", "T"),
+            ("1", 100, "A", "T"),
+        ] {
+            assert_eq!(
+                annotator
+                    .annotate(chrom, pos, reference, alternate)
+                    .unwrap(),
+                Vec::<String>::new(),
+                "{}:{}:{}>{}",
+                chrom,
+                pos,
+                reference,
+                alternate
+            );
+        }
+    }
+
+    #[test]
+    fn contig_names_are_resolved() {
+        let (_dir, path) = fixture();
+        let mut annotator = Annotator::from_spec(&spec(&path, "AF")).unwrap();
+
+        assert_eq!(
+            annotator.annotate("chr17", 100, "A", "G").unwrap(),
+            vec!["GNOMAD_AF=0.1"]
+        );
+        assert_eq!(
+            annotator.annotate("MT", 50, "G", "A").unwrap(),
+            vec!["GNOMAD_AF=1"]
+        );
+    }
+}
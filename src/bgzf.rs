@@ -0,0 +1,496 @@
+//! Minimal BGZF (blocked gzip) reader with virtual offset seeking, and writer
+
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::{Compression, Crc};
+use std::io::{self, BufRead, Error, ErrorKind, Read, Seek, SeekFrom, Write};
+
+/// Size of a block header with BC as its only extra subfield, as written by [`Writer`]
+const HEADER_SIZE: usize = 18;
+/// Size of the fixed part of a gzip header, up to and including XLEN
+const GZIP_HEADER_SIZE: usize = 12;
+const FOOTER_SIZE: usize = 8;
+
+/// Maximum size of a block header (a gzip header with the longest extra field)
+pub const MAX_HEADER_SIZE: usize = GZIP_HEADER_SIZE + u16::MAX as usize;
+
+/// Maximum uncompressed size of a block (same as htslib)
+const MAX_BLOCK_DATA_SIZE: usize = 0xff00;
+
+/// Empty block marking the end of a BGZF file
+const EOF_BLOCK: [u8; 28] = [
+    31, 139, 8, 4, 0, 0, 0, 0, 0, 255, 6, 0, b'B', b'C', 2, 0, 27, 0, 3, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+];
+
+/// Whether the bytes start with a BGZF block header
+///
+/// # Arguments
+///
+/// * `bytes`: start of the data, holding at least the whole header of the first block (up to
+///   [`MAX_HEADER_SIZE`] bytes)
+///
+/// returns: true if the bytes start with a gzip header with the BC extra subfield
+pub fn is_bgzf(bytes: &[u8]) -> bool {
+    bytes.len() >= GZIP_HEADER_SIZE
+        && bytes[0..4] == [31, 139, 8, 4]
+        && bytes
+            .get(GZIP_HEADER_SIZE..GZIP_HEADER_SIZE + extra_length(bytes))
+            .and_then(block_size)
+            .is_some()
+}
+
+/// XLEN of a gzip header
+fn extra_length(header: &[u8]) -> usize {
+    u16::from_le_bytes([header[10], header[11]]) as usize
+}
+
+/// Total size of a block (BSIZE + 1) by the BC subfield of the extra field of its header
+///
+/// The extra field may hold other subfields (SI1, SI2, SLEN and data) before or after BC.
+fn block_size(extra: &[u8]) -> Option<usize> {
+    let mut rest = extra;
+    while rest.len() >= 4 {
+        let length = u16::from_le_bytes([rest[2], rest[3]]) as usize;
+        let data = rest.get(4..4 + length)?;
+        if rest[0..2] == *b"BC" && length == 2 {
+            return Some(u16::from_le_bytes([data[0], data[1]]) as usize + 1);
+        }
+        rest = &rest[4 + length..];
+    }
+
+    None
+}
+
+/// Read the header of the block at `offset`
+///
+/// returns: the sizes of the header and of the block, `None` at the end of the data
+fn read_header<R: Read>(reader: &mut R, offset: u64) -> io::Result<Option<(usize, usize)>> {
+    let mut header = [0u8; GZIP_HEADER_SIZE];
+    match reader.read_exact(&mut header) {
+        Ok(_) => {}
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => Err(e)?,
+    }
+    let invalid = || {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("Invalid BGZF block at offset {}", offset),
+        )
+    };
+    if header[0..4] != [31, 139, 8, 4] {
+        Err(invalid())?
+    }
+
+    let mut extra = vec![0u8; extra_length(&header)];
+    reader.read_exact(&mut extra)?;
+    let header_size = GZIP_HEADER_SIZE + extra.len();
+    match block_size(&extra) {
+        Some(block_size) if block_size >= header_size + FOOTER_SIZE => {
+            Ok(Some((header_size, block_size)))
+        }
+        _ => Err(invalid()),
+    }
+}
+
+/// Find the block holding a position in the uncompressed data by the sizes in the block headers
+/// and footers, without decompressing
+///
+/// # Arguments
+///
+/// * `reader`: BGZF file
+/// * `position`: position in the uncompressed data
+///
+/// returns: the offset of the block in `reader` and the position in the block
+pub fn locate<R: Read + Seek>(reader: &mut R, position: u64) -> io::Result<(u64, u64)> {
+    let mut block_offset = 0;
+    // position of the block in the uncompressed data
+    let mut start = 0;
+    loop {
+        reader.seek(SeekFrom::Start(block_offset))?;
+        let block_size = match read_header(reader, block_offset)? {
+            Some((_, block_size)) => block_size as u64,
+            None if position == start => return Ok((block_offset, 0)),
+            None => Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Position {} is beyond the end of the data", position),
+            ))?,
+        };
+
+        reader.seek(SeekFrom::Start(block_offset + block_size - 4))?;
+        let mut size = [0u8; 4];
+        reader.read_exact(&mut size)?;
+        let size = u32::from_le_bytes(size) as u64;
+
+        if position < start + size {
+            return Ok((block_offset, position - start));
+        }
+        start += size;
+        block_offset += block_size;
+    }
+}
+
+pub struct Reader<R> {
+    inner: R,
+    block: Vec<u8>,
+    position: usize,
+    block_offset: u64,
+    next_block_offset: u64,
+}
+
+impl<R: Read + Seek> Reader<R> {
+    pub fn new(inner: R) -> Self {
+        Reader {
+            inner,
+            block: Vec::new(),
+            position: 0,
+            block_offset: 0,
+            next_block_offset: 0,
+        }
+    }
+
+    /// Current virtual offset (`compressed block offset << 16 | offset in block`)
+    pub fn virtual_position(&self) -> u64 {
+        if self.position == self.block.len() {
+            self.next_block_offset << 16
+        } else {
+            (self.block_offset << 16) | self.position as u64
+        }
+    }
+
+    /// Seek to a virtual offset
+    ///
+    /// The current block is reused if the offset points into it.
+    pub fn seek_virtual(&mut self, offset: u64) -> io::Result<()> {
+        let block_offset = offset >> 16;
+        let position = (offset & 0xffff) as usize;
+
+        if block_offset != self.block_offset || self.block.is_empty() {
+            self.inner.seek(SeekFrom::Start(block_offset))?;
+            self.next_block_offset = block_offset;
+            self.read_block()?;
+        }
+
+        if position > self.block.len() {
+            Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Invalid virtual offset: {}", offset),
+            ))?
+        }
+        self.position = position;
+
+        Ok(())
+    }
+
+    /// Read the block at `next_block_offset`; leaves an empty block at EOF
+    fn read_block(&mut self) -> io::Result<()> {
+        self.block_offset = self.next_block_offset;
+        self.block.clear();
+        self.position = 0;
+
+        let (header_size, block_size) = match read_header(&mut self.inner, self.block_offset)? {
+            Some(x) => x,
+            None => return Ok(()),
+        };
+
+        let mut rest = vec![0u8; block_size - header_size];
+        self.inner.read_exact(&mut rest)?;
+
+        let data = &rest[..rest.len() - FOOTER_SIZE];
+        DeflateDecoder::new(data).read_to_end(&mut self.block)?;
+
+        self.next_block_offset = self.block_offset + block_size as u64;
+
+        Ok(())
+    }
+}
+
+impl<R: Read + Seek> Read for Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+
+        Ok(n)
+    }
+}
+
+impl<R: Read + Seek> BufRead for Reader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        // skip empty blocks (e.g. the EOF marker) until data or EOF
+        while self.position == self.block.len() {
+            let offset = self.next_block_offset;
+            self.read_block()?;
+            if self.block.is_empty() && self.next_block_offset == offset {
+                break;
+            }
+        }
+
+        Ok(&self.block[self.position..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.position = (self.position + amt).min(self.block.len());
+    }
+}
+
+/// BGZF writer
+///
+/// Data is compressed in blocks of at most 0xff00 bytes. `flush` ends the current block.
+/// The EOF marker is written by `finish`, or on drop if `finish` was not called.
+pub struct Writer<W: Write> {
+    inner: Option<W>,
+    buf: Vec<u8>,
+    compression: Compression,
+    block_offset: u64,
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(inner: W) -> Self {
+        Writer {
+            inner: Some(inner),
+            buf: Vec::with_capacity(MAX_BLOCK_DATA_SIZE),
+            compression: Compression::default(),
+            block_offset: 0,
+        }
+    }
+
+    /// Current virtual offset (`compressed block offset << 16 | offset in block`)
+    pub fn virtual_position(&self) -> u64 {
+        (self.block_offset << 16) | self.buf.len() as u64
+    }
+
+    /// Underlying writer
+    pub fn get_ref(&self) -> &W {
+        self.inner.as_ref().expect("writer is not finished")
+    }
+
+    /// Write the pending block and the EOF marker
+    pub fn finish(mut self) -> io::Result<W> {
+        self.try_finish()?;
+
+        Ok(self.inner.take().expect("writer is not finished"))
+    }
+
+    fn try_finish(&mut self) -> io::Result<()> {
+        self.write_block()?;
+        if let Some(inner) = self.inner.as_mut() {
+            inner.write_all(&EOF_BLOCK)?;
+            inner.flush()?;
+        }
+
+        Ok(())
+    }
+
+    /// Compress the buffered data into a block
+    fn write_block(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let inner = match self.inner.as_mut() {
+            Some(x) => x,
+            None => return Ok(()),
+        };
+
+        let mut encoder = DeflateEncoder::new(Vec::new(), self.compression);
+        encoder.write_all(&self.buf)?;
+        let data = encoder.finish()?;
+
+        let mut crc = Crc::new();
+        crc.update(&self.buf);
+
+        let block_size = HEADER_SIZE + data.len() + FOOTER_SIZE;
+        let mut header = [
+            31, 139, 8, 4, 0, 0, 0, 0, 0, 255, 6, 0, b'B', b'C', 2, 0, 0, 0,
+        ];
+        header[16..18].copy_from_slice(&((block_size - 1) as u16).to_le_bytes());
+
+        inner.write_all(&header)?;
+        inner.write_all(&data)?;
+        inner.write_all(&crc.sum().to_le_bytes())?;
+        inner.write_all(&(self.buf.len() as u32).to_le_bytes())?;
+
+        self.block_offset += block_size as u64;
+        self.buf.clear();
+
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for Writer<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.buf.len() == MAX_BLOCK_DATA_SIZE {
+            self.write_block()?;
+        }
+        let n = buf.len().min(MAX_BLOCK_DATA_SIZE - self.buf.len());
+        self.buf.extend_from_slice(&buf[..n]);
+
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_block()?;
+        match self.inner.as_mut() {
+            Some(inner) => inner.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<W: Write> Drop for Writer<W> {
+    fn drop(&mut self) {
+        if self.inner.is_some() {
+            let _ = self.try_finish();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::MultiGzDecoder;
+    use std::io::Cursor;
+
+    /// Lines of 8 to 40 bytes, spanning several blocks
+    fn lines() -> Vec<String> {
+        (0..10000)
+            .map(|i| format!("line {} {}\n", i, "ACGT".repeat(i % 8)))
+            .collect()
+    }
+
+    /// BGZF data of the lines and the virtual offset of each line
+    fn compress(lines: &[String]) -> (Vec<u8>, Vec<u64>) {
+        let mut writer = Writer::new(Vec::new());
+        let mut offsets = Vec::new();
+        for line in lines {
+            offsets.push(writer.virtual_position());
+            writer.write_all(line.as_bytes()).unwrap();
+        }
+
+        (writer.finish().unwrap(), offsets)
+    }
+
+    #[test]
+    fn written_data_is_read_back() {
+        let lines = lines();
+        let text = lines.concat();
+        let (bytes, _) = compress(&lines);
+
+        assert!(is_bgzf(&bytes));
+        assert!(bytes.ends_with(&EOF_BLOCK));
+        assert!(text.len() > 2 * MAX_BLOCK_DATA_SIZE);
+
+        let mut read = String::new();
+        Reader::new(Cursor::new(&bytes))
+            .read_to_string(&mut read)
+            .unwrap();
+        assert_eq!(read, text);
+
+        // a valid multi-member gzip file
+        let mut read = String::new();
+        MultiGzDecoder::new(bytes.as_slice())
+            .read_to_string(&mut read)
+            .unwrap();
+        assert_eq!(read, text);
+    }
+
+    #[test]
+    fn virtual_offsets_of_the_writer_are_seekable() {
+        let lines = lines();
+        let (bytes, offsets) = compress(&lines);
+        let mut reader = Reader::new(Cursor::new(&bytes));
+
+        for i in [9999, 0, 1, 5400, 5399, 3333] {
+            reader.seek_virtual(offsets[i]).unwrap();
+            assert_eq!(reader.virtual_position(), offsets[i]);
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            assert_eq!(line, lines[i]);
+        }
+    }
+
+    #[test]
+    fn positions_are_located_without_decompressing() {
+        let lines = lines();
+        let text = lines.concat();
+        let (bytes, _) = compress(&lines);
+        let mut cursor = Cursor::new(&bytes);
+
+        for position in [0, 1, MAX_BLOCK_DATA_SIZE - 1, MAX_BLOCK_DATA_SIZE, 150_000] {
+            let (block_offset, within) = locate(&mut cursor, position as u64).unwrap();
+            let mut reader = Reader::new(Cursor::new(&bytes));
+            reader.seek_virtual(block_offset << 16 | within).unwrap();
+            let mut byte = [0u8];
+            reader.read_exact(&mut byte).unwrap();
+            assert_eq!(byte[0], text.as_bytes()[position], "{}", position);
+        }
+        let e = locate(&mut cursor, text.len() as u64 + 1).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidInput);
+    }
+
+    /// Insert extra subfields before and after BC in the header of every block
+    fn with_extra_subfields(bytes: &[u8]) -> Vec<u8> {
+        let before = [b'X', b'Y', 3, 0, 1, 2, 3];
+        let after = [b'Z', b'Z', 0, 0];
+        let mut output = Vec::new();
+        let mut rest = bytes;
+        while !rest.is_empty() {
+            let block_size = u16::from_le_bytes([rest[16], rest[17]]) as usize + 1;
+            let grown = block_size + before.len() + after.len();
+            let extra_length = 6 + before.len() + after.len();
+
+            output.extend_from_slice(&rest[..10]);
+            output.extend_from_slice(&(extra_length as u16).to_le_bytes());
+            output.extend_from_slice(&before);
+            output.extend_from_slice(&[b'B', b'C', 2, 0]);
+            output.extend_from_slice(&((grown - 1) as u16).to_le_bytes());
+            output.extend_from_slice(&after);
+            output.extend_from_slice(&rest[HEADER_SIZE..block_size]);
+            rest = &rest[block_size..];
+        }
+
+        output
+    }
+
+    #[test]
+    fn bc_is_found_among_other_subfields() {
+        let lines = lines();
+        let text = lines.concat();
+        let (bytes, _) = compress(&lines);
+        let bytes = with_extra_subfields(&bytes);
+
+        assert!(is_bgzf(&bytes));
+        let mut read = String::new();
+        Reader::new(Cursor::new(&bytes))
+            .read_to_string(&mut read)
+            .unwrap();
+        assert_eq!(read, text);
+
+        let (block_offset, within) = locate(&mut Cursor::new(&bytes), 100_000).unwrap();
+        let mut reader = Reader::new(Cursor::new(&bytes));
+        reader.seek_virtual(block_offset << 16 | within).unwrap();
+        let mut byte = [0u8];
+        reader.read_exact(&mut byte).unwrap();
+        assert_eq!(byte[0], text.as_bytes()[100_000]);
+    }
+
+    #[test]
+    fn gzip_without_bc_is_not_bgzf() {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"text").unwrap();
+        let gzip = encoder.finish().unwrap();
+        assert!(!is_bgzf(&gzip));
+
+        // an extra field without BC
+        let mut bytes = EOF_BLOCK.to_vec();
+        bytes[12..14].copy_from_slice(b"XY");
+        assert!(!is_bgzf(&bytes));
+        let e = Reader::new(Cursor::new(&bytes))
+            .read_to_end(&mut Vec::new())
+            .unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+
+        // truncated extra field
+        assert!(!is_bgzf(&EOF_BLOCK[..16]));
+    }
+}
@@ -1,436 +1,3183 @@
+use clinvar::annotate::Annotator;
+use clinvar::bgzf;
+use clinvar::converter::CONTIGS;
+use clinvar::diff::{self, Change};
+use clinvar::duplicates::{drop_duplicates, merge_duplicates};
+use clinvar::faidx;
+use clinvar::header;
+use clinvar::io_stats::{self, CountingReader, IoStats};
+use clinvar::json::JsonRecord;
+use clinvar::logger::{self, Level, LogConfig, LogFormat};
+use clinvar::normalize::normalize;
+use clinvar::progress;
+use clinvar::reader::{self, GZIP_MAGIC};
+use clinvar::schema;
+use clinvar::sort::sort_vcf;
+use clinvar::tabix::{self, IndexFormat};
+use clinvar::tsv;
 use clinvar::*;
-use flate2::bufread::GzDecoder;
-use quick_xml::de::Deserializer;
-use quick_xml::events::{BytesStart, Event};
-use quick_xml::{Reader, Writer};
-use serde::de::Deserialize;
+use flate2::bufread::MultiGzDecoder;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::ffi::OsStr;
-use std::fs::File;
-use std::io::ErrorKind::{AlreadyExists, InvalidInput, NotFound};
-use std::io::{self, BufRead, BufReader, BufWriter, Error, ErrorKind, Write};
+use std::fmt::{self, Display, Formatter};
+use std::fs::{File, OpenOptions};
+use std::io::ErrorKind::{AlreadyExists, InvalidData, InvalidInput, NotFound};
+use std::io::{self, BufRead, BufReader, BufWriter, Error, ErrorKind, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
-use std::process::{exit, Command};
-use std::str::from_utf8;
+use std::process::{exit, Command, Output};
+use std::rc::Rc;
+use std::str::FromStr;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
 use structopt::StructOpt;
 use strum::{AsRefStr, EnumString, VariantNames};
-use tempfile::tempdir;
-
-const VCF_HEADER: &str = r#"##fileformat=VCFv4.3
-##FILTER=<ID=PASS,Description="All filters passed">
-##ID=<Description="ClinVar Variation ID">
-##INFO=<ID=ALLELEID,Number=1,Type=Integer,Description="ClinVar Allele ID">
-##INFO=<ID=CONDITIONS,Number=1,Type=String,Description="MedGen:<ID1>/<ID2>/...:<Interpretation1>/<Interpretation2>/...:<SubmissionCount>|MedGen:...">
-##contig=<ID=1>
-##contig=<ID=2>
-##contig=<ID=3>
-##contig=<ID=4>
-##contig=<ID=5>
-##contig=<ID=6>
-##contig=<ID=7>
-##contig=<ID=8>
-##contig=<ID=9>
-##contig=<ID=10>
-##contig=<ID=11>
-##contig=<ID=12>
-##contig=<ID=13>
-##contig=<ID=14>
-##contig=<ID=15>
-##contig=<ID=16>
-##contig=<ID=17>
-##contig=<ID=18>
-##contig=<ID=19>
-##contig=<ID=20>
-##contig=<ID=21>
-##contig=<ID=22>
-##contig=<ID=X>
-##contig=<ID=Y>
-##contig=<ID=MT>
-#CHROM	POS	ID	REF	ALT	QUAL	FILTER	INFO"#;
-
-const EXTENSION_DEBUG_OUTPUT: &'static str = "vcf";
-const EXTENSION_OUTPUT: &'static str = "vcf.gz";
-const EXTENSION_FAI: &'static str = "gz.fai";
-const EXTENSION_GZI: &'static str = "gz.gzi";
-const FILE_NAME_TEMP_OUTPUT: &'static str = "output.vcf";
-const FILE_NAME_TEMP_SORTED: &'static str = "sorted.vcf.gz";
-const FILE_NAME_TEMP_NORMALIZED: &'static str = "normalized.vcf.gz";
-
-#[derive(Debug, EnumString, VariantNames, AsRefStr)]
-pub enum Assembly {
-    GRCh37,
-    GRCh38,
+use tempfile::{tempdir, Builder};
+use xz2::bufread::XzDecoder;
+
+/// Input path for stdin
+const STDIN: &str = "-";
+const STDOUT: &str = "-";
+/// `--info-fields` value selecting all the fields of the output format
+const INFO_FIELDS_ALL: &str = "all";
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+const XZ_MAGIC: [u8; 6] = [0xfd, 0x37, 0x7a, 0x58, 0x5a, 0x00];
+/// Records queued for each thread of --jobs
+const CHANNEL_CAPACITY_PER_JOB: usize = 64;
+const EXIT_FAILURE: i32 = 1;
+const EXIT_ERRORS_IGNORED: i32 = 2;
+const EXIT_INVALID_INPUT: i32 = 3;
+const EXIT_SORT: i32 = 5;
+const EXIT_NORMALIZE: i32 = 6;
+const EXIT_INDEX: i32 = 7;
+
+/// Files converted when the input is a directory
+const BATCH_INPUT_SUFFIXES: [&str; 4] = [".xml", ".xml.gz", ".xml.zst", ".xml.xz"];
+
+const EXTENSION_DEBUG_OUTPUT: &str = "vcf";
+const EXTENSION_JSONL: &str = "jsonl";
+const EXTENSION_TSV: &str = "tsv";
+const EXTENSION_OUTPUT: &str = "vcf.gz";
+const EXTENSION_FAI: &str = "gz.fai";
+const EXTENSION_GZI: &str = "gz.gzi";
+const SUFFIX_REF_MISMATCHES: &str = ".ref_mismatches.tsv";
+const FILE_NAME_TEMP_OUTPUT: &str = "output.vcf";
+const FILE_NAME_TEMP_OUTPUT_BGZF: &str = "output.vcf.gz";
+const FILE_NAME_TEMP_SORTED: &str = "sorted.vcf.gz";
+const FILE_NAME_TEMP_NORMALIZED: &str = "normalized.vcf.gz";
+const FILE_NAME_TEMP_DEDUPLICATED: &str = "deduplicated.vcf.gz";
+
+/// Handling of multiallelic sites by `bcftools norm`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString, VariantNames, AsRefStr)]
+#[strum(serialize_all = "lowercase")]
+pub enum Multiallelics {
+    /// Leave records as they are
+    Keep,
+    /// Split multiallelic records into biallelic ones (`-m -any`)
+    Split,
+    /// Join biallelic records at the same position into multiallelic ones (`-m +any`)
+    Join,
+}
+
+/// Implementation of left-alignment and trimming
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString, VariantNames, AsRefStr)]
+#[strum(serialize_all = "lowercase")]
+pub enum Normalizer {
+    /// Built-in, on conversion, reporting failures with the VariationID
+    Native,
+    /// `bcftools norm` over the sorted VCF
+    Bcftools,
+}
+
+/// Syntax of the output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString, VariantNames, AsRefStr)]
+#[strum(serialize_all = "lowercase")]
+pub enum OutputKind {
+    /// Sorted, normalized and indexed VCF (plain with --debug)
+    Vcf,
+    /// A JSON object per line and allele, in input order (see `clinvar::json::JsonRecord`)
+    Jsonl,
+    /// A row per allele and CONDITIONS entry with a header row, in input order (see
+    /// `clinvar::tsv`)
+    Tsv,
+}
+
+/// Handling of records with the same CHROM, POS, REF and ALT from different VariationIDs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString, VariantNames, AsRefStr)]
+#[strum(serialize_all = "lowercase")]
+pub enum Duplicates {
+    /// Write all of them
+    Keep,
+    /// Merge them into one, joining IDs by `;` and CONDITIONS entries by `|`
+    Merge,
+    /// Write the first of them
+    First,
+}
+
+/// Compression of the converted records before sorting
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString, VariantNames, AsRefStr)]
+#[strum(serialize_all = "lowercase")]
+pub enum Compress {
+    /// Plain text
+    None,
+    /// Blocked gzip, readable by bcftools and tabix
+    Bgzf,
+}
+
+/// Handling of records whose REF does not match the reference
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString, VariantNames, AsRefStr)]
+#[strum(serialize_all = "lowercase")]
+pub enum OnRefMismatch {
+    /// Do not emit the record
+    Drop,
+    /// Emit the record
+    Keep,
+    /// Abort
+    Fail,
+}
+
+/// Values of the former --field-names, now an alias of --format
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString, VariantNames, AsRefStr)]
+#[strum(serialize_all = "lowercase")]
+pub enum FieldNames {
+    /// --format togovar
+    Native,
+    /// --format ncbi
+    Clinvar,
+}
+
+impl From<FieldNames> for OutputFormat {
+    fn from(x: FieldNames) -> Self {
+        match x {
+            FieldNames::Native => OutputFormat::Togovar,
+            FieldNames::Clinvar => OutputFormat::Ncbi,
+        }
+    }
+}
+
+/// Index of the final output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString, VariantNames, AsRefStr)]
+#[strum(serialize_all = "lowercase")]
+pub enum Index {
+    /// Tabix index (`.tbi`)
+    Tbi,
+    /// CSI index (`.csi`), for contigs longer than 2^29 bp
+    Csi,
+    /// No index
+    None,
+}
+
+/// Version like `1.17`
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version(Vec<u32>);
+
+impl FromStr for Version {
+    type Err = String;
+
+    /// Anything after the numbers (e.g. `-12-gabcdef`) is ignored
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(|c: char| !c.is_ascii_digit() && c != '.')
+            .next()
+            .unwrap_or_default()
+            .split('.')
+            .map(|x| x.parse::<u32>())
+            .collect::<Result<Vec<u32>, _>>()
+            .map(Version)
+            .map_err(|_| format!("Invalid version: {}", s))
+    }
+}
+
+impl Display for Version {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let numbers = self
+            .0
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<String>>();
+        write!(f, "{}", numbers.join("."))
+    }
 }
 
 #[derive(Debug, StructOpt)]
+#[structopt(after_help = "EXIT STATUS:
+    0    Converted without errors
+    1    Failed, e.g. to read, decompress or write a file, or records lack --require-fields
+    2    Converted, but errors were ignored by --ignore-error
+    3    Malformed XML or an invalid record
+    5    bcftools sort failed
+    6    bcftools norm failed
+    7    bcftools index failed
+
+SUBCOMMANDS:
+    diff    Report the VariationIDs added, removed, reclassified or relocated between two releases
+            (see clinvar_xml2vcf diff --help)")]
 struct Options {
     /// Just output VCF (do not sort and normalize)
     #[structopt(long)]
     debug: bool,
 
-    /// Overwrite existing file
-    #[structopt(long)]
-    force: bool,
+    /// Overwrite existing file
+    #[structopt(long)]
+    force: bool,
+
+    /// Continue processing even if an error occurs (exits with 2 if any error is ignored)
+    #[structopt(long)]
+    ignore_error: bool,
+
+    /// Fail at the first element or attribute unknown to this tool, even with --ignore-error
+    /// (e.g. `--strict --limit 1000` after each ClinVar release)
+    #[structopt(long)]
+    strict: bool,
+
+    /// Abort when more than this many malformed or undeserializable records are ignored by
+    /// --ignore-error
+    #[structopt(long, name = "ERRORS")]
+    max_errors: Option<u64>,
+
+    /// Drop records that only have sentinel classifications (e.g. "no classifications from unflagged records")
+    #[structopt(long)]
+    exclude_unclassified: bool,
+
+    /// Write soft issues found while converting each record into the FLAGS INFO field
+    #[structopt(long)]
+    emit_flags: bool,
+
+    /// Write a file per chromosome (e.g. clinvar.chr1.vcf.gz for --output clinvar.vcf.gz), each
+    /// sorted, normalized and indexed (not available with --debug)
+    #[structopt(long)]
+    split_by_chromosome: bool,
+
+    /// Also write the files of chromosomes without records with --split-by-chromosome
+    #[structopt(long, requires = "split-by-chromosome")]
+    emit_empty: bool,
+
+    /// Previous output to update with the input, e.g. an incremental release: records of the input
+    /// replace those with the same VariationID, and removed ones are deleted (not available with
+    /// --debug)
+    #[structopt(long, parse(from_os_str))]
+    update_base: Option<PathBuf>,
+
+    /// Records with the same CHROM, POS, REF and ALT from different VariationIDs (merge: one record
+    /// with IDs joined by ; and CONDITIONS entries by |, first: the first one; ignored with --debug)
+    #[structopt(long, default_value = "keep", possible_values(Duplicates::VARIANTS))]
+    duplicates: Duplicates,
+
+    /// Output syntax (jsonl: a JSON object per line and allele, tsv: a row per allele and CONDITIONS
+    /// entry; neither sorted, normalized nor indexed)
+    #[structopt(long, default_value = "vcf", possible_values(OutputKind::VARIANTS))]
+    output_format: OutputKind,
+
+    /// Output format (ncbi: the names and formats of INFO fields of the official clinvar.vcf)
+    #[structopt(long, default_value = "togovar", possible_values(&OutputFormat::VARIANTS))]
+    format: OutputFormat,
+
+    /// Former name of --format (native: togovar, clinvar: ncbi)
+    #[structopt(
+        long,
+        hidden = true,
+        conflicts_with = "format",
+        possible_values(FieldNames::VARIANTS)
+    )]
+    field_names: Option<FieldNames>,
+
+    /// Layout of CONDITIONS (v2: a value per RCV and condition database, declared Number=.)
+    #[structopt(long, default_value = "v1", possible_values(&ConditionsFormat::VARIANTS))]
+    conditions_format: ConditionsFormat,
+
+    /// Write the submitted records (SCVs) with their submitters and classifications into the SCV
+    /// INFO field (togovar format) and the `scvs` field of JSON Lines, and their allele origins
+    /// into ORIGIN and `origins` (SCV_COUNT is otherwise the sum of the RCV submission counts)
+    #[structopt(long)]
+    include_scv: bool,
+
+    /// Write the PubMed IDs cited by the classifications (and by the SCVs with --include-scv) into
+    /// the PMIDS INFO field (togovar format) and the `pmids` field of JSON Lines
+    #[structopt(long)]
+    include_citations: bool,
+
+    /// Write only these INFO fields, skipping the extraction of the others (e.g.
+    /// ALLELEID,CONDITIONS,CLNSIG,RS, or all); SCV and ORIGIN imply --include-scv, PMIDS
+    /// --include-citations and FLAGS --emit-flags
+    #[structopt(long, name = "INFO_FIELDS", use_delimiter = true, require_delimiter = true, parse(try_from_str = parse_info_field))]
+    info_fields: Vec<String>,
+
+    /// Databases to take condition IDs from, in output order (e.g. MedGen,OMIM,Orphanet,MONDO,HP)
+    #[structopt(
+        long,
+        name = "DBS",
+        default_value = "MedGen",
+        use_delimiter = true,
+        require_delimiter = true
+    )]
+    condition_db: Vec<String>,
+
+    /// Keep only records with any of these record statuses (e.g. current,removed); records of
+    /// species other than Homo sapiens are always skipped
+    #[structopt(
+        long,
+        name = "STATUSES",
+        default_value = "current",
+        use_delimiter = true,
+        require_delimiter = true
+    )]
+    record_status: Vec<String>,
+
+    /// Chromosome names on output (ucsc: chr1, ..., chrX, chrY, chrM; refseq: NC_000001.11, ...)
+    /// [default: those of the reference .fai]
+    #[structopt(long, possible_values(ChrStyle::VARIANTS))]
+    chr_style: Option<ChrStyle>,
+
+    /// Keep the chromosome names of the XML instead of those of the reference
+    #[structopt(long, conflicts_with = "chr-style")]
+    no_chr_translate: bool,
+
+    /// Declare every sequence of the reference .fai as a contig, not only the chromosomes
+    #[structopt(long)]
+    all_contigs: bool,
+
+    /// Emit records on contigs other than 1-22, X, Y and MT if the reference .fai has them
+    #[structopt(long)]
+    keep_nonstandard_chr: bool,
+
+    /// Compression of the intermediate VCF (ignored with --debug, which writes plain text)
+    #[structopt(long, default_value = "bgzf", possible_values(Compress::VARIANTS))]
+    compress: Compress,
+
+    /// Handling of records whose REF does not match the reference (mismatches are listed in
+    /// <output>.ref_mismatches.tsv, or logged with --output -)
+    #[structopt(long, default_value = "drop", possible_values(OnRefMismatch::VARIANTS))]
+    on_ref_mismatch: OnRefMismatch,
+
+    /// Sort and normalize with bcftools instead of the built-in implementations (see also
+    /// --normalizer)
+    #[structopt(long)]
+    use_bcftools: bool,
+
+    /// Normalizer of the records [default: bcftools with --use-bcftools, otherwise native]
+    #[structopt(long, possible_values(Normalizer::VARIANTS))]
+    normalizer: Option<Normalizer>,
+
+    /// Run no external program: sort, normalize, bgzip and index with the built-in implementations
+    #[structopt(long, conflicts_with = "use-bcftools")]
+    no_external_tools: bool,
+
+    /// Run a failing bcftools command again up to this many times
+    #[structopt(long, name = "RETRIES", default_value = "0")]
+    retries: usize,
+
+    /// bcftools executable for --use-bcftools
+    #[structopt(long, default_value = "bcftools", parse(from_os_str))]
+    bcftools_path: PathBuf,
+
+    /// Number of extra compression threads of bcftools norm (bcftools sort has no such option)
+    #[structopt(long, name = "THREADS")]
+    bcftools_threads: Option<usize>,
+
+    /// Minimum version of bcftools required by --use-bcftools
+    #[structopt(long, name = "VERSION", default_value = "1.10")]
+    bcftools_min_version: Version,
+
+    /// Memory for buffering records on sorting in MB; sorted chunks are spilled to temporary files
+    /// beyond it
+    #[structopt(long, name = "SORT_MB", default_value = "768")]
+    sort_memory: usize,
+
+    /// Memory for sorting in bytes, or with a K, M or G suffix (e.g. 2G), in place of
+    /// --sort-memory; also given to bcftools sort --max-mem with --use-bcftools
+    #[structopt(long, name = "SIZE", parse(try_from_str = parse_size))]
+    sort_mem: Option<usize>,
+
+    /// Directory for the temporary files of sorting [default: the working directory for the native
+    /// sorter, the default of bcftools sort -T with --use-bcftools]
+    #[structopt(long, parse(from_os_str))]
+    sort_tmp_dir: Option<PathBuf>,
+
+    /// Index of the output (ignored with --debug); built natively, falling back to bcftools index
+    /// unless --no-external-tools
+    #[structopt(
+        long,
+        alias = "index-format",
+        default_value = "tbi",
+        possible_values(Index::VARIANTS)
+    )]
+    index: Index,
+
+    /// Handling of multiallelic sites on normalization (join requires --use-bcftools)
+    #[structopt(long, default_value = "keep", possible_values(Multiallelics::VARIANTS))]
+    multiallelics: Multiallelics,
+
+    /// Emit records whose RCVs have no condition from the condition databases, using condition names
+    /// in place of IDs (NA:<name>:...)
+    #[structopt(long)]
+    keep_unmapped_conditions: bool,
+
+    /// Keep only records with at least one RCV having one of these germline classifications (e.g.
+    /// pathogenic,likely_pathogenic); compound classifications match each of their terms
+    #[structopt(long, name = "SIGNIFICANCES", use_delimiter = true, require_delimiter = true, parse(try_from_str = parse_significance))]
+    include_significance: Vec<ClinicalSignificance>,
+
+    /// Keep only records located in these regions (e.g. 17, 17:43000000-43200000, 17:43000000-)
+    #[structopt(long, name = "REGIONS", use_delimiter = true, require_delimiter = true)]
+    regions: Vec<Region>,
+
+    /// Keep only records with any of these gene symbols (case-insensitive, e.g. BRCA1,BRCA2)
+    #[structopt(long, name = "GENES", use_delimiter = true, require_delimiter = true)]
+    genes: Vec<String>,
+
+    /// Stop after this many VariationArchive elements (counting emitted and skipped records)
+    #[structopt(long, name = "RECORDS")]
+    limit: Option<u64>,
+
+    /// Start from the VariationArchive element after this many
+    #[structopt(long, name = "OFFSET", default_value = "0")]
+    skip: u64,
+
+    /// Accept IUPAC ambiguity codes (including N) in REF/ALT instead of skipping such records
+    #[structopt(long)]
+    allow_iupac: bool,
+
+    /// Emit at most N RCVs (ranked by submission count, then by accession) per record, giving the
+    /// number of the others in RCV_TRUNCATED
+    #[structopt(long, name = "N")]
+    max_rcvs: Option<usize>,
+
+    /// Fail the run if an emitted record lacks any of these INFO fields (e.g. clnsig,conditions,revstat)
+    #[structopt(long, name = "FIELDS", use_delimiter = true, require_delimiter = true, parse(from_str = info_key))]
+    require_fields: Vec<String>,
+
+    /// Abort at the first record lacking a required field instead of reporting all of them at the end
+    #[structopt(long)]
+    require_fields_fatal: bool,
+
+    /// Copy INFO fields from a bgzipped and tabix-indexed VCF, matching on CHROM/POS/REF/ALT
+    /// [<vcf.gz>:<FIELD>,...[:<PREFIX>]]
+    #[structopt(long, number_of_values = 1)]
+    annotate: Vec<String>,
+
+    /// Log format
+    #[structopt(long, default_value = "text", possible_values(&LogFormat::VARIANTS))]
+    log_format: LogFormat,
+
+    /// Log more: -v for info, -vv for debug messages such as each skipped record
+    #[structopt(short, long, parse(from_occurrences))]
+    verbose: u8,
+
+    /// Log only errors
+    #[structopt(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Log at most this many messages of each skip reason, counting the rest (unlimited with -v)
+    #[structopt(long, name = "COUNT", default_value = "10")]
+    log_max_per_reason: u64,
+
+    /// Also write log messages to this file with timestamps, together with the options and the
+    /// summary of the run
+    #[structopt(long, parse(from_os_str))]
+    log_file: Option<PathBuf>,
+
+    /// Append to --log-file instead of truncating it
+    #[structopt(long)]
+    log_append: bool,
+
+    /// Rotate the log file when it reaches this size in MB
+    #[structopt(long, name = "MB")]
+    log_max_size_mb: Option<u64>,
+
+    /// Rotate the log file after this many seconds
+    #[structopt(long, name = "SECONDS")]
+    log_max_age_secs: Option<u64>,
+
+    /// Number of rotated log files to keep
+    #[structopt(long, default_value = "5")]
+    log_max_files: usize,
+
+    /// Drop log events after this many have been written
+    #[structopt(long, name = "EVENTS")]
+    log_max_events: Option<u64>,
+
+    /// Capacity of the input read buffers in MB [default: 1 for inputs on a network mount or larger
+    /// than 1 GB, otherwise 8 KB]
+    #[structopt(long, name = "READ_MB")]
+    read_buffer_mb: Option<usize>,
+
+    /// Write the statistics of the run to this file as JSON (one line per input and assembly)
+    #[structopt(long, parse(from_os_str))]
+    stats_json: Option<PathBuf>,
+
+    /// Write each skipped record to this file as JSON (one line per record and assembly)
+    #[structopt(long, parse(from_os_str))]
+    report: Option<PathBuf>,
+
+    /// Write the records skipped after parsing to this VCF with the reason in SKIP_REASON (one file
+    /// per assembly as the output; neither sorted nor normalized, CHROM, POS, REF and ALT are "."
+    /// if unavailable)
+    #[structopt(long, parse(from_os_str))]
+    rejects: Option<PathBuf>,
+
+    /// Do not show the progress (shown only when stderr is a terminal)
+    #[structopt(long)]
+    no_progress: bool,
+
+    /// Number of threads deserializing records (the output is then not in input order)
+    #[structopt(long, name = "JOBS", default_value = "1")]
+    jobs: usize,
+
+    /// Assembly; give several to convert them in a single pass, with one --reference each in the
+    /// same order (outputs are named <name>.<assembly>.vcf.gz)
+    #[structopt(
+        long,
+        required = true,
+        number_of_values = 1,
+        possible_values(Assembly::VARIANTS)
+    )]
+    assembly: Vec<Assembly>,
+
+    /// Reference fasta
+    #[structopt(long, required = true, number_of_values = 1, parse(from_os_str))]
+    reference: Vec<PathBuf>,
+
+    /// Keep the working directory with the intermediate files of each stage
+    #[structopt(long)]
+    keep_temp: bool,
+
+    /// Create the working directory for intermediate files in this directory [default: $TMPDIR]
+    #[structopt(long, parse(from_os_str))]
+    tmpdir: Option<PathBuf>,
+
+    /// Record the progress in this file every --checkpoint-interval records, keeping the working
+    /// directory, so that a killed conversion can be continued by --resume (requires plain or BGZF
+    /// input; not available with --debug, --jobs over 1, --update-base or --report)
+    #[structopt(long, parse(from_os_str))]
+    checkpoint: Option<PathBuf>,
+
+    /// Records between checkpoints
+    #[structopt(long, default_value = "100000")]
+    checkpoint_interval: u64,
+
+    /// Continue the conversion recorded in --checkpoint (from the beginning if the file does not
+    /// exist)
+    #[structopt(long, requires = "checkpoint")]
+    resume: bool,
+
+    /// Path to output [- (stdout, unsorted like --debug)]
+    #[structopt(long, short, parse(from_os_str))]
+    output: Option<PathBuf>,
+
+    /// Path to input [*.xml | *.xml.gz | *.xml.zst | *.xml.xz | - (stdin, requires --output) | directory
+    /// (converts each of such files into the --output directory)]
+    #[structopt(parse(from_os_str))]
+    input: PathBuf,
+}
+
+/// Syntax of the report of diff
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString, VariantNames, AsRefStr)]
+#[strum(serialize_all = "lowercase")]
+pub enum DiffFormat {
+    /// A header row and a row per change (see `clinvar::diff::COLUMNS`)
+    Tsv,
+    /// A JSON object per change (see `clinvar::diff::DiffEntry`)
+    Jsonl,
+}
+
+/// Options of `clinvar_xml2vcf diff`
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "clinvar_xml2vcf diff",
+    about = "Report the VariationIDs added, removed, reclassified (CLNSIG or CONDITIONS) or \
+             relocated (CHROM, POS, REF or ALT) between two releases"
+)]
+struct DiffOptions {
+    /// Assembly to convert XML inputs on
+    #[structopt(long, default_value = "GRCh38", possible_values(Assembly::VARIANTS))]
+    assembly: Assembly,
+
+    /// Chromosome names of XML inputs (ucsc: chr1, ..., chrX, chrY, chrM)
+    #[structopt(long, default_value = "plain", possible_values(ChrStyle::VARIANTS))]
+    chr_style: ChrStyle,
+
+    /// Skip malformed records of XML inputs
+    #[structopt(long)]
+    ignore_error: bool,
+
+    /// Syntax of the report
+    #[structopt(long, default_value = "tsv", possible_values(DiffFormat::VARIANTS))]
+    format: DiffFormat,
+
+    /// Memory for sorting each input by VariationID in MB
+    #[structopt(long, name = "SORT_MB", default_value = "256")]
+    sort_memory: usize,
+
+    /// Create the working directory for intermediate files in this directory [default: $TMPDIR]
+    #[structopt(long, parse(from_os_str))]
+    tmpdir: Option<PathBuf>,
+
+    /// Path to the report [default: stdout]
+    #[structopt(long, short, parse(from_os_str))]
+    output: Option<PathBuf>,
+
+    /// Previous release [*.vcf | *.vcf.gz | *.xml | *.xml.gz]
+    #[structopt(parse(from_os_str))]
+    old: PathBuf,
+
+    /// Current release [*.vcf | *.vcf.gz | *.xml | *.xml.gz]
+    #[structopt(parse(from_os_str))]
+    new: PathBuf,
+}
+
+/// Map a field name given on the command line to its INFO key (e.g. `revstat` to `CLNREVSTAT`)
+fn info_key(name: &str) -> String {
+    match name.trim().to_ascii_lowercase().as_str() {
+        "clnsig" | "significance" => "CLNSIG".to_string(),
+        "revstat" | "review_status" => "CLNREVSTAT".to_string(),
+        "conditions" => "CONDITIONS".to_string(),
+        x => x.to_ascii_uppercase(),
+    }
+}
+
+/// Name of an INFO field of any output format, or `all`
+fn parse_info_field(text: &str) -> Result<String, String> {
+    if text.eq_ignore_ascii_case(INFO_FIELDS_ALL) {
+        return Ok(INFO_FIELDS_ALL.to_string());
+    }
+
+    let names = OutputFormat::VARIANTS
+        .iter()
+        .filter_map(|x| x.parse().ok())
+        .flat_map(header::all_info_fields)
+        .map(|x| x.id)
+        .collect::<BTreeSet<&str>>();
+    let name = text.to_ascii_uppercase();
+    if names.contains(name.as_str()) {
+        Ok(name)
+    } else {
+        Err(format!(
+            "Unknown INFO field: {} [possible values: {}, {}]",
+            text,
+            INFO_FIELDS_ALL,
+            names.into_iter().collect::<Vec<&str>>().join(", ")
+        ))
+    }
+}
+
+/// Parse a size in bytes with an optional K, M or G suffix (powers of 1024)
+fn parse_size(text: &str) -> Result<usize, String> {
+    let (number, unit) = match text.char_indices().last() {
+        Some((i, c)) if c.is_ascii_alphabetic() => (&text[..i], c.to_ascii_uppercase()),
+        _ => (text, 'B'),
+    };
+    let multiplier = match unit {
+        'B' => 1,
+        'K' => 1 << 10,
+        'M' => 1 << 20,
+        'G' => 1 << 30,
+        _ => return Err(format!("Unknown unit of size: {}", text)),
+    };
+
+    number
+        .parse::<usize>()
+        .ok()
+        .and_then(|x| x.checked_mul(multiplier))
+        .filter(|x| *x > 0)
+        .ok_or_else(|| format!("Invalid size: {}", text))
+}
+
+fn parse_significance(text: &str) -> Result<ClinicalSignificance, String> {
+    text.parse()
+        .map_err(|x| format!("Unknown clinical significance: {}", x))
+}
+
+fn run() -> Result<Outcome, ClinvarError> {
+    let args = std::env::args_os().collect::<Vec<_>>();
+    if args.get(1).is_some_and(|x| x == "diff") {
+        return run_diff(&DiffOptions::from_iter(&args[1..]));
+    }
+    let mut options = Options::from_iter(args);
+
+    let level = match (options.quiet, options.verbose) {
+        (true, _) => Level::Error,
+        (_, 0) => Level::Warn,
+        (_, 1) => Level::Info,
+        _ => Level::Debug,
+    };
+    logger::init(&LogConfig {
+        format: options.log_format,
+        level,
+        path: options.log_file.clone(),
+        append: options.log_append,
+        max_size: options.log_max_size_mb.map(|x| x * 1024 * 1024),
+        max_age: options.log_max_age_secs.map(Duration::from_secs),
+        max_files: options.log_max_files,
+        max_events: options.log_max_events,
+        max_per_reason: (options.verbose == 0).then_some(options.log_max_per_reason),
+    })?;
+    logger::note(&format!(
+        "{} {}: {:?}",
+        env!("CARGO_BIN_NAME"),
+        env!("CARGO_PKG_VERSION"),
+        options
+    ));
+
+    if let Some(x) = options.field_names {
+        options.format = x.into();
+    }
+
+    let stdin = options.input == Path::new(STDIN);
+    let stdout = options.output.as_deref() == Some(Path::new(STDOUT));
+    if stdout {
+        if options.assembly.len() > 1 {
+            Err(Error::new(
+                InvalidInput,
+                "--output - is not available with more than one --assembly",
+            ))?
+        }
+        options.debug = true;
+    }
+    // the records are written as they are converted
+    if options.output_format != OutputKind::Vcf {
+        options.debug = true;
+    }
+    if options.split_by_chromosome && options.debug {
+        Err(Error::new(
+            InvalidInput,
+            "--split-by-chromosome is not available with --debug, --output - or --output-format \
+             other than vcf",
+        ))?
+    }
+    if let Some(base) = options.update_base.as_ref() {
+        if options.debug || options.assembly.len() > 1 || options.input.is_dir() {
+            Err(Error::new(
+                InvalidInput,
+                "--update-base is only available for a single --assembly and input file, without \
+                 --debug, --output - or --output-format other than vcf",
+            ))?
+        }
+        if !base.exists() {
+            Err(Error::new(NotFound, format!("{}", base.to_string_lossy())))?
+        }
+    }
+    if options.checkpoint.is_some() {
+        if options.debug
+            || options.jobs > 1
+            || options.update_base.is_some()
+            || options.report.is_some()
+            || options.rejects.is_some()
+            || stdin
+            || options.input.is_dir()
+        {
+            Err(Error::new(
+                InvalidInput,
+                "--checkpoint is only available for an input file, without --debug, --output -, \
+                 --output-format other than vcf, --jobs over 1, --update-base, --report or \
+                 --rejects",
+            ))?
+        }
+        if options.checkpoint_interval == 0 {
+            Err(Error::new(
+                InvalidInput,
+                "--checkpoint-interval must be positive",
+            ))?
+        }
+        // fail before converting rather than on resuming
+        seekable(&options.input)?;
+    }
+    if stdin && options.output.as_ref().is_none_or(|x| x.is_dir()) {
+        Err(Error::new(
+            InvalidInput,
+            "--output <file> is required to read from stdin",
+        ))?
+    }
+    if options.input.is_dir() && options.rejects.is_some() {
+        Err(Error::new(
+            InvalidInput,
+            "--rejects is not available to convert a directory",
+        ))?
+    }
+    if options.input.is_dir() && options.output.as_ref().is_none_or(|x| !x.is_dir()) {
+        Err(Error::new(
+            InvalidInput,
+            "--output <directory> is required to convert a directory",
+        ))?
+    }
+    if !stdin && !options.input.exists() {
+        Err(Error::new(
+            NotFound,
+            format!("{}", options.input.to_string_lossy()),
+        ))?
+    }
+
+    if options.assembly.len() != options.reference.len() {
+        Err(Error::new(
+            InvalidInput,
+            "Give one --reference for each --assembly",
+        ))?
+    }
+    if (1..options.assembly.len()).any(|i| options.assembly[..i].contains(&options.assembly[i])) {
+        Err(Error::new(InvalidInput, "Duplicate --assembly"))?
+    }
+    if options.assembly.len() > 1 && !options.annotate.is_empty() {
+        Err(Error::new(
+            InvalidInput,
+            "--annotate is not available with more than one --assembly",
+        ))?
+    }
+
+    if !options.info_fields.is_empty() {
+        let available = header::all_info_fields(options.format)
+            .into_iter()
+            .map(|x| x.id.to_string())
+            .collect::<Vec<String>>();
+        if let Some(x) = options
+            .info_fields
+            .iter()
+            .find(|x| *x != INFO_FIELDS_ALL && !available.contains(x))
+        {
+            Err(Error::new(
+                InvalidInput,
+                format!(
+                    "{} is not an INFO field of the --format [possible values: {}, {}]",
+                    x,
+                    INFO_FIELDS_ALL,
+                    available.join(", ")
+                ),
+            ))?
+        }
+        if options.info_fields.iter().any(|x| x == INFO_FIELDS_ALL) {
+            options.info_fields = available;
+        }
+        options.include_scv |= options
+            .info_fields
+            .iter()
+            .any(|x| x == "SCV" || x == "ORIGIN");
+        options.include_citations |= options.info_fields.iter().any(|x| x == "PMIDS");
+        options.emit_flags |= options.info_fields.iter().any(|x| x == "FLAGS");
+    }
+
+    let normalizer = match options.normalizer {
+        Some(x) => x,
+        None if options.use_bcftools => Normalizer::Bcftools,
+        None => Normalizer::Native,
+    };
+    options.normalizer = Some(normalizer);
+    if normalizer == Normalizer::Bcftools && options.no_external_tools {
+        Err(Error::new(
+            InvalidInput,
+            "--normalizer bcftools cannot be used with --no-external-tools",
+        ))?
+    }
+    if !options.debug
+        && normalizer == Normalizer::Native
+        && options.multiallelics == Multiallelics::Join
+    {
+        Err(Error::new(
+            InvalidInput,
+            "--multiallelics join is only available with --normalizer bcftools",
+        ))?
+    }
+
+    if !options.debug && (options.use_bcftools || normalizer == Normalizer::Bcftools) {
+        check_bcftools(&options.bcftools_path, &options.bcftools_min_version)?;
+    }
+
+    for reference in &options.reference {
+        if !reference.exists() {
+            Err(Error::new(
+                NotFound,
+                format!("{}", reference.to_string_lossy()),
+            ))?
+        }
+        let mut fai = reference.clone();
+        fai.set_extension(EXTENSION_FAI);
+        if !fai.exists() {
+            Err(Error::new(NotFound, format!("{}", fai.to_string_lossy())))?
+        }
+        let mut gzi = reference.clone();
+        gzi.set_extension(EXTENSION_GZI);
+        if !gzi.exists() {
+            Err(Error::new(NotFound, format!("{}", gzi.to_string_lossy())))?
+        }
+    }
+
+    for path in [options.stats_json.as_ref(), options.report.as_ref()]
+        .into_iter()
+        .flatten()
+    {
+        File::create(path).map_err(|e| write_error(path, e))?;
+    }
+
+    let outcome = if options.input.is_dir() {
+        convert_dir(&options)?
+    } else {
+        convert(&options.input, &options)?
+    };
+
+    let log_summary = logger::finish()?;
+    if options.log_file.is_some() {
+        eprintln!("Log files written: {}", log_summary.files_written);
+    }
+    if log_summary.dropped != 0 {
+        eprintln!("Log events dropped: {}", log_summary.dropped);
+    }
+
+    Ok(outcome)
+}
+
+fn main() {
+    match run() {
+        Ok(Outcome::Clean) => {}
+        Ok(Outcome::ErrorsIgnored) => {
+            eprintln!(
+                "Exit status {}: completed, but errors were ignored",
+                EXIT_ERRORS_IGNORED
+            );
+            exit(EXIT_ERRORS_IGNORED)
+        }
+        Ok(Outcome::Failed) => exit(EXIT_FAILURE),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            exit(match e {
+                ClinvarError::Io(_) => EXIT_FAILURE,
+                ClinvarError::Xml { .. }
+                | ClinvarError::Deserialize { .. }
+                | ClinvarError::InvalidRecord { .. } => EXIT_INVALID_INPUT,
+                ClinvarError::ExternalTool { stage, .. } => match stage {
+                    Stage::Sort => EXIT_SORT,
+                    Stage::Normalize => EXIT_NORMALIZE,
+                    Stage::Index => EXIT_INDEX,
+                },
+            })
+        }
+    }
+}
+
+/// Compare two releases, converting XML inputs into VCFs first
+fn run_diff(options: &DiffOptions) -> Result<Outcome, ClinvarError> {
+    for path in [&options.old, &options.new] {
+        if !path.exists() {
+            Err(Error::new(NotFound, format!("{}", path.to_string_lossy())))?
+        }
+    }
+    let work_dir = match options.tmpdir.as_ref() {
+        Some(dir) => Builder::new().tempdir_in(dir)?,
+        None => tempdir()?,
+    };
+
+    let mut outcome = Outcome::Clean;
+    let mut open = |path: &Path, name: &str| -> Result<Box<dyn BufRead>, ClinvarError> {
+        let mut file = BufReader::new(File::open(path)?);
+        let mut reader: Box<dyn BufRead> = if file.fill_buf()?.starts_with(&GZIP_MAGIC) {
+            Box::new(BufReader::new(MultiGzDecoder::new(file)))
+        } else {
+            Box::new(file)
+        };
+        if !reader.fill_buf()?.trim_ascii_start().starts_with(b"<") {
+            return Ok(reader);
+        }
+
+        let temp = work_dir.path().join(name);
+        let mut writer = BufWriter::new(File::create(&temp)?);
+        let stats = Converter::new(options.assembly)
+            .chr_style(options.chr_style)
+            .write(
+                &mut VariationArchiveReader::from_reader(reader)
+                    .skip_malformed(options.ignore_error),
+                &mut writer,
+            )?;
+        writer.flush()?;
+        if stats.errors() != 0 {
+            outcome = Outcome::ErrorsIgnored;
+        }
+        eprintln!(
+            "{}: {} records, {} VCF lines",
+            path.to_string_lossy(),
+            stats.records,
+            stats.written
+        );
+
+        Ok(Box::new(BufReader::new(File::open(temp)?)))
+    };
+    let old = open(&options.old, "old.vcf")?;
+    let new = open(&options.new, "new.vcf")?;
+
+    let mut writer: Box<dyn Write> = match options.output.as_ref() {
+        Some(path) => Box::new(BufWriter::new(
+            File::create(path).map_err(|e| write_error(path, e))?,
+        )),
+        None => Box::new(BufWriter::new(io::stdout().lock())),
+    };
+    if options.format == DiffFormat::Tsv {
+        writeln!(writer, "{}", diff::COLUMNS.join("\t"))?;
+    }
+    let mut counts = BTreeMap::new();
+    diff::diff(
+        old,
+        new,
+        options.sort_memory * 1024 * 1024,
+        work_dir.path(),
+        |entry| {
+            *counts.entry(entry.change).or_insert(0) += 1;
+            match options.format {
+                DiffFormat::Tsv => writeln!(writer, "{}", entry.tsv_row()),
+                DiffFormat::Jsonl => {
+                    serde_json::to_writer(&mut writer, &entry)?;
+                    writeln!(writer)
+                }
+            }
+        },
+    )?;
+    writer.flush()?;
+    work_dir.close()?;
+
+    for change in [
+        Change::Added,
+        Change::Removed,
+        Change::Reclassified,
+        Change::Relocated,
+    ] {
+        eprintln!(
+            "{}: {}",
+            change.code(),
+            counts.get(&change).copied().unwrap_or(0)
+        );
+    }
+
+    Ok(outcome)
+}
+
+/// Result of a completed conversion, ordered from the best
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Outcome {
+    Clean,
+    /// Malformed or undeserializable records are skipped by --ignore-error
+    ErrorsIgnored,
+    /// Emitted records lack required fields
+    Failed,
+}
+
+/// Convert each input file in the directory into the --output directory
+///
+/// A failure aborts the batch unless --ignore-error is given.
+///
+/// returns: the worst outcome of the files, where a file failing to convert is `Failed`
+fn convert_dir(options: &Options) -> Result<Outcome, ClinvarError> {
+    let mut inputs = std::fs::read_dir(&options.input)?
+        .map(|x| x.map(|x| x.path()))
+        .collect::<io::Result<Vec<PathBuf>>>()?;
+    inputs.retain(|x| {
+        let name = x.file_name().unwrap_or_default().to_string_lossy();
+        x.is_file() && BATCH_INPUT_SUFFIXES.iter().any(|s| name.ends_with(s))
+    });
+    inputs.sort();
+    if inputs.is_empty() {
+        Err(Error::new(
+            NotFound,
+            format!("No input files in {}", options.input.to_string_lossy()),
+        ))?
+    }
+
+    let mut results = Vec::new();
+    let mut error = None;
+    for input in &inputs {
+        eprintln!("Input: {}", input.to_string_lossy());
+        match convert(input, options) {
+            Ok(outcome) => results.push((input, Ok(outcome))),
+            Err(e) => {
+                error!("Failed to convert {}: {}", input.to_string_lossy(), e);
+                results.push((input, Err(e.to_string())));
+                if !options.ignore_error {
+                    error = Some(e);
+                    break;
+                }
+            }
+        }
+    }
+
+    eprintln!("Files:");
+    for (input, result) in &results {
+        let status = match result {
+            Ok(Outcome::Clean) => "ok".to_string(),
+            Ok(Outcome::ErrorsIgnored) => "ok, errors ignored".to_string(),
+            Ok(Outcome::Failed) => "missing required fields".to_string(),
+            Err(e) => format!("failed: {}", e),
+        };
+        eprintln!("  {}: {}", input.to_string_lossy(), status);
+    }
+    if results.len() < inputs.len() {
+        eprintln!("  ({} not converted)", inputs.len() - results.len());
+    }
+    if let Some(e) = error {
+        Err(e)?
+    }
+
+    Ok(results
+        .iter()
+        .map(|(_, x)| x.as_ref().map_or(Outcome::Failed, |x| *x))
+        .max()
+        .unwrap_or(Outcome::Clean))
+}
+
+/// Convert an input file into the outputs of every assembly
+///
+/// returns: `Failed` if any emitted record lacks the required fields, otherwise whether any
+/// error is ignored
+fn convert(input: &Path, options: &Options) -> Result<Outcome, ClinvarError> {
+    let stdin = input == Path::new(STDIN);
+    let stdout = options.output.as_deref() == Some(Path::new(STDOUT));
+
+    let output = if stdin || stdout {
+        options.output.clone().expect("--output is given")
+    } else if let Some(mut o) = options.output.clone() {
+        if o.is_dir() {
+            let file_name = input.file_name().ok_or(Error::new(
+                InvalidInput,
+                format!("{}", input.to_string_lossy()),
+            ))?;
+            o.push(file_name);
+            o.set_extension(output_extension(options));
+        }
+        o
+    } else {
+        let file_name = input.file_name().ok_or(Error::new(
+            InvalidInput,
+            format!("{}", input.to_string_lossy()),
+        ))?;
+        let mut o = Path::new(file_name).to_path_buf();
+        o.set_extension(output_extension(options));
+        o
+    };
+
+    let mut annotators = options
+        .annotate
+        .iter()
+        .map(|x| Annotator::from_spec(x))
+        .collect::<io::Result<Vec<Annotator>>>()?;
+    let annotation_header = annotators
+        .iter()
+        .flat_map(|x| x.header())
+        .collect::<Vec<String>>();
+
+    let command_line = std::env::args_os()
+        .map(|x| shell_quote(&x.to_string_lossy()))
+        .collect::<Vec<String>>()
+        .join(" ");
+    let mut targets = options
+        .assembly
+        .iter()
+        .zip(options.reference.iter())
+        .map(|(assembly, reference)| {
+            let mut fai = reference.clone();
+            fai.set_extension(EXTENSION_FAI);
+            let sequences = read_fai(&fai)?;
+            let chr_style = match options.chr_style {
+                Some(x) => x,
+                None if options.no_chr_translate => ChrStyle::Plain,
+                None => ChrStyle::detect(
+                    &sequences.iter().map(|x| &x.0).collect::<Vec<_>>(),
+                    *assembly,
+                )
+                .map_err(|e| Error::new(e.kind(), format!("{}: {}", fai.to_string_lossy(), e)))?,
+            };
+            let contigs = contigs(
+                &fai,
+                sequences,
+                *assembly,
+                chr_style,
+                options.all_contigs || options.keep_nonstandard_chr,
+            );
+            let config = FormatConfig {
+                max_rcvs: options.max_rcvs,
+                exclude_unclassified: options.exclude_unclassified,
+                emit_flags: options.emit_flags,
+                format: options.format,
+                conditions_format: options.conditions_format,
+                condition_dbs: options.condition_db.clone(),
+                keep_unmapped_conditions: options.keep_unmapped_conditions,
+                record_status: options.record_status.clone(),
+                include_significance: options.include_significance.clone(),
+                regions: options.regions.clone(),
+                genes: options.genes.clone(),
+                allow_iupac: options.allow_iupac,
+                contigs: options
+                    .keep_nonstandard_chr
+                    .then(|| contigs.iter().map(|x| x.0.clone()).collect()),
+                include_scv: options.include_scv,
+                include_citations: options.include_citations,
+                info_fields: (!options.info_fields.is_empty())
+                    .then(|| options.info_fields.iter().cloned().collect()),
+            };
+
+            Ok(Target {
+                assembly: *assembly,
+                reference: reference.clone(),
+                output: if options.assembly.len() > 1 {
+                    assembly_path(&output, *assembly)
+                } else {
+                    output.clone()
+                },
+                fasta: faidx::Reader::from_path(reference)?,
+                ref_mismatches: None,
+                rejects: options
+                    .rejects
+                    .as_ref()
+                    .map(|path| {
+                        let path = if options.assembly.len() > 1 {
+                            assembly_path(path, *assembly)
+                        } else {
+                            path.clone()
+                        };
+                        File::create(&path)
+                            .map(|x| (path.clone(), BufWriter::new(x)))
+                            .map_err(|e| write_error(&path, e))
+                    })
+                    .transpose()?,
+                converter: Converter::new(*assembly)
+                    .config(config)
+                    .chr_style(chr_style)
+                    .contigs(contigs)
+                    .per_allele_numbers(options.multiallelics == Multiallelics::Join)
+                    .source(format!(
+                        "{} {}",
+                        env!("CARGO_BIN_NAME"),
+                        env!("CARGO_PKG_VERSION")
+                    ))
+                    .reference(std::path::absolute(reference)?.to_string_lossy())
+                    .command_line(command_line.as_str())
+                    .header_lines(annotation_header.clone()),
+                summary: Summary::default(),
+                updated: options.update_base.as_ref().map(|_| HashSet::new()),
+            })
+        })
+        .collect::<io::Result<Vec<Target>>>()?;
+
+    for target in &targets {
+        // files of chromosomes are checked on splitting
+        if !stdout && !options.split_by_chromosome && target.output.exists() && !options.force {
+            Err(Error::new(
+                AlreadyExists,
+                format!("{}", target.output.to_string_lossy()),
+            ))?
+        }
+    }
+
+    let declared = header::info_fields(targets[0].converter.format_config())
+        .iter()
+        .map(|x| x.id.to_string())
+        .chain(annotation_header.iter().filter_map(|x| {
+            x.strip_prefix("##INFO=<ID=")
+                .and_then(|x| x.split(',').next())
+                .map(|x| x.to_string())
+        }))
+        .collect::<Vec<String>>();
+    if let Some(x) = options
+        .require_fields
+        .iter()
+        .find(|x| !declared.contains(x))
+    {
+        Err(Error::new(
+            InvalidInput,
+            format!("Unknown required field: {}", x),
+        ))?
+    }
+
+    // a missing checkpoint starts the conversion over
+    let resumed = match options.checkpoint.as_ref() {
+        Some(path) if options.resume && path.exists() => Some(Checkpoint::load(path)?),
+        _ => None,
+    };
+
+    let temp_dir = match options.tmpdir.as_ref() {
+        _ if options.debug || resumed.is_some() => None,
+        Some(dir) => {
+            if !dir.is_dir() {
+                Err(Error::new(
+                    NotFound,
+                    format!("Directory not found: {}", dir.to_string_lossy()),
+                ))?
+            }
+            Some(Builder::new().tempdir_in(dir).map_err(|e| {
+                Error::new(
+                    e.kind(),
+                    format!(
+                        "Failed to create a working directory in {}: {}",
+                        dir.to_string_lossy(),
+                        e
+                    ),
+                )
+            })?)
+        }
+        None => Some(tempdir()?),
+    };
+    // kept on errors as well, and to resume from with --checkpoint
+    let (work_dir, temp_dir) = match temp_dir {
+        Some(x) if options.keep_temp || options.checkpoint.is_some() => (x.into_path(), None),
+        Some(x) => (x.path().to_path_buf(), Some(x)),
+        None => match resumed.as_ref() {
+            Some(x) => (x.work_dir.clone(), None),
+            None => (PathBuf::new(), None),
+        },
+    };
+    let kept_dir = (options.keep_temp && !options.debug).then_some(work_dir.as_path());
+
+    let buffer_size = match options.read_buffer_mb {
+        Some(x) => x.max(1) * 1024 * 1024,
+        None => io_stats::default_buffer_size(input),
+    };
+    let io_stats = Rc::new(RefCell::new(IoStats::default()));
+    let temp_output_name = match options.compress {
+        Compress::None => FILE_NAME_TEMP_OUTPUT,
+        Compress::Bgzf => FILE_NAME_TEMP_OUTPUT_BGZF,
+    };
+    let mut checkpointer = match options.checkpoint.as_ref() {
+        Some(path) => {
+            let paths = targets
+                .iter()
+                .map(|x| x.temp_path(&work_dir, temp_output_name))
+                .collect();
+            let checkpoint = Checkpoint::new(input, &work_dir, paths)?;
+            if let Some(resumed) = resumed.as_ref() {
+                checkpoint.check(path, resumed)?;
+                resumed.restore(&mut targets)?;
+                eprintln!(
+                    "Resuming from {} records at position {}",
+                    resumed.count, resumed.position
+                );
+            }
+            Some(Checkpointer {
+                path: path.clone(),
+                resumed: resumed.is_some(),
+                checkpoint: resumed.unwrap_or(checkpoint),
+            })
+        }
+        None => None,
+    };
+    let (reader, input_size) = match checkpointer.as_ref() {
+        _ if stdin => (reader_from_stdin(buffer_size, io_stats.clone())?, None),
+        Some(x) if x.resumed => {
+            reader_resumed(input, &x.checkpoint, buffer_size, io_stats.clone())?
+        }
+        _ => reader_from_path(input, buffer_size, io_stats.clone())?,
+    };
+    let mut reader = reader.offset(options.skip).limit(options.limit);
+    {
+        let mut writers = targets
+            .iter()
+            .enumerate()
+            .map(|(i, x)| {
+                if stdout {
+                    return Ok(OutputWriter {
+                        path: PathBuf::from("stdout"),
+                        inner: Inner::Stdout(BufWriter::new(io::stdout())),
+                    });
+                }
+                let path = if options.debug {
+                    x.output.clone()
+                } else {
+                    x.temp_path(&work_dir, temp_output_name)
+                };
+                let file = match checkpointer.as_ref() {
+                    Some(c) if c.resumed => reopen(&path, c.checkpoint.targets[i].length)?,
+                    _ => File::create(&path)?,
+                };
+                let inner = match options.compress {
+                    Compress::Bgzf if !options.debug => Inner::Bgzf(bgzf::Writer::new(file)),
+                    _ => Inner::Plain(BufWriter::new(file)),
+                };
+                Ok(OutputWriter { path, inner })
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let mut reporter = options.report.as_deref().map(Reporter::open).transpose()?;
+
+        let _progress = (!options.no_progress).then(|| progress::start(input_size));
+        output_vcf(
+            &mut reader,
+            &io_stats,
+            &mut writers,
+            &mut targets,
+            options,
+            &mut annotators,
+            &mut reporter,
+            &mut checkpointer,
+        )?;
+
+        if let Some(reporter) = reporter {
+            reporter.finish()?;
+        }
+
+        for writer in writers {
+            writer.finish()?;
+        }
+        for target in targets.iter_mut() {
+            if let Some(writer) = target.ref_mismatches.as_mut() {
+                writer.flush()?;
+            }
+            if let Some((path, writer)) = target.rejects.as_mut() {
+                writer.flush().map_err(|e| write_error(path, e))?;
+            }
+        }
+    }
+
+    let mut summary = String::new();
+    for target in &targets {
+        if targets.len() > 1 {
+            summary += &format!("{}:\n", target.assembly.as_ref());
+        }
+        summary += &target.summary.to_string();
+    }
+    if options.ignore_error {
+        summary += &format!("Errors ignored: {}\n", targets[0].summary.stats.errors());
+    }
+    eprint!("{}", summary);
+    logger::note(summary.trim_end());
+    if let Some(path) = options.stats_json.as_ref() {
+        write_stats_json(path, input, &targets)?;
+    }
+    let io_summary = format!("Read buffer: {} bytes\n{}", buffer_size, io_stats.borrow());
+    eprintln!("{}", io_summary);
+    logger::note(&io_summary);
+
+    for target in &targets {
+        if !options.debug {
+            let temp_output = target.temp_path(&work_dir, temp_output_name);
+            let temp_sorted = target.temp_path(&work_dir, FILE_NAME_TEMP_SORTED);
+            let temp_normalized = target.temp_path(&work_dir, FILE_NAME_TEMP_NORMALIZED);
+
+            if let (Some(base), Some(updated)) = (options.update_base.as_ref(), &target.updated) {
+                let (kept, replaced) = append_base(base, &temp_output, options.compress, updated)?;
+                let message = format!(
+                    "Records of the update base kept: {}, replaced or removed: {}",
+                    kept, replaced
+                );
+                eprintln!("{}", message);
+                logger::note(&message);
+            }
+
+            let sorted = if options.use_bcftools {
+                vcf_sort(
+                    &temp_output,
+                    &temp_sorted,
+                    &options.bcftools_path,
+                    options.sort_mem,
+                    options.sort_tmp_dir.as_deref(),
+                    options.retries,
+                )
+            } else {
+                native_sort(
+                    &temp_output,
+                    &temp_sorted,
+                    options.compress,
+                    options
+                        .sort_mem
+                        .unwrap_or(options.sort_memory * 1024 * 1024),
+                    options.sort_tmp_dir.as_deref().unwrap_or(&work_dir),
+                )
+            };
+            if let Err(e) = sorted {
+                output_temp_file(&temp_output, &target.output, kept_dir)?;
+                Err(e)?
+            };
+
+            if options.normalizer == Some(Normalizer::Bcftools) {
+                if let Err(e) = vcf_normalize(
+                    &temp_sorted,
+                    &temp_normalized,
+                    &target.reference,
+                    &options.bcftools_path,
+                    options.bcftools_threads,
+                    options.multiallelics,
+                    options.on_ref_mismatch,
+                    options.retries,
+                ) {
+                    output_temp_file(&temp_sorted, &target.output, kept_dir)?;
+                    Err(e)?
+                };
+
+                verify_multiallelics(&temp_sorted, &temp_normalized, options.multiallelics)?;
+            }
+            // records were normalized on conversion by the native normalizer, so that shifted ones
+            // are sorted too
+            let temp_final = if options.normalizer == Some(Normalizer::Bcftools) {
+                &temp_normalized
+            } else {
+                &temp_sorted
+            };
+            let temp_final = if options.duplicates == Duplicates::Keep {
+                temp_final.clone()
+            } else {
+                let temp_deduplicated = target.temp_path(&work_dir, FILE_NAME_TEMP_DEDUPLICATED);
+                let count = resolve_duplicates(temp_final, &temp_deduplicated, options.duplicates)?;
+                let message = match options.duplicates {
+                    Duplicates::Merge => format!("Duplicate records merged: {}", count),
+                    _ => format!("Duplicate records dropped: {}", count),
+                };
+                if targets.len() > 1 {
+                    eprintln!("{}: {}", target.assembly.as_ref(), message);
+                } else {
+                    eprintln!("{}", message);
+                }
+                logger::note(&message);
+                temp_deduplicated
+            };
+            let temp_final = &temp_final;
+
+            let outputs = if options.split_by_chromosome {
+                split_by_chromosome(temp_final, &target.output, options)?
+            } else {
+                std::fs::copy(temp_final, &target.output)?;
+                vec![target.output.clone()]
+            };
+            let format = match options.index {
+                Index::Tbi => Some(IndexFormat::Tbi),
+                Index::Csi => Some(IndexFormat::Csi),
+                Index::None => None,
+            };
+            if let Some(format) = format {
+                thread::scope(|scope| {
+                    outputs
+                        .iter()
+                        .map(|x| scope.spawn(move || index_vcf(x, format, options)))
+                        .collect::<Vec<_>>()
+                        .into_iter()
+                        .try_for_each(|x| x.join().expect("indexing does not panic").map(|_| ()))
+                })?;
+            }
+            for output in &outputs {
+                eprintln!("Output to: {}", output.to_string_lossy());
+            }
+        } else if !stdout {
+            eprintln!("Output to: {}", &target.output.to_string_lossy());
+        }
+    }
+
+    match temp_dir {
+        Some(x) => x.close()?,
+        None if kept_dir.is_some() => {
+            eprintln!("Temp files kept in: {}", work_dir.to_string_lossy())
+        }
+        None if options.checkpoint.is_some() => std::fs::remove_dir_all(&work_dir)?,
+        None => {}
+    }
+    if let Some(checkpointer) = checkpointer {
+        if checkpointer.path.exists() {
+            std::fs::remove_file(&checkpointer.path)?;
+        }
+    }
+
+    if targets.iter().any(|x| !x.summary.violations.is_empty()) {
+        Ok(Outcome::Failed)
+    } else if targets[0].summary.stats.errors() != 0 {
+        Ok(Outcome::ErrorsIgnored)
+    } else {
+        Ok(Outcome::Clean)
+    }
+}
+
+/// Writer of the converted records of a target
+///
+/// Errors name the file, e.g. to tell which intermediate file ran out of space.
+struct OutputWriter {
+    path: PathBuf,
+    inner: Inner,
+}
+
+enum Inner {
+    Plain(BufWriter<File>),
+    Bgzf(bgzf::Writer<File>),
+    Stdout(BufWriter<io::Stdout>),
+}
+
+impl OutputWriter {
+    /// Flush the data (and write the EOF marker of BGZF)
+    fn finish(self) -> io::Result<()> {
+        let result = match self.inner {
+            Inner::Plain(mut x) => x.flush(),
+            Inner::Bgzf(x) => x.finish().map(|_| ()),
+            Inner::Stdout(mut x) => x.flush(),
+        };
+
+        result.map_err(|e| write_error(&self.path, e))
+    }
+
+    /// Flush the data to the disk (ending the current block of BGZF)
+    ///
+    /// returns: the length of the file
+    fn sync(&mut self) -> io::Result<u64> {
+        let result = match &mut self.inner {
+            Inner::Plain(x) => sync(x),
+            Inner::Bgzf(x) => x.flush().and_then(|_| {
+                let file = x.get_ref();
+                file.sync_data()?;
+                Ok(file.metadata()?.len())
+            }),
+            Inner::Stdout(x) => x.flush().map(|_| 0),
+        };
+
+        result.map_err(|e| write_error(&self.path, e))
+    }
+}
+
+impl Write for OutputWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let result = match &mut self.inner {
+            Inner::Plain(x) => x.write(buf),
+            Inner::Bgzf(x) => x.write(buf),
+            Inner::Stdout(x) => x.write(buf),
+        };
+
+        result.map_err(|e| write_error(&self.path, e))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let result = match &mut self.inner {
+            Inner::Plain(x) => x.flush(),
+            Inner::Bgzf(x) => x.flush(),
+            Inner::Stdout(x) => x.flush(),
+        };
+
+        result.map_err(|e| write_error(&self.path, e))
+    }
+}
+
+fn write_error(path: &Path, e: Error) -> Error {
+    Error::new(
+        e.kind(),
+        format!("Failed to write {}: {}", path.to_string_lossy(), e),
+    )
+}
+
+/// Line of --stats-json
+#[derive(Serialize)]
+struct StatsLine<'a> {
+    input: &'a Path,
+    assembly: &'a str,
+    #[serde(flatten)]
+    stats: &'a Stats,
+    ref_mismatches: u64,
+}
+
+/// Append the statistics of each target to the file as JSON lines
+fn write_stats_json(path: &Path, input: &Path, targets: &[Target]) -> io::Result<()> {
+    let mut writer = OpenOptions::new()
+        .append(true)
+        .open(path)
+        .map(BufWriter::new)
+        .map_err(|e| write_error(path, e))?;
+    for target in targets {
+        let line = StatsLine {
+            input,
+            assembly: target.assembly.as_ref(),
+            stats: &target.summary.stats,
+            ref_mismatches: target.summary.ref_mismatches,
+        };
+        serde_json::to_writer(&mut writer, &line)?;
+        writeln!(writer).map_err(|e| write_error(path, e))?;
+    }
+
+    writer.flush().map_err(|e| write_error(path, e))
+}
+
+/// Line of --report
+#[derive(Serialize)]
+struct ReportLine<'a> {
+    variation_id: Option<u64>,
+    accession: Option<&'a str>,
+    reason: SkipReason,
+    detail: &'a str,
+    /// Position in the input after the start tag of the record, or of the malformed XML
+    position: usize,
+}
+
+/// Writer of --report, appending a JSON line per skipped record
+struct Reporter {
+    path: PathBuf,
+    writer: BufWriter<File>,
+}
+
+impl Reporter {
+    fn open(path: &Path) -> io::Result<Self> {
+        let writer = OpenOptions::new()
+            .append(true)
+            .open(path)
+            .map(BufWriter::new)
+            .map_err(|e| write_error(path, e))?;
+
+        Ok(Reporter {
+            path: path.to_path_buf(),
+            writer,
+        })
+    }
+
+    fn report(
+        &mut self,
+        variation_id: Option<u64>,
+        accession: Option<&str>,
+        reason: SkipReason,
+        detail: &str,
+        position: usize,
+    ) -> io::Result<()> {
+        let line = ReportLine {
+            variation_id,
+            accession,
+            reason,
+            detail,
+            position,
+        };
+        serde_json::to_writer(&mut self.writer, &line)?;
+        writeln!(self.writer).map_err(|e| write_error(&self.path, e))
+    }
+
+    fn finish(mut self) -> io::Result<()> {
+        self.writer.flush().map_err(|e| write_error(&self.path, e))
+    }
+}
+
+/// Output of one assembly
+struct Target {
+    assembly: Assembly,
+    reference: PathBuf,
+    output: PathBuf,
+    fasta: faidx::Reader,
+    /// Opened on the first REF mismatch
+    ref_mismatches: Option<BufWriter<File>>,
+    /// Path and writer of --rejects
+    rejects: Option<(PathBuf, BufWriter<File>)>,
+    converter: Converter,
+    summary: Summary,
+    /// VariationIDs of the input with --update-base
+    updated: Option<HashSet<u64>>,
+}
+
+impl Target {
+    /// Compare REF with the reference, recording a mismatch
+    ///
+    /// returns: whether REF matches
+    fn check_ref(&mut self, record: &VcfRecord) -> io::Result<bool> {
+        let start = record.pos - 1;
+        let sequence =
+            self.fasta
+                .fetch(&record.chrom, start, start + record.reference.len() as u64)?;
+        if sequence
+            .as_ref()
+            .is_some_and(|x| x.eq_ignore_ascii_case(record.reference.as_bytes()))
+        {
+            return Ok(true);
+        }
+        let sequence = sequence
+            .map(|x| String::from_utf8_lossy(&x).to_ascii_uppercase())
+            .unwrap_or_else(|| "-".to_string());
+
+        self.summary.ref_mismatches += 1;
+        if self.output == Path::new(STDOUT) {
+            warn!(
+                reason = SkipReason::RefMismatch;
+                "REF mismatch: {} {}:{} {} (reference: {})",
+                record.id, record.chrom, record.pos, record.reference, sequence
+            );
+            return Ok(false);
+        }
+        let writer = match self.ref_mismatches.as_mut() {
+            Some(x) => x,
+            None => {
+                let mut writer = BufWriter::new(File::create(self.ref_mismatches_path())?);
+                writeln!(writer, "#variation_id\tchrom\tpos\tref\treference")?;
+                self.ref_mismatches.insert(writer)
+            }
+        };
+        writeln!(
+            writer,
+            "{}\t{}\t{}\t{}\t{}",
+            record.id, record.chrom, record.pos, record.reference, sequence
+        )?;
+
+        Ok(false)
+    }
+
+    /// Count a record skipped after parsing, writing it with --report and --rejects
+    ///
+    /// # Arguments
+    ///
+    /// * `variant`: `VariationArchive` of the record
+    /// * `rejected`: the record with the reason to skip it
+    /// * `detail`: description of the reason for --report
+    /// * `position`: position of the record in the input
+    /// * `reporter`: writer of --report
+    ///
+    /// returns: Result<(), Error>
+    fn reject(
+        &mut self,
+        variant: &VariationArchive,
+        mut rejected: RejectedRecord,
+        detail: &str,
+        position: usize,
+        reporter: &mut Option<Reporter>,
+    ) -> io::Result<()> {
+        self.summary.stats.skip(rejected.reason);
+        if let Some(reporter) = reporter.as_mut() {
+            reporter.report(
+                Some(variant.variation_id),
+                Some(&variant.accession),
+                rejected.reason,
+                detail,
+                position,
+            )?;
+        }
+        if let Some((path, writer)) = self.rejects.as_mut() {
+            let chr_style = self.converter.chr_style_on_output();
+            rejected.chrom = rejected.chrom.map(|x| chr_style.rename(&x, self.assembly));
+            writeln!(writer, "{}", rejected).map_err(|e| write_error(path, e))?;
+        }
+
+        Ok(())
+    }
+
+    /// Path to the list of REF mismatches
+    fn ref_mismatches_path(&self) -> PathBuf {
+        let mut path = self.output.clone().into_os_string();
+        path.push(SUFFIX_REF_MISMATCHES);
+        PathBuf::from(path)
+    }
+
+    /// Path to a temporary file of this assembly
+    fn temp_path(&self, dir: &Path, name: &str) -> PathBuf {
+        dir.join(format!("{}.{}", self.assembly.as_ref(), name))
+    }
+}
+
+/// Extension of the output named after the input
+fn output_extension(options: &Options) -> &'static str {
+    match options.output_format {
+        OutputKind::Jsonl => EXTENSION_JSONL,
+        OutputKind::Tsv => EXTENSION_TSV,
+        OutputKind::Vcf if options.debug => EXTENSION_DEBUG_OUTPUT,
+        OutputKind::Vcf => EXTENSION_OUTPUT,
+    }
+}
+
+/// Quote an argument for a POSIX shell, as it is if it has no special characters
+///
+/// Control characters are written in ANSI-C quoting (`$'\n'`) so that the header line is not
+/// broken.
+fn shell_quote(arg: &str) -> String {
+    if !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_-+./:=@%,".contains(c))
+    {
+        return arg.to_string();
+    }
+
+    if arg.chars().any(|c| c.is_ascii_control()) {
+        let mut quoted = String::from("$'");
+        for c in arg.chars() {
+            match c {
+                '\\' | '\'' => {
+                    quoted.push('\\');
+                    quoted.push(c);
+                }
+                c if c.is_ascii_control() => quoted.push_str(&format!("\\x{:02x}", c as u32)),
+                c => quoted.push(c),
+            }
+        }
+        quoted.push('\'');
+        quoted
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+/// Names and lengths of the sequences of a .fai
+fn read_fai(fai: &Path) -> io::Result<Vec<(String, u64)>> {
+    let mut sequences = Vec::new();
+    for line in BufReader::new(File::open(fai)?).lines() {
+        let line = line?;
+        let mut columns = line.split('\t');
+        let (name, length) = match (columns.next(), columns.next()) {
+            (Some(name), Some(length)) => (name, length),
+            _ => continue,
+        };
+        let length = length.parse::<u64>().map_err(|_| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("Invalid length in {}: {}", fai.to_string_lossy(), line),
+            )
+        })?;
+        sequences.push((name.to_string(), length));
+    }
+
+    Ok(sequences)
+}
+
+/// Contigs to declare, from the sequences of the .fai of the reference
+///
+/// Sequences named after the accepted chromosomes (in the style of `chr_style`) are taken in .fai
+/// order, or all sequences with `all`. Chromosomes missing from the .fai are appended without a
+/// length so that every record still has its contig declared.
+fn contigs(
+    fai: &Path,
+    sequences: Vec<(String, u64)>,
+    assembly: Assembly,
+    chr_style: ChrStyle,
+    all: bool,
+) -> Vec<(String, Option<u64>)> {
+    let chromosomes = CONTIGS
+        .iter()
+        .map(|x| chr_style.rename(x, assembly))
+        .collect::<Vec<String>>();
+
+    let mut contigs = sequences
+        .into_iter()
+        .filter(|x| all || chromosomes.contains(&x.0))
+        .map(|(name, length)| (name, Some(length)))
+        .collect::<Vec<_>>();
+
+    for chromosome in chromosomes {
+        if !contigs.iter().any(|x| x.0 == chromosome) {
+            warn!(
+                "Contig not found in {}: {}",
+                fai.to_string_lossy(),
+                chromosome
+            );
+            contigs.push((chromosome, None));
+        }
+    }
+
+    contigs
+}
+
+/// Insert the assembly before the extensions, e.g. `a.vcf.gz` to `a.GRCh38.vcf.gz`
+fn assembly_path(path: &Path, assembly: Assembly) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|x| x.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let name = match name.split_once('.') {
+        Some((stem, extension)) => format!("{}.{}.{}", stem, assembly.as_ref(), extension),
+        None => format!("{}.{}", name, assembly.as_ref()),
+    };
+
+    path.with_file_name(name)
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Summary {
+    stats: Stats,
+    unmapped_conditions: u64,
+    unknown_classifications: u64,
+    nonstandard_chr: u64,
+    ref_mismatches: u64,
+    normalized: u64,
+    violations: Vec<String>,
+}
+
+impl Display for Summary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.stats)?;
+
+        if self.unmapped_conditions != 0 {
+            writeln!(
+                f,
+                "Records kept with unmapped conditions: {}",
+                self.unmapped_conditions
+            )?;
+        }
+
+        if self.nonstandard_chr != 0 {
+            writeln!(
+                f,
+                "Records kept on nonstandard contigs: {}",
+                self.nonstandard_chr
+            )?;
+        }
+
+        if self.ref_mismatches != 0 {
+            writeln!(
+                f,
+                "Records with REF mismatching the reference: {}",
+                self.ref_mismatches
+            )?;
+        }
+
+        if self.normalized != 0 {
+            writeln!(f, "Records left-aligned or trimmed: {}", self.normalized)?;
+        }
+
+        if self.unknown_classifications != 0 {
+            writeln!(
+                f,
+                "Records with classifications outside the vocabulary: {}",
+                self.unknown_classifications
+            )?;
+        }
+
+        if !self.violations.is_empty() {
+            writeln!(
+                f,
+                "Records lacking required fields: {}",
+                self.violations.len()
+            )?;
+            for violation in &self.violations {
+                writeln!(f, "  {}", violation)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Progress of a conversion recorded by --checkpoint
+#[derive(Debug, Serialize, Deserialize)]
+struct Checkpoint {
+    /// Canonical path to the input
+    input: PathBuf,
+    input_size: u64,
+    /// Working directory of the intermediate files
+    work_dir: PathBuf,
+    release_date: Option<String>,
+    /// Position in the uncompressed input after the last record converted
+    position: usize,
+    /// `VariationArchive` elements read up to `position`
+    count: u64,
+    targets: Vec<TargetCheckpoint>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TargetCheckpoint {
+    /// Intermediate VCF
+    path: PathBuf,
+    /// Length of the intermediate VCF
+    length: u64,
+    /// Length of <output>.ref_mismatches.tsv once created
+    ref_mismatches: Option<u64>,
+    summary: Summary,
+}
+
+impl Checkpoint {
+    fn new(input: &Path, work_dir: &Path, paths: Vec<PathBuf>) -> io::Result<Self> {
+        Ok(Checkpoint {
+            input: input.canonicalize()?,
+            input_size: input.metadata()?.len(),
+            work_dir: work_dir.to_path_buf(),
+            release_date: None,
+            position: 0,
+            count: 0,
+            targets: paths
+                .into_iter()
+                .map(|path| TargetCheckpoint {
+                    path,
+                    length: 0,
+                    ref_mismatches: None,
+                    summary: Summary::default(),
+                })
+                .collect(),
+        })
+    }
+
+    fn load(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        serde_json::from_reader(BufReader::new(file)).map_err(|e| {
+            Error::new(
+                InvalidData,
+                format!("Invalid checkpoint {}: {}", path.to_string_lossy(), e),
+            )
+        })
+    }
+
+    /// Write to a temporary file renamed to the path, so that a kill leaves the previous one
+    fn save(&self, path: &Path) -> io::Result<()> {
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        let mut writer = BufWriter::new(File::create(&temp).map_err(|e| write_error(path, e))?);
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        let file = writer.into_inner().map_err(|e| e.into_error())?;
+        file.sync_all().map_err(|e| write_error(path, e))?;
+
+        std::fs::rename(temp, path)
+    }
+
+    /// Check that the checkpoint is of the conversion of the same input into the same files
+    fn check(&self, path: &Path, checkpoint: &Checkpoint) -> io::Result<()> {
+        let same_targets = self.targets.len() == checkpoint.targets.len()
+            && self
+                .targets
+                .iter()
+                .zip(checkpoint.targets.iter())
+                .all(|(x, y)| x.path == y.path);
+        if self.input != checkpoint.input
+            || self.input_size != checkpoint.input_size
+            || !same_targets
+        {
+            Err(Error::new(
+                InvalidInput,
+                format!(
+                    "Checkpoint {} is of another input or options; remove it to start over",
+                    path.to_string_lossy()
+                ),
+            ))?
+        }
+
+        Ok(())
+    }
+
+    /// Restore the summaries and REF mismatches of the targets
+    fn restore(&self, targets: &mut [Target]) -> io::Result<()> {
+        for (target, checkpoint) in targets.iter_mut().zip(self.targets.iter()) {
+            target.summary = checkpoint.summary.clone();
+            if let Some(length) = checkpoint.ref_mismatches {
+                let file = reopen(&target.ref_mismatches_path(), length)?;
+                target.ref_mismatches = Some(BufWriter::new(file));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// State of --checkpoint during a conversion
+struct Checkpointer {
+    path: PathBuf,
+    checkpoint: Checkpoint,
+    /// The header is already written
+    resumed: bool,
+}
+
+impl Checkpointer {
+    /// Record the progress after the outputs are synced to the disk
+    ///
+    /// # Arguments
+    ///
+    /// * `position`: position in the uncompressed input after the last record converted
+    /// * `count`: `VariationArchive` elements read up to `position`
+    /// * `writers`: writers of the intermediate VCFs of the targets
+    /// * `targets`: targets
+    ///
+    /// returns: ()
+    fn save(
+        &mut self,
+        position: usize,
+        count: u64,
+        writers: &mut [OutputWriter],
+        targets: &mut [Target],
+    ) -> io::Result<()> {
+        let checkpoints = self.checkpoint.targets.iter_mut();
+        for ((checkpoint, writer), target) in checkpoints.zip(writers).zip(targets) {
+            checkpoint.length = writer.sync()?;
+            checkpoint.ref_mismatches = target.ref_mismatches.as_mut().map(sync).transpose()?;
+            checkpoint.summary = target.summary.clone();
+        }
+        self.checkpoint.position = position;
+        self.checkpoint.count = count;
+
+        self.checkpoint.save(&self.path)
+    }
+}
+
+/// Open a file to append to after truncating it to the length
+fn reopen(path: &Path, length: u64) -> io::Result<File> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .open(path)
+        .map_err(|e| Error::new(e.kind(), format!("{}: {}", path.to_string_lossy(), e)))?;
+    file.set_len(length)?;
+    file.seek(SeekFrom::End(0))?;
+
+    Ok(file)
+}
+
+/// Flush a file to the disk
+///
+/// returns: the length of the file
+fn sync(writer: &mut BufWriter<File>) -> io::Result<u64> {
+    writer.flush()?;
+    let file = writer.get_ref();
+    file.sync_data()?;
+
+    Ok(file.metadata()?.len())
+}
+
+type XmlReader = VariationArchiveReader<Box<dyn BufRead>>;
+
+/// Reader of a file, decompressing gzip, zstd and xz by the extension or the magic bytes
+///
+/// returns: the reader and the file size, which `stats` counts the bytes of
+fn reader_from_path<T: AsRef<Path>>(
+    path: T,
+    capacity: usize,
+    stats: Rc<RefCell<IoStats>>,
+) -> io::Result<(XmlReader, Option<u64>)> {
+    let path = path.as_ref();
+    let file = File::open(path)?;
+    let size = file.metadata()?.len();
+    let mut f = BufReader::with_capacity(capacity, CountingReader::new(file, stats));
+    let magic = f.fill_buf()?;
+    let is = |extension: &str, bytes: &[u8]| {
+        path.extension().is_some_and(|x| x == extension) || magic.starts_with(bytes)
+    };
+    let r: Box<dyn BufRead> = if is("gz", &GZIP_MAGIC) {
+        Box::new(BufReader::with_capacity(capacity, MultiGzDecoder::new(f)))
+    } else if is("zst", &ZSTD_MAGIC) {
+        let r = zstd::stream::read::Decoder::with_buffer(f)?;
+        Box::new(BufReader::with_capacity(capacity, r))
+    } else if is("xz", &XZ_MAGIC) {
+        Box::new(BufReader::with_capacity(
+            capacity,
+            XzDecoder::new_multi_decoder(f),
+        ))
+    } else {
+        Box::new(f)
+    };
+
+    Ok((VariationArchiveReader::from_reader(r), Some(size)))
+}
+
+/// Format of an input that a conversion can be resumed in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Seekable {
+    Plain,
+    Bgzf,
+}
+
+/// Check that a conversion of the file can be resumed by seeking it
+fn seekable(path: &Path) -> io::Result<Seekable> {
+    let mut magic = Vec::new();
+    File::open(path)?
+        .take(bgzf::MAX_HEADER_SIZE as u64)
+        .read_to_end(&mut magic)?;
+    let not_seekable = |format: &str| {
+        Err(Error::new(
+            InvalidInput,
+            format!(
+                "{} cannot be seeked for --checkpoint, which requires plain XML or BGZF (a {} \
+                 input can be recompressed by bgzip)",
+                path.to_string_lossy(),
+                format
+            ),
+        ))
+    };
+    if bgzf::is_bgzf(&magic) {
+        Ok(Seekable::Bgzf)
+    } else if magic.starts_with(&GZIP_MAGIC) {
+        not_seekable("gzip")
+    } else if magic.starts_with(&ZSTD_MAGIC) {
+        not_seekable("zstd")
+    } else if magic.starts_with(&XZ_MAGIC) {
+        not_seekable("xz")
+    } else {
+        Ok(Seekable::Plain)
+    }
+}
+
+/// Reader of a file from the position of a checkpoint
+///
+/// returns: the reader and the size of the rest of the file, which `stats` counts the bytes of
+fn reader_resumed(
+    path: &Path,
+    checkpoint: &Checkpoint,
+    capacity: usize,
+    stats: Rc<RefCell<IoStats>>,
+) -> io::Result<(XmlReader, Option<u64>)> {
+    let mut file = File::open(path)?;
+    let size = file.metadata()?.len();
+    let position = checkpoint.position as u64;
+    let (r, offset): (Box<dyn BufRead>, _) = match seekable(path)? {
+        Seekable::Plain => {
+            file.seek(SeekFrom::Start(position))?;
+            let f = BufReader::with_capacity(capacity, CountingReader::new(file, stats));
+            (Box::new(f), position)
+        }
+        Seekable::Bgzf => {
+            let (block_offset, within) = bgzf::locate(&mut file, position)?;
+            file.seek(SeekFrom::Start(block_offset))?;
+            let f = BufReader::with_capacity(capacity, CountingReader::new(file, stats));
+            let mut r = BufReader::with_capacity(capacity, MultiGzDecoder::new(f));
+            io::copy(&mut (&mut r).take(within), &mut io::sink())?;
+            (Box::new(r), block_offset)
+        }
+    };
+
+    let reader = VariationArchiveReader::resume(
+        r,
+        checkpoint.release_date.as_deref(),
+        checkpoint.position,
+        checkpoint.count,
+    );
+    Ok((reader, Some(size.saturating_sub(offset))))
+}
+
+/// Reader of stdin, decompressing gzip detected by the magic bytes
+fn reader_from_stdin(capacity: usize, stats: Rc<RefCell<IoStats>>) -> io::Result<XmlReader> {
+    let mut r = BufReader::with_capacity(capacity, CountingReader::new(io::stdin(), stats));
+    let r: Box<dyn BufRead> = if r.fill_buf()?.starts_with(&GZIP_MAGIC) {
+        Box::new(BufReader::with_capacity(capacity, MultiGzDecoder::new(r)))
+    } else {
+        Box::new(r)
+    };
+
+    Ok(VariationArchiveReader::from_reader(r))
+}
+
+/// Item read from the input
+enum Input<T> {
+    /// `ReleaseDate` of the root element, given before any record
+    Header(Option<String>),
+    /// `VariationArchive` element and the position in the input after its start tag
+    Record(T, usize),
+    /// Malformed XML skipped by --ignore-error and the position in the input
+    XmlError(String, usize),
+    /// Position in the input after the last record and the number of elements read up to it,
+    /// given every --checkpoint-interval records
+    Checkpoint(usize, u64),
+}
+
+/// Convert all records, writing the records of each target to the writer at the same index
+///
+/// The header is written once the root element is seen, to include its `ReleaseDate`.
+/// With --jobs N, N threads deserialize the records read by this thread, and another thread
+/// writes them.
+#[allow(clippy::too_many_arguments)]
+fn output_vcf<R: BufRead>(
+    reader: &mut VariationArchiveReader<R>,
+    io_stats: &RefCell<IoStats>,
+    writers: &mut [OutputWriter],
+    targets: &mut [Target],
+    options: &Options,
+    annotators: &mut [Annotator],
+    reporter: &mut Option<Reporter>,
+    checkpointer: &mut Option<Checkpointer>,
+) -> io::Result<()> {
+    let mut output = |input: Input<Result<VariationArchive, ClinvarError>>| match input {
+        Input::Header(release_date) => {
+            if let Some(checkpointer) = checkpointer.as_mut() {
+                checkpointer
+                    .checkpoint
+                    .release_date
+                    .clone_from(&release_date);
+                if checkpointer.resumed {
+                    return Ok(());
+                }
+            }
+            for (writer, target) in writers.iter_mut().zip(targets.iter_mut()) {
+                match options.output_format {
+                    OutputKind::Vcf => target
+                        .converter
+                        .write_header(writer, release_date.as_deref())?,
+                    OutputKind::Jsonl => {}
+                    OutputKind::Tsv => writeln!(writer, "{}", tsv::header())?,
+                }
+                if let Some((path, writer)) = target.rejects.as_mut() {
+                    target
+                        .converter
+                        .write_rejects_header(writer, release_date.as_deref())
+                        .map_err(|e| write_error(path, e))?;
+                }
+            }
+            Ok(())
+        }
+        Input::Record(Ok(variant), position) => {
+            let mut written = false;
+            for (writer, target) in writers.iter_mut().zip(targets.iter_mut()) {
+                written |= output_record(
+                    writer, &variant, target, options, annotators, position, reporter,
+                )?;
+            }
+            progress::record(written);
+            Ok(())
+        }
+        Input::XmlError(e, position) => {
+            for target in targets.iter_mut() {
+                target.summary.stats.skip(SkipReason::XmlError);
+            }
+            if let Some(reporter) = reporter.as_mut() {
+                reporter.report(None, None, SkipReason::XmlError, &e, position)?;
+            }
+            check_errors(targets, options)
+        }
+        Input::Record(Err(e @ ClinvarError::InvalidRecord { .. }), _) => Err(e.into()),
+        Input::Record(Err(e), position) => {
+            progress::record(false);
+            for target in targets.iter_mut() {
+                target.summary.stats.records += 1;
+                target.summary.stats.skip(SkipReason::DeserializationError);
+            }
+            if let Some(reporter) = reporter.as_mut() {
+                let (variation_id, detail) = match &e {
+                    ClinvarError::Deserialize {
+                        variation_id,
+                        source,
+                        ..
+                    } => (*variation_id, source.to_string()),
+                    e => (None, e.to_string()),
+                };
+                reporter.report(
+                    variation_id,
+                    None,
+                    SkipReason::DeserializationError,
+                    &detail,
+                    position,
+                )?;
+            }
+            error!(reason = SkipReason::DeserializationError; "{}", e);
+            if options.ignore_error {
+                return check_errors(targets, options);
+            }
+            Err(e.into())
+        }
+        Input::Checkpoint(position, count) => match checkpointer.as_mut() {
+            Some(x) => x.save(position, count, writers, targets),
+            None => Ok(()),
+        },
+    };
+
+    if options.jobs <= 1 {
+        read_input(reader, io_stats, options, |input| match input {
+            Input::Header(x) => output(Input::Header(x)),
+            Input::XmlError(e, position) => output(Input::XmlError(e, position)),
+            Input::Record(bytes, position) => {
+                output(Input::Record(decode(bytes, position, options), position))
+            }
+            Input::Checkpoint(position, count) => output(Input::Checkpoint(position, count)),
+        })?;
+    } else {
+        thread::scope(|scope| {
+            let (record_tx, record_rx) =
+                mpsc::sync_channel::<(Vec<u8>, usize)>(options.jobs * CHANNEL_CAPACITY_PER_JOB);
+            // dropped with the last worker, so that the reader stops when the workers stop
+            let record_rx = Arc::new(Mutex::new(record_rx));
+            let (variant_tx, variant_rx) =
+                mpsc::sync_channel(options.jobs * CHANNEL_CAPACITY_PER_JOB);
 
-    /// Continue processing even if an error occurs
-    #[structopt(long)]
-    ignore_error: bool,
+            for _ in 0..options.jobs {
+                let record_rx = record_rx.clone();
+                let variant_tx = variant_tx.clone();
+                scope.spawn(move || loop {
+                    let received = record_rx.lock().expect("no worker panics").recv();
+                    let Ok((bytes, position)) = received else {
+                        break;
+                    };
+                    let variant = decode(&bytes, position, options);
+                    if variant_tx.send(Input::Record(variant, position)).is_err() {
+                        break;
+                    }
+                });
+            }
+            drop(record_rx);
 
-    /// Assembly
-    #[structopt(long, possible_values(Assembly::VARIANTS))]
-    assembly: Assembly,
+            let writer = scope.spawn(move || {
+                for input in variant_rx {
+                    output(input)?;
+                }
+                Ok(())
+            });
 
-    /// Reference fasta
-    #[structopt(long, parse(from_os_str))]
-    reference: PathBuf,
+            let stopped = || Error::other("Conversion stopped");
+            let read = read_input(reader, io_stats, options, move |input| match input {
+                Input::Header(x) => variant_tx.send(Input::Header(x)).map_err(|_| stopped()),
+                Input::XmlError(e, position) => variant_tx
+                    .send(Input::XmlError(e, position))
+                    .map_err(|_| stopped()),
+                Input::Record(bytes, position) => record_tx
+                    .send((bytes.to_vec(), position))
+                    .map_err(|_| stopped()),
+                // not given, as --checkpoint is not available with --jobs over 1
+                Input::Checkpoint(..) => Ok(()),
+            });
 
-    /// Path to output
-    #[structopt(long, short, parse(from_os_str))]
-    output: Option<PathBuf>,
+            // an error of the writer is the cause of stopping
+            writer.join().expect("the writer does not panic").and(read)
+        })?;
+    }
 
-    /// Path to input [*.xml | *.xml.gz]
-    #[structopt(parse(from_os_str))]
-    input: PathBuf,
-}
+    for writer in writers.iter_mut() {
+        writer.flush()?;
+    }
 
-fn main() -> io::Result<()> {
-    let options = Options::from_args();
+    Ok(())
+}
 
-    if !options.input.exists() {
-        Err(Error::new(
-            NotFound,
-            format!("{}", options.input.to_string_lossy()),
-        ))?
+/// Deserialize a `VariationArchive` element, checking it with --strict and reading its SCVs with
+/// --include-scv
+fn decode(
+    bytes: &[u8],
+    position: usize,
+    options: &Options,
+) -> Result<VariationArchive, ClinvarError> {
+    if options.strict {
+        schema::validate(bytes, position)?;
     }
 
-    if !options.reference.exists() {
-        Err(Error::new(
-            NotFound,
-            format!("{}", options.reference.to_string_lossy()),
-        ))?
-    }
-    let mut fai = options.reference.clone();
-    fai.set_extension(EXTENSION_FAI);
-    if !fai.exists() {
-        Err(Error::new(NotFound, format!("{}", fai.to_string_lossy())))?
-    }
-    let mut gzi = options.reference.clone();
-    gzi.set_extension(EXTENSION_GZI);
-    if !gzi.exists() {
-        Err(Error::new(NotFound, format!("{}", gzi.to_string_lossy())))?
+    let mut archive = reader::deserialize(bytes, position)?;
+    if options.include_scv {
+        reader::deserialize_scvs(&mut archive, bytes, position)?;
     }
 
-    let file_name = options.input.file_name().ok_or(Error::new(
-        InvalidInput,
-        format!("{}", options.input.to_string_lossy()),
-    ))?;
+    Ok(archive)
+}
 
-    let output = if let Some(mut o) = options.output {
-        if o.is_dir() {
-            o.push(file_name);
-            o.set_extension(if options.debug {
-                EXTENSION_DEBUG_OUTPUT
-            } else {
-                EXTENSION_OUTPUT
-            });
+/// Read the `VariationArchive` elements to convert, giving the header first
+///
+/// The progress is updated with the bytes counted in `io_stats`.
+fn read_input<R: BufRead>(
+    reader: &mut VariationArchiveReader<R>,
+    io_stats: &RefCell<IoStats>,
+    options: &Options,
+    mut handle: impl FnMut(Input<&[u8]>) -> io::Result<()>,
+) -> io::Result<()> {
+    // malformed XML is skipped with --ignore-error, while a broken input stream is not
+    let ignore = |e: ClinvarError, handle: &mut dyn FnMut(Input<&[u8]>) -> io::Result<()>| {
+        if let ClinvarError::Xml { .. } = e {
+            error!(reason = SkipReason::XmlError; "{}", e);
+        }
+        match e {
+            ClinvarError::Xml { position, source } if options.ignore_error => {
+                handle(Input::XmlError(source.to_string(), position))
+            }
+            e => Err(e.into()),
         }
-        o
-    } else {
-        let mut o = Path::new(file_name).to_path_buf();
-        o.set_extension(if options.debug {
-            EXTENSION_DEBUG_OUTPUT
-        } else {
-            EXTENSION_OUTPUT
-        });
-        o
     };
 
-    if output.exists() && !options.force {
-        Err(Error::new(
-            AlreadyExists,
-            format!("{}", output.to_string_lossy()),
-        ))?
+    let release_date = loop {
+        match reader.read_header() {
+            Ok(x) => break x.map(|x| x.to_string()),
+            Err(e) => ignore(e, &mut handle)?,
+        }
+    };
+    handle(Input::Header(release_date))?;
+
+    let mut since_checkpoint = 0;
+    loop {
+        progress::set_bytes(io_stats.borrow().bytes);
+        match reader.read_raw() {
+            Some(Ok((bytes, position))) => {
+                handle(Input::Record(bytes, position))?;
+                since_checkpoint += 1;
+                if options.checkpoint.is_some() && since_checkpoint == options.checkpoint_interval {
+                    since_checkpoint = 0;
+                    let position = VariationArchiveReader::position(reader);
+                    let count = VariationArchiveReader::count(reader);
+                    handle(Input::Checkpoint(position, count))?;
+                }
+            }
+            Some(Err(e)) => ignore(e, &mut handle)?,
+            None => break,
+        }
     }
 
-    let temp_dir = tempdir()?;
+    Ok(())
+}
 
-    let mut reader = reader_from_path(options.input)?;
-    {
-        let mut writer = if options.debug {
-            BufWriter::new(File::create(&output)?)
-        } else {
-            BufWriter::new(File::create(temp_dir.path().join(FILE_NAME_TEMP_OUTPUT))?)
-        };
+/// Abort if more errors are ignored than --max-errors
+fn check_errors(targets: &[Target], options: &Options) -> io::Result<()> {
+    let errors = targets[0].summary.stats.errors();
+    match options.max_errors {
+        Some(max) if errors > max => Err(Error::new(
+            InvalidData,
+            format!("Too many errors: {} (--max-errors {})", errors, max),
+        )),
+        _ => Ok(()),
+    }
+}
 
-        output_vcf(
-            &mut reader,
-            &mut writer,
-            options.assembly.as_ref(),
-            options.ignore_error,
-        )?;
+/// Write the VCF records of a variant
+///
+/// returns: whether any record is written
+fn output_record<W: Write>(
+    writer: &mut W,
+    variant: &VariationArchive,
+    target: &mut Target,
+    options: &Options,
+    annotators: &mut [Annotator],
+    position: usize,
+    reporter: &mut Option<Reporter>,
+) -> io::Result<bool> {
+    target.summary.stats.records += 1;
+    if let Some(updated) = target.updated.as_mut() {
+        updated.insert(variant.variation_id);
     }
+    let mut written = false;
+    for result in target.converter.records(variant) {
+        match result {
+            Ok(mut record) => {
+                if !target.check_ref(&record)? {
+                    match options.on_ref_mismatch {
+                        OnRefMismatch::Drop => {
+                            let detail = format!(
+                                "REF {} at {}:{} does not match the reference, assembly = {}",
+                                record.reference,
+                                record.chrom,
+                                record.pos,
+                                target.assembly.as_ref()
+                            );
+                            target.reject(
+                                variant,
+                                RejectedRecord::from_record(&record, SkipReason::RefMismatch),
+                                &detail,
+                                position,
+                                reporter,
+                            )?;
+                            continue;
+                        }
+                        OnRefMismatch::Keep => {}
+                        OnRefMismatch::Fail => Err(ClinvarError::InvalidRecord {
+                            reason: SkipReason::RefMismatch,
+                            detail: format!(
+                                "variation_id = {}, {}:{}",
+                                record.id, record.chrom, record.pos
+                            ),
+                        })?,
+                    }
+                }
 
-    if !options.debug {
-        if let Err(e) = vcf_sort(
-            temp_dir.path().join(FILE_NAME_TEMP_OUTPUT),
-            temp_dir.path().join(FILE_NAME_TEMP_SORTED),
-        ) {
-            std::fs::copy(temp_dir.path().join(FILE_NAME_TEMP_OUTPUT), &output)?;
-            eprintln!("Error: {}", e);
-            eprintln!("Output temp file to: {}", &output.to_string_lossy());
-            exit(1)
-        };
+                if !options.debug
+                    && options.normalizer == Some(Normalizer::Native)
+                    && normalize(&mut record, &mut target.fasta).map_err(|e| {
+                        Error::new(
+                            e.kind(),
+                            format!(
+                                "Failed to normalize variation_id = {}, {}:{}: {}",
+                                record.id, record.chrom, record.pos, e
+                            ),
+                        )
+                    })?
+                {
+                    target.summary.normalized += 1;
+                }
 
-        if let Err(e) = vcf_normalize(
-            temp_dir.path().join(FILE_NAME_TEMP_SORTED),
-            temp_dir.path().join(FILE_NAME_TEMP_NORMALIZED),
-            options.reference,
-        ) {
-            std::fs::copy(temp_dir.path().join(FILE_NAME_TEMP_SORTED), &output)?;
-            eprintln!("Error: {}", e);
-            eprintln!("Output temp file to: {}", &output.to_string_lossy());
-            exit(1)
-        };
+                let summary = &mut target.summary;
+                if !REGEX_CHROMOSOME.is_match(&record.chrom) {
+                    summary.nonstandard_chr += 1;
+                }
+                if record.has_info("CONDITIONS_UNMAPPED") {
+                    summary.unmapped_conditions += 1;
+                }
+                if record.flags.contains(&QcFlag::UnknownClassification) {
+                    summary.unknown_classifications += 1;
+                }
 
-        std::fs::copy(temp_dir.path().join(FILE_NAME_TEMP_NORMALIZED), &output)?;
-        vcf_index(&output)?;
-    }
+                let annotated = record.info.len();
+                for annotator in annotators.iter_mut() {
+                    let annotations = annotator.annotate(
+                        &record.chrom,
+                        record.pos,
+                        &record.reference,
+                        &record.alternate,
+                    )?;
+                    record.info.extend(annotations);
+                }
+
+                let missing = options
+                    .require_fields
+                    .iter()
+                    .filter(|x| !record.has_info(x))
+                    .map(|x| x.as_str())
+                    .collect::<Vec<&str>>();
+                if !missing.is_empty() {
+                    let violation = format!(
+                        "variation_id = {}, {}:{}: missing {}",
+                        record.id,
+                        record.chrom,
+                        record.pos,
+                        missing.join(",")
+                    );
+                    if options.require_fields_fatal {
+                        Err(Error::new(
+                            ErrorKind::InvalidData,
+                            format!("Required field violation: {}", violation),
+                        ))?
+                    }
+                    summary.violations.push(violation);
+                }
 
-    eprintln!("Output to: {}", &output.to_string_lossy());
+                record.chrom = target
+                    .converter
+                    .chr_style_on_output()
+                    .rename(&record.chrom, target.assembly);
+                match options.output_format {
+                    OutputKind::Vcf => writeln!(writer, "{}", record)?,
+                    OutputKind::Jsonl | OutputKind::Tsv => {
+                        let mut json = JsonRecord::new(
+                            variant,
+                            &record,
+                            target.assembly,
+                            target.converter.format_config(),
+                        )
+                        .expect("a converted record has a SimpleAllele");
+                        json.annotations = record.info[annotated..].to_vec();
+                        if options.output_format == OutputKind::Jsonl {
+                            writeln!(writer, "{}", serde_json::to_string(&json)?)?;
+                        } else {
+                            for row in tsv::rows(&json) {
+                                writeln!(writer, "{}", row)?;
+                            }
+                        }
+                    }
+                }
+                target.summary.stats.written += 1;
+                if record.flags.contains(&QcFlag::ConditionsTruncated) {
+                    target.summary.stats.truncated += 1;
+                }
+                written = true;
+            }
+            Err(reason) => {
+                let detail = format!("{}, assembly = {}", reason, target.assembly.as_ref());
+                target.reject(
+                    variant,
+                    RejectedRecord::new(variant, target.assembly, reason),
+                    &detail,
+                    position,
+                    reporter,
+                )?;
+            }
+        }
+    }
 
-    temp_dir.close()
+    Ok(written)
 }
 
-fn reader_from_path<T: AsRef<Path>>(path: T) -> io::Result<Reader<Box<dyn BufRead>>> {
-    let f = File::open(path.as_ref())?;
-    let r: Box<dyn BufRead> = match path.as_ref().extension() {
-        Some(ext) if ext == "gz" => Box::new(BufReader::new(GzDecoder::new(BufReader::new(f)))),
-        _ => Box::new(BufReader::new(f)),
-    };
+/// Check that bcftools can be run and is not older than `min_version`
+fn check_bcftools(bcftools: &Path, min_version: &Version) -> io::Result<()> {
+    let process = Command::new(bcftools)
+        .arg("--version")
+        .output()
+        .map_err(|e| match e.kind() {
+            NotFound => Error::new(
+                NotFound,
+                format!(
+                    "{} (>= {}) not found",
+                    bcftools.to_string_lossy(),
+                    min_version
+                ),
+            ),
+            kind => Error::new(
+                kind,
+                format!("Failed to run {}: {}", bcftools.to_string_lossy(), e),
+            ),
+        })?;
+    let stdout = String::from_utf8_lossy(&process.stdout);
+    let text = stdout
+        .lines()
+        .next()
+        .and_then(|x| x.strip_prefix("bcftools "))
+        .unwrap_or_default()
+        .trim();
+    let version = text.parse::<Version>().map_err(|_| {
+        Error::new(
+            InvalidData,
+            format!(
+                "Failed to get the version of bcftools: {}",
+                stdout.trim_end()
+            ),
+        )
+    })?;
+
+    if &version < min_version {
+        Err(Error::new(
+            InvalidInput,
+            format!(
+                "bcftools {} is older than the required {}",
+                text, min_version
+            ),
+        ))?
+    }
 
-    Ok(Reader::from_reader(r))
+    Ok(())
 }
 
-fn read_record<R: BufRead>(
-    reader: &mut Reader<R>,
-    start_tag: &BytesStart,
-    buf: &mut Vec<u8>,
-) -> Result<Vec<u8>, quick_xml::Error> {
-    let tag_name = start_tag.name();
-    let mut output_buf = Vec::new();
-    let mut w = Writer::new(&mut output_buf);
+/// Echo the output of bcftools, failing if it could not be run or exited with an error
+fn check_process(process: io::Result<Output>, bcftools: &Path, stage: Stage) -> io::Result<()> {
+    let bcftools = bcftools.to_string_lossy();
+    let process = process.map_err(|e| match e.kind() {
+        NotFound => Error::new(NotFound, format!("{} not found", bcftools)),
+        kind => Error::new(kind, format!("Failed to run {}: {}", bcftools, e)),
+    })?;
+
+    io::stdout().write_all(&process.stdout)?;
+    if !process.status.success() {
+        Err(ClinvarError::ExternalTool {
+            tool: bcftools.to_string(),
+            stage,
+            status: process.status,
+            stderr: String::from_utf8_lossy(&process.stderr)
+                .trim_end()
+                .to_string(),
+        })?
+    }
+    io::stderr().write_all(&process.stderr)?;
 
-    w.write_event(Event::Start(start_tag.clone()))?;
+    Ok(())
+}
 
-    let mut depth = 0;
+/// Run a bcftools command, again up to `retries` times while it fails
+///
+/// returns: the result of the last run
+fn run_bcftools(command: &mut Command, stage: Stage, retries: usize) -> io::Result<()> {
+    let bcftools = PathBuf::from(command.get_program());
+    let mut attempts = 0;
     loop {
-        buf.clear();
+        match check_process(command.output(), &bcftools, stage) {
+            Err(e) if attempts < retries && e.kind() != NotFound => {
+                attempts += 1;
+                warn!("{}; retrying ({}/{})", e, attempts, retries);
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Copy an intermediate file to the output path for inspection, unless the output exists
+///
+/// `kept_dir` is the working directory kept by --keep-temp.
+fn output_temp_file<T: AsRef<Path>>(temp: T, output: T, kept_dir: Option<&Path>) -> io::Result<()> {
+    if output.as_ref().exists() {
+        eprintln!(
+            "Temp file not copied over existing output: {}",
+            output.as_ref().to_string_lossy()
+        );
+    } else {
+        std::fs::copy(temp, output.as_ref())?;
+        eprintln!("Output temp file to: {}", output.as_ref().to_string_lossy());
+    }
+    match kept_dir {
+        Some(dir) => eprintln!("Temp files kept in: {}", dir.to_string_lossy()),
+        None => eprintln!("Rerun with --keep-temp to keep the files of all stages"),
+    }
+
+    Ok(())
+}
 
-        let event = reader.read_event_into(buf)?;
+/// Split a sorted bgzipped VCF into a bgzipped VCF per chromosome, named after `output`
+///
+/// Each file has the whole header. Contigs declared in the header without any records get a file
+/// only with --emit-empty.
+///
+/// returns: the paths written, in the order of the contigs
+fn split_by_chromosome(input: &Path, output: &Path, options: &Options) -> io::Result<Vec<PathBuf>> {
+    let create = |chrom: &str, header: &str| -> io::Result<(PathBuf, bgzf::Writer<File>)> {
+        let path = chromosome_path(output, chrom);
+        if path.exists() && !options.force {
+            Err(Error::new(
+                AlreadyExists,
+                format!("{}", path.to_string_lossy()),
+            ))?
+        }
+        let mut writer = bgzf::Writer::new(File::create(&path)?);
+        writer
+            .write_all(header.as_bytes())
+            .map_err(|e| write_error(&path, e))?;
 
-        w.write_event(&event)?;
+        Ok((path, writer))
+    };
 
-        match event {
-            Event::Start(e) if e.name() == tag_name => depth += 1,
-            Event::End(e) if e.name() == tag_name => {
-                if depth == 0 {
-                    return Ok(output_buf);
-                }
-                depth -= 1;
+    let reader = BufReader::new(MultiGzDecoder::new(BufReader::new(File::open(input)?)));
+    let mut header = String::new();
+    let mut contigs = Vec::new();
+    // (CHROM, path) of each file written
+    let mut outputs: Vec<(String, PathBuf)> = Vec::new();
+    let mut writer: Option<bgzf::Writer<File>> = None;
+    for line in reader.lines() {
+        let line = line?;
+        if line.starts_with('#') {
+            if let Some(x) = line.strip_prefix("##contig=<ID=") {
+                contigs.push(x.split([',', '>']).next().unwrap_or_default().to_string());
             }
-            Event::Eof => {
-                return Err(quick_xml::Error::UnexpectedEof(
-                    "Unexpected end of file (EOF) encountered.".to_string(),
-                ));
+            header.push_str(&line);
+            header.push('\n');
+            continue;
+        }
+
+        let chrom = line.split('\t').next().unwrap_or_default();
+        if outputs.last().is_none_or(|x| x.0 != chrom) {
+            if let Some(x) = writer.take() {
+                x.finish()?;
             }
-            _ => {}
+            let (path, x) = create(chrom, &header)?;
+            outputs.push((chrom.to_string(), path));
+            writer = Some(x);
         }
+        let (path, x) = (
+            &outputs.last().expect("created above").1,
+            writer.as_mut().expect("created above"),
+        );
+        writeln!(x, "{}", line).map_err(|e| write_error(path, e))?;
     }
-}
+    if let Some(x) = writer.take() {
+        x.finish()?;
+    }
+
+    if options.emit_empty {
+        for contig in &contigs {
+            if !outputs.iter().any(|x| &x.0 == contig) {
+                let (path, x) = create(contig, &header)?;
+                x.finish()?;
+                outputs.push((contig.clone(), path));
+            }
+        }
+    }
+    outputs.sort_by_key(|x| contigs.iter().position(|y| *y == x.0));
 
-fn handle_variation_archive(bytes: &Vec<u8>) -> Result<VariationArchive, String> {
-    let str = from_utf8(&bytes).map_err(|e| format!("{}", e))?;
+    Ok(outputs.into_iter().map(|x| x.1).collect())
+}
 
-    let mut deserializer = Deserializer::from_str(str);
+/// Path of the output of a chromosome, e.g. `clinvar.chr1.vcf.gz` for `clinvar.vcf.gz`
+fn chromosome_path(path: &Path, chrom: &str) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|x| x.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let suffix = format!(".{}", EXTENSION_OUTPUT);
+    let name = match name.strip_suffix(&suffix) {
+        Some(stem) => format!("{}.{}{}", stem, chrom, suffix),
+        None => format!("{}.{}", name, chrom),
+    };
 
-    VariationArchive::deserialize(&mut deserializer).map_err(|e| format!("{}", e))
+    path.with_file_name(name)
 }
 
-fn output_vcf<R: BufRead, W: Write>(
-    mut reader: &mut Reader<R>,
-    mut writer: &mut W,
-    assembly: &str,
-    ignore_error: bool,
+/// Sort `input` into a bgzipped VCF
+fn native_sort<T: AsRef<Path>>(
+    input: T,
+    output: T,
+    compress: Compress,
+    memory: usize,
+    temp_dir: &Path,
 ) -> io::Result<()> {
-    writeln!(writer, "{}", VCF_HEADER)?;
+    let file = BufReader::new(File::open(input)?);
+    let reader: Box<dyn BufRead> = match compress {
+        Compress::None => Box::new(file),
+        Compress::Bgzf => Box::new(BufReader::new(MultiGzDecoder::new(BufReader::new(file)))),
+    };
+    let mut writer = bgzf::Writer::new(File::create(output.as_ref())?);
+    sort_vcf(reader, &mut writer, memory, temp_dir)
+        .and_then(|_| writer.finish())
+        .map(|_| ())
+        .map_err(|e| {
+            Error::new(
+                e.kind(),
+                format!(
+                    "Failed to sort into {}: {}",
+                    output.as_ref().to_string_lossy(),
+                    e
+                ),
+            )
+        })
+}
 
-    let mut buf = Vec::new();
-    let mut junk_buf = Vec::new();
-    loop {
-        let event = match reader.read_event_into(&mut buf) {
-            Ok(e) => e,
-            Err(e) => {
-                error!("Error at position {}: {}", reader.buffer_position(), e);
-                if ignore_error {
-                    continue;
-                }
-                Err(Error::new(ErrorKind::InvalidData, format!("{}", e)))?
-            }
-        };
+/// Append the records of --update-base whose VariationIDs are not in the input to the converted
+/// ones, before sorting
+///
+/// A record of several VariationIDs (see --duplicates merge) is replaced if any of them is in the
+/// input.
+///
+/// returns: numbers of the records kept and of those replaced or removed
+fn append_base(
+    base: &Path,
+    output: &Path,
+    compress: Compress,
+    updated: &HashSet<u64>,
+) -> io::Result<(u64, u64)> {
+    let mut file = BufReader::new(File::open(base)?);
+    let reader: Box<dyn BufRead> = if file.fill_buf()?.starts_with(&GZIP_MAGIC) {
+        Box::new(BufReader::new(MultiGzDecoder::new(file)))
+    } else {
+        Box::new(file)
+    };
 
-        match event {
-            Event::Eof => break,
-            Event::Start(start_tag) => match start_tag.name().as_ref() {
-                b"VariationArchive" => {
-                    match read_record(&mut reader, &start_tag, &mut junk_buf) {
-                        Ok(bytes) => match handle_variation_archive(&bytes) {
-                            Ok(variant) => output_record(&mut writer, &variant, assembly)?,
-                            Err(e) => {
-                                error!("Error at position {}: {}", reader.buffer_position(), e);
-                                if ignore_error {
-                                    continue;
-                                }
-                                Err(Error::new(ErrorKind::InvalidData, format!("{}", e)))?
-                            }
-                        },
-                        Err(e) => {
-                            error!("Error at position {}: {}", reader.buffer_position(), e);
-                            if ignore_error {
-                                continue;
-                            }
-                            Err(Error::new(ErrorKind::InvalidData, format!("{}", e)))?
-                        }
-                    };
-                }
-                _ => {}
-            },
-            _ => {}
+    let file = OpenOptions::new()
+        .append(true)
+        .open(output)
+        .map_err(|e| write_error(output, e))?;
+    // a BGZF file may be continued by more blocks
+    match compress {
+        Compress::None => {
+            let mut writer = BufWriter::new(file);
+            let counts = filter_base(reader, &mut writer, base, output, updated)?;
+            writer.flush().map(|_| counts)
+        }
+        Compress::Bgzf => {
+            let mut writer = bgzf::Writer::new(file);
+            let counts = filter_base(reader, &mut writer, base, output, updated)?;
+            writer.finish().map(|_| counts)
         }
-
-        buf.clear();
     }
-
-    writer.flush()
+    .map_err(|e| write_error(output, e))
 }
 
-fn output_record<W: Write>(
+/// Write the records of --update-base whose VariationIDs are not updated
+fn filter_base<R: BufRead, W: Write>(
+    reader: R,
     writer: &mut W,
-    variant: &VariationArchive,
-    assembly: &str,
-) -> io::Result<()> {
-    if let Some(ref record) = variant.classified_record {
-        if let Some(allele) = record.simple_allele.as_ref() {
-            if let Some(loc) = extract_location(allele, assembly) {
-                let conditions = extract_conditions(record);
-
-                if conditions.is_empty() {
-                    warn!(
-                        "No ClassifiedCondition associated with MedGen: variation_id = {}",
-                        variant.variation_id
-                    );
-                    return Ok(());
-                }
-
-                writeln!(
-                    writer,
-                    "{}\t{}\t{}\t{}\t{}\t.\t.\tALLELEID={};CONDITIONS={}",
-                    loc.0,
-                    loc.1,
-                    allele.variation_id,
-                    loc.2.to_uppercase(),
-                    loc.3.to_uppercase(),
-                    allele.allele_id,
-                    conditions
-                )?
-            }
+    base: &Path,
+    output: &Path,
+    updated: &HashSet<u64>,
+) -> io::Result<(u64, u64)> {
+    let (mut kept, mut replaced) = (0, 0);
+    for line in reader.lines() {
+        let line = line.map_err(|e| {
+            Error::new(
+                e.kind(),
+                format!("Failed to read {}: {}", base.to_string_lossy(), e),
+            )
+        })?;
+        if line.starts_with('#') {
+            continue;
+        }
+        let id = line.split('\t').nth(2).ok_or_else(|| {
+            Error::new(
+                InvalidData,
+                format!("Invalid VCF line in {}: {}", base.to_string_lossy(), line),
+            )
+        })?;
+        if id
+            .split(';')
+            .any(|x| x.parse().is_ok_and(|x| updated.contains(&x)))
+        {
+            replaced += 1;
         } else {
-            warn!(
-                "SimpleAllele not found: variation_id = {}",
-                variant.variation_id
-            );
+            writeln!(writer, "{}", line).map_err(|e| write_error(output, e))?;
+            kept += 1;
         }
-    } else {
-        warn!(
-            "ClassifiedRecord not found: variation_id = {}",
-            variant.variation_id
-        );
-        return Ok(());
     }
 
-    Ok(())
+    Ok((kept, replaced))
 }
 
-const DB_MEDGEN: &'static str = "MedGen";
+/// Merge or drop records with the same CHROM, POS, REF and ALT of a sorted VCF
+///
+/// returns: number of records merged or dropped
+fn resolve_duplicates(input: &Path, output: &Path, duplicates: Duplicates) -> io::Result<u64> {
+    let reader = BufReader::new(MultiGzDecoder::new(BufReader::new(File::open(input)?)));
+    let mut writer = bgzf::Writer::new(File::create(output)?);
+    let count = match duplicates {
+        Duplicates::Keep => unreachable!("records are written as they are"),
+        Duplicates::Merge => merge_duplicates(reader, &mut writer),
+        Duplicates::First => drop_duplicates(reader, &mut writer),
+    }
+    .and_then(|x| writer.finish().map(|_| x))
+    .map_err(|e| write_error(output, e))?;
 
-fn extract_conditions(record: &ClassifiedRecord) -> String {
-    record
-        .rcv_list
-        .rcv_accession
-        .iter()
-        .filter_map(|rcv| {
-            let medgen = rcv
-                .classified_condition_list
-                .classified_condition
-                .iter()
-                .filter_map(|x| {
-                    if x.db.as_ref().map(|x| x.as_str()) == Some(DB_MEDGEN) {
-                        x.id.clone()
-                    } else {
-                        None
-                    }
-                })
-                .collect::<Vec<String>>();
+    Ok(count)
+}
 
-            if medgen.len() != 0 {
-                rcv.rcv_classifications
-                    .germline_classification
-                    .as_ref()
-                    .map(|x| {
-                        (
-                            x.description
-                                .text
-                                .split(&['/', ';'][..])
-                                .map(|x| x.trim().replace(" ", "_").to_lowercase())
-                                .collect::<Vec<String>>()
-                                .join("/"),
-                            x.description.submission_count,
-                        )
-                    })
-                    .map(|(interpretations, submissions)| {
-                        format!(
-                            "{}:{}:{}:{}",
-                            DB_MEDGEN,
-                            medgen.join("/"),
-                            interpretations,
-                            submissions
-                        )
-                    })
-            } else {
-                None
-            }
-        })
-        .collect::<Vec<String>>()
-        .join("|")
+fn vcf_sort<T: AsRef<OsStr>>(
+    input: T,
+    output: T,
+    bcftools: &Path,
+    memory: Option<usize>,
+    temp_dir: Option<&Path>,
+    retries: usize,
+) -> io::Result<()> {
+    let mut command = sort_command(input, output, bcftools, memory, temp_dir);
+
+    run_bcftools(&mut command, Stage::Sort, retries)
 }
 
-fn vcf_sort<T: AsRef<OsStr>>(input: T, output: T) -> io::Result<()> {
-    let process = Command::new("bcftools")
-        .arg("sort")
+/// `bcftools sort` of `input` into a bgzipped `output`
+fn sort_command<T: AsRef<OsStr>>(
+    input: T,
+    output: T,
+    bcftools: &Path,
+    memory: Option<usize>,
+    temp_dir: Option<&Path>,
+) -> Command {
+    let mut command = Command::new(bcftools);
+    command.arg("sort");
+    if let Some(memory) = memory {
+        command.arg("--max-mem").arg(memory.to_string());
+    }
+    if let Some(temp_dir) = temp_dir {
+        command.arg("--temp-dir").arg(temp_dir);
+    }
+    command
         .arg("--output-type")
         .arg("z")
         .arg("--output")
         .arg(output.as_ref())
-        .arg(input.as_ref())
-        .output()?;
+        .arg(input.as_ref());
 
-    io::stdout().write_all(&process.stdout)?;
-    io::stderr().write_all(&process.stderr)?;
+    command
+}
 
-    Ok(())
+/// Index a bgzipped VCF, by bcftools index if the built-in indexer fails
+fn index_vcf(path: &Path, format: IndexFormat, options: &Options) -> io::Result<()> {
+    let e = match tabix::build_index(path, format) {
+        Ok(_) => return Ok(()),
+        Err(e) if options.no_external_tools => return Err(e),
+        Err(e) => e,
+    };
+    warn!(
+        "Failed to index {}, trying bcftools index: {}",
+        path.to_string_lossy(),
+        e
+    );
+
+    let mut command = index_command(path, format, &options.bcftools_path);
+
+    run_bcftools(&mut command, Stage::Index, options.retries)
+}
+
+/// `bcftools index` writing the index of `path` next to it
+fn index_command(path: &Path, format: IndexFormat, bcftools: &Path) -> Command {
+    let mut command = Command::new(bcftools);
+    command
+        .arg("index")
+        .arg("--force")
+        .arg(match format {
+            IndexFormat::Tbi => "--tbi",
+            IndexFormat::Csi => "--csi",
+        })
+        .arg(path);
+
+    command
+}
+
+#[allow(clippy::too_many_arguments)]
+fn vcf_normalize<T: AsRef<OsStr>>(
+    input: T,
+    output: T,
+    reference: T,
+    bcftools: &Path,
+    threads: Option<usize>,
+    multiallelics: Multiallelics,
+    on_ref_mismatch: OnRefMismatch,
+    retries: usize,
+) -> io::Result<()> {
+    let mut command = normalize_command(
+        input,
+        output,
+        reference,
+        bcftools,
+        threads,
+        multiallelics,
+        on_ref_mismatch,
+    );
+
+    run_bcftools(&mut command, Stage::Normalize, retries)
 }
 
-fn vcf_normalize<T: AsRef<OsStr>>(input: T, output: T, reference: T) -> io::Result<()> {
-    let process = Command::new("bcftools")
-        .arg("norm")
+/// `bcftools norm` of `input` against `reference` into a bgzipped `output`
+fn normalize_command<T: AsRef<OsStr>>(
+    input: T,
+    output: T,
+    reference: T,
+    bcftools: &Path,
+    threads: Option<usize>,
+    multiallelics: Multiallelics,
+    on_ref_mismatch: OnRefMismatch,
+) -> Command {
+    let mut command = Command::new(bcftools);
+    command.arg("norm");
+    if let Some(threads) = threads {
+        command.arg("--threads").arg(threads.to_string());
+    }
+    match multiallelics {
+        Multiallelics::Keep => {}
+        Multiallelics::Split => {
+            command.arg("--multiallelics").arg("-any");
+        }
+        Multiallelics::Join => {
+            command.arg("--multiallelics").arg("+any");
+        }
+    }
+    command
         .arg("--no-version")
         .arg("--output-type")
         .arg("z")
@@ -439,28 +3186,203 @@ fn vcf_normalize<T: AsRef<OsStr>>(input: T, output: T, reference: T) -> io::Resu
         .arg("--rm-dup")
         .arg("none")
         .arg("--check-ref")
-        .arg("x")
+        .arg(match on_ref_mismatch {
+            OnRefMismatch::Keep => "w",
+            _ => "x",
+        })
         .arg("--fasta-ref")
         .arg(reference.as_ref())
-        .arg(input.as_ref())
-        .output()?;
+        .arg(input.as_ref());
 
-    io::stdout().write_all(&process.stdout)?;
-    io::stderr().write_all(&process.stderr)?;
+    command
+}
 
-    Ok(())
+/// Count data lines of a (b)gzipped VCF
+fn count_records<T: AsRef<Path>>(path: T) -> io::Result<u64> {
+    let mut reader = BufReader::new(MultiGzDecoder::new(BufReader::new(File::open(path)?)));
+    let mut line = String::new();
+    let mut count = 0;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(count);
+        }
+        if !line.starts_with('#') {
+            count += 1;
+        }
+    }
 }
 
-fn vcf_index<T: AsRef<OsStr>>(input: T) -> io::Result<()> {
-    let process = Command::new("bcftools")
-        .arg("index")
-        .arg("--force")
-        .arg("--tbi")
-        .arg(input.as_ref())
-        .output()?;
+/// Check that normalization changed the number of records as expected for `multiallelics`
+///
+/// Splitting can only increase the number of records, joining only decrease it, and keeping must
+/// not change it. Anomalies are reported as warnings.
+fn verify_multiallelics<T: AsRef<Path>>(
+    input: T,
+    output: T,
+    multiallelics: Multiallelics,
+) -> io::Result<()> {
+    let before = count_records(input)?;
+    let after = count_records(output)?;
+    eprintln!(
+        "Records before/after normalization: {} -> {}",
+        before, after
+    );
 
-    io::stdout().write_all(&process.stdout)?;
-    io::stderr().write_all(&process.stderr)?;
+    let expected = match multiallelics {
+        Multiallelics::Keep => after == before,
+        Multiallelics::Split => after >= before,
+        Multiallelics::Join => after <= before,
+    };
+    if !expected {
+        warn!(
+            "Unexpected number of records after normalization with --multiallelics {}: {} -> {}",
+            multiallelics.as_ref(),
+            before,
+            after
+        );
+    }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(command: &Command) -> Vec<&str> {
+        command.get_args().map(|x| x.to_str().unwrap()).collect()
+    }
+
+    #[test]
+    fn sort_command_passes_memory_and_temp_dir() {
+        let bcftools = Path::new("/opt/bcftools-1.17/bin/bcftools");
+
+        let command = sort_command("in.vcf.gz", "out.vcf.gz", bcftools, None, None);
+        assert_eq!(command.get_program(), bcftools);
+        assert_eq!(
+            args(&command),
+            [
+                "sort",
+                "--output-type",
+                "z",
+                "--output",
+                "out.vcf.gz",
+                "in.vcf.gz"
+            ]
+        );
+
+        let command = sort_command(
+            "in.vcf.gz",
+            "out.vcf.gz",
+            bcftools,
+            Some(768_000_000),
+            Some(Path::new("/scratch/tmp")),
+        );
+        assert_eq!(
+            args(&command),
+            [
+                "sort",
+                "--max-mem",
+                "768000000",
+                "--temp-dir",
+                "/scratch/tmp",
+                "--output-type",
+                "z",
+                "--output",
+                "out.vcf.gz",
+                "in.vcf.gz"
+            ]
+        );
+    }
+
+    #[test]
+    fn normalize_command_passes_threads_and_options() {
+        let bcftools = Path::new("bcftools");
+
+        let command = normalize_command(
+            "in.vcf.gz",
+            "out.vcf.gz",
+            "ref.fa.gz",
+            bcftools,
+            None,
+            Multiallelics::Keep,
+            OnRefMismatch::Drop,
+        );
+        assert_eq!(command.get_program(), "bcftools");
+        assert_eq!(
+            args(&command),
+            [
+                "norm",
+                "--no-version",
+                "--output-type",
+                "z",
+                "--output",
+                "out.vcf.gz",
+                "--rm-dup",
+                "none",
+                "--check-ref",
+                "x",
+                "--fasta-ref",
+                "ref.fa.gz",
+                "in.vcf.gz"
+            ]
+        );
+
+        let command = normalize_command(
+            "in.vcf.gz",
+            "out.vcf.gz",
+            "ref.fa.gz",
+            bcftools,
+            Some(4),
+            Multiallelics::Split,
+            OnRefMismatch::Keep,
+        );
+        assert_eq!(
+            args(&command),
+            [
+                "norm",
+                "--threads",
+                "4",
+                "--multiallelics",
+                "-any",
+                "--no-version",
+                "--output-type",
+                "z",
+                "--output",
+                "out.vcf.gz",
+                "--rm-dup",
+                "none",
+                "--check-ref",
+                "w",
+                "--fasta-ref",
+                "ref.fa.gz",
+                "in.vcf.gz"
+            ]
+        );
+
+        let command = normalize_command(
+            "in.vcf.gz",
+            "out.vcf.gz",
+            "ref.fa.gz",
+            bcftools,
+            None,
+            Multiallelics::Join,
+            OnRefMismatch::Fail,
+        );
+        let args = args(&command);
+        assert_eq!(args[1..3], ["--multiallelics", "+any"]);
+        assert!(args.windows(2).any(|x| x == ["--check-ref", "x"]));
+    }
+
+    #[test]
+    fn index_command_selects_the_format() {
+        let bcftools = Path::new("/usr/local/bin/bcftools");
+
+        for (format, flag) in [(IndexFormat::Tbi, "--tbi"), (IndexFormat::Csi, "--csi")] {
+            let command = index_command(Path::new("out.vcf.gz"), format, bcftools);
+            assert_eq!(command.get_program(), bcftools);
+            assert_eq!(args(&command), ["index", "--force", flag, "out.vcf.gz"]);
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use clinvar::fixture_gen::{self, Spec};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs::{self, File};
+use std::io::ErrorKind::{AlreadyExists, InvalidData, NotFound};
+use std::io::{self, BufWriter, Error, Write};
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+struct Options {
+    /// Overwrite existing file
+    #[structopt(long)]
+    force: bool,
+
+    /// Compress output with gzip
+    #[structopt(long)]
+    gzip: bool,
+
+    /// Shuffle records with the seed
+    #[structopt(long)]
+    shuffle: Option<u64>,
+
+    /// Cut the document after this many bytes (before compression)
+    #[structopt(long)]
+    truncate: Option<usize>,
+
+    /// Path to output (default: stdout)
+    #[structopt(short, long, parse(from_os_str))]
+    output: Option<PathBuf>,
+
+    /// Path to TOML spec
+    #[structopt(name = "FILE", parse(from_os_str))]
+    input: PathBuf,
+}
+
+fn main() -> io::Result<()> {
+    let options = Options::from_args();
+
+    if !options.input.exists() {
+        Err(Error::new(
+            NotFound,
+            format!("{}", options.input.to_string_lossy()),
+        ))?
+    }
+
+    let mut spec: Spec = toml::from_str(&fs::read_to_string(&options.input)?)
+        .map_err(|e| Error::new(InvalidData, e.to_string()))?;
+
+    if let Some(seed) = options.shuffle {
+        fixture_gen::shuffle(&mut spec, seed);
+    }
+
+    let mut xml = fixture_gen::generate(&spec).into_bytes();
+    if let Some(n) = options.truncate {
+        xml.truncate(n);
+    }
+
+    let writer: Box<dyn Write> = match options.output.as_ref() {
+        Some(path) => {
+            if !options.force && path.exists() {
+                Err(Error::new(
+                    AlreadyExists,
+                    format!("{}", path.to_string_lossy()),
+                ))?
+            }
+            Box::new(BufWriter::new(File::create(path)?))
+        }
+        None => Box::new(BufWriter::new(io::stdout().lock())),
+    };
+
+    if options.gzip {
+        let mut encoder = GzEncoder::new(writer, Compression::default());
+        encoder.write_all(&xml)?;
+        encoder.finish()?.flush()?;
+    } else {
+        let mut writer = writer;
+        writer.write_all(&xml)?;
+        writer.flush()?;
+    }
+
+    Ok(())
+}
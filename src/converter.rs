@@ -0,0 +1,561 @@
+//! Conversion of a ClinVarVariationRelease XML into VCF
+//!
+//! [`Converter`] holds the assembly and the formatting options, and writes the header and the
+//! records of each `VariationArchive`. Checking REF against the reference, normalization and
+//! sorting are left to the caller.
+
+use crate::header::{info_fields, InfoField, INFO_SKIP_REASON};
+use crate::{
+    encode_info_value, to_vcf_records, Assembly, ClinvarError, ConditionsFormat, FormatConfig,
+    OutputFormat, QcFlag, SkipReason, Stats, VariationArchive, VariationArchiveReader, VcfRecord,
+};
+use std::io::{self, BufRead, Error, ErrorKind, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+use strum::{AsRefStr, EnumString, VariantNames};
+
+const VCF_HEADER: &str = r#"##fileformat=VCFv4.3
+##FILTER=<ID=PASS,Description="All filters passed">
+##FILTER=<ID=conflicting,Description="Conflicting germline classifications, in the aggregate or between pathogenic and benign RCVs">
+##FILTER=<ID=no_assertion,Description="No assertion criteria provided for any classification">
+##clinvar_id_description=ClinVar Variation ID"#;
+
+/// Chromosomes accepted from the XML, in the order declared when missing from the .fai
+pub const CONTIGS: [&str; 25] = [
+    "1", "2", "3", "4", "5", "6", "7", "8", "9", "10", "11", "12", "13", "14", "15", "16", "17",
+    "18", "19", "20", "21", "22", "X", "Y", "MT",
+];
+
+/// RefSeq accessions of [`CONTIGS`] in GRCh37
+const REFSEQ_GRCH37: [&str; 25] = [
+    "NC_000001.10",
+    "NC_000002.11",
+    "NC_000003.11",
+    "NC_000004.11",
+    "NC_000005.9",
+    "NC_000006.11",
+    "NC_000007.13",
+    "NC_000008.10",
+    "NC_000009.11",
+    "NC_000010.10",
+    "NC_000011.9",
+    "NC_000012.11",
+    "NC_000013.10",
+    "NC_000014.8",
+    "NC_000015.9",
+    "NC_000016.9",
+    "NC_000017.10",
+    "NC_000018.9",
+    "NC_000019.9",
+    "NC_000020.10",
+    "NC_000021.8",
+    "NC_000022.10",
+    "NC_000023.10",
+    "NC_000024.9",
+    "NC_012920.1",
+];
+
+/// RefSeq accessions of [`CONTIGS`] in GRCh38
+const REFSEQ_GRCH38: [&str; 25] = [
+    "NC_000001.11",
+    "NC_000002.12",
+    "NC_000003.12",
+    "NC_000004.12",
+    "NC_000005.10",
+    "NC_000006.12",
+    "NC_000007.14",
+    "NC_000008.11",
+    "NC_000009.12",
+    "NC_000010.11",
+    "NC_000011.10",
+    "NC_000012.12",
+    "NC_000013.11",
+    "NC_000014.9",
+    "NC_000015.10",
+    "NC_000016.10",
+    "NC_000017.11",
+    "NC_000018.10",
+    "NC_000019.10",
+    "NC_000020.11",
+    "NC_000021.9",
+    "NC_000022.11",
+    "NC_000023.11",
+    "NC_000024.10",
+    "NC_012920.1",
+];
+
+/// Number of sequence names listed when the naming of a reference cannot be determined
+const EXAMPLE_CONTIGS: usize = 5;
+
+const VCF_COLUMN_HEADER: &str = "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO";
+
+/// Fields with a single comma-free value per allele, declared `Number=A` when joining multiallelics
+/// so that bcftools keeps the value of every allele
+const PER_ALLELE_FIELDS: [&str; 10] = [
+    "ALLELEID",
+    "RS",
+    "CLNHGVS",
+    "HGVSP",
+    "CLNDN",
+    "RCV",
+    "ORIGIN",
+    "CLNDATELASTEVAL",
+    "DATECREATED",
+    "DATELASTUPDATED",
+];
+
+fn per_allele_number(field: InfoField) -> InfoField {
+    if field.number == "1" && PER_ALLELE_FIELDS.contains(&field.id) {
+        InfoField {
+            number: "A",
+            ..field
+        }
+    } else {
+        field
+    }
+}
+
+/// Chromosome names on output
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, EnumString, VariantNames, AsRefStr)]
+#[strum(serialize_all = "lowercase")]
+pub enum ChrStyle {
+    /// As in the XML (`1`, `X`, `MT`)
+    #[default]
+    Plain,
+    /// UCSC style (`chr1`, `chrX`, `chrM`)
+    Ucsc,
+    /// RefSeq accessions of the assembly (`NC_000001.11`, `NC_000023.11`, `NC_012920.1`)
+    Refseq,
+}
+
+impl ChrStyle {
+    pub const ALL: [ChrStyle; 3] = [ChrStyle::Plain, ChrStyle::Ucsc, ChrStyle::Refseq];
+
+    /// Name of a chromosome in the XML in this style, other contigs as they are
+    ///
+    /// # Arguments
+    ///
+    /// * `chrom`: chromosome name in the XML
+    /// * `assembly`: assembly of the RefSeq accessions
+    ///
+    /// returns: String
+    pub fn rename(&self, chrom: &str, assembly: Assembly) -> String {
+        match (self, chrom) {
+            (ChrStyle::Plain, x) => x.to_string(),
+            (ChrStyle::Ucsc, "MT") => "chrM".to_string(),
+            (ChrStyle::Ucsc, x) if CONTIGS.contains(&x) => format!("chr{}", x),
+            (ChrStyle::Ucsc, x) => x.to_string(),
+            (ChrStyle::Refseq, x) => match refseq_accession(x, assembly) {
+                Some(accession) => accession.to_string(),
+                None => x.to_string(),
+            },
+        }
+    }
+
+    /// Naming of the chromosomes among the sequence names of a reference
+    ///
+    /// # Arguments
+    ///
+    /// * `names`: sequence names, e.g. of the .fai
+    /// * `assembly`: assembly of the reference, for the RefSeq accessions
+    ///
+    /// returns: the only style in which any of 1-22, X, Y and MT is named, or an error listing
+    /// example names if there is none or several
+    pub fn detect<S: AsRef<str>>(names: &[S], assembly: Assembly) -> io::Result<ChrStyle> {
+        let examples = |style: ChrStyle| {
+            let chromosomes = CONTIGS
+                .iter()
+                .map(|x| style.rename(x, assembly))
+                .collect::<Vec<String>>();
+            names
+                .iter()
+                .map(|x| x.as_ref())
+                .filter(|x| chromosomes.iter().any(|y| y == x))
+                .take(EXAMPLE_CONTIGS)
+                .collect::<Vec<&str>>()
+        };
+
+        let found = ChrStyle::ALL
+            .into_iter()
+            .map(|x| (x, examples(x)))
+            .filter(|x| !x.1.is_empty())
+            .collect::<Vec<_>>();
+        match &found[..] {
+            [(style, _)] => Ok(*style),
+            [] => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "No chromosome of {} named in any of {} styles, contigs: {}",
+                    assembly.as_ref(),
+                    ChrStyle::VARIANTS.join(", "),
+                    names
+                        .iter()
+                        .map(|x| x.as_ref())
+                        .take(EXAMPLE_CONTIGS)
+                        .collect::<Vec<&str>>()
+                        .join(", ")
+                ),
+            )),
+            _ => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Mixed chromosome naming, contigs: {}",
+                    found
+                        .iter()
+                        .map(|(style, examples)| {
+                            format!("{} ({})", examples.join(", "), style.as_ref())
+                        })
+                        .collect::<Vec<String>>()
+                        .join("; ")
+                ),
+            )),
+        }
+    }
+}
+
+/// RefSeq accession of a chromosome in the XML, `None` for other contigs
+pub fn refseq_accession(chrom: &str, assembly: Assembly) -> Option<&'static str> {
+    let accessions = match assembly {
+        Assembly::GRCh37 => &REFSEQ_GRCH37,
+        Assembly::GRCh38 => &REFSEQ_GRCH38,
+    };
+
+    CONTIGS
+        .iter()
+        .position(|x| *x == chrom)
+        .map(|i| accessions[i])
+}
+
+/// Chromosome in the XML of a RefSeq accession of either assembly
+pub fn chromosome_of_accession(accession: &str) -> Option<&'static str> {
+    [&REFSEQ_GRCH37, &REFSEQ_GRCH38]
+        .iter()
+        .find_map(|x| x.iter().position(|x| *x == accession))
+        .map(|i| CONTIGS[i])
+}
+
+/// Today in UTC as `YYYYMMDD`
+pub fn today() -> String {
+    let days = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_secs() / 86400)
+        .unwrap_or_default() as i64;
+    let (year, month, day) = crate::logger::civil_date(days);
+
+    format!("{:04}{:02}{:02}", year, month, day)
+}
+
+/// Converter of `VariationArchive` into the VCF of an assembly
+///
+/// Options default to those of the command line without flags; the filters and formatting
+/// options can also be given at once by [`config`](Self::config).
+#[derive(Debug, Clone)]
+pub struct Converter {
+    assembly: Assembly,
+    config: FormatConfig,
+    chr_style: ChrStyle,
+    /// `##contig` lines as (ID, length), the chromosomes without lengths if `None`
+    contigs: Option<Vec<(String, Option<u64>)>>,
+    per_allele_numbers: bool,
+    source: String,
+    /// Path of the reference FASTA for `##reference`
+    reference: Option<String>,
+    /// Command line for `##clinvar_xml2vcf_command`
+    command_line: Option<String>,
+    /// Lines written after `##contig` lines, e.g. `##INFO` of annotations
+    header_lines: Vec<String>,
+}
+
+impl Converter {
+    pub fn new(assembly: Assembly) -> Self {
+        Converter {
+            assembly,
+            config: FormatConfig::default(),
+            chr_style: ChrStyle::default(),
+            contigs: None,
+            per_allele_numbers: false,
+            source: format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
+            reference: None,
+            command_line: None,
+            header_lines: Vec::new(),
+        }
+    }
+
+    /// Replace all the filters and formatting options
+    pub fn config(mut self, config: FormatConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Output format
+    pub fn format(mut self, format: OutputFormat) -> Self {
+        self.config.format = format;
+        self
+    }
+
+    /// Layout of the CONDITIONS field of the togovar format
+    pub fn conditions_format(mut self, format: ConditionsFormat) -> Self {
+        self.config.conditions_format = format;
+        self
+    }
+
+    pub fn include_scv(mut self, include: bool) -> Self {
+        self.config.include_scv = include;
+        self
+    }
+
+    pub fn include_citations(mut self, include: bool) -> Self {
+        self.config.include_citations = include;
+        self
+    }
+
+    /// Databases to take condition IDs from, in output order (MedGen if empty)
+    pub fn condition_dbs<S: AsRef<str>>(mut self, dbs: &[S]) -> Self {
+        self.config.condition_dbs = dbs.iter().map(|x| x.as_ref().to_string()).collect();
+        self
+    }
+
+    /// Keep only records with any of these record statuses (current only if empty)
+    pub fn record_status<S: AsRef<str>>(mut self, statuses: &[S]) -> Self {
+        self.config.record_status = statuses.iter().map(|x| x.as_ref().to_string()).collect();
+        self
+    }
+
+    /// Use condition names for RCVs without conditions from the condition databases
+    pub fn keep_unmapped_conditions(mut self, keep: bool) -> Self {
+        self.config.keep_unmapped_conditions = keep;
+        self
+    }
+
+    /// Emit at most this many RCVs per record
+    pub fn max_rcvs(mut self, max: Option<usize>) -> Self {
+        self.config.max_rcvs = max;
+        self
+    }
+
+    /// Skip records that only have sentinel classifications
+    pub fn exclude_unclassified(mut self, exclude: bool) -> Self {
+        self.config.exclude_unclassified = exclude;
+        self
+    }
+
+    /// Write soft issues of each record into the FLAGS INFO field
+    pub fn emit_flags(mut self, emit: bool) -> Self {
+        self.config.emit_flags = emit;
+        self
+    }
+
+    /// Accept IUPAC ambiguity codes (including N) in alleles
+    pub fn allow_iupac(mut self, allow: bool) -> Self {
+        self.config.allow_iupac = allow;
+        self
+    }
+
+    /// Chromosome names on output
+    pub fn chr_style(mut self, chr_style: ChrStyle) -> Self {
+        self.chr_style = chr_style;
+        self
+    }
+
+    /// Contigs to declare as (ID, length), named in the chromosome style on output
+    ///
+    /// Defaults to 1-22, X, Y and MT without lengths.
+    pub fn contigs(mut self, contigs: Vec<(String, Option<u64>)>) -> Self {
+        self.contigs = Some(contigs);
+        self
+    }
+
+    /// Declare the fields with a single value per allele as `Number=A`, for joining multiallelics
+    pub fn per_allele_numbers(mut self, per_allele: bool) -> Self {
+        self.per_allele_numbers = per_allele;
+        self
+    }
+
+    /// `##source` of the header
+    pub fn source<S: Into<String>>(mut self, source: S) -> Self {
+        self.source = source.into();
+        self
+    }
+
+    /// Reference FASTA of `##reference`, written as a `file://` URI of the path
+    pub fn reference<S: Into<String>>(mut self, path: S) -> Self {
+        self.reference = Some(path.into());
+        self
+    }
+
+    /// Command line of `##clinvar_xml2vcf_command`, with the arguments quoted by the caller
+    pub fn command_line<S: Into<String>>(mut self, command_line: S) -> Self {
+        self.command_line = Some(command_line.into());
+        self
+    }
+
+    /// Lines to add to the header after `##contig` lines
+    pub fn header_lines(mut self, lines: Vec<String>) -> Self {
+        self.header_lines = lines;
+        self
+    }
+
+    pub fn assembly(&self) -> Assembly {
+        self.assembly
+    }
+
+    pub fn format_config(&self) -> &FormatConfig {
+        &self.config
+    }
+
+    pub fn chr_style_on_output(&self) -> ChrStyle {
+        self.chr_style
+    }
+
+    /// Write the VCF header up to the column header
+    ///
+    /// # Arguments
+    ///
+    /// * `writer`: output
+    /// * `release_date`: `ReleaseDate` of the XML, written as `##clinvar_release`
+    ///
+    /// returns: Result<(), Error>
+    pub fn write_header<W: Write>(
+        &self,
+        writer: &mut W,
+        release_date: Option<&str>,
+    ) -> io::Result<()> {
+        writeln!(writer, "{}", VCF_HEADER)?;
+        self.write_provenance(writer, release_date)?;
+        for field in info_fields(&self.config) {
+            if self.per_allele_numbers {
+                writeln!(writer, "{}", per_allele_number(field))?;
+            } else {
+                writeln!(writer, "{}", field)?;
+            }
+        }
+        match &self.contigs {
+            Some(contigs) => {
+                for (name, length) in contigs {
+                    match length {
+                        Some(length) => {
+                            writeln!(writer, "##contig=<ID={},length={}>", name, length)?
+                        }
+                        None => writeln!(writer, "##contig=<ID={}>", name)?,
+                    }
+                }
+            }
+            None => {
+                for name in CONTIGS {
+                    writeln!(
+                        writer,
+                        "##contig=<ID={}>",
+                        self.chr_style.rename(name, self.assembly)
+                    )?;
+                }
+            }
+        }
+        for line in &self.header_lines {
+            writeln!(writer, "{}", line)?;
+        }
+        writeln!(writer, "{}", VCF_COLUMN_HEADER)
+    }
+
+    /// Write the header of the records skipped after parsing (see [`crate::RejectedRecord`])
+    ///
+    /// # Arguments
+    ///
+    /// * `writer`: output
+    /// * `release_date`: `ReleaseDate` of the XML, written as `##clinvar_release`
+    ///
+    /// returns: Result<(), Error>
+    pub fn write_rejects_header<W: Write>(
+        &self,
+        writer: &mut W,
+        release_date: Option<&str>,
+    ) -> io::Result<()> {
+        writeln!(writer, "{}", VCF_HEADER)?;
+        self.write_provenance(writer, release_date)?;
+        writeln!(writer, "{}", INFO_SKIP_REASON)?;
+        writeln!(writer, "{}", VCF_COLUMN_HEADER)
+    }
+
+    /// Write `##fileDate`, `##source`, `##reference`, `##clinvar_xml2vcf_command` and
+    /// `##clinvar_release`
+    fn write_provenance<W: Write>(
+        &self,
+        writer: &mut W,
+        release_date: Option<&str>,
+    ) -> io::Result<()> {
+        writeln!(writer, "##fileDate={}", today())?;
+        writeln!(writer, "##source={}", self.source)?;
+        if let Some(reference) = &self.reference {
+            writeln!(
+                writer,
+                "##reference=file://{}",
+                encode_info_value(reference, &[])
+            )?;
+        }
+        if let Some(command_line) = &self.command_line {
+            writeln!(writer, "##clinvar_xml2vcf_command={}", command_line)?;
+        }
+        if let Some(release_date) = release_date {
+            writeln!(writer, "##clinvar_release={}", release_date)?;
+        }
+
+        Ok(())
+    }
+
+    /// VCF records of a variant with the chromosome names of the XML, or the reason to skip each
+    pub fn records(&self, variant: &VariationArchive) -> Vec<Result<VcfRecord, SkipReason>> {
+        to_vcf_records(variant, self.assembly, &self.config)
+    }
+
+    /// Convert all records of the reader, writing the header first
+    ///
+    /// Records are written in input order with their chromosomes renamed, neither checked against
+    /// the reference nor normalized.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader`: input, whose malformed records are counted in the stats if it skips them
+    /// * `writer`: output
+    ///
+    /// returns: Result<Stats, ClinvarError>
+    pub fn write<R: BufRead, W: Write>(
+        &self,
+        reader: &mut VariationArchiveReader<R>,
+        writer: &mut W,
+    ) -> Result<Stats, ClinvarError> {
+        reader.include_scv |= self.config.include_scv;
+        let release_date = reader.read_header()?.map(|x| x.to_string());
+        self.write_header(writer, release_date.as_deref())?;
+
+        let mut stats = Stats::default();
+        while let Some(result) = reader.read_record() {
+            let variant = match result {
+                Ok(x) => x,
+                Err(ClinvarError::Xml { .. }) if reader.skips_malformed() => {
+                    stats.skip(SkipReason::XmlError);
+                    continue;
+                }
+                Err(ClinvarError::Deserialize { .. }) if reader.skips_malformed() => {
+                    stats.records += 1;
+                    stats.skip(SkipReason::DeserializationError);
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            stats.records += 1;
+            for result in self.records(&variant) {
+                match result {
+                    Ok(mut record) => {
+                        record.chrom = self.chr_style.rename(&record.chrom, self.assembly);
+                        writeln!(writer, "{}", record)?;
+                        stats.written += 1;
+                        if record.flags.contains(&QcFlag::ConditionsTruncated) {
+                            stats.truncated += 1;
+                        }
+                    }
+                    Err(reason) => stats.skip(reason),
+                }
+            }
+        }
+
+        Ok(stats)
+    }
+}
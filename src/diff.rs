@@ -0,0 +1,303 @@
+//! Comparison of the VCFs of two releases by VariationID
+//!
+//! Each VCF is reduced to a line per VariationID and record, sorted by VariationID with the external
+//! sort of [`crate::sort`], and the two are then merged as sorted streams, so that memory is bounded
+//! by the sort buffer rather than by the number of records.
+
+use crate::sort::Sorter;
+use serde::Serialize;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Error, ErrorKind, Lines, Seek, SeekFrom};
+use std::iter::Peekable;
+use std::path::Path;
+
+/// Columns of the TSV report
+pub const COLUMNS: [&str; 8] = [
+    "variation_id",
+    "change",
+    "position_before",
+    "position_after",
+    "clnsig_before",
+    "clnsig_after",
+    "conditions_before",
+    "conditions_after",
+];
+
+/// Kind of change of a VariationID
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Change {
+    /// Only in the new VCF
+    Added,
+    /// Only in the old VCF
+    Removed,
+    /// CLNSIG or CONDITIONS changed
+    Reclassified,
+    /// CHROM, POS, REF or ALT changed
+    Relocated,
+}
+
+impl Change {
+    /// Code in the report
+    pub fn code(&self) -> &'static str {
+        match self {
+            Change::Added => "added",
+            Change::Removed => "removed",
+            Change::Reclassified => "reclassified",
+            Change::Relocated => "relocated",
+        }
+    }
+}
+
+/// Record of a VariationID, with INFO values as written in the VCF
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct Allele {
+    pub chrom: String,
+    pub pos: u64,
+    #[serde(rename = "ref")]
+    pub reference: String,
+    pub alt: String,
+    pub clnsig: Option<String>,
+    pub conditions: Option<String>,
+}
+
+impl Allele {
+    fn position(&self) -> (&str, u64, &str, &str) {
+        (&self.chrom, self.pos, &self.reference, &self.alt)
+    }
+
+    fn classification(&self) -> (Option<&str>, Option<&str>) {
+        (self.clnsig.as_deref(), self.conditions.as_deref())
+    }
+}
+
+/// A change of a VariationID, with its records in both VCFs
+#[derive(Debug, Clone, Serialize)]
+pub struct DiffEntry {
+    pub variation_id: u64,
+    pub change: Change,
+    pub before: Vec<Allele>,
+    pub after: Vec<Allele>,
+}
+
+impl DiffEntry {
+    fn new(variation_id: u64, change: Change, before: Vec<Allele>, after: Vec<Allele>) -> Self {
+        DiffEntry {
+            variation_id,
+            change,
+            before,
+            after,
+        }
+    }
+
+    /// Row of the TSV report, joining the values of several records by `,`
+    pub fn tsv_row(&self) -> String {
+        let join = |alleles: &[Allele], f: &dyn Fn(&Allele) -> String| {
+            alleles.iter().map(f).collect::<Vec<_>>().join(",")
+        };
+        let position = |x: &Allele| format!("{}:{}:{}:{}", x.chrom, x.pos, x.reference, x.alt);
+        let clnsig = |x: &Allele| x.clnsig.clone().unwrap_or_default();
+        let conditions = |x: &Allele| x.conditions.clone().unwrap_or_default();
+
+        [
+            self.variation_id.to_string(),
+            self.change.code().to_string(),
+            join(&self.before, &position),
+            join(&self.after, &position),
+            join(&self.before, &clnsig),
+            join(&self.after, &clnsig),
+            join(&self.before, &conditions),
+            join(&self.after, &conditions),
+        ]
+        .join("\t")
+    }
+}
+
+/// Compare two VCFs by the VariationIDs of the ID column
+///
+/// A record of several VariationIDs (e.g. merged by `--duplicates merge`) counts for each of
+/// them. A VariationID present in both VCFs yields an entry for each of `relocated` and
+/// `reclassified` that applies, comparing the sets of its records.
+///
+/// # Arguments
+///
+/// * `old`: VCF of the previous release
+/// * `new`: VCF of the current release
+/// * `memory`: approximate size of the sort buffer in bytes, for each VCF
+/// * `temp_dir`: directory for the temporary files
+/// * `f`: called with each change in the order of VariationIDs
+///
+/// returns: ()
+pub fn diff<R1, R2, F>(old: R1, new: R2, memory: usize, temp_dir: &Path, mut f: F) -> io::Result<()>
+where
+    R1: BufRead,
+    R2: BufRead,
+    F: FnMut(DiffEntry) -> io::Result<()>,
+{
+    let mut old = Groups(sort_by_id(old, memory, temp_dir)?.lines().peekable());
+    let mut new = Groups(sort_by_id(new, memory, temp_dir)?.lines().peekable());
+
+    let (mut before, mut after) = (old.next()?, new.next()?);
+    loop {
+        match (before.take(), after.take()) {
+            (None, None) => break,
+            (Some((id, alleles)), Some(x)) if id < x.0 => {
+                f(DiffEntry::new(id, Change::Removed, alleles, Vec::new()))?;
+                before = old.next()?;
+                after = Some(x);
+            }
+            (Some(x), Some((id, alleles))) if id < x.0 => {
+                f(DiffEntry::new(id, Change::Added, Vec::new(), alleles))?;
+                before = Some(x);
+                after = new.next()?;
+            }
+            (Some((id, mut old_alleles)), Some((_, mut new_alleles))) => {
+                old_alleles.sort();
+                new_alleles.sort();
+                let relocated = !same(&old_alleles, &new_alleles, Allele::position);
+                let reclassified = !same(&old_alleles, &new_alleles, Allele::classification);
+                if relocated {
+                    f(DiffEntry::new(
+                        id,
+                        Change::Relocated,
+                        old_alleles.clone(),
+                        new_alleles.clone(),
+                    ))?;
+                }
+                if reclassified {
+                    f(DiffEntry::new(
+                        id,
+                        Change::Reclassified,
+                        old_alleles,
+                        new_alleles,
+                    ))?;
+                }
+                before = old.next()?;
+                after = new.next()?;
+            }
+            (Some((id, alleles)), None) => {
+                f(DiffEntry::new(id, Change::Removed, alleles, Vec::new()))?;
+                before = old.next()?;
+            }
+            (None, Some((id, alleles))) => {
+                f(DiffEntry::new(id, Change::Added, Vec::new(), alleles))?;
+                after = new.next()?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether the records have the same set of values
+fn same<'a, T: Ord>(a: &'a [Allele], b: &'a [Allele], f: fn(&'a Allele) -> T) -> bool {
+    let values = |x: &'a [Allele]| {
+        let mut values = x.iter().map(f).collect::<Vec<T>>();
+        values.sort();
+        values.dedup();
+        values
+    };
+
+    values(a) == values(b)
+}
+
+/// Reduce a VCF to `VariationID CHROM POS REF ALT CLNSIG CONDITIONS` lines sorted by VariationID,
+/// in a temporary file rewound for reading
+fn sort_by_id<R: BufRead>(
+    reader: R,
+    memory: usize,
+    temp_dir: &Path,
+) -> io::Result<BufReader<File>> {
+    let mut sorter = Sorter::new(memory, temp_dir);
+    for line in reader.lines() {
+        let line = line?;
+        if line.starts_with('#') {
+            continue;
+        }
+
+        let columns = line.split('\t').collect::<Vec<_>>();
+        if columns.len() < 8 {
+            Err(invalid_line(&line))?
+        }
+        let info = |key: &str| {
+            columns[7]
+                .split(';')
+                .find_map(|x| x.strip_prefix(key)?.strip_prefix('='))
+                .unwrap_or_default()
+        };
+        for id in columns[2].split(';').filter(|x| *x != ".") {
+            let id = id.parse::<u64>().map_err(|_| invalid_line(&line))?;
+            let summary = format!(
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                id,
+                columns[0],
+                columns[1],
+                columns[3],
+                columns[4],
+                info("CLNSIG"),
+                info("CONDITIONS")
+            );
+            sorter.push(id, summary)?;
+        }
+    }
+
+    let mut writer = BufWriter::new(tempfile::tempfile_in(temp_dir)?);
+    sorter.finish(&mut writer, id_of)?;
+    let mut file = writer.into_inner().map_err(|e| e.into_error())?;
+    file.seek(SeekFrom::Start(0))?;
+
+    Ok(BufReader::new(file))
+}
+
+fn id_of(line: &str) -> io::Result<u64> {
+    line.split('\t')
+        .next()
+        .and_then(|x| x.parse().ok())
+        .ok_or_else(|| invalid_line(line))
+}
+
+fn invalid_line(line: &str) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("Invalid VCF line: {}", line.trim_end()),
+    )
+}
+
+/// Records of each VariationID from lines sorted by VariationID
+struct Groups<R: BufRead>(Peekable<Lines<R>>);
+
+impl<R: BufRead> Groups<R> {
+    fn next(&mut self) -> io::Result<Option<(u64, Vec<Allele>)>> {
+        let Some(line) = self.0.next() else {
+            return Ok(None);
+        };
+        let line = line?;
+        let id = id_of(&line)?;
+        let mut alleles = vec![parse_summary(&line)?];
+        while let Some(Ok(next)) = self.0.peek() {
+            if id_of(next)? != id {
+                break;
+            }
+            let next = self.0.next().expect("peeked")?;
+            alleles.push(parse_summary(&next)?);
+        }
+
+        Ok(Some((id, alleles)))
+    }
+}
+
+fn parse_summary(line: &str) -> io::Result<Allele> {
+    let columns = line.split('\t').collect::<Vec<_>>();
+    let value = |x: &str| (!x.is_empty()).then(|| x.to_string());
+    match columns[..] {
+        [_, chrom, pos, reference, alt, clnsig, conditions] => Ok(Allele {
+            chrom: chrom.to_string(),
+            pos: pos.parse().map_err(|_| invalid_line(line))?,
+            reference: reference.to_string(),
+            alt: alt.to_string(),
+            clnsig: value(clnsig),
+            conditions: value(conditions),
+        }),
+        _ => Err(invalid_line(line)),
+    }
+}
@@ -0,0 +1,279 @@
+//! Resolution of records with the same CHROM, POS, REF and ALT in a sorted VCF
+//!
+//! Distinct VariationIDs may describe the same allele, e.g. after normalization shifts an indel.
+//! Equal records are found within the records at the same position, so that the pass streams
+//! over the VCF with a buffer of a single position.
+
+use std::io::{self, BufRead, Write};
+
+/// INFO fields of `|` separated entries, concatenated on merge
+const MERGED_FIELDS: [&str; 5] = ["CONDITIONS", "CLNDN", "RCV", "ONC", "CLNDISDB"];
+
+/// Header of CONDITIONS with a value per entry (`--conditions-format v2`), concatenated by `,`
+const HEADER_CONDITIONS_V2: &str = "##INFO=<ID=CONDITIONS,Number=.,";
+
+/// INFO fields of counts, summed on merge
+const SUMMED_FIELDS: [&str; 1] = ["RCV_TRUNCATED"];
+
+/// Flag kept on merge only if all of the records have it
+const FLAG_ALL: &str = "CONDITIONS_UNMAPPED";
+
+/// Merge records with the same CHROM, POS, REF and ALT into one
+///
+/// IDs are joined by `;`, entries of CONDITIONS, CLNDN, RCV, ONC and CLNDISDB are concatenated
+/// by `|` (`,` for CONDITIONS declared `Number=.`), RCV_TRUNCATED is summed, CONDITIONS_UNMAPPED
+/// is kept if all of the records have it and the other INFO fields are those of the first record
+/// that has them.
+///
+/// # Arguments
+///
+/// * `reader`: VCF sorted by position
+/// * `writer`: output
+///
+/// returns: number of records merged into a preceding one
+pub fn merge_duplicates<R: BufRead, W: Write>(reader: R, writer: &mut W) -> io::Result<u64> {
+    resolve(reader, writer, |header, x| merge(header, &x))
+}
+
+/// Keep the first of the records with the same CHROM, POS, REF and ALT
+///
+/// # Arguments
+///
+/// * `reader`: VCF sorted by position
+/// * `writer`: output
+///
+/// returns: number of records dropped
+pub fn drop_duplicates<R: BufRead, W: Write>(reader: R, writer: &mut W) -> io::Result<u64> {
+    resolve(reader, writer, |_, x| x[0].to_string())
+}
+
+/// Write the records, resolving each group of equal ones by `f`
+fn resolve<R, W, F>(reader: R, writer: &mut W, f: F) -> io::Result<u64>
+where
+    R: BufRead,
+    W: Write,
+    F: Fn(&[String], Vec<&str>) -> String,
+{
+    let mut header: Vec<String> = Vec::new();
+    let mut position: Vec<String> = Vec::new();
+    let mut count = 0;
+
+    for line in reader.lines() {
+        let line = line?;
+        if line.starts_with('#') {
+            writeln!(writer, "{}", line)?;
+            header.push(line);
+            continue;
+        }
+
+        if position
+            .first()
+            .is_some_and(|x| columns(x, 2) != columns(&line, 2))
+        {
+            count += flush(&mut position, &header, writer, &f)?;
+        }
+        position.push(line);
+    }
+    count += flush(&mut position, &header, writer, &f)?;
+
+    Ok(count)
+}
+
+/// Write the records of a position in the order of their first occurrence
+fn flush<W, F>(
+    position: &mut Vec<String>,
+    header: &[String],
+    writer: &mut W,
+    f: &F,
+) -> io::Result<u64>
+where
+    W: Write,
+    F: Fn(&[String], Vec<&str>) -> String,
+{
+    let mut groups: Vec<Vec<&str>> = Vec::new();
+    for line in position.iter() {
+        match groups.iter_mut().find(|x| key(x[0]) == key(line)) {
+            Some(group) => group.push(line),
+            None => groups.push(vec![line]),
+        }
+    }
+
+    let mut count = 0;
+    for group in groups {
+        count += group.len() as u64 - 1;
+        if group.len() == 1 {
+            writeln!(writer, "{}", group[0])?;
+        } else {
+            writeln!(writer, "{}", f(header, group))?;
+        }
+    }
+    position.clear();
+
+    Ok(count)
+}
+
+/// CHROM, POS, REF and ALT
+fn key(line: &str) -> (&str, &str, &str, &str) {
+    let mut columns = line.split('\t');
+    (
+        columns.next().unwrap_or_default(),
+        columns.next().unwrap_or_default(),
+        columns.nth(1).unwrap_or_default(),
+        columns.next().unwrap_or_default(),
+    )
+}
+
+/// First `n` columns
+fn columns(line: &str, n: usize) -> Vec<&str> {
+    line.splitn(n + 1, '\t').take(n).collect()
+}
+
+fn merge(header: &[String], lines: &[&str]) -> String {
+    let conditions_v2 = header.iter().any(|x| x.starts_with(HEADER_CONDITIONS_V2));
+    let records = lines
+        .iter()
+        .map(|x| x.split('\t').collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+
+    let mut ids: Vec<&str> = Vec::new();
+    for id in records.iter().flat_map(|x| x[2].split(';')) {
+        if id != "." && !ids.contains(&id) {
+            ids.push(id);
+        }
+    }
+
+    let mut info: Vec<(&str, Option<String>)> = Vec::new();
+    for record in &records {
+        for field in record.get(7).into_iter().flat_map(|x| x.split(';')) {
+            let (name, value) = match field.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (field, None),
+            };
+            if name == "." {
+                continue;
+            }
+            match info.iter_mut().find(|x| x.0 == name) {
+                Some((_, Some(x))) if MERGED_FIELDS.contains(&name) => {
+                    if let Some(value) = value {
+                        x.push(if name == "CONDITIONS" && conditions_v2 {
+                            ','
+                        } else {
+                            '|'
+                        });
+                        x.push_str(value);
+                    }
+                }
+                Some((_, Some(x))) if SUMMED_FIELDS.contains(&name) => {
+                    let sum = x
+                        .parse::<u64>()
+                        .ok()
+                        .zip(value.and_then(|x| x.parse::<u64>().ok()));
+                    if let Some((a, b)) = sum {
+                        *x = (a + b).to_string();
+                    }
+                }
+                Some(_) => {}
+                None => info.push((name, value.map(|x| x.to_string()))),
+            }
+        }
+    }
+    let has_flag = |record: &Vec<&str>| {
+        record
+            .get(7)
+            .is_some_and(|x| x.split(';').any(|x| x == FLAG_ALL))
+    };
+    if !records.iter().all(has_flag) {
+        info.retain(|x| x.0 != FLAG_ALL);
+    }
+
+    let mut columns = records[0].clone();
+    let id = if ids.is_empty() {
+        ".".to_string()
+    } else {
+        ids.join(";")
+    };
+    let info = if info.is_empty() {
+        ".".to_string()
+    } else {
+        info.iter()
+            .map(|(name, value)| match value {
+                Some(value) => format!("{}={}", name, value),
+                None => name.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join(";")
+    };
+    columns[2] = &id;
+    if columns.len() > 7 {
+        columns[7] = &info;
+    }
+
+    columns.join("\t")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_concatenates_entries_and_sums_truncation() {
+        let vcf = "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n\
+                   1\t100\t1\tA\tG\t.\tPASS\tCONDITIONS=a|b;RCV=R1|R2;RCV_TRUNCATED=3;CLNSIG=x\n\
+                   1\t100\t2\tA\tG\t.\tPASS\tCONDITIONS=c;RCV=R3;RCV_TRUNCATED=2;CLNSIG=y\n\
+                   1\t100\t3\tA\tT\t.\tPASS\tCONDITIONS=d;RCV=R4\n";
+        let mut output = Vec::new();
+
+        let merged = merge_duplicates(vcf.as_bytes(), &mut output).unwrap();
+
+        assert_eq!(merged, 1);
+        let output = String::from_utf8(output).unwrap();
+        let lines = output.lines().skip(1).collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            vec![
+                "1\t100\t1;2\tA\tG\t.\tPASS\tCONDITIONS=a|b|c;RCV=R1|R2|R3;RCV_TRUNCATED=5;CLNSIG=x",
+                "1\t100\t3\tA\tT\t.\tPASS\tCONDITIONS=d;RCV=R4",
+            ]
+        );
+    }
+
+    #[test]
+    fn drop_keeps_the_first_of_equal_records() {
+        let vcf = "#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n\
+                   1\t100\t1\tA\tG\t.\tPASS\tCONDITIONS=a\n\
+                   1\t100\t2\tA\tT\t.\tPASS\tCONDITIONS=b\n\
+                   1\t100\t3\tA\tG\t.\tPASS\tCONDITIONS=c\n\
+                   1\t200\t4\tA\tG\t.\tPASS\tCONDITIONS=d\n\
+                   2\t200\t5\tA\tG\t.\tPASS\tCONDITIONS=e\n";
+        let mut output = Vec::new();
+
+        let dropped = drop_duplicates(vcf.as_bytes(), &mut output).unwrap();
+
+        assert_eq!(dropped, 1);
+        let output = String::from_utf8(output).unwrap();
+        let ids = output
+            .lines()
+            .skip(1)
+            .map(|x| x.split('\t').nth(2).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec!["1", "2", "4", "5"]);
+    }
+
+    #[test]
+    fn merge_joins_conditions_v2_by_comma_and_drops_flags_not_all_have() {
+        let vcf = "##INFO=<ID=CONDITIONS,Number=.,Type=String,Description=\"\">\n\
+                   #CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n\
+                   1\t100\t1\tA\tG\t.\tPASS\tCONDITIONS=a,b;CONDITIONS_UNMAPPED;GENEINFO=X:1\n\
+                   1\t100\t1;2\tA\tG\t.\tPASS\tCONDITIONS=c;GENEINFO=Y:2\n";
+        let mut output = Vec::new();
+
+        let merged = merge_duplicates(vcf.as_bytes(), &mut output).unwrap();
+
+        assert_eq!(merged, 1);
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(
+            output.lines().last().unwrap(),
+            "1\t100\t1;2\tA\tG\t.\tPASS\tCONDITIONS=a,b,c;GENEINFO=X:1"
+        );
+    }
+}
@@ -0,0 +1,176 @@
+//! Error type telling a malformed record from a broken input stream or a failing external tool
+//!
+//! Functions returning `io::Result` wrap a [`ClinvarError`] into an [`io::Error`], which
+//! `From<io::Error>` unwraps again, so the variant survives passing through them.
+
+use crate::SkipReason;
+use std::io;
+use std::process::ExitStatus;
+use thiserror::Error;
+
+/// Stage of the conversion run by an external tool
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// `bcftools sort`
+    Sort,
+    /// `bcftools norm`
+    Normalize,
+    /// `bcftools index`
+    Index,
+}
+
+impl Stage {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Stage::Sort => "sort",
+            Stage::Normalize => "normalize",
+            Stage::Index => "index",
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ClinvarError {
+    /// Failure to read or write, including a corrupt compressed stream
+    #[error("{0}")]
+    Io(#[source] io::Error),
+    /// Malformed XML at the position in the input
+    #[error("{} at position {position}: {source}", SkipReason::XmlError)]
+    Xml {
+        position: usize,
+        source: quick_xml::Error,
+    },
+    /// `VariationArchive` element that failed to deserialize, at the position in the input after
+    /// its start tag
+    #[error(
+        "{} at position {position}{}: {source}",
+        SkipReason::DeserializationError,
+        variation_id.map(|x| format!(" (variation_id = {})", x)).unwrap_or_default()
+    )]
+    Deserialize {
+        variation_id: Option<u64>,
+        position: usize,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    /// External command that exited with an error
+    #[error(
+        "{tool} failed to {} ({status}){}",
+        stage.name(),
+        if stderr.is_empty() { String::new() } else { format!(": {}", stderr) }
+    )]
+    ExternalTool {
+        tool: String,
+        stage: Stage,
+        status: ExitStatus,
+        stderr: String,
+    },
+    /// Record rejected on conversion
+    #[error("Invalid record ({}): {detail}", reason.code())]
+    InvalidRecord { reason: SkipReason, detail: String },
+}
+
+impl From<io::Error> for ClinvarError {
+    fn from(e: io::Error) -> Self {
+        if e.get_ref().is_some_and(|x| x.is::<ClinvarError>()) {
+            let inner = e.into_inner().expect("checked above");
+            return *inner.downcast::<ClinvarError>().expect("checked above");
+        }
+
+        ClinvarError::Io(e)
+    }
+}
+
+impl From<ClinvarError> for io::Error {
+    fn from(e: ClinvarError) -> Self {
+        match e {
+            ClinvarError::Io(e) => e,
+            ClinvarError::ExternalTool { .. } => io::Error::other(e),
+            e => io::Error::new(io::ErrorKind::InvalidData, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error as _;
+
+    fn invalid_record() -> ClinvarError {
+        ClinvarError::InvalidRecord {
+            reason: SkipReason::RefMismatch,
+            detail: "variation_id = 1, 1:100".to_string(),
+        }
+    }
+
+    #[test]
+    fn variants_survive_io_errors() {
+        let e = io::Error::from(invalid_record());
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert!(matches!(
+            ClinvarError::from(e),
+            ClinvarError::InvalidRecord {
+                reason: SkipReason::RefMismatch,
+                ..
+            }
+        ));
+
+        let e = io::Error::new(io::ErrorKind::UnexpectedEof, "truncated");
+        match ClinvarError::from(e) {
+            ClinvarError::Io(e) => assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof),
+            e => panic!("{:?}", e),
+        }
+    }
+
+    #[test]
+    fn messages_name_the_reason() {
+        assert_eq!(
+            invalid_record().to_string(),
+            "Invalid record (ref_mismatch): variation_id = 1, 1:100"
+        );
+
+        let e = ClinvarError::Deserialize {
+            variation_id: Some(7),
+            position: 42,
+            source: "missing field `@AlleleID`".into(),
+        };
+        assert_eq!(
+            e.to_string(),
+            "Failed to deserialize VariationArchive at position 42 (variation_id = 7): \
+             missing field `@AlleleID`"
+        );
+        assert!(e.source().is_some());
+
+        let e = ClinvarError::Deserialize {
+            variation_id: None,
+            position: 42,
+            source: "invalid UTF-8".into(),
+        };
+        assert_eq!(
+            e.to_string(),
+            "Failed to deserialize VariationArchive at position 42: invalid UTF-8"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn external_tool_messages_have_stderr_if_any() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let error = |stderr: &str| ClinvarError::ExternalTool {
+            tool: "bcftools".to_string(),
+            stage: Stage::Sort,
+            status: ExitStatus::from_raw(1 << 8),
+            stderr: stderr.to_string(),
+        };
+
+        assert_eq!(
+            error("").to_string(),
+            "bcftools failed to sort (exit status: 1)"
+        );
+        assert_eq!(
+            error("No space left on device").to_string(),
+            "bcftools failed to sort (exit status: 1): No space left on device"
+        );
+        assert_eq!(io::Error::from(error("")).kind(), io::ErrorKind::Other);
+    }
+}
@@ -0,0 +1,159 @@
+//! Random access to a bgzipped FASTA with its .fai and .gzi indexes
+
+use crate::bgzf;
+use crate::converter::{chromosome_of_accession, refseq_accession};
+use crate::Assembly;
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Error, ErrorKind, Read};
+use std::path::{Path, PathBuf};
+
+/// Line of a .fai
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    length: u64,
+    offset: u64,
+    line_bases: u64,
+    line_width: u64,
+}
+
+pub struct Reader {
+    entries: HashMap<String, Entry>,
+    /// (compressed offset, uncompressed offset) of blocks, from the .gzi
+    blocks: Vec<(u64, u64)>,
+    inner: bgzf::Reader<BufReader<File>>,
+}
+
+impl Reader {
+    /// Open `path` (`*.fa.gz`) with `path.fai` and `path.gzi`
+    pub fn from_path<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+
+        let mut entries = HashMap::new();
+        let fai = with_suffix(path, ".fai");
+        for line in BufReader::new(File::open(&fai)?).lines() {
+            let line = line?;
+            let columns = line.split('\t').collect::<Vec<&str>>();
+            let parse = |i: usize| {
+                columns
+                    .get(i)
+                    .and_then(|x| x.parse::<u64>().ok())
+                    .ok_or_else(|| {
+                        Error::new(
+                            ErrorKind::InvalidData,
+                            format!("Invalid line in {}: {}", fai.to_string_lossy(), line),
+                        )
+                    })
+            };
+            let entry = Entry {
+                length: parse(1)?,
+                offset: parse(2)?,
+                line_bases: parse(3)?,
+                line_width: parse(4)?,
+            };
+            entries.insert(columns[0].to_string(), entry);
+        }
+
+        let mut gzi = Vec::new();
+        File::open(with_suffix(path, ".gzi"))?.read_to_end(&mut gzi)?;
+        let blocks = std::iter::once((0, 0))
+            .chain(gzi.get(8..).unwrap_or_default().chunks_exact(16).map(|x| {
+                (
+                    u64::from_le_bytes(x[0..8].try_into().expect("8 bytes")),
+                    u64::from_le_bytes(x[8..16].try_into().expect("8 bytes")),
+                )
+            }))
+            .collect();
+
+        Ok(Reader {
+            entries,
+            blocks,
+            inner: bgzf::Reader::new(BufReader::new(File::open(path)?)),
+        })
+    }
+
+    /// Find a sequence, also trying the name with or without the `chr` prefix (`MT` for `chrM`)
+    /// and, for a chromosome, its RefSeq accessions
+    fn entry(&self, name: &str) -> Option<Entry> {
+        let name = chromosome_of_accession(name).unwrap_or(name);
+        let alias = match name {
+            "MT" => "chrM".to_string(),
+            "chrM" => "MT".to_string(),
+            x => match x.strip_prefix("chr") {
+                Some(x) => x.to_string(),
+                None => format!("chr{}", x),
+            },
+        };
+
+        self.entries
+            .get(name)
+            .or_else(|| self.entries.get(&alias))
+            .or_else(|| {
+                let chrom = if name == "chrM" {
+                    "MT"
+                } else {
+                    name.trim_start_matches("chr")
+                };
+                Assembly::ALL
+                    .iter()
+                    .filter_map(|x| refseq_accession(chrom, *x))
+                    .find_map(|x| self.entries.get(x))
+            })
+            .copied()
+    }
+
+    /// Sequence of `[start, end)` (0-based)
+    ///
+    /// # Arguments
+    ///
+    /// * `name`: sequence name, with or without the `chr` prefix
+    /// * `start`: 0-based start
+    /// * `end`: 0-based end (exclusive)
+    ///
+    /// returns: the bases, or `None` if the sequence is not found or shorter than `end`
+    pub fn fetch(&mut self, name: &str, start: u64, end: u64) -> io::Result<Option<Vec<u8>>> {
+        let entry = match self.entry(name) {
+            Some(x) if start <= end && end <= x.length => x,
+            _ => return Ok(None),
+        };
+
+        let offset =
+            entry.offset + start / entry.line_bases * entry.line_width + start % entry.line_bases;
+        let i = self.blocks.partition_point(|x| x.1 <= offset) - 1;
+        let (compressed, uncompressed) = self.blocks[i];
+        self.inner
+            .seek_virtual((compressed << 16) | (offset - uncompressed))?;
+
+        let length = (end - start) as usize;
+        let mut bases = Vec::with_capacity(length);
+        while bases.len() < length {
+            let buf = self.inner.fill_buf()?;
+            if buf.is_empty() {
+                Err(Error::new(
+                    ErrorKind::UnexpectedEof,
+                    format!("Unexpected end of FASTA in {}", name),
+                ))?
+            }
+            let mut consumed = 0;
+            for x in buf {
+                if bases.len() == length {
+                    break;
+                }
+                if *x != b'\n' && *x != b'\r' {
+                    bases.push(*x);
+                }
+                consumed += 1;
+            }
+            self.inner.consume(consumed);
+        }
+
+        Ok(Some(bases))
+    }
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = OsString::from(path.as_os_str());
+    path.push(suffix);
+    PathBuf::from(path)
+}
@@ -0,0 +1,527 @@
+//! Generator of synthetic ClinVarVariationRelease XML from a compact TOML spec
+//!
+//! ```toml
+//! release_date = "2024-05-01"
+//!
+//! [[records]]
+//! variation_id = 12345
+//! allele_id = 111
+//! genes = [{ symbol = "BRCA1", id = 672 }]
+//! locations = [{ assembly = "GRCh38", chr = "17", position = 43000000, reference = "A", alternate = "G" }]
+//! aggregate = { classification = "Pathogenic", review_status = "criteria provided, single submitter", submission_count = 1 }
+//!
+//! [[records.rcvs]]
+//! accession = "RCV000000001"
+//! conditions = [{ db = "MedGen", id = "C0001", name = "Breast cancer" }]
+//! classification = "Pathogenic"
+//! review_status = "criteria provided, single submitter"
+//! citations = [{ source = "PubMed", id = "12345678" }]
+//! ```
+//!
+//! `structure = "haplotype"` or `"genotype"` wraps the allele accordingly, and `malformations`
+//! lists deliberate defects (see [`Malformation`]).
+
+use serde::Deserialize;
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Spec {
+    #[serde(default)]
+    pub release_date: Option<String>,
+    #[serde(default)]
+    pub records: Vec<RecordSpec>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RecordSpec {
+    pub variation_id: u64,
+    #[serde(default)]
+    pub allele_id: Option<u64>,
+    #[serde(default)]
+    pub record_status: Option<String>,
+    #[serde(default)]
+    pub species: Option<String>,
+    #[serde(default)]
+    pub date_created: Option<String>,
+    #[serde(default)]
+    pub date_last_updated: Option<String>,
+    #[serde(default)]
+    pub structure: Structure,
+    #[serde(default)]
+    pub variant_type: Option<String>,
+    #[serde(default)]
+    pub genes: Vec<GeneSpec>,
+    #[serde(default)]
+    pub locations: Vec<LocationSpec>,
+    #[serde(default)]
+    pub xrefs: Vec<XRefSpec>,
+    #[serde(default)]
+    pub rcvs: Vec<RcvSpec>,
+    #[serde(default)]
+    pub aggregate: Option<AggregateSpec>,
+    #[serde(default)]
+    pub malformations: Vec<Malformation>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Structure {
+    #[default]
+    Simple,
+    Haplotype,
+    Genotype,
+}
+
+/// Deliberate defects of a generated record
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Malformation {
+    /// Omit `@AlleleID` of `SimpleAllele`
+    MissingAlleleId,
+    /// Omit `@positionVCF` of every `SequenceLocation`
+    MissingPositionVcf,
+    /// Omit `@SubmissionCount` of RCV classifications
+    MissingSubmissionCount,
+    /// Omit the closing tag of `VariationArchive`
+    Unclosed,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GeneSpec {
+    pub symbol: String,
+    #[serde(default)]
+    pub id: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LocationSpec {
+    pub assembly: String,
+    pub chr: String,
+    pub position: u64,
+    pub reference: String,
+    pub alternate: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct XRefSpec {
+    pub db: String,
+    pub id: String,
+    #[serde(default)]
+    pub r#type: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RcvSpec {
+    pub accession: String,
+    #[serde(default = "default_version")]
+    pub version: i32,
+    #[serde(default)]
+    pub conditions: Vec<ConditionSpec>,
+    #[serde(default)]
+    pub kind: ClassificationKind,
+    pub classification: String,
+    #[serde(default)]
+    pub review_status: Option<String>,
+    #[serde(default = "default_submission_count")]
+    pub submission_count: i32,
+    #[serde(default)]
+    pub date_last_evaluated: Option<String>,
+    #[serde(default)]
+    pub citations: Vec<CitationSpec>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClassificationKind {
+    #[default]
+    Germline,
+    Somatic,
+    Oncogenicity,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConditionSpec {
+    pub db: String,
+    pub id: String,
+    pub name: String,
+}
+
+/// `Citation` of an RCV classification, e.g. `{ source = "PubMed", id = "12345678" }`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CitationSpec {
+    pub source: String,
+    pub id: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AggregateSpec {
+    pub classification: String,
+    #[serde(default)]
+    pub review_status: Option<String>,
+    #[serde(default = "default_submission_count")]
+    pub submission_count: i32,
+    #[serde(default)]
+    pub date_last_evaluated: Option<String>,
+}
+
+fn default_version() -> i32 {
+    1
+}
+
+fn default_submission_count() -> i32 {
+    1
+}
+
+/// Generate a ClinVarVariationRelease document
+///
+/// # Arguments
+///
+/// * `spec`: `Spec`
+///
+/// returns: String
+pub fn generate(spec: &Spec) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    match &spec.release_date {
+        Some(x) => {
+            let _ = writeln!(
+                xml,
+                "<ClinVarVariationRelease ReleaseDate=\"{}\">",
+                escape(x)
+            );
+        }
+        None => xml.push_str("<ClinVarVariationRelease>\n"),
+    }
+    for record in &spec.records {
+        xml.push_str(&generate_record(record));
+    }
+    xml.push_str("</ClinVarVariationRelease>\n");
+
+    xml
+}
+
+/// Generate a single `VariationArchive` element
+pub fn generate_record(record: &RecordSpec) -> String {
+    let mut xml = String::new();
+
+    let _ = write!(
+        xml,
+        "<VariationArchive VariationID=\"{0}\" Accession=\"VCV{0:09}\" RecordStatus=\"{1}\"",
+        record.variation_id,
+        escape(record.record_status.as_deref().unwrap_or("current"))
+    );
+    if let Some(x) = &record.date_created {
+        let _ = write!(xml, " DateCreated=\"{}\"", escape(x));
+    }
+    if let Some(x) = &record.date_last_updated {
+        let _ = write!(xml, " DateLastUpdated=\"{}\"", escape(x));
+    }
+    xml.push_str(">\n");
+    if let Some(x) = &record.species {
+        let _ = writeln!(xml, "  <Species>{}</Species>", escape(x));
+    }
+    xml.push_str("  <ClassifiedRecord>\n");
+
+    let allele = simple_allele(record);
+    match record.structure {
+        Structure::Simple => xml.push_str(&allele),
+        Structure::Haplotype => {
+            let _ = write!(
+                xml,
+                "    <Haplotype VariationID=\"{}\">\n{}    </Haplotype>\n",
+                record.variation_id, allele
+            );
+        }
+        Structure::Genotype => {
+            let _ = write!(
+                xml,
+                "    <Genotype VariationID=\"{}\">\n{}    </Genotype>\n",
+                record.variation_id, allele
+            );
+        }
+    }
+
+    xml.push_str("    <RCVList>\n");
+    for rcv in &record.rcvs {
+        xml.push_str(&rcv_accession(record, rcv));
+    }
+    xml.push_str("    </RCVList>\n");
+
+    if let Some(aggregate) = &record.aggregate {
+        xml.push_str("    <Classifications>\n      <GermlineClassification>");
+        if let Some(x) = &aggregate.review_status {
+            let _ = write!(xml, "<ReviewStatus>{}</ReviewStatus>", escape(x));
+        }
+        let _ = write!(
+            xml,
+            "<Description SubmissionCount=\"{}\"",
+            aggregate.submission_count
+        );
+        if let Some(x) = &aggregate.date_last_evaluated {
+            let _ = write!(xml, " DateLastEvaluated=\"{}\"", escape(x));
+        }
+        let _ = write!(
+            xml,
+            ">{}</Description></GermlineClassification>\n    </Classifications>\n",
+            escape(&aggregate.classification)
+        );
+    }
+
+    xml.push_str("  </ClassifiedRecord>\n");
+    if !record.malformations.contains(&Malformation::Unclosed) {
+        xml.push_str("</VariationArchive>\n");
+    }
+
+    xml
+}
+
+fn simple_allele(record: &RecordSpec) -> String {
+    let mut xml = String::from("    <SimpleAllele");
+    if !record
+        .malformations
+        .contains(&Malformation::MissingAlleleId)
+    {
+        let _ = write!(
+            xml,
+            " AlleleID=\"{}\"",
+            record.allele_id.unwrap_or(record.variation_id)
+        );
+    }
+    let _ = writeln!(xml, " VariationID=\"{}\">", record.variation_id);
+
+    if !record.genes.is_empty() {
+        xml.push_str("      <GeneList>");
+        for gene in &record.genes {
+            let _ = write!(xml, "<Gene Symbol=\"{}\"", escape(&gene.symbol));
+            if let Some(id) = gene.id {
+                let _ = write!(xml, " GeneID=\"{}\"", id);
+            }
+            xml.push_str("/>");
+        }
+        xml.push_str("</GeneList>\n");
+    }
+    if let Some(x) = &record.variant_type {
+        let _ = writeln!(xml, "      <VariantType>{}</VariantType>", escape(x));
+    }
+
+    xml.push_str("      <Location>");
+    for location in &record.locations {
+        let _ = write!(
+            xml,
+            "<SequenceLocation Assembly=\"{}\" Chr=\"{}\"",
+            escape(&location.assembly),
+            escape(&location.chr)
+        );
+        if !record
+            .malformations
+            .contains(&Malformation::MissingPositionVcf)
+        {
+            let _ = write!(xml, " positionVCF=\"{}\"", location.position);
+        }
+        let _ = write!(
+            xml,
+            " referenceAlleleVCF=\"{}\" alternateAlleleVCF=\"{}\"/>",
+            escape(&location.reference),
+            escape(&location.alternate)
+        );
+    }
+    xml.push_str("</Location>\n");
+
+    if !record.xrefs.is_empty() {
+        xml.push_str("      <XRefList>");
+        for xref in &record.xrefs {
+            let _ = write!(
+                xml,
+                "<XRef DB=\"{}\" ID=\"{}\"",
+                escape(&xref.db),
+                escape(&xref.id)
+            );
+            if let Some(x) = &xref.r#type {
+                let _ = write!(xml, " Type=\"{}\"", escape(x));
+            }
+            xml.push_str("/>");
+        }
+        xml.push_str("</XRefList>\n");
+    }
+
+    xml.push_str("    </SimpleAllele>\n");
+
+    xml
+}
+
+fn rcv_accession(record: &RecordSpec, rcv: &RcvSpec) -> String {
+    let mut xml = String::new();
+
+    let _ = writeln!(
+        xml,
+        "      <RCVAccession Accession=\"{}\" Version=\"{}\">",
+        escape(&rcv.accession),
+        rcv.version
+    );
+
+    xml.push_str("        <ClassifiedConditionList>");
+    for condition in &rcv.conditions {
+        let _ = write!(
+            xml,
+            "<ClassifiedCondition DB=\"{}\" ID=\"{}\">{}</ClassifiedCondition>",
+            escape(&condition.db),
+            escape(&condition.id),
+            escape(&condition.name)
+        );
+    }
+    xml.push_str("</ClassifiedConditionList>\n");
+
+    let tag = match rcv.kind {
+        ClassificationKind::Germline => "GermlineClassification",
+        ClassificationKind::Somatic => "SomaticClinicalImpact",
+        ClassificationKind::Oncogenicity => "OncogenicityClassification",
+    };
+    let _ = write!(xml, "        <RCVClassifications><{}>", tag);
+    if let Some(x) = &rcv.review_status {
+        let _ = write!(xml, "<ReviewStatus>{}</ReviewStatus>", escape(x));
+    }
+    xml.push_str("<Description");
+    if !record
+        .malformations
+        .contains(&Malformation::MissingSubmissionCount)
+    {
+        let _ = write!(xml, " SubmissionCount=\"{}\"", rcv.submission_count);
+    }
+    if let Some(x) = &rcv.date_last_evaluated {
+        let _ = write!(xml, " DateLastEvaluated=\"{}\"", escape(x));
+    }
+    let _ = write!(xml, ">{}</Description>", escape(&rcv.classification));
+    for citation in &rcv.citations {
+        let _ = write!(
+            xml,
+            "<Citation Type=\"general\"><ID Source=\"{}\">{}</ID></Citation>",
+            escape(&citation.source),
+            escape(&citation.id)
+        );
+    }
+    let _ = writeln!(xml, "</{}></RCVClassifications>", tag);
+
+    xml.push_str("      </RCVAccession>\n");
+
+    xml
+}
+
+/// Reorder records deterministically from `seed` (xorshift), so that tests do not depend on the
+/// document order
+pub fn shuffle(spec: &mut Spec, seed: u64) {
+    let mut state = seed.max(1);
+    for i in (1..spec.records.len()).rev() {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        spec.records.swap(i, (state % (i as u64 + 1)) as usize);
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::VariationArchiveReader;
+
+    fn record(variation_id: u64) -> RecordSpec {
+        RecordSpec {
+            variation_id,
+            locations: vec![LocationSpec {
+                assembly: "GRCh38".to_string(),
+                chr: "1".to_string(),
+                position: 100,
+                reference: "A".to_string(),
+                alternate: "G".to_string(),
+            }],
+            rcvs: vec![RcvSpec {
+                accession: format!("RCV{}", variation_id),
+                conditions: vec![ConditionSpec {
+                    db: "MedGen".to_string(),
+                    id: "C0001".to_string(),
+                    name: "Disease & <x>".to_string(),
+                }],
+                classification: "Pathogenic".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn generated_document_is_read_back() {
+        let spec = Spec {
+            release_date: Some("2024-05-01".to_string()),
+            records: vec![record(1), record(2)],
+        };
+
+        let archives = VariationArchiveReader::from_reader(generate(&spec).as_bytes())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        assert_eq!(
+            archives.iter().map(|x| x.variation_id).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        let record = archives[0].classified_record.as_ref().unwrap();
+        let allele = record.simple_allele.as_ref().unwrap();
+        assert_eq!(allele.allele_id, 1);
+        let rcv = &record.rcv_list.rcv_accession[0];
+        assert_eq!(rcv.accession, "RCV1");
+        assert_eq!(
+            rcv.classified_condition_list.classified_condition[0].text,
+            "Disease & <x>"
+        );
+    }
+
+    #[test]
+    fn unclosed_record_is_malformed() {
+        let mut broken = record(2);
+        broken.malformations = vec![Malformation::Unclosed];
+        let spec = Spec {
+            release_date: None,
+            records: vec![record(1), broken],
+        };
+
+        let results =
+            VariationArchiveReader::from_reader(generate(&spec).as_bytes()).collect::<Vec<_>>();
+
+        assert!(results[0].is_ok());
+        assert!(results.iter().skip(1).any(|x| x.is_err()));
+    }
+
+    #[test]
+    fn shuffle_is_deterministic() {
+        let ids = |seed| {
+            let mut spec = Spec {
+                release_date: None,
+                records: (1..=10).map(record).collect(),
+            };
+            shuffle(&mut spec, seed);
+            spec.records
+                .iter()
+                .map(|x| x.variation_id)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(ids(42), ids(42));
+        assert_ne!(ids(42), (1..=10).collect::<Vec<_>>());
+        let mut sorted = ids(42);
+        sorted.sort();
+        assert_eq!(sorted, (1..=10).collect::<Vec<_>>());
+    }
+}
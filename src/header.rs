@@ -0,0 +1,338 @@
+//! Definitions of the INFO fields declared in the VCF header
+//!
+//! Each output format has a table of its fields, from which the `##INFO` lines are generated, so
+//! that every field is declared once with its `Number` and `Type`.
+
+use crate::{ConditionsFormat, FormatConfig, OutputFormat};
+use std::fmt;
+
+/// Definition of an INFO field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InfoField {
+    pub id: &'static str,
+    /// `1`, `A`, `.`, ... as in the VCF specification (`0` for flags)
+    pub number: &'static str,
+    /// `Integer`, `Float`, `Flag`, `Character` or `String`
+    pub r#type: &'static str,
+    pub description: &'static str,
+}
+
+impl fmt::Display for InfoField {
+    /// `##INFO` line
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            r#"##INFO=<ID={},Number={},Type={},Description="{}">"#,
+            self.id, self.number, self.r#type, self.description
+        )
+    }
+}
+
+/// Fields of the togovar format
+pub const INFO_FIELDS_TOGOVAR: [InfoField; 26] = [
+    InfoField {
+        id: "ALLELEID",
+        number: "1",
+        r#type: "Integer",
+        description: "ClinVar Allele ID",
+    },
+    InfoField {
+        id: "CONDITIONS",
+        number: "1",
+        r#type: "String",
+        description: "Entries joined by | in a single value, <DB>:<ID1>/<ID2>/...:<Interpretation1>/<Interpretation2>/...:<SubmissionCount>:<ReviewStatus>|<DB>:... (one entry per RCV and condition database, ordered by RCV accession then by --condition-db, IDs sorted, components percent-encoded)",
+    },
+    InfoField {
+        id: "CLNDN",
+        number: "1",
+        r#type: "String",
+        description: "Condition names of each CONDITIONS entry, in the same order (<Name1>/<Name2>/...|..., spaces replaced by underscores, percent-encoded)",
+    },
+    InfoField {
+        id: "RCV",
+        number: "1",
+        r#type: "String",
+        description: "RCV accession.version of each CONDITIONS entry, in the same order",
+    },
+    InfoField {
+        id: "RS",
+        number: "1",
+        r#type: "Integer",
+        description: "dbSNP rs number (the lowest one if the allele has several)",
+    },
+    InfoField {
+        id: "CLNVI",
+        number: ".",
+        r#type: "String",
+        description: "Variant cross references other than dbSNP and ClinGen as <DB>:<ID>|... (percent-encoded)",
+    },
+    InfoField {
+        id: "GENEINFO",
+        number: "1",
+        r#type: "String",
+        description: "Gene(s) for the variant as <Symbol>:<GeneID>|<Symbol>:<GeneID>|... (ordered by symbol, GeneID is empty if unknown)",
+    },
+    InfoField {
+        id: "MC",
+        number: ".",
+        r#type: "String",
+        description: "Molecular consequence as <SO accession>|<consequence> (deduplicated across transcripts, ordered by SO accession)",
+    },
+    InfoField {
+        id: "CLNHGVS",
+        number: "1",
+        r#type: "String",
+        description: "Genomic HGVS expression on the NC_ accession of the assembly (percent-encoded)",
+    },
+    InfoField {
+        id: "HGVSP",
+        number: "1",
+        r#type: "String",
+        description: "Protein HGVS expression of the MANE Select transcript (percent-encoded)",
+    },
+    InfoField {
+        id: "CLNVC",
+        number: "1",
+        r#type: "String",
+        description: "Variant type (e.g. single_nucleotide_variant, Deletion, copy_number_loss)",
+    },
+    InfoField {
+        id: "FLAGS",
+        number: ".",
+        r#type: "String",
+        description: "Soft issues found while converting the record [condition_id_normalized, submission_count_clamped, review_status_missing, sentinel_classification, conditions_truncated, unknown_classification]",
+    },
+    InfoField {
+        id: "CONDITIONS_UNMAPPED",
+        number: "0",
+        r#type: "Flag",
+        description: "No RCV has a condition from the condition databases, CONDITIONS/ONC use condition names (NA:<Name>:...) by --keep-unmapped-conditions",
+    },
+    InfoField {
+        id: "RCV_TRUNCATED",
+        number: "1",
+        r#type: "Integer",
+        description: "Number of RCVs omitted from CONDITIONS/ONC (and RCV/CLNDN) by --max-rcvs, which keeps those with the most submissions",
+    },
+    InfoField {
+        id: "CLNSIG",
+        number: "1",
+        r#type: "String",
+        description: "Aggregate germline classification for this variation (<Token1>/<Token2>/..., tokens of a controlled vocabulary or other:<Text>)",
+    },
+    InfoField {
+        id: "CLNSIGCONF",
+        number: ".",
+        r#type: "String",
+        description: "Submission counts per germline classification of the RCVs if the aggregate is conflicting, e.g. Pathogenic(3)|Uncertain_significance(2) (RCVs classified as a range or as conflicting are not counted)",
+    },
+    InfoField {
+        id: "CLNREVSTAT",
+        number: "1",
+        r#type: "String",
+        description: "Review status of the aggregate germline classification (spaces replaced by underscores, percent-encoded, e.g. criteria_provided%2C_single_submitter)",
+    },
+    InfoField {
+        id: "ORIGIN",
+        number: "1",
+        r#type: "String",
+        description: "Allele origins reported by submitters, sorted and joined by / (e.g. de_novo/germline; only with --include-scv)",
+    },
+    InfoField {
+        id: "CLNDATELASTEVAL",
+        number: "1",
+        r#type: "String",
+        description: "Most recent date the classifications were last evaluated (YYYY-MM-DD)",
+    },
+    InfoField {
+        id: "DATECREATED",
+        number: "1",
+        r#type: "String",
+        description: "Date the variation record was created (YYYY-MM-DD)",
+    },
+    InfoField {
+        id: "DATELASTUPDATED",
+        number: "1",
+        r#type: "String",
+        description: "Date the variation record was last updated (YYYY-MM-DD)",
+    },
+    InfoField {
+        id: "ONC",
+        number: "1",
+        r#type: "String",
+        description: "<DB>:<ID1>/<ID2>/...:<Oncogenicity1>/<Oncogenicity2>/...:<SubmissionCount>:<ReviewStatus>|<DB>:... (one entry per RCV and condition database, ordered by RCV accession then by --condition-db, IDs sorted)",
+    },
+    InfoField {
+        id: "GERMLINE_SUBMISSIONS",
+        number: "1",
+        r#type: "Integer",
+        description: "Sum of the submission counts of the germline classifications of the RCVs (omitted if 0)",
+    },
+    InfoField {
+        id: "SOMATIC_SUBMISSIONS",
+        number: "1",
+        r#type: "Integer",
+        description: "Sum of the submission counts of the somatic clinical impacts of the RCVs (omitted if 0)",
+    },
+    InfoField {
+        id: "ONC_SUBMISSIONS",
+        number: "1",
+        r#type: "Integer",
+        description: "Sum of the submission counts of the oncogenicity classifications of the RCVs (omitted if 0)",
+    },
+    InfoField {
+        id: "SCV_COUNT",
+        number: "1",
+        r#type: "Integer",
+        description: "Number of submitted records (SCVs) with --include-scv, or the sum of all the submission counts of the RCVs otherwise or if they are not listed (omitted if 0)",
+    },
+];
+
+/// CONDITIONS of [`ConditionsFormat::V2`], replacing that of [`INFO_FIELDS_TOGOVAR`]
+pub const INFO_CONDITIONS_V2: InfoField = InfoField {
+    id: "CONDITIONS",
+    number: ".",
+    r#type: "String",
+    description: "Germline classification of each RCV and condition database as <DB>|<ID1>/<ID2>/...|<Interpretation1>/<Interpretation2>/...|<ReviewStatus>|<SubmissionCount> (ordered by RCV accession then by --condition-db, IDs sorted, components percent-encoded)",
+};
+
+/// SCV of the togovar format with `--include-scv`, after the other fields
+pub const INFO_SCV: InfoField = InfoField {
+    id: "SCV",
+    number: "1",
+    r#type: "String",
+    description: "Submitted records as <SCV>.<Version>:<SubmitterName>:<Classification>|... (ordered by accession, components percent-encoded)",
+};
+
+/// PMIDS of the togovar format with `--include-citations`, after the other fields
+pub const INFO_PMIDS: InfoField = InfoField {
+    id: "PMIDS",
+    number: "1",
+    r#type: "String",
+    description:
+        "PubMed IDs cited by the classifications as <PMID1>|<PMID2>|... (sorted, deduplicated)",
+};
+
+/// SKIP_REASON of the records written by `--rejects`
+pub const INFO_SKIP_REASON: InfoField = InfoField {
+    id: "SKIP_REASON",
+    number: "1",
+    r#type: "String",
+    description: "Reason why the record was skipped (e.g. non_acgt_ref, ref_equals_alt, nonstandard_chromosome, no_medgen_condition, ref_mismatch)",
+};
+
+/// Fields of the ncbi format, as declared in the official ClinVar VCF
+pub const INFO_FIELDS_NCBI: [InfoField; 12] = [
+    InfoField {
+        id: "ALLELEID",
+        number: "1",
+        r#type: "Integer",
+        description: "the ClinVar Allele ID",
+    },
+    InfoField {
+        id: "CLNDISDB",
+        number: ".",
+        r#type: "String",
+        description: "Tag-value pairs of disease database name and identifier, e.g. MedGen:NNNNNN",
+    },
+    InfoField {
+        id: "CLNDN",
+        number: ".",
+        r#type: "String",
+        description: "ClinVar's preferred disease name for the concept specified by disease identifiers in CLNDISDB",
+    },
+    InfoField {
+        id: "CLNHGVS",
+        number: ".",
+        r#type: "String",
+        description: "Top-level (primary assembly, alt, or patch) HGVS expression.",
+    },
+    InfoField {
+        id: "CLNREVSTAT",
+        number: ".",
+        r#type: "String",
+        description: "ClinVar review status for the Variation ID",
+    },
+    InfoField {
+        id: "CLNSIG",
+        number: ".",
+        r#type: "String",
+        description: "Clinical significance for this single variant; multiple values are separated by a vertical bar",
+    },
+    InfoField {
+        id: "CLNSIGCONF",
+        number: ".",
+        r#type: "String",
+        description: "Conflicting clinical significance for this single variant; multiple values are separated by a vertical bar",
+    },
+    InfoField {
+        id: "CLNVC",
+        number: "1",
+        r#type: "String",
+        description: "Variant type",
+    },
+    InfoField {
+        id: "CLNVI",
+        number: ".",
+        r#type: "String",
+        description: "the variant's clinical sources reported as tag-value pairs of database and variant identifier",
+    },
+    InfoField {
+        id: "GENEINFO",
+        number: "1",
+        r#type: "String",
+        description: "Gene(s) for the variant reported as gene symbol:gene id. The gene symbol and id are delimited by a colon (:) and each pair is delimited by a vertical bar (|)",
+    },
+    InfoField {
+        id: "MC",
+        number: ".",
+        r#type: "String",
+        description: "comma separated list of molecular consequence in the form of Sequence Ontology ID|molecular_consequence",
+    },
+    InfoField {
+        id: "RS",
+        number: ".",
+        r#type: "String",
+        description: "dbSNP ID (i.e. rs number)",
+    },
+];
+
+/// Fields of an output format in the order of the header, including the optional ones
+///
+/// # Arguments
+///
+/// * `format`: output format
+///
+/// returns: Vec<InfoField>
+pub fn all_info_fields(format: OutputFormat) -> Vec<InfoField> {
+    match format {
+        OutputFormat::Togovar => INFO_FIELDS_TOGOVAR
+            .iter()
+            .chain([INFO_SCV, INFO_PMIDS].iter())
+            .copied()
+            .collect(),
+        OutputFormat::Ncbi => INFO_FIELDS_NCBI.to_vec(),
+    }
+}
+
+/// Fields written with a `FormatConfig` in the order of the header
+///
+/// # Arguments
+///
+/// * `config`: `FormatConfig` of the output
+///
+/// returns: Vec<InfoField>
+pub fn info_fields(config: &FormatConfig) -> Vec<InfoField> {
+    all_info_fields(config.format)
+        .into_iter()
+        .filter(|x| config.emits(x.id))
+        .map(|x| match config.conditions_format {
+            ConditionsFormat::V2
+                if config.format == OutputFormat::Togovar && x.id == INFO_CONDITIONS_V2.id =>
+            {
+                INFO_CONDITIONS_V2
+            }
+            _ => x,
+        })
+        .collect()
+}
@@ -0,0 +1,217 @@
+//! Read instrumentation and buffer sizing for the input stack
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+use std::rc::Rc;
+
+/// Default capacity of `BufReader` (same as std)
+pub const DEFAULT_BUFFER_SIZE: usize = 8 * 1024;
+
+/// Capacity used for inputs on network mounts or larger than [`LARGE_INPUT_THRESHOLD`]
+pub const LARGE_BUFFER_SIZE: usize = 1024 * 1024;
+
+pub const LARGE_INPUT_THRESHOLD: u64 = 1024 * 1024 * 1024;
+
+const NETWORK_FILESYSTEMS: [&str; 12] = [
+    "nfs",
+    "nfs4",
+    "cifs",
+    "smb3",
+    "smbfs",
+    "afs",
+    "9p",
+    "lustre",
+    "gpfs",
+    "beegfs",
+    "ceph",
+    "fuse.sshfs",
+];
+
+/// Counters of the reads issued to the underlying reader
+#[derive(Debug, Clone, Default)]
+pub struct IoStats {
+    pub reads: u64,
+    pub bytes: u64,
+    /// Number of reads by size, keyed by the smallest power of two not less than the size
+    pub histogram: BTreeMap<u64, u64>,
+}
+
+impl IoStats {
+    fn record(&mut self, n: usize) {
+        self.reads += 1;
+        self.bytes += n as u64;
+        *self
+            .histogram
+            .entry((n as u64).next_power_of_two())
+            .or_default() += 1;
+    }
+}
+
+impl fmt::Display for IoStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Read calls: {}", self.reads)?;
+        writeln!(f, "Bytes read: {}", self.bytes)?;
+        write!(f, "Bytes per read:")?;
+        for (size, count) in &self.histogram {
+            write!(f, "\n  <= {}: {}", human_size(*size), count)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Reader recording every read into shared [`IoStats`]
+pub struct CountingReader<R> {
+    inner: R,
+    stats: Rc<RefCell<IoStats>>,
+}
+
+impl<R: Read> CountingReader<R> {
+    pub fn new(inner: R, stats: Rc<RefCell<IoStats>>) -> Self {
+        CountingReader { inner, stats }
+    }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.stats.borrow_mut().record(n);
+
+        Ok(n)
+    }
+}
+
+/// Pick a buffer capacity for the input
+///
+/// Inputs on a network mount or larger than [`LARGE_INPUT_THRESHOLD`] get [`LARGE_BUFFER_SIZE`],
+/// others [`DEFAULT_BUFFER_SIZE`].
+///
+/// # Arguments
+///
+/// * `path`: path to input
+///
+/// returns: usize
+pub fn default_buffer_size<P: AsRef<Path>>(path: P) -> usize {
+    let path = path.as_ref();
+
+    buffer_size(
+        fs::metadata(path).ok().map(|x| x.len()),
+        is_network_mount(path),
+    )
+}
+
+/// Buffer capacity for an input of `len` bytes (`None` if unknown), on a network mount or not
+fn buffer_size(len: Option<u64>, network: bool) -> usize {
+    if network || len.is_some_and(|x| x > LARGE_INPUT_THRESHOLD) {
+        LARGE_BUFFER_SIZE
+    } else {
+        DEFAULT_BUFFER_SIZE
+    }
+}
+
+/// Whether the path is on a network file system, judged from `/proc/self/mounts`
+///
+/// Always false where the mount table is unavailable.
+pub fn is_network_mount<P: AsRef<Path>>(path: P) -> bool {
+    let path = match fs::canonicalize(path) {
+        Ok(x) => x,
+        Err(_) => return false,
+    };
+    let mounts = match fs::read_to_string("/proc/self/mounts") {
+        Ok(x) => x,
+        Err(_) => return false,
+    };
+
+    on_network_filesystem(&path, &mounts)
+}
+
+/// Whether the innermost mount point containing `path` in the mount table `mounts` (in the format
+/// of `/proc/self/mounts`) has a network file system
+fn on_network_filesystem(path: &Path, mounts: &str) -> bool {
+    mounts
+        .lines()
+        .filter_map(|x| {
+            let mut columns = x.split_whitespace();
+            let mount_point = columns.nth(1)?.replace("\\040", " ");
+            let fs_type = columns.next()?;
+            Some((mount_point, fs_type))
+        })
+        .filter(|(mount_point, _)| path.starts_with(mount_point))
+        .max_by_key(|(mount_point, _)| mount_point.len())
+        .is_some_and(|(_, fs_type)| NETWORK_FILESYSTEMS.contains(&fs_type))
+}
+
+pub(crate) fn human_size(bytes: u64) -> String {
+    match bytes {
+        x if x >= 1024 * 1024 => format!("{} MiB", x / (1024 * 1024)),
+        x if x >= 1024 => format!("{} KiB", x / 1024),
+        x => format!("{} B", x),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+
+    const MOUNTS: &str = "\
+/dev/sda1 / ext4 rw,relatime 0 0
+server:/export /mnt/data nfs4 rw,relatime 0 0
+/dev/sdb1 /mnt/data/local xfs rw,relatime 0 0
+//host/share /mnt/my\\040share cifs rw 0 0
+";
+
+    #[test]
+    fn buffer_size_by_input() {
+        assert_eq!(buffer_size(Some(1024), false), DEFAULT_BUFFER_SIZE);
+        assert_eq!(buffer_size(None, false), DEFAULT_BUFFER_SIZE);
+        assert_eq!(
+            buffer_size(Some(LARGE_INPUT_THRESHOLD), false),
+            DEFAULT_BUFFER_SIZE
+        );
+        assert_eq!(
+            buffer_size(Some(LARGE_INPUT_THRESHOLD + 1), false),
+            LARGE_BUFFER_SIZE
+        );
+        assert_eq!(buffer_size(Some(1024), true), LARGE_BUFFER_SIZE);
+        assert_eq!(buffer_size(None, true), LARGE_BUFFER_SIZE);
+    }
+
+    #[test]
+    fn network_filesystem_of_innermost_mount() {
+        let network = |x: &str| on_network_filesystem(Path::new(x), MOUNTS);
+
+        assert!(!network("/home/user/clinvar.xml.gz"));
+        assert!(network("/mnt/data/clinvar.xml.gz"));
+        assert!(!network("/mnt/data/local/clinvar.xml.gz"));
+        assert!(!network("/mnt/database/clinvar.xml.gz"));
+        assert!(network("/mnt/my share/clinvar.xml.gz"));
+        assert!(!on_network_filesystem(Path::new("/mnt/data/x"), ""));
+    }
+
+    #[test]
+    fn default_buffer_size_by_file_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let small = dir.path().join("small.xml");
+        fs::write(&small, "<ClinVarVariationRelease/>").unwrap();
+        // sparse, so that no disk space is used
+        let large = dir.path().join("large.xml");
+        File::create(&large)
+            .unwrap()
+            .set_len(LARGE_INPUT_THRESHOLD + 1)
+            .unwrap();
+
+        if !is_network_mount(dir.path()) {
+            assert_eq!(default_buffer_size(&small), DEFAULT_BUFFER_SIZE);
+        }
+        assert_eq!(default_buffer_size(&large), LARGE_BUFFER_SIZE);
+        assert_eq!(
+            default_buffer_size(dir.path().join("missing.xml")),
+            DEFAULT_BUFFER_SIZE
+        );
+    }
+}
@@ -0,0 +1,183 @@
+//! JSON Lines output, one [`JsonRecord`] per emitted allele
+//!
+//! Field names are part of the public interface and must not be changed once released. Missing
+//! values are written as `null` and missing lists as `[]`, so that every line has all fields.
+
+use crate::vcf::{
+    extract_conditions, extract_date_last_evaluated, extract_genomic_hgvs, extract_oncogenicity,
+    extract_origin, extract_pmids, extract_protein_hgvs, extract_review_status, extract_rs,
+    extract_significance, extract_variant_type, ConditionEntry,
+};
+use crate::{
+    normalize_date, Assembly, Diagnostics, FormatConfig, Scv, VariationArchive, VcfRecord,
+};
+use serde::Serialize;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JsonRecord {
+    pub chrom: String,
+    pub pos: u64,
+    #[serde(rename = "ref")]
+    pub reference: String,
+    pub alt: String,
+    pub variation_id: u64,
+    pub allele_id: u64,
+    /// Variant type, as in CLNVC
+    pub variant_type: Option<String>,
+    /// dbSNP rs number (the lowest one if the allele has several)
+    pub rs: Option<u64>,
+    /// Ordered by symbol
+    pub genes: Vec<JsonGene>,
+    /// Deduplicated across transcripts, ordered by SO accession
+    pub molecular_consequences: Vec<JsonConsequence>,
+    /// Genomic HGVS expression on the NC_ accession of the assembly
+    pub hgvs_g: Option<String>,
+    /// Protein HGVS expression of the MANE Select transcript
+    pub hgvs_p: Option<String>,
+    /// Germline classifications of the RCVs, as in CONDITIONS
+    pub conditions: Vec<ConditionEntry>,
+    /// Oncogenicity classifications of the RCVs, as in ONC
+    pub oncogenicity: Vec<ConditionEntry>,
+    /// RCVs were omitted by `max_rcvs`
+    pub rcv_truncated: bool,
+    /// No RCV has a condition from the condition databases
+    pub conditions_unmapped: bool,
+    /// Aggregate germline classification, as in CLNSIG
+    pub clinical_significance: Option<String>,
+    /// Review status of the aggregate germline classification
+    pub review_status: Option<String>,
+    /// As in the FILTER column (`PASS`, `conflicting` or `no_assertion`)
+    pub filter: &'static str,
+    /// Allele origins reported by submitters, sorted, only with `include_scv`
+    pub origins: Vec<String>,
+    /// `YYYY-MM-DD`
+    pub date_last_evaluated: Option<String>,
+    /// `YYYY-MM-DD`
+    pub date_created: Option<String>,
+    /// `YYYY-MM-DD`
+    pub date_last_updated: Option<String>,
+    /// Codes of the soft issues found while converting the record
+    pub flags: Vec<&'static str>,
+    /// `KEY=VALUE` INFO fields copied from other VCFs
+    pub annotations: Vec<String>,
+    /// Submitted records ordered by accession, only with `include_scv`
+    pub scvs: Vec<Scv>,
+    /// PubMed IDs, as in PMIDS, only with `include_citations`
+    pub pmids: Vec<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct JsonGene {
+    pub symbol: String,
+    pub id: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct JsonConsequence {
+    /// SO accession, e.g. `SO:0001583`
+    pub id: String,
+    /// e.g. `missense variant`
+    pub name: String,
+}
+
+impl JsonRecord {
+    /// Build the record of a converted variant
+    ///
+    /// # Arguments
+    ///
+    /// * `archive`: `VariationArchive`
+    /// * `record`: the VCF record of `archive`, whose coordinates and flags are taken
+    /// * `assembly`: `Assembly`
+    /// * `config`: `FormatConfig` the VCF record is converted with
+    ///
+    /// returns: Option<JsonRecord>
+    ///          `None` if `archive` has no classified `SimpleAllele`
+    pub fn new(
+        archive: &VariationArchive,
+        record: &VcfRecord,
+        assembly: Assembly,
+        config: &FormatConfig,
+    ) -> Option<Self> {
+        let classified = archive.classified_record.as_ref()?;
+        let allele = classified.simple_allele.as_ref()?;
+
+        let mut genes = allele
+            .gene_list
+            .iter()
+            .flat_map(|x| x.gene.iter())
+            .filter_map(|x| {
+                x.symbol.as_ref().map(|symbol| JsonGene {
+                    symbol: symbol.trim().to_string(),
+                    id: x.gene_id,
+                })
+            })
+            .collect::<Vec<JsonGene>>();
+        genes.sort();
+        genes.dedup();
+
+        let mut molecular_consequences = allele
+            .hgvs_list
+            .iter()
+            .flat_map(|x| x.hgvs.iter())
+            .flat_map(|x| x.molecular_consequence.iter())
+            .map(|x| JsonConsequence {
+                id: x.id.trim().to_string(),
+                name: x.r#type.trim().to_string(),
+            })
+            .collect::<Vec<JsonConsequence>>();
+        molecular_consequences.sort();
+        molecular_consequences.dedup();
+
+        let mut diagnostics = Diagnostics::default();
+        let conditions = extract_conditions(classified, config, &mut diagnostics);
+        let oncogenicity = extract_oncogenicity(classified, config, &mut diagnostics);
+
+        Some(JsonRecord {
+            chrom: record.chrom.clone(),
+            pos: record.pos,
+            reference: record.reference.clone(),
+            alt: record.alternate.clone(),
+            variation_id: archive.variation_id,
+            allele_id: allele.allele_id,
+            variant_type: extract_variant_type(allele),
+            rs: extract_rs(allele),
+            genes,
+            molecular_consequences,
+            hgvs_g: extract_genomic_hgvs(allele, assembly),
+            hgvs_p: extract_protein_hgvs(allele),
+            rcv_truncated: conditions.omitted != 0 || oncogenicity.omitted != 0,
+            conditions_unmapped: conditions.mapped == 0 && oncogenicity.mapped == 0,
+            conditions: conditions.conditions,
+            oncogenicity: oncogenicity.conditions,
+            clinical_significance: extract_significance(classified, &mut diagnostics),
+            review_status: extract_review_status(classified),
+            filter: record.filter.id(),
+            origins: extract_origin(classified)
+                .map(|x| x.split('/').map(|x| x.to_string()).collect())
+                .unwrap_or_default(),
+            date_last_evaluated: extract_date_last_evaluated(classified),
+            date_created: archive
+                .date_created
+                .as_deref()
+                .and_then(normalize_date)
+                .map(|x| x.to_string()),
+            date_last_updated: archive
+                .date_last_updated
+                .as_deref()
+                .and_then(normalize_date)
+                .map(|x| x.to_string()),
+            flags: record.flags.iter().map(|x| x.code()).collect(),
+            annotations: Vec::new(),
+            scvs: config
+                .include_scv
+                .then(|| archive.scvs.clone())
+                .flatten()
+                .unwrap_or_default(),
+            pmids: if config.include_citations {
+                extract_pmids(archive)
+            } else {
+                Vec::new()
+            },
+        })
+    }
+}
@@ -1,25 +1,85 @@
+pub mod annotate;
+pub mod bgzf;
+pub mod converter;
+pub mod diff;
+pub mod duplicates;
+pub mod error;
+pub mod faidx;
+pub mod fixture_gen;
+pub mod header;
+pub mod io_stats;
+pub mod json;
+pub mod logger;
+pub mod normalize;
+pub mod progress;
+pub mod reader;
+pub mod schema;
+pub mod sort;
+pub mod tabix;
+pub mod tsv;
+pub mod vcf;
+
+pub use converter::{ChrStyle, Converter};
+pub use error::{ClinvarError, Stage};
+pub use reader::VariationArchiveReader;
+pub use vcf::{
+    to_vcf_lines, to_vcf_records, ConditionsFormat, Diagnostics, FormatConfig, OutputFormat,
+    QcFlag, RcvEntries, RejectedRecord, VcfRecord,
+};
+
 use once_cell::sync::Lazy;
 use regex::Regex;
-use serde::Deserialize;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use strum::{AsRefStr, EnumString, VariantNames};
+
+// The logging macros forward to the `log` crate; `reason = ...;` logs with the code of a
+// `SkipReason` as target, whose events `logger` writes up to a limit per reason.
+#[doc(hidden)]
+pub use log as __log;
+
+#[macro_export]
+macro_rules! debug {
+    (reason = $reason:expr; $($arg:tt)+) => {
+        $crate::__log::debug!(target: $crate::SkipReason::code(&$reason), $($arg)+)
+    };
+    ($($arg:tt)+) => {
+        $crate::__log::debug!($($arg)+)
+    };
+}
+
+#[macro_export]
+macro_rules! info {
+    (reason = $reason:expr; $($arg:tt)+) => {
+        $crate::__log::info!(target: $crate::SkipReason::code(&$reason), $($arg)+)
+    };
+    ($($arg:tt)+) => {
+        $crate::__log::info!($($arg)+)
+    };
+}
 
 #[macro_export]
 macro_rules! warn {
-    () => {
-        eprint!("\n")
+    (reason = $reason:expr; $($arg:tt)+) => {
+        $crate::__log::warn!(target: $crate::SkipReason::code(&$reason), $($arg)+)
+    };
+    ($($arg:tt)+) => {
+        $crate::__log::warn!($($arg)+)
     };
-    ($($arg:tt)*) => {{
-        eprint!("[\x1b[33mWARN\x1b[0m] {}\n", format!($($arg)*))
-    }};
 }
 
 #[macro_export]
 macro_rules! error {
-    () => {
-        eprint!("\n")
+    (reason = $reason:expr; $($arg:tt)+) => {
+        $crate::__log::error!(target: $crate::SkipReason::code(&$reason), $($arg)+)
+    };
+    ($($arg:tt)+) => {
+        $crate::__log::error!($($arg)+)
     };
-    ($($arg:tt)*) => {{
-        eprint!("[\x1b[31mERROR\x1b[0m] {}\n", format!($($arg)*))
-    }};
 }
 
 #[derive(Debug, Deserialize)]
@@ -28,10 +88,40 @@ pub struct VariationArchive {
     pub variation_id: u64,
     #[serde(rename = "@Accession")]
     pub accession: String,
+    #[serde(rename = "@DateCreated")]
+    pub date_created: Option<String>,
+    #[serde(rename = "@DateLastUpdated")]
+    pub date_last_updated: Option<String>,
+    #[serde(rename = "@RecordStatus")]
+    pub record_status: Option<String>,
+    /// `RecordStatus` given as an element instead of the attribute
+    #[serde(rename = "RecordStatus")]
+    pub record_status_element: Option<String>,
+    #[serde(rename = "Species")]
+    pub species: Option<Species>,
     #[serde(rename = "ClassifiedRecord")]
     pub classified_record: Option<ClassifiedRecord>,
     #[serde(rename = "IncludedRecord")]
     pub included_record: Option<IncludedRecord>,
+    /// Submitted records of `ClinicalAssertionList`, which is only read with
+    /// [`VariationArchiveReader::include_scv`] as it makes up most of the element
+    #[serde(skip)]
+    pub scvs: Option<Vec<Scv>>,
+}
+
+impl VariationArchive {
+    /// `RecordStatus` of the attribute or the element (e.g. current, removed, replaced)
+    pub fn record_status(&self) -> Option<&str> {
+        self.record_status
+            .as_deref()
+            .or(self.record_status_element.as_deref())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Species {
+    #[serde(rename = "$text")]
+    pub text: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -44,6 +134,11 @@ pub struct ClassifiedRecord {
     pub genotype: Option<Genotype>,
     #[serde(rename = "RCVList")]
     pub rcv_list: RCVList,
+    #[serde(rename = "Classifications")]
+    pub classifications: Option<Classifications>,
+    /// Origins of the submitted records, read with [`VariationArchive::scvs`] (`None` otherwise)
+    #[serde(skip)]
+    pub clinical_assertion_list: Option<ClinicalAssertionList>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -76,8 +171,87 @@ pub struct SimpleAllele {
     pub allele_id: u64,
     #[serde(rename = "@VariationID")]
     pub variation_id: u64,
+    /// e.g. `single nucleotide variant`, `Deletion`, `copy number loss`
+    #[serde(rename = "VariantType")]
+    pub variant_type: Option<String>,
+    #[serde(rename = "GeneList")]
+    pub gene_list: Option<GeneList>,
     #[serde(rename = "Location")]
     pub location: Option<Location>,
+    #[serde(rename = "HGVSlist")]
+    pub hgvs_list: Option<HGVSList>,
+    #[serde(rename = "XRefList")]
+    pub xref_list: Option<XRefList>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GeneList {
+    #[serde(default, rename = "Gene")]
+    pub gene: Vec<Gene>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Gene {
+    #[serde(rename = "@Symbol")]
+    pub symbol: Option<String>,
+    #[serde(rename = "@GeneID")]
+    pub gene_id: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HGVSList {
+    #[serde(default, rename = "HGVS")]
+    pub hgvs: Vec<HGVS>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HGVS {
+    #[serde(rename = "@Type")]
+    pub r#type: Option<String>,
+    #[serde(rename = "@Assembly")]
+    pub assembly: Option<String>,
+    #[serde(rename = "NucleotideExpression")]
+    pub nucleotide_expression: Option<Expression>,
+    #[serde(rename = "ProteinExpression")]
+    pub protein_expression: Option<Expression>,
+    #[serde(default, rename = "MolecularConsequence")]
+    pub molecular_consequence: Vec<MolecularConsequence>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Expression {
+    #[serde(rename = "@Assembly")]
+    pub assembly: Option<String>,
+    #[serde(rename = "@MANESelect")]
+    pub mane_select: Option<String>,
+    #[serde(rename = "Expression")]
+    pub expression: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MolecularConsequence {
+    #[serde(rename = "@ID")]
+    pub id: String,
+    #[serde(rename = "@Type")]
+    pub r#type: String,
+    #[serde(rename = "@DB")]
+    pub db: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct XRefList {
+    #[serde(default, rename = "XRef")]
+    pub xref: Vec<XRef>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct XRef {
+    #[serde(rename = "@DB")]
+    pub db: String,
+    #[serde(rename = "@ID")]
+    pub id: String,
+    #[serde(rename = "@Type")]
+    pub r#type: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -98,6 +272,61 @@ pub struct SequenceLocation {
     pub reference: Option<String>,
     #[serde(rename = "@alternateAlleleVCF")]
     pub alternate: Option<String>,
+    #[serde(rename = "@start")]
+    pub start: Option<u64>,
+    #[serde(rename = "@stop")]
+    pub stop: Option<u64>,
+    #[serde(rename = "@referenceAllele")]
+    pub reference_allele: Option<String>,
+    #[serde(rename = "@alternateAllele")]
+    pub alternate_allele: Option<String>,
+    #[serde(rename = "@variantLength")]
+    pub variant_length: Option<u64>,
+}
+
+impl SequenceLocation {
+    /// VCF representation built from `@start`/`@stop` and `@referenceAllele`/`@alternateAllele`
+    ///
+    /// Only possible when both alleles are given and non-empty (substitutions and deletion-
+    /// insertions), so that no anchor base has to be fetched from the reference; POS is `@start`
+    /// as both are 1-based. `None` for insertions and deletions, and when `@referenceAllele` does
+    /// not span `@start`..=`@stop`.
+    ///
+    /// returns: Option<(u64, &String, &String)>
+    ///          (POS, REF, ALT)
+    pub fn coordinate_alleles(&self) -> Option<(u64, &String, &String)> {
+        let start = self.start?;
+        let reference = self.reference_allele.as_ref().filter(|x| is_allele(x))?;
+        let alternate = self.alternate_allele.as_ref().filter(|x| is_allele(x))?;
+
+        let length = match self.stop {
+            Some(stop) if stop >= start => stop - start + 1,
+            Some(_) => return None,
+            None => self.variant_length.unwrap_or(reference.len() as u64),
+        };
+        if reference.len() as u64 != length {
+            return None;
+        }
+
+        Some((start, reference, alternate))
+    }
+
+    /// (POS, REF, ALT) by the VCF attributes, or by the coordinates if any of them is missing
+    pub fn vcf_alleles(&self) -> Option<(u64, &String, &String)> {
+        match (self.pos, &self.reference, &self.alternate) {
+            (Some(p), Some(r), Some(a)) => Some((p, r, a)),
+            _ => self.coordinate_alleles(),
+        }
+    }
+
+    /// Whether there are coordinates without any usable alleles
+    pub fn is_coordinate_only(&self) -> bool {
+        self.start.is_some() && self.coordinate_alleles().is_none()
+    }
+}
+
+fn is_allele(text: &str) -> bool {
+    !text.is_empty() && text != "-"
 }
 
 #[derive(Debug, Deserialize)]
@@ -106,6 +335,20 @@ pub struct RCVList {
     pub rcv_accession: Vec<RCVAccession>,
 }
 
+impl RCVList {
+    /// RCVs in deterministic output order (by accession, then version), independent of the XML
+    /// document order
+    pub fn sorted(&self) -> Vec<&RCVAccession> {
+        let mut rcvs = self.rcv_accession.iter().collect::<Vec<_>>();
+        rcvs.sort_by(|a, b| {
+            a.accession
+                .cmp(&b.accession)
+                .then(a.version.cmp(&b.version))
+        });
+        rcvs
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct RCVAccession {
     #[serde(rename = "@Title")]
@@ -120,6 +363,50 @@ pub struct RCVAccession {
     pub rcv_classifications: RCVClassifications,
 }
 
+#[derive(Debug, Default)]
+pub struct ClinicalAssertionList {
+    pub clinical_assertion: Vec<ClinicalAssertion>,
+}
+
+#[derive(Debug)]
+pub struct ClinicalAssertion {
+    pub id: Option<u64>,
+    pub observed_in_list: Option<ObservedInList>,
+}
+
+/// Submitted record (SCV) of a `ClinicalAssertion`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Scv {
+    /// `<accession>.<version>`
+    pub accession: String,
+    pub submitter_name: Option<String>,
+    /// Germline classification, somatic clinical impact or oncogenicity classification as
+    /// submitted
+    pub classification: Option<String>,
+    /// `YYYY-MM-DD`
+    pub date_last_evaluated: Option<String>,
+    /// PubMed IDs cited by the submitter, sorted
+    pub pmids: Vec<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ObservedInList {
+    #[serde(default, rename = "ObservedIn")]
+    pub observed_in: Vec<ObservedIn>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ObservedIn {
+    #[serde(rename = "Sample")]
+    pub sample: Option<Sample>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Sample {
+    #[serde(rename = "Origin")]
+    pub origin: Option<String>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct ClassifiedConditionList {
     #[serde(default, rename = "ClassifiedCondition")]
@@ -148,87 +435,1575 @@ pub struct RCVClassifications {
 
 #[derive(Debug, Deserialize)]
 pub struct GermlineClassification {
+    #[serde(rename = "ReviewStatus")]
+    pub review_status: Option<String>,
     #[serde(rename = "Description")]
     pub description: Description,
+    #[serde(default, rename = "Citation")]
+    pub citation: Vec<Citation>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct SomaticClinicalImpact {
+    #[serde(rename = "ReviewStatus")]
+    pub review_status: Option<String>,
     #[serde(rename = "Description")]
     pub description: Description,
+    #[serde(default, rename = "Citation")]
+    pub citation: Vec<Citation>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct OncogenicityClassification {
+    #[serde(rename = "ReviewStatus")]
+    pub review_status: Option<String>,
     #[serde(rename = "Description")]
     pub description: Description,
+    #[serde(default, rename = "Citation")]
+    pub citation: Vec<Citation>,
+}
+
+/// Common accessors of the RCV-level classifications
+pub trait Classification {
+    fn review_status(&self) -> Option<&str>;
+    fn description(&self) -> &Description;
+    fn citations(&self) -> &[Citation];
+}
+
+macro_rules! impl_classification {
+    ($($t:ty),*) => {
+        $(
+            impl Classification for $t {
+                fn review_status(&self) -> Option<&str> {
+                    self.review_status.as_deref()
+                }
+
+                fn description(&self) -> &Description {
+                    &self.description
+                }
+
+                fn citations(&self) -> &[Citation] {
+                    &self.citation
+                }
+            }
+        )*
+    };
+}
+
+impl_classification!(
+    GermlineClassification,
+    SomaticClinicalImpact,
+    OncogenicityClassification
+);
+
+/// `Source` of the `ID` of a `Citation` for PubMed
+const CITATION_SOURCE_PUBMED: &str = "PubMed";
+
+#[derive(Debug, Deserialize)]
+pub struct Citation {
+    #[serde(default, rename = "ID")]
+    pub id: Vec<CitationId>,
+}
+
+impl Citation {
+    /// PubMed IDs, ignoring the other sources (e.g. DOI, BookShelf) and malformed IDs
+    pub fn pmids(&self) -> impl Iterator<Item = u64> + '_ {
+        self.id
+            .iter()
+            .filter(|x| x.source.eq_ignore_ascii_case(CITATION_SOURCE_PUBMED))
+            .filter_map(|x| x.text.trim().parse().ok())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CitationId {
+    #[serde(rename = "@Source")]
+    pub source: String,
+    #[serde(default, rename = "$text")]
+    pub text: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Classifications {
+    #[serde(rename = "GermlineClassification")]
+    pub germline_classification: Option<AggregateGermlineClassification>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AggregateGermlineClassification {
+    #[serde(rename = "ReviewStatus")]
+    pub review_status: Option<String>,
+    #[serde(rename = "Description")]
+    pub description: AggregateDescription,
+    #[serde(default, rename = "Citation")]
+    pub citation: Vec<Citation>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AggregateDescription {
+    #[serde(rename = "@DateLastEvaluated")]
+    pub date_last_evaluated: Option<String>,
+    #[serde(rename = "$text")]
+    pub text: String,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct Description {
     #[serde(rename = "@SubmissionCount")]
     pub submission_count: i32,
+    #[serde(rename = "@DateLastEvaluated")]
+    pub date_last_evaluated: Option<String>,
     #[serde(rename = "$text")]
     pub text: String,
 }
 
+/// Reason why a record was not emitted
+///
+/// The codes returned by [`SkipReason::code`] (and used for serialization) are part of the
+/// public interface and must not be changed once released.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum SkipReason {
+    XmlError,
+    DeserializationError,
+    SchemaViolation,
+    NoClassifiedRecord,
+    NoSimpleAllele,
+    NoLocation,
+    IncompleteLocation,
+    CoordinateOnly,
+    NonstandardChromosome,
+    UnknownContig,
+    NonAcgtRef,
+    NonAcgtAlt,
+    RefContainsN,
+    AltContainsN,
+    RefEqualsAlt,
+    RefMismatch,
+    NoMedgenCondition,
+    Unclassified,
+    RecordStatusFiltered,
+    SpeciesFiltered,
+    SignificanceFiltered,
+    RegionFiltered,
+    GeneFiltered,
+}
+
+impl SkipReason {
+    pub const ALL: [SkipReason; 23] = [
+        SkipReason::XmlError,
+        SkipReason::DeserializationError,
+        SkipReason::SchemaViolation,
+        SkipReason::NoClassifiedRecord,
+        SkipReason::NoSimpleAllele,
+        SkipReason::NoLocation,
+        SkipReason::IncompleteLocation,
+        SkipReason::CoordinateOnly,
+        SkipReason::NonstandardChromosome,
+        SkipReason::UnknownContig,
+        SkipReason::NonAcgtRef,
+        SkipReason::NonAcgtAlt,
+        SkipReason::RefContainsN,
+        SkipReason::AltContainsN,
+        SkipReason::RefEqualsAlt,
+        SkipReason::RefMismatch,
+        SkipReason::NoMedgenCondition,
+        SkipReason::Unclassified,
+        SkipReason::RecordStatusFiltered,
+        SkipReason::SpeciesFiltered,
+        SkipReason::SignificanceFiltered,
+        SkipReason::RegionFiltered,
+        SkipReason::GeneFiltered,
+    ];
+
+    /// Stable machine-readable code
+    pub fn code(&self) -> &'static str {
+        match self {
+            SkipReason::XmlError => "xml_error",
+            SkipReason::DeserializationError => "deserialization_error",
+            SkipReason::SchemaViolation => "schema_violation",
+            SkipReason::NoClassifiedRecord => "no_classified_record",
+            SkipReason::NoSimpleAllele => "no_simple_allele",
+            SkipReason::NoLocation => "no_location",
+            SkipReason::IncompleteLocation => "incomplete_location",
+            SkipReason::CoordinateOnly => "coordinate_only",
+            SkipReason::NonstandardChromosome => "nonstandard_chromosome",
+            SkipReason::UnknownContig => "unknown_contig",
+            SkipReason::NonAcgtRef => "non_acgt_ref",
+            SkipReason::NonAcgtAlt => "non_acgt_alt",
+            SkipReason::RefContainsN => "ref_contains_n",
+            SkipReason::AltContainsN => "alt_contains_n",
+            SkipReason::RefEqualsAlt => "ref_equals_alt",
+            SkipReason::RefMismatch => "ref_mismatch",
+            SkipReason::NoMedgenCondition => "no_medgen_condition",
+            SkipReason::Unclassified => "unclassified",
+            SkipReason::RecordStatusFiltered => "record_status_filtered",
+            SkipReason::SpeciesFiltered => "species_filtered",
+            SkipReason::SignificanceFiltered => "significance_filtered",
+            SkipReason::RegionFiltered => "region_filtered",
+            SkipReason::GeneFiltered => "gene_filtered",
+        }
+    }
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            SkipReason::XmlError => "Malformed XML",
+            SkipReason::DeserializationError => "Failed to deserialize VariationArchive",
+            SkipReason::SchemaViolation => "Unknown element or attribute",
+            SkipReason::NoClassifiedRecord => "ClassifiedRecord not found",
+            SkipReason::NoSimpleAllele => "SimpleAllele not found",
+            SkipReason::NoLocation => "No SequenceLocation for assembly",
+            SkipReason::IncompleteLocation => "No VCF representation",
+            SkipReason::CoordinateOnly => "No VCF representation, coordinate-only",
+            SkipReason::NonstandardChromosome => "Skip chromosome",
+            SkipReason::UnknownContig => "Skip contig not found in the reference",
+            SkipReason::NonAcgtRef => "Skip non-ACGT reference",
+            SkipReason::NonAcgtAlt => "Skip non-ACGT alternate",
+            SkipReason::RefContainsN => "Skip reference containing N",
+            SkipReason::AltContainsN => "Skip alternate containing N",
+            SkipReason::RefEqualsAlt => "Skip ref == alt",
+            SkipReason::RefMismatch => "Skip REF mismatching the reference",
+            SkipReason::NoMedgenCondition => {
+                "No ClassifiedCondition associated with the condition databases"
+            }
+            SkipReason::Unclassified => "Skip unclassified record",
+            SkipReason::RecordStatusFiltered => "Skip by record status",
+            SkipReason::SpeciesFiltered => "Skip non-human record",
+            SkipReason::SignificanceFiltered => "Skip by clinical significance",
+            SkipReason::RegionFiltered => "Skip by region",
+            SkipReason::GeneFiltered => "Skip by gene",
+        };
+
+        f.write_str(text)
+    }
+}
+
+impl Serialize for SkipReason {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.code())
+    }
+}
+
+impl<'de> Deserialize<'de> for SkipReason {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let code = String::deserialize(deserializer)?;
+        SkipReason::ALL
+            .into_iter()
+            .find(|x| x.code() == code)
+            .ok_or_else(|| de::Error::custom(format!("Unknown skip reason: {}", code)))
+    }
+}
+
+/// Counters of a conversion
+///
+/// Serialized with the skip reasons keyed by [`SkipReason::code`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Stats {
+    /// `VariationArchive` elements read, including skipped ones
+    pub records: u64,
+    /// VCF lines written
+    pub written: u64,
+    /// VCF lines written with RCVs omitted by `max_rcvs`
+    #[serde(default)]
+    pub truncated: u64,
+    /// Records not emitted by reason
+    pub skipped: BTreeMap<SkipReason, u64>,
+}
+
+impl Stats {
+    pub fn skip(&mut self, reason: SkipReason) {
+        *self.skipped.entry(reason).or_default() += 1;
+    }
+
+    /// Number of records skipped for any reason
+    pub fn skipped_total(&self) -> u64 {
+        self.skipped.values().sum()
+    }
+
+    /// Number of records skipped for malformed XML or failing to deserialize
+    pub fn errors(&self) -> u64 {
+        [SkipReason::XmlError, SkipReason::DeserializationError]
+            .iter()
+            .filter_map(|x| self.skipped.get(x))
+            .sum()
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Records read: {}", self.records)?;
+        writeln!(f, "VCF lines written: {}", self.written)?;
+        if self.truncated != 0 {
+            writeln!(f, "Records with truncated RCVs: {}", self.truncated)?;
+        }
+        write!(f, "Skipped records: {}", self.skipped_total())?;
+        for (reason, count) in &self.skipped {
+            write!(f, "\n  {} ({}): {}", reason, reason.code(), count)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Classification descriptions that do not represent a clinical significance, with the token
+/// emitted in their place
+pub const SENTINEL_CLASSIFICATIONS: [(&str, &str); 3] = [
+    (
+        "no classifications from unflagged records",
+        "no_classification_unflagged",
+    ),
+    (
+        "no classification for the single variant",
+        "no_classification_single_variant",
+    ),
+    (
+        "no interpretation for the single variant",
+        "no_classification_single_variant",
+    ),
+];
+
+/// Map a sentinel classification description to its token
+///
+/// # Arguments
+///
+/// * `text`: Text of `Description`
+///
+/// returns: Option<&str>
+///          `None` if `text` is a real classification
+pub fn sentinel_classification(text: &str) -> Option<&'static str> {
+    let text = text.trim();
+
+    SENTINEL_CLASSIFICATIONS
+        .iter()
+        .find(|(x, _)| x.eq_ignore_ascii_case(text))
+        .map(|(_, token)| *token)
+}
+
+/// Compound phrases which ClinVar uses as a single classification, with their token
+///
+/// These are matched before a description is split on `/` so that e.g. `Benign/Likely benign`
+/// stays one assertion (a range) instead of becoming two.
+pub const COMPOUND_CLASSIFICATIONS: [(&str, &str); 14] = [
+    (
+        "conflicting data from submitters",
+        "conflicting_data_from_submitters",
+    ),
+    (
+        "conflicting interpretations of pathogenicity",
+        "conflicting_classifications_of_pathogenicity",
+    ),
+    (
+        "conflicting classifications of pathogenicity",
+        "conflicting_classifications_of_pathogenicity",
+    ),
+    (
+        "pathogenic/likely pathogenic/pathogenic, low penetrance",
+        "pathogenic_or_likely_pathogenic_or_pathogenic_low_penetrance",
+    ),
+    (
+        "pathogenic/likely pathogenic/likely risk allele",
+        "pathogenic_or_likely_pathogenic_or_likely_risk_allele",
+    ),
+    (
+        "pathogenic/likely pathogenic",
+        "pathogenic_or_likely_pathogenic",
+    ),
+    (
+        "pathogenic/pathogenic, low penetrance",
+        "pathogenic_or_pathogenic_low_penetrance",
+    ),
+    (
+        "pathogenic/likely risk allele",
+        "pathogenic_or_likely_risk_allele",
+    ),
+    (
+        "likely pathogenic/likely pathogenic, low penetrance",
+        "likely_pathogenic_or_likely_pathogenic_low_penetrance",
+    ),
+    (
+        "likely pathogenic/likely risk allele",
+        "likely_pathogenic_or_likely_risk_allele",
+    ),
+    ("pathogenic, low penetrance", "pathogenic_low_penetrance"),
+    (
+        "likely pathogenic, low penetrance",
+        "likely_pathogenic_low_penetrance",
+    ),
+    ("benign/likely benign", "benign_or_likely_benign"),
+    (
+        "uncertain significance/uncertain risk allele",
+        "uncertain_significance_or_uncertain_risk_allele",
+    ),
+];
+
+/// Map a compound classification phrase to its token, ignoring case and runs of whitespace
+///
+/// # Arguments
+///
+/// * `text`: classification phrase
+///
+/// returns: Option<&str>
+///          `None` if `text` is not a known compound phrase
+pub fn compound_classification(text: &str) -> Option<&'static str> {
+    let text = text.split_whitespace().collect::<Vec<&str>>().join(" ");
+
+    COMPOUND_CLASSIFICATIONS
+        .iter()
+        .find(|(x, _)| x.eq_ignore_ascii_case(&text))
+        .map(|(_, token)| *token)
+}
+
+/// Split a classification description into tokens
+///
+/// The description is split on `;` into classifications. Within each, runs of `/`-separated terms
+/// forming a compound phrase (see [`COMPOUND_CLASSIFICATIONS`]) become one token, and any other
+/// term becomes its own token, keeping modifiers attached to it (e.g. `, low penetrance`). Tokens
+/// are lowercased with commas dropped and whitespace replaced by underscores.
+///
+/// # Arguments
+///
+/// * `text`: classification description
+///
+/// returns: Vec<String>
+///
+/// e.g. `Pathogenic/Likely pathogenic, low penetrance; risk factor` to
+/// `["pathogenic", "likely_pathogenic_low_penetrance", "risk_factor"]`
+pub fn normalize_classification(text: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    for part in text.split(';') {
+        let terms = part
+            .split('/')
+            .map(|x| x.trim())
+            .filter(|x| !x.is_empty())
+            .collect::<Vec<&str>>();
+
+        let mut i = 0;
+        while i < terms.len() {
+            match (i + 1..=terms.len())
+                .rev()
+                .find_map(|j| Some((j, compound_classification(&terms[i..j].join("/"))?)))
+            {
+                Some((j, token)) => {
+                    tokens.push(token.to_string());
+                    i = j;
+                }
+                None => {
+                    tokens.push(normalize_term(terms[i]));
+                    i += 1;
+                }
+            }
+        }
+    }
+
+    tokens
+}
+
+fn normalize_term(term: &str) -> String {
+    term.replace(',', " ")
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join("_")
+        .to_lowercase()
+}
+
+/// Controlled vocabulary of classification tokens
+///
+/// ACMG terms and their compound forms (see [`COMPOUND_CLASSIFICATIONS`]), oncogenicity terms and
+/// the other terms ClinVar uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ClinicalSignificance {
+    Pathogenic,
+    LikelyPathogenic,
+    UncertainSignificance,
+    LikelyBenign,
+    Benign,
+    PathogenicLowPenetrance,
+    LikelyPathogenicLowPenetrance,
+    PathogenicOrLikelyPathogenic,
+    PathogenicOrLikelyPathogenicOrPathogenicLowPenetrance,
+    PathogenicOrLikelyPathogenicOrLikelyRiskAllele,
+    PathogenicOrPathogenicLowPenetrance,
+    PathogenicOrLikelyRiskAllele,
+    LikelyPathogenicOrLikelyPathogenicLowPenetrance,
+    LikelyPathogenicOrLikelyRiskAllele,
+    BenignOrLikelyBenign,
+    UncertainSignificanceOrUncertainRiskAllele,
+    EstablishedRiskAllele,
+    LikelyRiskAllele,
+    UncertainRiskAllele,
+    Oncogenic,
+    LikelyOncogenic,
+    DrugResponse,
+    RiskFactor,
+    Association,
+    AssociationNotFound,
+    Protective,
+    Affects,
+    ConfersSensitivity,
+    Other,
+    NotProvided,
+    Conflicting,
+    ConflictingDataFromSubmitters,
+}
+
+const SIGNIFICANCE_TOKENS: [(ClinicalSignificance, &str); 32] = [
+    (ClinicalSignificance::Pathogenic, "pathogenic"),
+    (ClinicalSignificance::LikelyPathogenic, "likely_pathogenic"),
+    (
+        ClinicalSignificance::UncertainSignificance,
+        "uncertain_significance",
+    ),
+    (ClinicalSignificance::LikelyBenign, "likely_benign"),
+    (ClinicalSignificance::Benign, "benign"),
+    (
+        ClinicalSignificance::PathogenicLowPenetrance,
+        "pathogenic_low_penetrance",
+    ),
+    (
+        ClinicalSignificance::LikelyPathogenicLowPenetrance,
+        "likely_pathogenic_low_penetrance",
+    ),
+    (
+        ClinicalSignificance::PathogenicOrLikelyPathogenic,
+        "pathogenic_or_likely_pathogenic",
+    ),
+    (
+        ClinicalSignificance::PathogenicOrLikelyPathogenicOrPathogenicLowPenetrance,
+        "pathogenic_or_likely_pathogenic_or_pathogenic_low_penetrance",
+    ),
+    (
+        ClinicalSignificance::PathogenicOrLikelyPathogenicOrLikelyRiskAllele,
+        "pathogenic_or_likely_pathogenic_or_likely_risk_allele",
+    ),
+    (
+        ClinicalSignificance::PathogenicOrPathogenicLowPenetrance,
+        "pathogenic_or_pathogenic_low_penetrance",
+    ),
+    (
+        ClinicalSignificance::PathogenicOrLikelyRiskAllele,
+        "pathogenic_or_likely_risk_allele",
+    ),
+    (
+        ClinicalSignificance::LikelyPathogenicOrLikelyPathogenicLowPenetrance,
+        "likely_pathogenic_or_likely_pathogenic_low_penetrance",
+    ),
+    (
+        ClinicalSignificance::LikelyPathogenicOrLikelyRiskAllele,
+        "likely_pathogenic_or_likely_risk_allele",
+    ),
+    (
+        ClinicalSignificance::BenignOrLikelyBenign,
+        "benign_or_likely_benign",
+    ),
+    (
+        ClinicalSignificance::UncertainSignificanceOrUncertainRiskAllele,
+        "uncertain_significance_or_uncertain_risk_allele",
+    ),
+    (
+        ClinicalSignificance::EstablishedRiskAllele,
+        "established_risk_allele",
+    ),
+    (ClinicalSignificance::LikelyRiskAllele, "likely_risk_allele"),
+    (
+        ClinicalSignificance::UncertainRiskAllele,
+        "uncertain_risk_allele",
+    ),
+    (ClinicalSignificance::Oncogenic, "oncogenic"),
+    (ClinicalSignificance::LikelyOncogenic, "likely_oncogenic"),
+    (ClinicalSignificance::DrugResponse, "drug_response"),
+    (ClinicalSignificance::RiskFactor, "risk_factor"),
+    (ClinicalSignificance::Association, "association"),
+    (
+        ClinicalSignificance::AssociationNotFound,
+        "association_not_found",
+    ),
+    (ClinicalSignificance::Protective, "protective"),
+    (ClinicalSignificance::Affects, "affects"),
+    (
+        ClinicalSignificance::ConfersSensitivity,
+        "confers_sensitivity",
+    ),
+    (ClinicalSignificance::Other, "other"),
+    (ClinicalSignificance::NotProvided, "not_provided"),
+    (
+        ClinicalSignificance::Conflicting,
+        "conflicting_classifications_of_pathogenicity",
+    ),
+    (
+        ClinicalSignificance::ConflictingDataFromSubmitters,
+        "conflicting_data_from_submitters",
+    ),
+];
+
+impl ClinicalSignificance {
+    pub fn as_str(&self) -> &'static str {
+        SIGNIFICANCE_TOKENS
+            .iter()
+            .find(|(x, _)| x == self)
+            .map(|(_, token)| *token)
+            .unwrap_or_default()
+    }
+}
+
+impl fmt::Display for ClinicalSignificance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ClinicalSignificance {
+    /// The text sanitized to a token, for a term outside the vocabulary
+    type Err = String;
+
+    /// Parse a term or a token, ignoring case and punctuation
+    ///
+    /// e.g. `Likely pathogenic`, `likely_pathogenic` and `Likely Pathogenic - low penetrance`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let token = match compound_classification(s) {
+            Some(x) => x.to_string(),
+            None => s
+                .split(|c: char| !c.is_ascii_alphanumeric())
+                .filter(|x| !x.is_empty())
+                .collect::<Vec<&str>>()
+                .join("_")
+                .to_lowercase(),
+        };
+        let token = match token.as_str() {
+            "conflicting_interpretations_of_pathogenicity" => {
+                "conflicting_classifications_of_pathogenicity".to_string()
+            }
+            _ => token,
+        };
+
+        SIGNIFICANCE_TOKENS
+            .iter()
+            .find(|(_, x)| *x == token)
+            .map(|(x, _)| *x)
+            .ok_or(token)
+    }
+}
+
+/// Side of the pathogenicity scale a classification token falls on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Pathogenicity {
+    Pathogenic,
+    Uncertain,
+    Benign,
+}
+
+/// Classify a token from [`normalize_classification`] on the pathogenicity scale
+///
+/// Compound tokens such as `pathogenic_or_likely_pathogenic` are a range within one side. Tokens
+/// outside the scale (e.g. `drug_response`, `risk_factor`) return `None`.
+pub fn pathogenicity(token: &str) -> Option<Pathogenicity> {
+    match token {
+        "uncertain_significance" | "uncertain_significance_or_uncertain_risk_allele" => {
+            Some(Pathogenicity::Uncertain)
+        }
+        "benign" | "likely_benign" | "benign_or_likely_benign" => Some(Pathogenicity::Benign),
+        x if x.starts_with("pathogenic") || x.starts_with("likely_pathogenic") => {
+            Some(Pathogenicity::Pathogenic)
+        }
+        _ => None,
+    }
+}
+
+/// Percent-encode a value for an INFO field following VCF 4.3
+///
+/// `%`, `;`, `=`, `,`, spaces and control characters are always encoded, as well as the
+/// sub-delimiters of the field given in `delimiters` (e.g. `:` and `|` in CONDITIONS), so that
+/// literal characters in the data cannot be taken for delimiters.
+///
+/// # Arguments
+///
+/// * `value`: raw value
+/// * `delimiters`: characters the field uses as sub-delimiters
+///
+/// returns: String
+///
+/// e.g. `Ehlers-Danlos syndrome, type 4; autosomal dominant` to
+/// `Ehlers-Danlos%20syndrome%2C%20type%204%3B%20autosomal%20dominant`
+///
+/// # Examples
+///
+/// ```
+/// use clinvar::encode_info_value;
+///
+/// assert_eq!(
+///     encode_info_value("Ehlers-Danlos syndrome, type 4; autosomal dominant", &[]),
+///     "Ehlers-Danlos%20syndrome%2C%20type%204%3B%20autosomal%20dominant"
+/// );
+/// assert_eq!(encode_info_value("MONDO:0008840", &[]), "MONDO:0008840");
+/// assert_eq!(encode_info_value("MONDO:0008840", &[':', '|']), "MONDO%3A0008840");
+/// ```
+pub fn encode_info_value(value: &str, delimiters: &[char]) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '%' | ';' | '=' | ',' | ' ') || c.is_control() || delimiters.contains(&c) {
+            let mut buf = [0; 4];
+            for b in c.encode_utf8(&mut buf).bytes() {
+                encoded.push_str(&format!("%{:02X}", b));
+            }
+        } else {
+            encoded.push(c);
+        }
+    }
+
+    encoded
+}
+
+/// Normalize review status to the underscore-joined form used by NCBI
+///
+/// e.g. `criteria provided, multiple submitters, no conflicts` to
+/// `criteria_provided,_multiple_submitters,_no_conflicts`
+pub fn normalize_review_status(text: &str) -> String {
+    text.trim().replace(' ', "_")
+}
+
+/// Compare identifiers numerically if both are integers, otherwise lexically
+pub fn compare_ids(a: &str, b: &str) -> Ordering {
+    match (a.parse::<u64>(), b.parse::<u64>()) {
+        (Ok(x), Ok(y)) => x.cmp(&y).then_with(|| a.cmp(b)),
+        _ => a.cmp(b),
+    }
+}
+
+/// Sort and deduplicate identifiers with [`compare_ids`]
+pub fn sort_ids(ids: &mut Vec<String>) {
+    ids.sort_by(|a, b| compare_ids(a, b));
+    ids.dedup();
+}
+
+static REGEX_DATE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\A\d{4}-\d{2}-\d{2}").unwrap());
+
+/// Normalize a ClinVar date (e.g. `2024-04-20` or `2024-04-20T00:00:00`) to ISO 8601 `YYYY-MM-DD`
+///
+/// returns: Option<&str>
+///          `None` if the text does not start with a date
+pub fn normalize_date(text: &str) -> Option<&str> {
+    REGEX_DATE.find(text.trim()).map(|x| x.as_str())
+}
+
 pub static REGEX_CHROMOSOME: Lazy<Regex> =
     Lazy::new(|| Regex::new(r"\A([1-9]|1[0-9]|2[0-2]|X|Y|MT)\z").unwrap());
 pub static REGEX_ALLELE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\A[ACGT]+\z").unwrap());
+static REGEX_ALLELE_N: Lazy<Regex> = Lazy::new(|| Regex::new(r"\A[ACGTN]+\z").unwrap());
+pub static REGEX_ALLELE_IUPAC: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\A[ACGTRYSWKMBDHVN]+\z").unwrap());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, EnumString, VariantNames, AsRefStr)]
+pub enum Assembly {
+    GRCh37,
+    GRCh38,
+}
+
+impl Assembly {
+    pub const ALL: [Assembly; 2] = [Assembly::GRCh37, Assembly::GRCh38];
+
+    /// Whether the assembly name in ClinVar is this assembly, tolerating a patch suffix (e.g.
+    /// GRCh38.p14)
+    pub fn matches(&self, name: &str) -> bool {
+        name.strip_prefix(self.as_ref())
+            .is_some_and(|x| x.is_empty() || x.starts_with(".p"))
+    }
+
+    /// Assembly of the `SequenceLocation`, `None` if it is not supported
+    pub fn from_sequence_location(location: &SequenceLocation) -> Option<Assembly> {
+        Assembly::ALL
+            .into_iter()
+            .find(|x| x.matches(&location.assembly))
+    }
+}
+
+/// VCF coordinates of a `SimpleAllele`, borrowing from it
+///
+/// REF and ALT are uppercase, as they were validated and compared.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VcfCoordinates<'a> {
+    pub chrom: &'a str,
+    pub pos: u64,
+    pub reference: Cow<'a, str>,
+    pub alternate: Cow<'a, str>,
+}
 
 /// Extract sequence location from `SimpleAllele`
 ///
 /// # Arguments
 ///
 /// * `allele`: `SimpleAllele`
-/// * `assembly`: GRCh38 or GRCh37
+/// * `assembly`: `Assembly`
 ///
-/// returns: Option<(&String, u64, &String, &String)>
-///          (CHROM, POS, REF, ALT)
+/// returns: Option<VcfCoordinates>
 pub fn extract_location<'a>(
     allele: &'a SimpleAllele,
-    assembly: &'a str,
-) -> Option<(&'a String, u64, &'a String, &'a String)> {
-    allele
-        .location
-        .as_ref()
-        .and_then(|x| x.sequence_location.iter().find(|x| x.assembly == assembly))
-        .and_then(|x| match (&x.chr, x.pos, &x.reference, &x.alternate) {
-            (c, Some(p), Some(r), Some(a)) => {
-                let reference = r.to_uppercase();
-                let alternate = a.to_uppercase();
-
-                if !REGEX_CHROMOSOME.is_match(c) {
-                    warn!(
-                        "Skip chromosome {}: variation_id = {}",
-                        c, allele.variation_id
-                    );
-                    return None;
-                }
-                if !REGEX_ALLELE.is_match(reference.as_str()) {
-                    warn!(
-                        "Skip non-ACGT reference: {}, variation_id = {}",
-                        reference, allele.variation_id
-                    );
-                    return None;
-                }
-                if !REGEX_ALLELE.is_match(alternate.as_str()) {
-                    warn!(
-                        "Skip non-ACGT alternate: {}, variation_id = {}",
-                        alternate, allele.variation_id
-                    );
-                    return None;
+    assembly: Assembly,
+) -> Option<VcfCoordinates<'a>> {
+    try_extract_location(allele, assembly, &FormatConfig::default()).ok()
+}
+
+/// Extract sequence location from `SimpleAllele` by the name of the assembly
+///
+/// returns: Option<VcfCoordinates>
+///          `None` also if the name is not of an `Assembly`
+#[deprecated(note = "use extract_location with Assembly")]
+pub fn extract_location_by_name<'a>(
+    allele: &'a SimpleAllele,
+    assembly: &str,
+) -> Option<VcfCoordinates<'a>> {
+    extract_location(allele, Assembly::from_str(assembly).ok()?)
+}
+
+/// Extract sequence location from `SimpleAllele`, returning the reason on failure
+///
+/// # Arguments
+///
+/// * `allele`: `SimpleAllele`
+/// * `assembly`: `Assembly`
+/// * `config`: `FormatConfig` (`allow_iupac` and `contigs` are used)
+///
+/// returns: Result<VcfCoordinates, SkipReason>
+///
+/// # Examples
+///
+/// ```
+/// use clinvar::{try_extract_location, Assembly, FormatConfig, SimpleAllele, SkipReason};
+///
+/// let allele: SimpleAllele = quick_xml::de::from_str(
+///     r#"<SimpleAllele AlleleID="15041" VariationID="2">
+///          <Location>
+///            <SequenceLocation Assembly="GRCh38" Chr="7" positionVCF="4781213"
+///                              referenceAlleleVCF="ggat" alternateAlleleVCF="TGCTGTAAA"/>
+///            <SequenceLocation Assembly="GRCh37" Chr="Un" positionVCF="4820844"
+///                              referenceAlleleVCF="GGAT" alternateAlleleVCF="TGCTG"/>
+///          </Location>
+///        </SimpleAllele>"#,
+/// )
+/// .unwrap();
+/// let config = FormatConfig::default();
+///
+/// let coordinates = try_extract_location(&allele, Assembly::GRCh38, &config).unwrap();
+/// assert_eq!((coordinates.chrom, coordinates.pos), ("7", 4781213));
+/// assert_eq!(coordinates.reference, "GGAT");
+/// assert_eq!(coordinates.alternate, "TGCTGTAAA");
+///
+/// assert_eq!(
+///     try_extract_location(&allele, Assembly::GRCh37, &config).unwrap_err(),
+///     SkipReason::NonstandardChromosome
+/// );
+/// ```
+pub fn try_extract_location<'a>(
+    allele: &'a SimpleAllele,
+    assembly: Assembly,
+    config: &FormatConfig,
+) -> Result<VcfCoordinates<'a>, SkipReason> {
+    let location = select_location(allele, assembly).ok_or(SkipReason::NoLocation)?;
+
+    match (&location.chr, location.vcf_alleles()) {
+        (c, Some((p, r, a))) => {
+            let reference = to_uppercase(r);
+            let alternate = to_uppercase(a);
+
+            if !REGEX_CHROMOSOME.is_match(c) {
+                match &config.contigs {
+                    Some(contigs) if contigs.contains(c) => {}
+                    Some(_) => {
+                        debug!(
+                            reason = SkipReason::UnknownContig;
+                            "{}: {}, variation_id = {}",
+                            SkipReason::UnknownContig,
+                            c,
+                            allele.variation_id
+                        );
+                        return Err(SkipReason::UnknownContig);
+                    }
+                    None => {
+                        debug!(
+                            reason = SkipReason::NonstandardChromosome;
+                            "{}: {}, variation_id = {}",
+                            SkipReason::NonstandardChromosome,
+                            c,
+                            allele.variation_id
+                        );
+                        return Err(SkipReason::NonstandardChromosome);
+                    }
                 }
-                if reference == alternate {
-                    warn!(
-                        "Skip ref == alt: {} == {}, variation_id = {}",
-                        reference, alternate, allele.variation_id
+            }
+            let checks = [
+                (&reference, SkipReason::RefContainsN, SkipReason::NonAcgtRef),
+                (&alternate, SkipReason::AltContainsN, SkipReason::NonAcgtAlt),
+            ];
+            for (x, contains_n, non_acgt) in checks {
+                let valid = if config.allow_iupac {
+                    REGEX_ALLELE_IUPAC.is_match(x)
+                } else {
+                    REGEX_ALLELE.is_match(x)
+                };
+                if !valid {
+                    let reason = if !config.allow_iupac && REGEX_ALLELE_N.is_match(x) {
+                        contains_n
+                    } else {
+                        non_acgt
+                    };
+                    debug!(
+                        reason = reason;
+                        "{}: {}, variation_id = {}",
+                        reason,
+                        x,
+                        allele.variation_id
                     );
-                    return None;
+                    return Err(reason);
                 }
-
-                Some((c, p, r, a))
             }
-            _ => None,
+            if reference == alternate {
+                debug!(
+                    reason = SkipReason::RefEqualsAlt;
+                    "{}: {} == {}, variation_id = {}",
+                    SkipReason::RefEqualsAlt,
+                    reference,
+                    alternate,
+                    allele.variation_id
+                );
+                return Err(SkipReason::RefEqualsAlt);
+            }
+
+            Ok(VcfCoordinates {
+                chrom: c,
+                pos: p,
+                reference,
+                alternate,
+            })
+        }
+        _ if location.is_coordinate_only() => {
+            debug!(
+                reason = SkipReason::CoordinateOnly;
+                "{}: {}:{}, variation_id = {}",
+                SkipReason::CoordinateOnly,
+                location.chr,
+                location.start.unwrap_or_default(),
+                allele.variation_id
+            );
+            Err(SkipReason::CoordinateOnly)
+        }
+        _ => Err(SkipReason::IncompleteLocation),
+    }
+}
+
+/// `SequenceLocation` of an assembly to take the VCF coordinates from
+///
+/// The primary chromosome with VCF attributes is preferred over patches, alt loci and incomplete
+/// entries, whatever their order in the XML.
+///
+/// # Examples
+///
+/// ```
+/// use clinvar::{select_location, Assembly, SimpleAllele};
+///
+/// let allele: SimpleAllele = quick_xml::de::from_str(
+///     r#"<SimpleAllele AlleleID="15041" VariationID="2">
+///          <Location>
+///            <SequenceLocation Assembly="GRCh38" Chr="7" start="4781213" stop="4781216"/>
+///            <SequenceLocation Assembly="GRCh38.p14" Chr="7" positionVCF="4781213"
+///                              referenceAlleleVCF="GGAT" alternateAlleleVCF="TGCTG"/>
+///          </Location>
+///        </SimpleAllele>"#,
+/// )
+/// .unwrap();
+///
+/// let location = select_location(&allele, Assembly::GRCh38).unwrap();
+/// assert_eq!(location.pos, Some(4781213));
+/// assert!(select_location(&allele, Assembly::GRCh37).is_none());
+/// ```
+pub fn select_location(allele: &SimpleAllele, assembly: Assembly) -> Option<&SequenceLocation> {
+    allele.location.as_ref().and_then(|x| {
+        x.sequence_location
+            .iter()
+            .filter(|x| assembly.matches(&x.assembly))
+            .min_by_key(|x| {
+                let vcf = x.pos.is_some() && x.reference.is_some() && x.alternate.is_some();
+                (
+                    !REGEX_CHROMOSOME.is_match(&x.chr),
+                    !vcf,
+                    !vcf && x.coordinate_alleles().is_none(),
+                )
+            })
+    })
+}
+
+/// Uppercase text, allocating only if it has lowercase letters (alleles rarely have)
+fn to_uppercase(text: &str) -> Cow<'_, str> {
+    if text.chars().any(char::is_lowercase) {
+        Cow::Owned(text.to_uppercase())
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+/// Genomic region given as `<CHROM>`, `<CHROM>:<START>-<END>` or `<CHROM>:<START>-` (1-based,
+/// inclusive)
+///
+/// Chromosome names are accepted with or without a `chr` prefix, and `M` for `MT`.
+///
+/// # Examples
+///
+/// ```
+/// use clinvar::Region;
+///
+/// let region = "chr17:43,044,295-43,125,483".parse::<Region>().unwrap();
+/// assert_eq!(region.chrom, "17");
+/// assert_eq!((region.start, region.end), (43044295, Some(43125483)));
+/// assert!(region.contains("17", 43044295));
+/// assert!(!region.contains("17", 43125484));
+///
+/// let region = "chrM".parse::<Region>().unwrap();
+/// assert!(region.contains("MT", 16569));
+///
+/// assert!("17:200-100".parse::<Region>().is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    pub chrom: String,
+    pub start: u64,
+    pub end: Option<u64>,
+}
+
+impl Region {
+    pub fn contains(&self, chrom: &str, pos: u64) -> bool {
+        self.chrom == chrom && self.start <= pos && self.end.is_none_or(|x| pos <= x)
+    }
+}
+
+impl FromStr for Region {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (chrom, range) = match s.trim().split_once(':') {
+            Some((chrom, range)) => (chrom, Some(range)),
+            None => (s.trim(), None),
+        };
+
+        let chrom = chrom.strip_prefix("chr").unwrap_or(chrom);
+        let chrom = if chrom == "M" { "MT" } else { chrom };
+        if !REGEX_CHROMOSOME.is_match(chrom) {
+            return Err(format!("Unknown chromosome: {}", s));
+        }
+
+        let parse = |x: &str| {
+            x.replace(',', "")
+                .parse::<u64>()
+                .map_err(|_| format!("Invalid position: {}", s))
+        };
+        let (start, end) = match range {
+            None => (1, None),
+            Some(range) => match range.split_once('-') {
+                Some((start, "")) => (parse(start)?, None),
+                Some((start, end)) => (parse(start)?, Some(parse(end)?)),
+                None => (parse(range)?, Some(parse(range)?)),
+            },
+        };
+        if end.is_some_and(|x| x < start) {
+            return Err(format!("Invalid region: {}", s));
+        }
+
+        Ok(Region {
+            chrom: chrom.to_string(),
+            start,
+            end,
         })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    /// Codes are part of the public interface (rejects, reports and stats JSON)
+    #[test]
+    fn skip_reason_codes_are_stable() {
+        let codes = SkipReason::ALL.iter().map(|x| x.code()).collect::<Vec<_>>();
+
+        assert_eq!(
+            codes,
+            vec![
+                "xml_error",
+                "deserialization_error",
+                "schema_violation",
+                "no_classified_record",
+                "no_simple_allele",
+                "no_location",
+                "incomplete_location",
+                "coordinate_only",
+                "nonstandard_chromosome",
+                "unknown_contig",
+                "non_acgt_ref",
+                "non_acgt_alt",
+                "ref_contains_n",
+                "alt_contains_n",
+                "ref_equals_alt",
+                "ref_mismatch",
+                "no_medgen_condition",
+                "unclassified",
+                "record_status_filtered",
+                "species_filtered",
+                "significance_filtered",
+                "region_filtered",
+                "gene_filtered",
+            ]
+        );
+    }
+
+    #[test]
+    fn skip_reason_round_trips() {
+        let mut messages = BTreeSet::new();
+        for reason in SkipReason::ALL {
+            let json = serde_json::to_string(&reason).unwrap();
+            assert_eq!(json, format!("\"{}\"", reason.code()));
+            assert_eq!(serde_json::from_str::<SkipReason>(&json).unwrap(), reason);
+
+            let message = reason.to_string();
+            assert!(!message.is_empty());
+            assert!(
+                messages.insert(message),
+                "duplicate message of {:?}",
+                reason
+            );
+        }
+
+        assert!(serde_json::from_str::<SkipReason>("\"unknown\"").is_err());
+    }
+
+    #[test]
+    fn stats_round_trip() {
+        let mut stats = Stats {
+            records: 3,
+            written: 1,
+            ..Default::default()
+        };
+        stats.skip(SkipReason::RefMismatch);
+        stats.skip(SkipReason::RefMismatch);
+
+        let json = serde_json::to_string(&stats).unwrap();
+
+        assert!(json.contains("\"skipped\":{\"ref_mismatch\":2}"));
+        assert_eq!(serde_json::from_str::<Stats>(&json).unwrap(), stats);
+        assert_eq!(
+            stats.to_string(),
+            "Records read: 3\nVCF lines written: 1\nSkipped records: 2\n  Skip REF mismatching the reference (ref_mismatch): 2"
+        );
+    }
+
+    #[test]
+    fn classifications_are_normalized() {
+        for (text, expected) in [
+            // sentinels are tokenized as any other term
+            (
+                "no classifications from unflagged records",
+                vec!["no_classifications_from_unflagged_records"],
+            ),
+            (
+                "No classification for the single variant",
+                vec!["no_classification_for_the_single_variant"],
+            ),
+            // "/"-separated compounds
+            ("Benign/Likely benign", vec!["benign_or_likely_benign"]),
+            (
+                "Pathogenic/Likely pathogenic/Likely risk allele",
+                vec!["pathogenic_or_likely_pathogenic_or_likely_risk_allele"],
+            ),
+            // ","-separated modifiers and compounds
+            (
+                "Pathogenic, low penetrance",
+                vec!["pathogenic_low_penetrance"],
+            ),
+            (
+                "Pathogenic/Likely pathogenic/Pathogenic, low penetrance",
+                vec!["pathogenic_or_likely_pathogenic_or_pathogenic_low_penetrance"],
+            ),
+            (
+                "Likely pathogenic, low penetrance; risk factor",
+                vec!["likely_pathogenic_low_penetrance", "risk_factor"],
+            ),
+            // terms outside a compound
+            (
+                "Pathogenic/Likely pathogenic, low penetrance; risk factor",
+                vec![
+                    "pathogenic",
+                    "likely_pathogenic_low_penetrance",
+                    "risk_factor",
+                ],
+            ),
+            ("Benign/drug response", vec!["benign", "drug_response"]),
+            // case and whitespace
+            ("PATHOGENIC", vec!["pathogenic"]),
+            ("  Likely   benign ", vec!["likely_benign"]),
+            (" benign / LIKELY  benign ", vec!["benign_or_likely_benign"]),
+            (
+                "Uncertain significance ;Pathogenic",
+                vec!["uncertain_significance", "pathogenic"],
+            ),
+            // unknown terms are kept
+            ("Made-up term", vec!["made-up_term"]),
+            ("Pathogenic/made up", vec!["pathogenic", "made_up"]),
+            ("", vec![]),
+            (" / ; ", vec![]),
+        ] {
+            assert_eq!(normalize_classification(text), expected, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn sentinels_are_recognized() {
+        for (text, expected) in [
+            (
+                "no classifications from unflagged records",
+                Some("no_classification_unflagged"),
+            ),
+            (
+                " No classification for the single variant ",
+                Some("no_classification_single_variant"),
+            ),
+            (
+                "no interpretation for the single variant",
+                Some("no_classification_single_variant"),
+            ),
+            ("Pathogenic", None),
+            ("no classification", None),
+        ] {
+            assert_eq!(sentinel_classification(text), expected, "{:?}", text);
+        }
+    }
+
+    /// `SequenceLocation` on GRCh38 chromosome 1 with the given attributes
+    fn location(attributes: &str) -> SequenceLocation {
+        let xml = format!(
+            r#"<SequenceLocation Assembly="GRCh38" Chr="1" {}/>"#,
+            attributes
+        );
+
+        quick_xml::de::from_str(&xml).unwrap()
+    }
+
+    fn alleles<'a>(
+        location: Option<(u64, &'a String, &'a String)>,
+    ) -> Option<(u64, &'a str, &'a str)> {
+        location.map(|(p, r, a)| (p, r.as_str(), a.as_str()))
+    }
+
+    #[test]
+    fn coordinate_alleles_span_start_to_stop() {
+        for (attributes, expected) in [
+            // SNV
+            (
+                r#"start="100" stop="100" referenceAllele="A" alternateAllele="G""#,
+                Some((100, "A", "G")),
+            ),
+            // MNV and deletion-insertion, POS is `@start`
+            (
+                r#"start="100" stop="102" referenceAllele="ACG" alternateAllele="TTA""#,
+                Some((100, "ACG", "TTA")),
+            ),
+            (
+                r#"start="100" stop="101" referenceAllele="AC" alternateAllele="T""#,
+                Some((100, "AC", "T")),
+            ),
+            // REF one base shorter or longer than start..=stop
+            (
+                r#"start="100" stop="101" referenceAllele="A" alternateAllele="G""#,
+                None,
+            ),
+            (
+                r#"start="100" stop="101" referenceAllele="ACG" alternateAllele="G""#,
+                None,
+            ),
+            (
+                r#"start="101" stop="100" referenceAllele="A" alternateAllele="G""#,
+                None,
+            ),
+            // without `@stop`, by `@variantLength` or REF
+            (
+                r#"start="100" variantLength="2" referenceAllele="AC" alternateAllele="GT""#,
+                Some((100, "AC", "GT")),
+            ),
+            (
+                r#"start="100" variantLength="3" referenceAllele="AC" alternateAllele="GT""#,
+                None,
+            ),
+            (
+                r#"start="100" referenceAllele="AC" alternateAllele="GT""#,
+                Some((100, "AC", "GT")),
+            ),
+            // insertions and deletions need an anchor base
+            (
+                r#"start="100" stop="101" referenceAllele="-" alternateAllele="T""#,
+                None,
+            ),
+            (
+                r#"start="100" stop="101" referenceAllele="AC" alternateAllele="-""#,
+                None,
+            ),
+            (
+                r#"start="100" stop="101" referenceAllele="AC" alternateAllele="""#,
+                None,
+            ),
+            (r#"start="100" stop="100" referenceAllele="A""#, None),
+            (r#"referenceAllele="A" alternateAllele="G""#, None),
+        ] {
+            assert_eq!(
+                alleles(location(attributes).coordinate_alleles()),
+                expected,
+                "{}",
+                attributes
+            );
+        }
+    }
+
+    #[test]
+    fn vcf_alleles_prefer_the_vcf_attributes() {
+        for (attributes, expected, coordinate_only) in [
+            // deletion and insertion, anchored on the preceding base
+            (
+                r#"start="100" stop="101" referenceAllele="AC" alternateAllele="-"
+                   positionVCF="99" referenceAlleleVCF="GAC" alternateAlleleVCF="G""#,
+                Some((99, "GAC", "G")),
+                true,
+            ),
+            (
+                r#"start="100" stop="101" referenceAllele="-" alternateAllele="T"
+                   positionVCF="100" referenceAlleleVCF="A" alternateAlleleVCF="AT""#,
+                Some((100, "A", "AT")),
+                true,
+            ),
+            // SNV given both ways
+            (
+                r#"start="100" stop="100" referenceAllele="A" alternateAllele="G"
+                   positionVCF="100" referenceAlleleVCF="A" alternateAlleleVCF="G""#,
+                Some((100, "A", "G")),
+                false,
+            ),
+            // incomplete VCF attributes fall back to the coordinates
+            (
+                r#"start="100" stop="100" referenceAllele="A" alternateAllele="G"
+                   positionVCF="100" referenceAlleleVCF="A""#,
+                Some((100, "A", "G")),
+                false,
+            ),
+            (
+                r#"start="100" stop="101" referenceAllele="AC" alternateAllele="-"
+                   positionVCF="99" referenceAlleleVCF="GAC""#,
+                None,
+                true,
+            ),
+            (r#"positionVCF="99" referenceAlleleVCF="GAC""#, None, false),
+        ] {
+            let location = location(attributes);
+            assert_eq!(alleles(location.vcf_alleles()), expected, "{}", attributes);
+            assert_eq!(
+                location.is_coordinate_only(),
+                coordinate_only,
+                "{}",
+                attributes
+            );
+        }
+    }
+
+    #[test]
+    fn assembly_names_may_have_a_patch_suffix() {
+        for (name, grch37, grch38) in [
+            ("GRCh38", false, true),
+            ("GRCh38.p14", false, true),
+            ("GRCh37.p13", true, false),
+            ("GRCh38p14", false, false),
+            ("GRCh38.1", false, false),
+            ("GRCh380", false, false),
+            ("grch38", false, false),
+            ("NCBI36", false, false),
+        ] {
+            assert_eq!(Assembly::GRCh37.matches(name), grch37, "{}", name);
+            assert_eq!(Assembly::GRCh38.matches(name), grch38, "{}", name);
+        }
+    }
+
+    /// `SimpleAllele` with the `SequenceLocation`s given as XML
+    fn simple_allele(locations: &[&str]) -> SimpleAllele {
+        let xml = format!(
+            r#"<SimpleAllele AlleleID="1" VariationID="1"><Location>{}</Location></SimpleAllele>"#,
+            locations.concat()
+        );
+
+        quick_xml::de::from_str(&xml).unwrap()
+    }
+
+    #[test]
+    fn primary_chromosome_is_preferred_over_patches() {
+        let patch = r#"<SequenceLocation Assembly="GRCh38.p14" Chr="NW_009646201.1"
+            positionVCF="5000" referenceAlleleVCF="C" alternateAlleleVCF="T"/>"#;
+        let primary = r#"<SequenceLocation Assembly="GRCh38.p14" Chr="17"
+            positionVCF="43000000" referenceAlleleVCF="A" alternateAlleleVCF="G"/>"#;
+        let grch37 = r#"<SequenceLocation Assembly="GRCh37.p13" Chr="17"
+            positionVCF="41000000" referenceAlleleVCF="A" alternateAlleleVCF="G"/>"#;
+
+        for locations in [[patch, primary, grch37], [grch37, primary, patch]] {
+            let allele = simple_allele(&locations);
+
+            let location = select_location(&allele, Assembly::GRCh38).unwrap();
+            assert_eq!(
+                (location.chr.as_str(), location.pos),
+                ("17", Some(43000000))
+            );
+            let location = select_location(&allele, Assembly::GRCh37).unwrap();
+            assert_eq!(
+                (location.chr.as_str(), location.pos),
+                ("17", Some(41000000))
+            );
+        }
+    }
+
+    #[test]
+    fn patch_only_location_is_a_nonstandard_chromosome() {
+        let allele = simple_allele(&[r#"<SequenceLocation Assembly="GRCh38.p14"
+            Chr="NW_009646201.1" positionVCF="5000" referenceAlleleVCF="C"
+            alternateAlleleVCF="T"/>"#]);
+
+        let location = select_location(&allele, Assembly::GRCh38).unwrap();
+        assert_eq!(location.chr, "NW_009646201.1");
+        assert!(select_location(&allele, Assembly::GRCh37).is_none());
+
+        let config = FormatConfig::default();
+        assert_eq!(
+            try_extract_location(&allele, Assembly::GRCh38, &config).unwrap_err(),
+            SkipReason::NonstandardChromosome
+        );
+        assert_eq!(
+            try_extract_location(&allele, Assembly::GRCh37, &config).unwrap_err(),
+            SkipReason::NoLocation
+        );
+
+        // accepted if the reference has the contig
+        let config = FormatConfig {
+            contigs: Some(BTreeSet::from(["NW_009646201.1".to_string()])),
+            ..Default::default()
+        };
+        let coordinates = try_extract_location(&allele, Assembly::GRCh38, &config).unwrap();
+        assert_eq!(
+            (coordinates.chrom, coordinates.pos),
+            ("NW_009646201.1", 5000)
+        );
+        let config = FormatConfig {
+            contigs: Some(BTreeSet::from(["NT_187513.1".to_string()])),
+            ..Default::default()
+        };
+        assert_eq!(
+            try_extract_location(&allele, Assembly::GRCh38, &config).unwrap_err(),
+            SkipReason::UnknownContig
+        );
+    }
+
+    /// `SimpleAllele` of an SNV on GRCh38 chromosome 1 with the VCF alleles given
+    fn snv(reference: &str, alternate: &str) -> SimpleAllele {
+        simple_allele(&[&format!(
+            r#"<SequenceLocation Assembly="GRCh38" Chr="1" positionVCF="100"
+                referenceAlleleVCF="{}" alternateAlleleVCF="{}"/>"#,
+            reference, alternate
+        )])
+    }
+
+    #[test]
+    fn mixed_case_alleles_are_uppercased() {
+        for (reference, alternate) in [("a", "g"), ("aCgT", "Tg"), ("A", "g")] {
+            let allele = snv(reference, alternate);
+
+            let coordinates = extract_location(&allele, Assembly::GRCh38).unwrap();
+            assert_eq!(
+                coordinates,
+                VcfCoordinates {
+                    chrom: "1",
+                    pos: 100,
+                    reference: Cow::Owned(reference.to_uppercase()),
+                    alternate: Cow::Owned(alternate.to_uppercase()),
+                }
+            );
+            #[allow(deprecated)]
+            let by_name = extract_location_by_name(&allele, "GRCh38");
+            assert_eq!(by_name, Some(coordinates));
+        }
+
+        // borrowed unless lowercase
+        let allele = snv("A", "g");
+        let coordinates = extract_location(&allele, Assembly::GRCh38).unwrap();
+        assert!(matches!(coordinates.reference, Cow::Borrowed("A")));
+        assert!(matches!(coordinates.alternate, Cow::Owned(_)));
+
+        // checked after uppercasing
+        let config = FormatConfig::default();
+        for (reference, alternate, reason) in [
+            ("a", "A", SkipReason::RefEqualsAlt),
+            ("an", "G", SkipReason::RefContainsN),
+            ("A", "n", SkipReason::AltContainsN),
+            ("r", "G", SkipReason::NonAcgtRef),
+        ] {
+            let allele = snv(reference, alternate);
+
+            assert_eq!(
+                try_extract_location(&allele, Assembly::GRCh38, &config).unwrap_err(),
+                reason,
+                "{} {}",
+                reference,
+                alternate
+            );
+            assert!(extract_location(&allele, Assembly::GRCh38).is_none());
+        }
+    }
+
+    #[test]
+    fn regions_are_parsed() {
+        let region = |chrom: &str, start, end| Region {
+            chrom: chrom.to_string(),
+            start,
+            end,
+        };
+
+        for (text, expected) in [
+            ("17", region("17", 1, None)),
+            ("chr17", region("17", 1, None)),
+            (" 17:100-200 ", region("17", 100, Some(200))),
+            ("17:1,000-2,000", region("17", 1000, Some(2000))),
+            ("17:100-", region("17", 100, None)),
+            ("17:100", region("17", 100, Some(100))),
+            ("X:5-5", region("X", 5, Some(5))),
+            ("M", region("MT", 1, None)),
+            ("chrM:1-100", region("MT", 1, Some(100))),
+            ("MT:1-100", region("MT", 1, Some(100))),
+        ] {
+            assert_eq!(text.parse::<Region>(), Ok(expected), "{}", text);
+        }
+    }
+
+    #[test]
+    fn invalid_regions_are_rejected() {
+        for text in [
+            "",
+            "chr",
+            "23:1-100",
+            "chrUn_KI270742v1",
+            "17:",
+            "17:-100",
+            "17:abc",
+            "17:100-abc",
+            "17:200-100",
+        ] {
+            assert!(text.parse::<Region>().is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn open_regions_contain_positions_from_the_start() {
+        let region = "chr2:100-".parse::<Region>().unwrap();
+
+        assert!(!region.contains("2", 99));
+        assert!(region.contains("2", 100));
+        assert!(region.contains("2", u64::MAX));
+        assert!(!region.contains("chr2", 100));
+        assert!(!region.contains("20", 100));
+    }
 }
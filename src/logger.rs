@@ -0,0 +1,594 @@
+//! Backend of the `log` crate behind the `debug!`, `info!`, `warn!` and `error!` macros
+//!
+//! [`init`] installs it as the global logger of the `log` crate. Events go to stderr. With a log file configured they are also written there with a
+//! timestamp, rotated by size and/or age; rotation always happens between lines so a JSON event
+//! is never split across files.
+//!
+//! Events below the configured level are dropped by the `log` macros before being formatted.
+//! Events logged with a skip reason, i.e. with its code as target, are written up to a limit per
+//! reason; the number of the rest is written at the end. Text events on stderr are colored only
+//! if it is a terminal and `NO_COLOR` is not set.
+
+use crate::{progress, SkipReason};
+pub use log::Level;
+use log::{Log, Metadata, Record};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Mutex, MutexGuard, Once};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+static LOGGER: Lazy<Sink> = Lazy::new(|| Sink(Mutex::new(Logger::default())));
+static INSTALL: Once = Once::new();
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl LogFormat {
+    pub const VARIANTS: [&'static str; 2] = ["text", "json"];
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("Unknown log format: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LogConfig {
+    pub format: LogFormat,
+    /// Drop events less severe than this level
+    pub level: Level,
+    /// Also write events to this file
+    pub path: Option<PathBuf>,
+    /// Append to the log file instead of truncating it
+    pub append: bool,
+    /// Rotate the log file once it would grow beyond this many bytes
+    pub max_size: Option<u64>,
+    /// Rotate the log file once it has been open this long
+    pub max_age: Option<Duration>,
+    /// Number of rotated files to keep (`<path>.1` is the newest)
+    pub max_files: usize,
+    /// Drop events after this many have been written
+    pub max_events: Option<u64>,
+    /// Write at most this many events of each skip reason
+    pub max_per_reason: Option<u64>,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            format: LogFormat::default(),
+            level: Level::Warn,
+            path: None,
+            append: false,
+            max_size: None,
+            max_age: None,
+            max_files: 0,
+            max_events: None,
+            max_per_reason: None,
+        }
+    }
+}
+
+/// Counters reported at the end of a run
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LogSummary {
+    pub events: u64,
+    pub dropped: u64,
+    pub files_written: usize,
+}
+
+#[derive(Serialize)]
+struct JsonEvent<'a> {
+    seq: u64,
+    time: f64,
+    level: &'a str,
+    message: &'a str,
+}
+
+/// Global logger of the `log` crate
+struct Sink(Mutex<Logger>);
+
+impl Sink {
+    fn lock(&self) -> MutexGuard<'_, Logger> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Log for Sink {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    /// Write an event; failures to write the log are reported on stderr and otherwise ignored
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let reason = SkipReason::ALL
+            .into_iter()
+            .find(|x| x.code() == record.target());
+        if reason.is_some_and(|x| !self.lock().admit(x)) {
+            return;
+        }
+
+        let message = record.args().to_string();
+        if let Err(e) = self.lock().log(record.level(), &message) {
+            eprintln!("Failed to write log: {}", e);
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(file) = self.lock().file.as_mut() {
+            let _ = file.writer.flush();
+        }
+    }
+}
+
+#[derive(Default)]
+struct Logger {
+    format: LogFormat,
+    color: bool,
+    file: Option<RotatingFile>,
+    max_events: Option<u64>,
+    max_per_reason: Option<u64>,
+    /// Events logged with each skip reason, including suppressed ones
+    occurrences: BTreeMap<SkipReason, u64>,
+    seq: u64,
+    dropped: u64,
+}
+
+impl Logger {
+    /// Count an event of the skip reason, returning whether it is written
+    fn admit(&mut self, reason: SkipReason) -> bool {
+        let max = self.max_per_reason;
+        let count = self.occurrences.entry(reason).or_default();
+        *count += 1;
+
+        max.is_none_or(|x| *count <= x)
+    }
+
+    fn log(&mut self, level: Level, message: &str) -> io::Result<()> {
+        if self.max_events.is_some_and(|x| self.seq >= x) {
+            self.dropped += 1;
+            return Ok(());
+        }
+        self.seq += 1;
+
+        let time = SystemTime::now();
+        let line = self.format_line(level, message, time, self.color)?;
+        progress::suspend(|| writeln!(io::stderr(), "{}", line))?;
+        self.note(level, message, time)
+    }
+
+    /// Write an event to the log file only
+    fn note(&mut self, level: Level, message: &str, time: SystemTime) -> io::Result<()> {
+        if self.file.is_none() {
+            return Ok(());
+        }
+        let line = match self.format {
+            LogFormat::Json => self.format_line(level, message, time, false)?,
+            LogFormat::Text => format!(
+                "{} {}",
+                timestamp(time),
+                self.format_line(level, message, time, false)?
+            ),
+        };
+
+        match self.file.as_mut() {
+            Some(file) => file.write_line(&line),
+            None => Ok(()),
+        }
+    }
+
+    fn format_line(
+        &self,
+        level: Level,
+        message: &str,
+        time: SystemTime,
+        color: bool,
+    ) -> io::Result<String> {
+        let line = match self.format {
+            LogFormat::Json => {
+                let event = JsonEvent {
+                    seq: self.seq,
+                    time: time
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_secs_f64(),
+                    level: &level.as_str().to_ascii_lowercase(),
+                    message,
+                };
+                serde_json::to_string(&event)?
+            }
+            LogFormat::Text if !color => format!("[{}] {}", level, message),
+            LogFormat::Text => match level {
+                Level::Trace => format!("[\x1b[90mTRACE\x1b[0m] {}", message),
+                Level::Debug => format!("[\x1b[90mDEBUG\x1b[0m] {}", message),
+                Level::Info => format!("[\x1b[32mINFO\x1b[0m] {}", message),
+                Level::Warn => format!("[\x1b[33mWARN\x1b[0m] {}", message),
+                Level::Error => format!("[\x1b[31mERROR\x1b[0m] {}", message),
+            },
+        };
+
+        Ok(line)
+    }
+}
+
+struct RotatingFile {
+    path: PathBuf,
+    max_size: Option<u64>,
+    max_age: Option<Duration>,
+    max_files: usize,
+    writer: BufWriter<File>,
+    size: u64,
+    opened: Instant,
+    files_written: usize,
+}
+
+impl RotatingFile {
+    fn create(config: &LogConfig, path: &Path) -> io::Result<Self> {
+        if let Some(parent) = path.parent().filter(|x| !x.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .append(config.append)
+            .truncate(!config.append)
+            .open(path)?;
+        let size = file.metadata()?.len();
+
+        Ok(RotatingFile {
+            path: path.to_path_buf(),
+            max_size: config.max_size,
+            max_age: config.max_age,
+            max_files: config.max_files,
+            writer: BufWriter::new(file),
+            size,
+            opened: Instant::now(),
+            files_written: 1,
+        })
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let length = line.len() as u64 + 1;
+        let full = self.max_size.is_some_and(|x| self.size + length > x);
+        let expired = self.max_age.is_some_and(|x| self.opened.elapsed() >= x);
+        if self.size != 0 && (full || expired) {
+            self.rotate()?;
+        }
+
+        writeln!(self.writer, "{}", line)?;
+        self.size += length;
+
+        Ok(())
+    }
+
+    /// Shift `<path>` to `<path>.1`, `<path>.1` to `<path>.2`... dropping the oldest
+    fn rotate(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+
+        if self.max_files == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            let oldest = self.rotated_path(self.max_files);
+            if oldest.exists() {
+                fs::remove_file(oldest)?;
+            }
+            for i in (1..self.max_files).rev() {
+                let from = self.rotated_path(i);
+                if from.exists() {
+                    fs::rename(from, self.rotated_path(i + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rotated_path(1))?;
+        }
+
+        self.writer = BufWriter::new(
+            OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(&self.path)?,
+        );
+        self.size = 0;
+        self.opened = Instant::now();
+        self.files_written += 1;
+
+        Ok(())
+    }
+
+    fn rotated_path(&self, i: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", i));
+        PathBuf::from(path)
+    }
+}
+
+/// Configure the global logger and install it as the logger of the `log` crate
+///
+/// # Arguments
+///
+/// * `config`: `LogConfig`
+///
+/// returns: Result<(), Error>
+pub fn init(config: &LogConfig) -> io::Result<()> {
+    let file = match config.path.as_deref() {
+        Some(path) => Some(RotatingFile::create(config, path).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Failed to open log file {}: {}", path.to_string_lossy(), e),
+            )
+        })?),
+        None => None,
+    };
+
+    let mut installed = Ok(());
+    INSTALL.call_once(|| installed = log::set_logger(&*LOGGER));
+    installed.map_err(io::Error::other)?;
+    log::set_max_level(config.level.to_level_filter());
+
+    *LOGGER.lock() = Logger {
+        format: config.format,
+        color: use_color(
+            io::stderr().is_terminal(),
+            std::env::var_os("NO_COLOR").as_deref(),
+        ),
+        file,
+        max_events: config.max_events,
+        max_per_reason: config.max_per_reason,
+        occurrences: BTreeMap::new(),
+        seq: 0,
+        dropped: 0,
+    };
+
+    Ok(())
+}
+
+/// Whether to color events on stderr, following <https://no-color.org>
+///
+/// # Arguments
+///
+/// * `terminal`: whether stderr is a terminal
+/// * `no_color`: value of `NO_COLOR`
+///
+/// returns: true if stderr is a terminal and `NO_COLOR` is unset or empty
+fn use_color(terminal: bool, no_color: Option<&OsStr>) -> bool {
+    terminal && no_color.is_none_or(|x| x.is_empty())
+}
+
+/// Write a message to the log file only, regardless of the level, e.g. the summary of a run
+pub fn note(message: &str) {
+    let mut logger = LOGGER.lock();
+    if let Err(e) = logger.note(Level::Info, message, SystemTime::now()) {
+        eprintln!("Failed to write log: {}", e);
+    }
+}
+
+/// Write the number of suppressed events, flush the log file and return the counters of this run
+pub fn finish() -> io::Result<LogSummary> {
+    let mut logger = LOGGER.lock();
+
+    if let Some(max) = logger.max_per_reason {
+        let suppressed = logger
+            .occurrences
+            .iter()
+            .filter(|(_, &count)| count > max)
+            .map(|(reason, count)| (*reason, count - max))
+            .collect::<Vec<_>>();
+        for (reason, count) in suppressed {
+            let message = format!("...suppressed {} further '{}' messages", count, reason);
+            logger.log(Level::Warn, &message)?;
+        }
+    }
+
+    let files_written = match logger.file.as_mut() {
+        Some(file) => {
+            file.writer.flush()?;
+            file.files_written
+        }
+        None => 0,
+    };
+
+    Ok(LogSummary {
+        events: logger.seq,
+        dropped: logger.dropped,
+        files_written,
+    })
+}
+
+/// Time in UTC as ISO 8601 with milliseconds, e.g. `2024-01-31T12:34:56.789Z`
+fn timestamp(time: SystemTime) -> String {
+    let elapsed = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = elapsed.as_secs();
+    let (year, month, day) = civil_date((secs / 86400) as i64);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        secs / 3600 % 24,
+        secs / 60 % 60,
+        secs % 60,
+        elapsed.subsec_millis()
+    )
+}
+
+/// Convert days since the Unix epoch into (year, month, day)
+pub fn civil_date(days: i64) -> (i64, i64, i64) {
+    // H. Hinnant's days_from_civil inverted
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn color_needs_a_terminal_and_no_no_color() {
+        for (terminal, no_color, expected) in [
+            (true, None, true),
+            (true, Some(""), true),
+            (true, Some("1"), false),
+            (true, Some("0"), false),
+            (false, None, false),
+            (false, Some(""), false),
+            (false, Some("1"), false),
+        ] {
+            assert_eq!(
+                use_color(terminal, no_color.map(OsStr::new)),
+                expected,
+                "terminal = {}, NO_COLOR = {:?}",
+                terminal,
+                no_color
+            );
+        }
+    }
+
+    #[test]
+    fn events_are_capped_per_reason() {
+        let mut logger = Logger {
+            max_per_reason: Some(2),
+            ..Default::default()
+        };
+
+        let admitted = (0..4)
+            .map(|_| logger.admit(SkipReason::RefMismatch))
+            .collect::<Vec<_>>();
+
+        assert_eq!(admitted, vec![true, true, false, false]);
+        assert!(logger.admit(SkipReason::NoSimpleAllele));
+        assert_eq!(logger.occurrences[&SkipReason::RefMismatch], 4);
+    }
+
+    #[test]
+    fn lines_are_formatted() {
+        let mut logger = Logger::default();
+        let time = UNIX_EPOCH + Duration::from_millis(1500);
+
+        let line = logger.format_line(Level::Warn, "message", time, false);
+        assert_eq!(line.unwrap(), "[WARN] message");
+        let line = logger.format_line(Level::Error, "message", time, true);
+        assert_eq!(line.unwrap(), "[\x1b[31mERROR\x1b[0m] message");
+
+        logger.format = LogFormat::Json;
+        logger.seq = 7;
+        let line = logger.format_line(Level::Info, "a \"quoted\" message", time, true);
+        assert_eq!(
+            line.unwrap(),
+            r#"{"seq":7,"time":1.5,"level":"info","message":"a \"quoted\" message"}"#
+        );
+    }
+
+    fn rotating_file(dir: &Path, max_size: u64, max_files: usize) -> RotatingFile {
+        let config = LogConfig {
+            max_size: Some(max_size),
+            max_files,
+            ..Default::default()
+        };
+
+        RotatingFile::create(&config, &dir.join("log")).unwrap()
+    }
+
+    fn read(path: &Path) -> String {
+        fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn files_are_rotated_by_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut file = rotating_file(dir.path(), 12, 2);
+
+        // 6 bytes per line, 2 lines per file
+        for i in 0..7 {
+            file.write_line(&format!("line{}", i)).unwrap();
+        }
+        file.writer.flush().unwrap();
+
+        assert_eq!(file.files_written, 4);
+        assert_eq!(read(&dir.path().join("log")), "line6\n");
+        assert_eq!(read(&dir.path().join("log.1")), "line4\nline5\n");
+        assert_eq!(read(&dir.path().join("log.2")), "line2\nline3\n");
+        assert!(!dir.path().join("log.3").exists());
+    }
+
+    #[test]
+    fn lines_are_never_split() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut file = rotating_file(dir.path(), 4, 1);
+
+        file.write_line("longer than the limit").unwrap();
+        file.write_line("another one").unwrap();
+        file.writer.flush().unwrap();
+
+        assert_eq!(read(&dir.path().join("log")), "another one\n");
+        assert_eq!(read(&dir.path().join("log.1")), "longer than the limit\n");
+    }
+
+    #[test]
+    fn no_rotated_file_is_kept_without_max_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut file = rotating_file(dir.path(), 6, 0);
+
+        for i in 0..3 {
+            file.write_line(&format!("line{}", i)).unwrap();
+        }
+        file.writer.flush().unwrap();
+
+        assert_eq!(file.files_written, 3);
+        assert_eq!(read(&dir.path().join("log")), "line2\n");
+        assert!(!dir.path().join("log.1").exists());
+    }
+
+    #[test]
+    fn civil_date_of_days_since_epoch() {
+        for (days, expected) in [
+            (0, (1970, 1, 1)),
+            (-1, (1969, 12, 31)),
+            (59, (1970, 3, 1)),
+            (11016, (2000, 2, 29)),
+            (11017, (2000, 3, 1)),
+            (19782, (2024, 2, 29)),
+            (19783, (2024, 3, 1)),
+            (47540, (2100, 2, 28)),
+            (47541, (2100, 3, 1)),
+        ] {
+            assert_eq!(civil_date(days), expected, "{}", days);
+        }
+    }
+
+    #[test]
+    fn timestamps_are_iso_8601() {
+        let time = UNIX_EPOCH + Duration::from_millis(1_709_209_496_789);
+
+        assert_eq!(timestamp(time), "2024-02-29T12:24:56.789Z");
+    }
+}
@@ -0,0 +1,222 @@
+//! Left-alignment and trimming of alleles against the reference, as `bcftools norm` does
+
+use crate::faidx;
+use crate::vcf::VcfRecord;
+use std::io;
+
+/// Left-align an indel and trim bases shared by REF and ALT, keeping one anchor base
+///
+/// Records with symbolic, missing or multiple ALT alleles are left as they are.
+///
+/// # Arguments
+///
+/// * `record`: record whose REF matches the reference
+/// * `fasta`: reference
+///
+/// returns: whether the record was changed
+pub fn normalize(record: &mut VcfRecord, fasta: &mut faidx::Reader) -> io::Result<bool> {
+    let is_bases = |x: &str| !x.is_empty() && x.bytes().all(|x| b"ACGTNacgtn".contains(&x));
+    if !is_bases(&record.reference)
+        || !is_bases(&record.alternate)
+        || record.reference.eq_ignore_ascii_case(&record.alternate)
+    {
+        return Ok(false);
+    }
+
+    let mut pos = record.pos;
+    let mut reference = record.reference.as_bytes().to_vec();
+    let mut alternate = record.alternate.as_bytes().to_vec();
+
+    // drop the shared last base, extending both alleles to the left once one would become empty
+    while let (Some(r), Some(a)) = (reference.last(), alternate.last()) {
+        if !r.eq_ignore_ascii_case(a) {
+            break;
+        }
+        if reference.len() == 1 || alternate.len() == 1 {
+            if pos == 1 {
+                break;
+            }
+            let base = match fasta.fetch(&record.chrom, pos - 2, pos - 1)? {
+                Some(x) => x[0].to_ascii_uppercase(),
+                None => break,
+            };
+            reference.insert(0, base);
+            alternate.insert(0, base);
+            pos -= 1;
+        }
+        reference.pop();
+        alternate.pop();
+    }
+
+    // drop the shared first bases, keeping one
+    let shared = reference
+        .iter()
+        .zip(&alternate)
+        .take(reference.len().min(alternate.len()) - 1)
+        .take_while(|(r, a)| r.eq_ignore_ascii_case(a))
+        .count();
+    reference.drain(..shared);
+    alternate.drain(..shared);
+    pos += shared as u64;
+
+    let reference = String::from_utf8_lossy(&reference).into_owned();
+    let alternate = String::from_utf8_lossy(&alternate).into_owned();
+    if pos == record.pos && reference == record.reference && alternate == record.alternate {
+        return Ok(false);
+    }
+    record.pos = pos;
+    record.reference = reference;
+    record.alternate = alternate;
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bgzf;
+    use crate::vcf::Filter;
+    use std::fs::{self, File};
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    /// Chromosome 1 with a poly-A at 6-10, a CA repeat at 13-19 and TT at 21-22
+    const SEQUENCE: &str = "CAGTCAAAAAGTCACACACGTTGCA";
+
+    /// Bgzipped FASTA of `SEQUENCE` with its .fai and .gzi
+    fn fasta() -> (TempDir, faidx::Reader) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ref.fa.gz");
+        let mut writer = bgzf::Writer::new(File::create(&path).unwrap());
+        write!(writer, ">1\n{}\n", SEQUENCE).unwrap();
+        writer.finish().unwrap();
+        fs::write(
+            dir.path().join("ref.fa.gz.fai"),
+            format!(
+                "1\t{}\t3\t{}\t{}\n",
+                SEQUENCE.len(),
+                SEQUENCE.len(),
+                SEQUENCE.len() + 1
+            ),
+        )
+        .unwrap();
+        fs::write(dir.path().join("ref.fa.gz.gzi"), 0u64.to_le_bytes()).unwrap();
+
+        let reader = faidx::Reader::from_path(&path).unwrap();
+        (dir, reader)
+    }
+
+    fn record(chrom: &str, pos: u64, reference: &str, alternate: &str) -> VcfRecord {
+        VcfRecord {
+            chrom: chrom.to_string(),
+            pos,
+            id: "1".to_string(),
+            reference: reference.to_string(),
+            alternate: alternate.to_string(),
+            filter: Filter::Pass,
+            info: Vec::new(),
+            flags: Default::default(),
+        }
+    }
+
+    /// (POS, REF, ALT) after normalization, and whether it changed
+    fn normalized(pos: u64, reference: &str, alternate: &str) -> ((u64, String, String), bool) {
+        let (_dir, mut fasta) = fasta();
+        let mut record = record("1", pos, reference, alternate);
+
+        let changed = normalize(&mut record, &mut fasta).unwrap();
+
+        ((record.pos, record.reference, record.alternate), changed)
+    }
+
+    fn alleles(pos: u64, reference: &str, alternate: &str) -> (u64, String, String) {
+        (pos, reference.to_string(), alternate.to_string())
+    }
+
+    #[test]
+    fn shared_suffixes_and_prefixes_are_trimmed() {
+        for ((pos, reference, alternate), expected) in [
+            ((11, "GTCA", "GTTA"), (13, "C", "T")),
+            ((11, "GTCA", "GTTG"), (13, "CA", "TG")),
+            ((20, "GTT", "GT"), (20, "GT", "G")),
+            // trimmed, then shifted to the start of the poly-A
+            ((3, "GTC", "GTCAA"), (5, "C", "CAA")),
+        ] {
+            assert_eq!(
+                normalized(pos, reference, alternate),
+                (alleles(expected.0, expected.1, expected.2), true),
+                "{}:{}>{}",
+                pos,
+                reference,
+                alternate
+            );
+        }
+    }
+
+    #[test]
+    fn indels_are_left_shifted() {
+        for ((pos, reference, alternate), expected) in [
+            // deletion and insertion in a homopolymer, anchored on the base before it
+            ((10, "AG", "G"), (5, "CA", "C")),
+            ((9, "AA", "A"), (5, "CA", "C")),
+            ((9, "A", "AA"), (5, "C", "CA")),
+            // deletion of a unit of a dinucleotide repeat
+            ((17, "CAC", "C"), (12, "TCA", "T")),
+            ((18, "ACA", "A"), (12, "TCA", "T")),
+            ((21, "TT", "T"), (20, "GT", "G")),
+        ] {
+            assert_eq!(
+                normalized(pos, reference, alternate),
+                (alleles(expected.0, expected.1, expected.2), true),
+                "{}:{}>{}",
+                pos,
+                reference,
+                alternate
+            );
+        }
+    }
+
+    /// The base before the allele is fetched from `[pos - 2, pos - 1)` (0-based)
+    #[test]
+    fn left_extension_fetches_the_preceding_base() {
+        // C inserted before A at 2, given without an anchor base
+        assert_eq!(normalized(2, "A", "CA"), (alleles(1, "C", "CC"), true));
+        // A deleted at 7, extended to 5 base by base
+        assert_eq!(normalized(7, "A", ""), (alleles(7, "A", ""), false));
+        assert_eq!(normalized(7, "AA", "A"), (alleles(5, "CA", "C"), true));
+    }
+
+    #[test]
+    fn shifting_stops_at_position_1() {
+        assert_eq!(normalized(1, "C", "CC"), (alleles(1, "C", "CC"), false));
+        assert_eq!(normalized(1, "CAG", "CG"), (alleles(1, "CA", "C"), true));
+    }
+
+    #[test]
+    fn other_records_are_left_as_they_are() {
+        for (pos, reference, alternate) in [
+            (5, "C", "T"),
+            (5, "CA", "C"),
+            (5, "C", "<DEL>"),
+            (5, "C", "."),
+            (5, "CA", "C,CAA"),
+            (5, "C", "c"),
+            (5, "C", ""),
+        ] {
+            assert_eq!(
+                normalized(pos, reference, alternate),
+                (alleles(pos, reference, alternate), false),
+                "{}:{}>{}",
+                pos,
+                reference,
+                alternate
+            );
+        }
+
+        // unknown contig
+        let (_dir, mut fasta) = fasta();
+        let mut record = record("2", 10, "AG", "G");
+        assert!(!normalize(&mut record, &mut fasta).unwrap());
+        assert_eq!((record.pos, record.reference.as_str()), (10, "AG"));
+    }
+}
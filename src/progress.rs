@@ -0,0 +1,141 @@
+//! Progress bar on stderr, drawn by `indicatif`
+//!
+//! The bar is hidden when stderr is not a terminal, and cleared while log events are written to
+//! stderr so that they do not interleave with it.
+
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressState, ProgressStyle};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Redraws per second at most
+const REFRESH_RATE: u8 = 5;
+/// Interval to redraw the spinner and the counts while no byte is read
+const TICK_INTERVAL: Duration = Duration::from_millis(200);
+const BAR_TEMPLATE: &str =
+    "[{bar:20}] {percent:>3}% {binary_bytes}/{binary_total_bytes} | {records} | ETA {eta_precise}";
+const SPINNER_TEMPLATE: &str = "{spinner} {records} | {elapsed_precise}";
+
+/// Skips locking when no progress is shown
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static PROGRESS: Mutex<Option<ProgressBar>> = Mutex::new(None);
+static RECORDS: AtomicU64 = AtomicU64::new(0);
+static EMITTED: AtomicU64 = AtomicU64::new(0);
+
+/// Clears the progress bar when dropped
+pub struct ProgressGuard(());
+
+impl Drop for ProgressGuard {
+    fn drop(&mut self) {
+        ENABLED.store(false, Ordering::SeqCst);
+        let progress = PROGRESS.lock().unwrap_or_else(|e| e.into_inner()).take();
+        if let Some(x) = progress {
+            x.finish_and_clear();
+        }
+    }
+}
+
+/// Start showing progress until the returned guard is dropped
+///
+/// Nothing is drawn if stderr is not a terminal.
+///
+/// # Arguments
+///
+/// * `total`: number of input bytes counted by [`set_bytes`], or `None` to show a spinner
+///
+/// returns: ProgressGuard
+pub fn start(total: Option<u64>) -> ProgressGuard {
+    RECORDS.store(0, Ordering::Relaxed);
+    EMITTED.store(0, Ordering::Relaxed);
+
+    let total = total.filter(|x| *x != 0);
+    let style = match total {
+        Some(_) => ProgressStyle::with_template(BAR_TEMPLATE)
+            .expect("valid template")
+            .progress_chars("#-"),
+        None => ProgressStyle::with_template(SPINNER_TEMPLATE)
+            .expect("valid template")
+            .tick_chars("|/-\\ "),
+    };
+    let bar =
+        ProgressBar::with_draw_target(total, ProgressDrawTarget::stderr_with_hz(REFRESH_RATE))
+            .with_style(style.with_key("records", write_counts));
+    if !bar.is_hidden() {
+        bar.enable_steady_tick(TICK_INTERVAL);
+    }
+
+    *PROGRESS.lock().unwrap_or_else(|e| e.into_inner()) = Some(bar);
+    ENABLED.store(true, Ordering::SeqCst);
+
+    ProgressGuard(())
+}
+
+/// Set the number of input bytes read
+pub fn set_bytes(bytes: u64) {
+    if let Some(x) = bar() {
+        x.set_position(bytes);
+    }
+}
+
+/// Count a processed record
+///
+/// # Arguments
+///
+/// * `emitted`: whether any VCF record is written for it
+pub fn record(emitted: bool) {
+    RECORDS.fetch_add(1, Ordering::Relaxed);
+    EMITTED.fetch_add(emitted as u64, Ordering::Relaxed);
+}
+
+/// Run `f` writing to stderr with the progress bar cleared, then draw the bar again
+pub(crate) fn suspend<T>(f: impl FnOnce() -> T) -> T {
+    match bar() {
+        Some(x) => x.suspend(f),
+        None => f(),
+    }
+}
+
+/// The progress bar being shown
+fn bar() -> Option<ProgressBar> {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return None;
+    }
+
+    PROGRESS.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Write the `{records}` key of the templates
+fn write_counts(_: &ProgressState, w: &mut dyn Write) {
+    let records = RECORDS.load(Ordering::Relaxed);
+    let emitted = EMITTED.load(Ordering::Relaxed);
+    let _ = write!(
+        w,
+        "{} records ({} emitted, {} skipped)",
+        records,
+        emitted,
+        records.saturating_sub(emitted)
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{self, IsTerminal};
+
+    #[test]
+    fn hidden_without_a_terminal() {
+        // `cargo test` may run with stderr on a terminal
+        if io::stderr().is_terminal() {
+            return;
+        }
+
+        let guard = start(Some(100));
+        set_bytes(50);
+        record(true);
+
+        assert!(bar().unwrap().is_hidden());
+        drop(guard);
+        assert!(bar().is_none());
+    }
+}
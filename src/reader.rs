@@ -0,0 +1,848 @@
+//! Streaming reader of the `VariationArchive` elements of a ClinVarVariationRelease XML
+//!
+//! Each element is read as the input bytes and deserialized on its own, so a malformed record
+//! can be skipped without stopping the stream.
+
+use crate::{
+    normalize_date, schema, Citation, ClinicalAssertion, ClinicalAssertionList, ClinvarError,
+    ObservedInList, Scv, VariationArchive,
+};
+use flate2::bufread::MultiGzDecoder;
+use quick_xml::de::Deserializer;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use serde::Deserialize;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+use std::str::from_utf8;
+
+pub const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+const TAG_RELEASE: &[u8] = b"ClinVarVariationRelease";
+const TAG_VARIATION_ARCHIVE: &[u8] = b"VariationArchive";
+
+/// Reader of `VariationArchive` elements, iterating over them deserialized
+///
+/// Iterating stops at the first error unless [`skip_malformed`](Self::skip_malformed) is set;
+/// [`read_raw`](Self::read_raw) gives the input bytes of each element instead, leaving errors to
+/// the caller.
+///
+/// # Examples
+///
+/// ```no_run
+/// use clinvar::reader::VariationArchiveReader;
+///
+/// for record in VariationArchiveReader::from_path("clinvar.xml.gz")? {
+///     let record = record?;
+///     println!("{}", record.variation_id);
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct VariationArchiveReader<R> {
+    reader: Reader<RecordingReader<R>>,
+    buf: Vec<u8>,
+    junk_buf: Vec<u8>,
+    /// `ReleaseDate` of the root element, set once the header is read
+    release_date: Option<Option<String>>,
+    /// Start tag read with the header that is not the root element
+    pending: Option<BytesStart<'static>>,
+    /// A stream ending inside the root element is truncated
+    in_release: bool,
+    /// `VariationArchive` elements seen, including those skipped by the offset
+    count: u64,
+    offset: u64,
+    limit: Option<u64>,
+    skip_malformed: bool,
+    strict: bool,
+    pub(crate) include_scv: bool,
+    skipped: u64,
+    done: bool,
+    /// Position in the input the inner reader starts at (see [`resume`](Self::resume))
+    start: usize,
+    /// Bytes given before the inner reader, which are not in the input
+    prefix: usize,
+}
+
+impl<R: BufRead> VariationArchiveReader<R> {
+    pub fn from_reader(inner: R) -> Self {
+        VariationArchiveReader {
+            reader: Reader::from_reader(RecordingReader::new(inner)),
+            buf: Vec::new(),
+            junk_buf: Vec::new(),
+            release_date: None,
+            pending: None,
+            in_release: false,
+            count: 0,
+            offset: 0,
+            limit: None,
+            skip_malformed: false,
+            strict: false,
+            include_scv: false,
+            skipped: 0,
+            done: false,
+            start: 0,
+            prefix: 0,
+        }
+    }
+
+    /// Start from the element after this many
+    pub fn offset(mut self, offset: u64) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Stop after this many elements, not counting those skipped by the offset
+    pub fn limit(mut self, limit: Option<u64>) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Skip malformed XML and elements failing to deserialize on iterating, counting them in
+    /// [`skipped`](Self::skipped)
+    pub fn skip_malformed(mut self, skip: bool) -> Self {
+        self.skip_malformed = skip;
+        self
+    }
+
+    /// Fail on iterating at the first element or attribute unknown to this tool (see
+    /// [`schema::validate`])
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Read the submitted records into [`VariationArchive::scvs`] and their origins on iterating
+    /// (see [`deserialize_scvs`])
+    pub fn include_scv(mut self, include: bool) -> Self {
+        self.include_scv = include;
+        self
+    }
+
+    /// Whether malformed records are skipped on iterating
+    pub fn skips_malformed(&self) -> bool {
+        self.skip_malformed
+    }
+
+    /// Number of malformed records skipped on iterating
+    pub fn skipped(&self) -> u64 {
+        self.skipped
+    }
+
+    /// Position in the input
+    pub fn position(&self) -> usize {
+        self.input_position()
+    }
+
+    fn input_position(&self) -> usize {
+        shift(self.reader.buffer_position(), self.start, self.prefix)
+    }
+
+    /// Number of `VariationArchive` elements seen, including those skipped by the offset
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Read up to the root element, returning its `ReleaseDate`
+    ///
+    /// This is done by the first read of a record if not called. Calling it again after an
+    /// error of malformed XML continues reading.
+    ///
+    /// returns: the `ReleaseDate` normalized by [`normalize_date`], `None` if the root element
+    ///          has none or the input has no root element
+    pub fn read_header(&mut self) -> Result<Option<&str>, ClinvarError> {
+        while self.release_date.is_none() {
+            self.buf.clear();
+            let event = match self.reader.read_event_into(&mut self.buf) {
+                Ok(x) => x,
+                Err(e) => return Err(error(e, self.input_position(), &mut self.done)),
+            };
+            let release_date = match event {
+                Event::Start(x) if x.name().as_ref() == TAG_RELEASE => {
+                    self.in_release = true;
+                    x.try_get_attribute("ReleaseDate")
+                        .ok()
+                        .flatten()
+                        .and_then(|x| x.unescape_value().ok())
+                        .and_then(|x| normalize_date(&x).map(|x| x.to_string()))
+                }
+                Event::Start(x) => {
+                    self.pending = Some(x.into_owned());
+                    None
+                }
+                Event::Empty(_) | Event::Eof => None,
+                _ => continue,
+            };
+            self.release_date = Some(release_date);
+        }
+
+        Ok(self.release_date.as_ref().and_then(|x| x.as_deref()))
+    }
+
+    /// Read the next element as the input bytes, which are valid until the next read
+    ///
+    /// After an error of malformed XML, the next call continues with the following elements.
+    /// Errors reading the input and a truncated input end the stream.
+    ///
+    /// returns: the bytes of the element and the position in the input after its start tag, or
+    ///          `None` at the end
+    pub fn read_raw(&mut self) -> Option<Result<(&[u8], usize), ClinvarError>> {
+        if self.done {
+            return None;
+        }
+        if let Err(e) = self.read_header() {
+            return Some(Err(e));
+        }
+
+        loop {
+            if self.limit.is_some_and(|x| self.count >= self.offset + x) {
+                self.done = true;
+                return None;
+            }
+
+            if let Some(start_tag) = self.pending.take() {
+                if start_tag.name().as_ref() == TAG_VARIATION_ARCHIVE {
+                    self.count += 1;
+                    let skip = self.count <= self.offset;
+                    let position = shift(self.reader.buffer_position(), self.start, self.prefix);
+                    match read_element(&mut self.reader, &start_tag, &mut self.junk_buf, skip) {
+                        Ok(true) => return Some(Ok((self.reader.get_mut().stop(), position))),
+                        Ok(false) => {}
+                        Err(e) => {
+                            return Some(Err(error(e, self.input_position(), &mut self.done)))
+                        }
+                    }
+                }
+                continue;
+            }
+
+            self.buf.clear();
+            let event = match self.reader.read_event_into(&mut self.buf) {
+                Ok(x) => x,
+                Err(e) => return Some(Err(error(e, self.input_position(), &mut self.done))),
+            };
+            match event {
+                Event::Eof if self.in_release => {
+                    self.done = true;
+                    return Some(Err(ClinvarError::Io(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        format!(
+                            "Input ended before </ClinVarVariationRelease> at position {} \
+                             (truncated?)",
+                            self.input_position()
+                        ),
+                    ))));
+                }
+                Event::Eof => {
+                    self.done = true;
+                    return None;
+                }
+                Event::Start(x) if x.name().as_ref() == TAG_RELEASE => self.in_release = true,
+                Event::End(x) if x.name().as_ref() == TAG_RELEASE => self.in_release = false,
+                Event::Start(start_tag) if start_tag.name().as_ref() == TAG_VARIATION_ARCHIVE => {
+                    self.count += 1;
+                    let skip = self.count <= self.offset;
+                    let position = shift(self.reader.buffer_position(), self.start, self.prefix);
+                    match read_element(&mut self.reader, &start_tag, &mut self.junk_buf, skip) {
+                        Ok(true) => return Some(Ok((self.reader.get_mut().stop(), position))),
+                        Ok(false) => {}
+                        Err(e) => {
+                            return Some(Err(error(e, self.input_position(), &mut self.done)))
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Position in the input of a position of the reader after `prefix` bytes, starting at `start`
+fn shift(position: usize, start: usize, prefix: usize) -> usize {
+    (position + start).saturating_sub(prefix)
+}
+
+/// Error reading XML at the current position, ending the stream if the input failed
+fn error(e: quick_xml::Error, position: usize, done: &mut bool) -> ClinvarError {
+    match e {
+        quick_xml::Error::Io(e) => {
+            *done = true;
+            ClinvarError::Io(io::Error::new(
+                e.kind(),
+                format!("Failed to read the input at position {}: {}", position, e),
+            ))
+        }
+        source => ClinvarError::Xml { position, source },
+    }
+}
+
+impl VariationArchiveReader<Box<dyn BufRead>> {
+    /// Open a file, decompressing gzip detected by the magic bytes
+    pub fn from_path<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut f = BufReader::new(File::open(path)?);
+        let r: Box<dyn BufRead> = if f.fill_buf()?.starts_with(&GZIP_MAGIC) {
+            Box::new(BufReader::new(MultiGzDecoder::new(f)))
+        } else {
+            Box::new(f)
+        };
+
+        Ok(Self::from_reader(r))
+    }
+}
+
+impl VariationArchiveReader<Box<dyn BufRead>> {
+    /// Continue reading a release between two elements, e.g. from a [`position`](Self::position)
+    /// after an element the input was seeked to
+    ///
+    /// # Arguments
+    ///
+    /// * `inner`: the input from `position`
+    /// * `release_date`: `ReleaseDate` of the root element, as given by
+    ///   [`read_header`](Self::read_header)
+    /// * `position`: position in the input `inner` starts at
+    /// * `count`: [`count`](Self::count) of elements before `position`
+    ///
+    /// returns: VariationArchiveReader<Box<dyn BufRead>>
+    pub fn resume(
+        inner: Box<dyn BufRead>,
+        release_date: Option<&str>,
+        position: usize,
+        count: u64,
+    ) -> Self {
+        // the root element is given again, so that its end tag is matched
+        let root = match release_date {
+            Some(x) => format!(
+                "<{} ReleaseDate=\"{}\">",
+                from_utf8(TAG_RELEASE).unwrap(),
+                x
+            ),
+            None => format!("<{}>", from_utf8(TAG_RELEASE).unwrap()),
+        }
+        .into_bytes();
+        let prefix = root.len();
+
+        let mut reader = Self::from_reader(Box::new(io::Cursor::new(root).chain(inner)) as _);
+        reader.start = position;
+        reader.prefix = prefix;
+        reader.count = count;
+        reader
+    }
+}
+
+impl<R: BufRead> VariationArchiveReader<R> {
+    /// Read and deserialize the next element, checking it in the strict mode
+    pub(crate) fn read_record(&mut self) -> Option<Result<VariationArchive, ClinvarError>> {
+        let strict = self.strict;
+        let include_scv = self.include_scv;
+        Some(self.read_raw()?.and_then(|(bytes, position)| {
+            if strict {
+                schema::validate(bytes, position)?;
+            }
+            let mut archive = deserialize(bytes, position)?;
+            if include_scv {
+                deserialize_scvs(&mut archive, bytes, position)?;
+            }
+            Ok(archive)
+        }))
+    }
+}
+
+impl<R: BufRead> Iterator for VariationArchiveReader<R> {
+    type Item = Result<VariationArchive, ClinvarError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.read_record()? {
+                Err(ClinvarError::Xml { .. } | ClinvarError::Deserialize { .. })
+                    if self.skip_malformed =>
+                {
+                    self.skipped += 1;
+                }
+                result => return Some(result),
+            }
+        }
+    }
+}
+
+/// Deserialize a `VariationArchive` element
+///
+/// # Arguments
+///
+/// * `bytes`: the element
+/// * `position`: position in the input after its start tag, to report on failure
+///
+/// returns: Result<VariationArchive, ClinvarError>
+pub fn deserialize(bytes: &[u8], position: usize) -> Result<VariationArchive, ClinvarError> {
+    let error = |source: Box<dyn std::error::Error + Send + Sync>| ClinvarError::Deserialize {
+        variation_id: variation_id(bytes),
+        position,
+        source,
+    };
+    let str = from_utf8(bytes).map_err(|e| error(e.into()))?;
+
+    let mut deserializer = Deserializer::from_str(str);
+
+    VariationArchive::deserialize(&mut deserializer).map_err(|e| error(e.into()))
+}
+
+/// Deserialize the submitted records of a `VariationArchive` element into
+/// [`VariationArchive::scvs`], ordered by accession, and their origins into
+/// [`ClassifiedRecord::clinical_assertion_list`](crate::ClassifiedRecord::clinical_assertion_list)
+///
+/// They are read apart from [`deserialize`], which skips the `ClinicalAssertionList`, so that
+/// their cost is only paid on request.
+///
+/// # Arguments
+///
+/// * `archive`: `VariationArchive` deserialized from `bytes`
+/// * `bytes`: `VariationArchive` element
+/// * `position`: position of the element in the input, for errors
+///
+/// returns: Result<(), ClinvarError>
+pub fn deserialize_scvs(
+    archive: &mut VariationArchive,
+    bytes: &[u8],
+    position: usize,
+) -> Result<(), ClinvarError> {
+    let error = |source: Box<dyn std::error::Error + Send + Sync>| ClinvarError::Deserialize {
+        variation_id: variation_id(bytes),
+        position,
+        source,
+    };
+    let str = from_utf8(bytes).map_err(|e| error(e.into()))?;
+
+    let mut deserializer = Deserializer::from_str(str);
+    let scv_archive = ScvArchive::deserialize(&mut deserializer).map_err(|e| error(e.into()))?;
+    let Some(assertion_list) = scv_archive
+        .classified_record
+        .and_then(|x| x.clinical_assertion_list)
+    else {
+        archive.scvs = Some(Vec::new());
+        return Ok(());
+    };
+
+    let mut assertions = Vec::with_capacity(assertion_list.clinical_assertion.len());
+    let mut scvs = assertion_list
+        .clinical_assertion
+        .into_iter()
+        .filter_map(|x| {
+            assertions.push(ClinicalAssertion {
+                id: x.id,
+                observed_in_list: x.observed_in_list,
+            });
+            let accession = x.accession?;
+            let classification = x.classification;
+            let mut pmids = x
+                .citation
+                .iter()
+                .chain(classification.iter().flat_map(|x| x.citation.iter()))
+                .flat_map(|x| x.pmids())
+                .collect::<Vec<u64>>();
+            pmids.sort_unstable();
+            pmids.dedup();
+            Some(Scv {
+                accession: match accession.version {
+                    Some(version) => format!("{}.{}", accession.accession, version),
+                    None => accession.accession,
+                },
+                submitter_name: accession
+                    .submitter_name
+                    .map(|x| x.trim().to_string())
+                    .filter(|x| !x.is_empty()),
+                classification: classification.as_ref().and_then(|x| {
+                    [
+                        &x.germline_classification,
+                        &x.somatic_clinical_impact,
+                        &x.oncogenicity_classification,
+                    ]
+                    .into_iter()
+                    .flatten()
+                    .map(|x| x.text.trim().to_string())
+                    .find(|x| !x.is_empty())
+                }),
+                date_last_evaluated: classification
+                    .as_ref()
+                    .and_then(|x| x.date_last_evaluated.as_deref())
+                    .and_then(normalize_date)
+                    .map(|x| x.to_string()),
+                pmids,
+            })
+        })
+        .collect::<Vec<Scv>>();
+    scvs.sort_by(|a, b| a.accession.cmp(&b.accession));
+
+    archive.scvs = Some(scvs);
+    if let Some(record) = archive.classified_record.as_mut() {
+        record.clinical_assertion_list = Some(ClinicalAssertionList {
+            clinical_assertion: assertions,
+        });
+    }
+
+    Ok(())
+}
+
+/// `VariationArchive` with only the submitted records, for [`deserialize_scvs`]
+#[derive(Debug, Deserialize)]
+struct ScvArchive {
+    #[serde(rename = "ClassifiedRecord")]
+    classified_record: Option<ScvRecord>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ScvRecord {
+    #[serde(rename = "ClinicalAssertionList")]
+    clinical_assertion_list: Option<ScvAssertionList>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ScvAssertionList {
+    #[serde(default, rename = "ClinicalAssertion")]
+    clinical_assertion: Vec<ScvAssertion>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ScvAssertion {
+    #[serde(rename = "@ID")]
+    id: Option<u64>,
+    #[serde(rename = "ObservedInList")]
+    observed_in_list: Option<ObservedInList>,
+    #[serde(rename = "ClinVarAccession")]
+    accession: Option<ScvAccession>,
+    #[serde(rename = "Classification")]
+    classification: Option<ScvClassification>,
+    #[serde(default, rename = "Citation")]
+    citation: Vec<Citation>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ScvAccession {
+    #[serde(rename = "@Accession")]
+    accession: String,
+    #[serde(rename = "@Version")]
+    version: Option<i32>,
+    #[serde(rename = "@SubmitterName")]
+    submitter_name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ScvClassification {
+    #[serde(rename = "@DateLastEvaluated")]
+    date_last_evaluated: Option<String>,
+    #[serde(rename = "GermlineClassification")]
+    germline_classification: Option<ScvText>,
+    #[serde(rename = "SomaticClinicalImpact")]
+    somatic_clinical_impact: Option<ScvText>,
+    #[serde(rename = "OncogenicityClassification")]
+    oncogenicity_classification: Option<ScvText>,
+    #[serde(default, rename = "Citation")]
+    citation: Vec<Citation>,
+}
+
+/// Text of an element which may have attributes
+#[derive(Debug, Deserialize)]
+struct ScvText {
+    #[serde(default, rename = "$text")]
+    text: String,
+}
+
+/// `VariationID` in the start tag of a `VariationArchive` element
+fn variation_id(bytes: &[u8]) -> Option<u64> {
+    match Reader::from_reader(bytes).read_event().ok()? {
+        Event::Start(x) => from_utf8(&x.try_get_attribute("VariationID").ok()??.value)
+            .ok()?
+            .parse()
+            .ok(),
+        _ => None,
+    }
+}
+
+/// Read the rest of the element after its start tag
+///
+/// returns: false if skipped, otherwise the input bytes of the whole element are recorded
+fn read_element<R: BufRead>(
+    reader: &mut Reader<RecordingReader<R>>,
+    start_tag: &BytesStart,
+    buf: &mut Vec<u8>,
+    skip: bool,
+) -> Result<bool, quick_xml::Error> {
+    let tag_name = start_tag.name();
+    if skip {
+        buf.clear();
+        reader.read_to_end_into(tag_name, buf)?;
+        return Ok(false);
+    }
+    reader.get_mut().start(start_tag);
+
+    let mut depth = 0;
+    loop {
+        buf.clear();
+
+        let event = reader.read_event_into(buf)?;
+
+        match event {
+            Event::Start(e) if e.name() == tag_name => depth += 1,
+            Event::End(e) if e.name() == tag_name => {
+                if depth == 0 {
+                    return Ok(true);
+                }
+                depth -= 1;
+            }
+            Event::Eof => {
+                return Err(quick_xml::Error::UnexpectedEof(
+                    "Unexpected end of file (EOF) encountered.".to_string(),
+                ));
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Reader keeping the bytes consumed from the inner reader since [`RecordingReader::start`]
+///
+/// This gives the input bytes of an element without serializing its events again.
+struct RecordingReader<R> {
+    inner: R,
+    recording: bool,
+    /// Reused across records
+    recorded: Vec<u8>,
+}
+
+impl<R: BufRead> RecordingReader<R> {
+    fn new(inner: R) -> Self {
+        RecordingReader {
+            inner,
+            recording: false,
+            recorded: Vec::new(),
+        }
+    }
+
+    /// Start recording after the start tag just read
+    fn start(&mut self, start_tag: &BytesStart) {
+        self.recorded.clear();
+        self.recorded.push(b'<');
+        self.recorded.extend_from_slice(start_tag);
+        self.recorded.push(b'>');
+        self.recording = true;
+    }
+
+    fn stop(&mut self) -> &[u8] {
+        self.recording = false;
+        &self.recorded
+    }
+}
+
+impl<R: BufRead> Read for RecordingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if self.recording {
+            self.recorded.extend_from_slice(&buf[..n]);
+        }
+
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for RecordingReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if self.recording {
+            // the bytes to consume are still buffered, so this does not read
+            if let Ok(x) = self.inner.fill_buf() {
+                self.recorded.extend_from_slice(&x[..amt.min(x.len())]);
+            }
+        }
+        self.inner.consume(amt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture_gen::{self, LocationSpec, Malformation, RcvSpec, RecordSpec, Spec};
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    fn record(variation_id: u64) -> RecordSpec {
+        RecordSpec {
+            variation_id,
+            locations: vec![LocationSpec {
+                assembly: "GRCh38".to_string(),
+                chr: "1".to_string(),
+                position: variation_id * 100,
+                reference: "A".to_string(),
+                alternate: "G".to_string(),
+            }],
+            rcvs: vec![RcvSpec {
+                accession: format!("RCV{:09}", variation_id),
+                classification: "Pathogenic".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    fn document(records: Vec<RecordSpec>) -> String {
+        fixture_gen::generate(&Spec {
+            release_date: Some("2024-05-01".to_string()),
+            records,
+        })
+    }
+
+    /// Results of reading `xml` until the first error
+    fn read(xml: &[u8]) -> (Vec<u64>, Option<ClinvarError>) {
+        let mut ids = Vec::new();
+        for result in VariationArchiveReader::from_reader(xml) {
+            match result {
+                Ok(x) => ids.push(x.variation_id),
+                Err(e) => return (ids, Some(e)),
+            }
+        }
+
+        (ids, None)
+    }
+
+    #[test]
+    fn complete_document_has_no_error() {
+        let (ids, error) = read(document(vec![record(1), record(2)]).as_bytes());
+
+        assert_eq!(ids, vec![1, 2]);
+        assert!(error.is_none(), "{:?}", error);
+    }
+
+    #[test]
+    fn truncated_compressed_stream_is_an_io_error() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder
+            .write_all(document(vec![record(1), record(2)]).as_bytes())
+            .unwrap();
+        let compressed = encoder.finish().unwrap();
+        let inner = BufReader::new(MultiGzDecoder::new(&compressed[..compressed.len() / 2]));
+
+        let error = VariationArchiveReader::from_reader(inner).find_map(|x| x.err());
+
+        assert!(matches!(error, Some(ClinvarError::Io(_))), "{:?}", error);
+    }
+
+    #[test]
+    fn document_truncated_between_elements_is_an_io_error() {
+        let xml = document(vec![record(1), record(2)]);
+        let cut = xml.rfind("</ClinVarVariationRelease>").unwrap();
+
+        let (ids, error) = read(&xml.as_bytes()[..cut]);
+
+        assert_eq!(ids, vec![1, 2]);
+        match error {
+            Some(ClinvarError::Io(e)) => {
+                assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+                assert!(e.to_string().contains("(truncated?)"), "{}", e);
+            }
+            e => panic!("{:?}", e),
+        }
+    }
+
+    #[test]
+    fn document_truncated_within_an_element_is_an_xml_error() {
+        let xml = document(vec![record(1), record(2)]);
+        let cut = xml.rfind("<RCVList>").unwrap();
+
+        let (ids, error) = read(&xml.as_bytes()[..cut]);
+
+        assert_eq!(ids, vec![1]);
+        assert!(
+            matches!(error, Some(ClinvarError::Xml { .. })),
+            "{:?}",
+            error
+        );
+    }
+
+    #[test]
+    fn mismatched_tags_are_an_xml_error() {
+        let xml = document(vec![record(1)]).replace("</SimpleAllele>", "</Allele>");
+        let tag = xml.find("</Allele>").unwrap();
+
+        let (_, error) = read(xml.as_bytes());
+
+        match error {
+            Some(ClinvarError::Xml { position, .. }) => {
+                assert!(
+                    (tag..tag + "</Allele>".len()).contains(&position),
+                    "{}",
+                    position
+                )
+            }
+            e => panic!("{:?}", e),
+        }
+    }
+
+    #[test]
+    fn undeserializable_element_is_a_deserialize_error() {
+        let mut broken = record(2);
+        broken.malformations = vec![Malformation::MissingAlleleId];
+
+        let (ids, error) = read(document(vec![record(1), broken]).as_bytes());
+
+        assert_eq!(ids, vec![1]);
+        match error {
+            Some(ClinvarError::Deserialize { variation_id, .. }) => {
+                assert_eq!(variation_id, Some(2))
+            }
+            e => panic!("{:?}", e),
+        }
+    }
+
+    #[test]
+    fn submitted_records_are_only_read_on_request() {
+        let assertions = r#"<ClinicalAssertionList>
+<ClinicalAssertion ID="20">
+<ClinVarAccession Accession="SCV000000002" Version="3" SubmitterName="Lab B"/>
+<ObservedInList><ObservedIn><Sample><Origin>de novo</Origin></Sample></ObservedIn></ObservedInList>
+</ClinicalAssertion>
+<ClinicalAssertion ID="10">
+<ClinVarAccession Accession="SCV000000001" Version="1"/>
+<ObservedInList><ObservedIn><Sample><Origin>germline</Origin></Sample></ObservedIn></ObservedInList>
+</ClinicalAssertion>
+</ClinicalAssertionList>
+</ClassifiedRecord>"#;
+        let xml = document(vec![record(1)]).replace("</ClassifiedRecord>", assertions);
+        let read = |include_scv| {
+            VariationArchiveReader::from_reader(xml.as_bytes())
+                .include_scv(include_scv)
+                .next()
+                .unwrap()
+                .unwrap()
+        };
+
+        let archive = read(false);
+        assert!(archive.scvs.is_none());
+        let record = archive.classified_record.as_ref().unwrap();
+        assert!(record.clinical_assertion_list.is_none());
+
+        let archive = read(true);
+        let accessions = archive
+            .scvs
+            .as_ref()
+            .unwrap()
+            .iter()
+            .map(|x| x.accession.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(accessions, vec!["SCV000000001.1", "SCV000000002.3"]);
+        let record = archive.classified_record.as_ref().unwrap();
+        let ids = record
+            .clinical_assertion_list
+            .as_ref()
+            .unwrap()
+            .clinical_assertion
+            .iter()
+            .map(|x| x.id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![Some(20), Some(10)]);
+        assert_eq!(
+            crate::vcf::extract_origin(record).as_deref(),
+            Some("de_novo/germline")
+        );
+    }
+}
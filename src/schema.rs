@@ -0,0 +1,530 @@
+//! Check of `VariationArchive` elements against the elements and attributes known to this tool
+//!
+//! Deserializing ignores anything unknown, so a change of the ClinVar schema would silently drop
+//! data. Each element read into [`VariationArchive`](crate::VariationArchive) is listed here with
+//! its attributes and children; the subtrees of children left out on purpose are allowlisted and
+//! not checked.
+
+use crate::{ClinvarError, SkipReason};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::str::from_utf8;
+
+/// Element read into the structs
+struct Element {
+    name: &'static str,
+    attributes: &'static [&'static str],
+    /// Children read into the structs, checked if listed themselves
+    children: &'static [&'static str],
+    /// Children not read, whose subtrees are not checked
+    ignored: &'static [&'static str],
+}
+
+const SCHEMA: &[Element] = &[
+    Element {
+        name: "VariationArchive",
+        attributes: &[
+            "VariationID",
+            "VariationName",
+            "VariationType",
+            "Accession",
+            "Version",
+            "RecordType",
+            "RecordStatus",
+            "NumberOfSubmitters",
+            "NumberOfSubmissions",
+            "DateCreated",
+            "DateLastUpdated",
+            "MostRecentSubmission",
+        ],
+        children: &[
+            "RecordStatus",
+            "Species",
+            "ClassifiedRecord",
+            "IncludedRecord",
+        ],
+        ignored: &["ReplacedBy", "ReplacedList", "Comment"],
+    },
+    Element {
+        name: "ClassifiedRecord",
+        attributes: &[],
+        children: &[
+            "SimpleAllele",
+            "Haplotype",
+            "Genotype",
+            "RCVList",
+            "Classifications",
+            "ClinicalAssertionList",
+        ],
+        ignored: &["TraitMappingList", "DeletedSCVList", "GeneralCitations"],
+    },
+    Element {
+        name: "IncludedRecord",
+        attributes: &[],
+        children: &["SimpleAllele", "Haplotype"],
+        ignored: &[
+            "Classifications",
+            "SubmittedClassificationList",
+            "ClassifiedVariationList",
+            "GeneralCitations",
+        ],
+    },
+    Element {
+        name: "SimpleAllele",
+        attributes: &["AlleleID", "VariationID"],
+        children: &[
+            "VariantType",
+            "GeneList",
+            "Location",
+            "HGVSlist",
+            "XRefList",
+        ],
+        ignored: &[
+            "Name",
+            "CanonicalSPDI",
+            "OtherNameList",
+            "ProteinChange",
+            "Classifications",
+            "Comment",
+            "FunctionalConsequence",
+            "AlleleFrequencyList",
+            "GlobalMinorAlleleFrequency",
+        ],
+    },
+    Element {
+        name: "Haplotype",
+        attributes: &["VariationID", "NumberOfChromosomes", "NumberOfCopies"],
+        children: &["SimpleAllele"],
+        ignored: &[
+            "Name",
+            "VariationType",
+            "OtherNameList",
+            "HGVS",
+            "Classifications",
+            "FunctionalConsequence",
+            "XRefList",
+            "CitationList",
+            "Comment",
+        ],
+    },
+    Element {
+        name: "Genotype",
+        attributes: &["VariationID"],
+        children: &["SimpleAllele", "Haplotype"],
+        ignored: &[
+            "Name",
+            "VariationType",
+            "OtherNameList",
+            "HGVSlist",
+            "FunctionalConsequence",
+            "XRefList",
+            "CitationList",
+            "Comment",
+        ],
+    },
+    Element {
+        name: "GeneList",
+        attributes: &[],
+        children: &["Gene"],
+        ignored: &[],
+    },
+    Element {
+        name: "Gene",
+        attributes: &[
+            "Symbol",
+            "FullName",
+            "GeneID",
+            "HGNC_ID",
+            "Source",
+            "RelationshipType",
+        ],
+        children: &[],
+        ignored: &[
+            "Location",
+            "OMIM",
+            "Haploinsufficiency",
+            "Triplosensitivity",
+            "Property",
+            "Comment",
+        ],
+    },
+    Element {
+        name: "Location",
+        attributes: &[],
+        children: &["SequenceLocation"],
+        ignored: &["CytogeneticLocation", "GeneLocation", "XRef"],
+    },
+    Element {
+        name: "SequenceLocation",
+        attributes: &[
+            "Assembly",
+            "AssemblyAccessionVersion",
+            "AssemblyStatus",
+            "Chr",
+            "Accession",
+            "forDisplay",
+            "start",
+            "stop",
+            "innerStart",
+            "innerStop",
+            "outerStart",
+            "outerStop",
+            "display_start",
+            "display_stop",
+            "Strand",
+            "variantLength",
+            "referenceAllele",
+            "alternateAllele",
+            "positionVCF",
+            "referenceAlleleVCF",
+            "alternateAlleleVCF",
+        ],
+        children: &[],
+        ignored: &[],
+    },
+    Element {
+        name: "HGVSlist",
+        attributes: &[],
+        children: &["HGVS"],
+        ignored: &[],
+    },
+    Element {
+        name: "HGVS",
+        attributes: &["Type", "Assembly"],
+        children: &[
+            "NucleotideExpression",
+            "ProteinExpression",
+            "MolecularConsequence",
+        ],
+        ignored: &[],
+    },
+    Element {
+        name: "NucleotideExpression",
+        attributes: &[
+            "sequenceType",
+            "sequenceAccessionVersion",
+            "sequenceAccession",
+            "sequenceVersion",
+            "change",
+            "Assembly",
+            "Submitted",
+            "MANESelect",
+            "MANEPlusClinical",
+        ],
+        children: &["Expression"],
+        ignored: &[],
+    },
+    Element {
+        name: "ProteinExpression",
+        attributes: &[
+            "sequenceType",
+            "sequenceAccessionVersion",
+            "sequenceAccession",
+            "sequenceVersion",
+            "change",
+            "Assembly",
+            "Submitted",
+            "MANESelect",
+            "MANEPlusClinical",
+        ],
+        children: &["Expression"],
+        ignored: &[],
+    },
+    Element {
+        name: "MolecularConsequence",
+        attributes: &["ID", "Type", "DB", "URL"],
+        children: &[],
+        ignored: &[],
+    },
+    Element {
+        name: "XRefList",
+        attributes: &[],
+        children: &["XRef"],
+        ignored: &[],
+    },
+    Element {
+        name: "XRef",
+        attributes: &["DB", "ID", "Type", "URL", "Status"],
+        children: &[],
+        ignored: &[],
+    },
+    Element {
+        name: "RCVList",
+        attributes: &[],
+        children: &["RCVAccession"],
+        ignored: &[],
+    },
+    Element {
+        name: "RCVAccession",
+        attributes: &["Title", "Accession", "Version"],
+        children: &["ClassifiedConditionList", "RCVClassifications"],
+        ignored: &[],
+    },
+    Element {
+        name: "ClassifiedConditionList",
+        attributes: &["TraitSetID"],
+        children: &["ClassifiedCondition"],
+        ignored: &[],
+    },
+    Element {
+        name: "ClassifiedCondition",
+        attributes: &["DB", "ID"],
+        children: &[],
+        ignored: &[],
+    },
+    Element {
+        name: "RCVClassifications",
+        attributes: &[],
+        children: &[
+            "GermlineClassification",
+            "SomaticClinicalImpact",
+            "OncogenicityClassification",
+        ],
+        ignored: &[],
+    },
+    Element {
+        name: "Classifications",
+        attributes: &[],
+        children: &["GermlineClassification"],
+        ignored: &["SomaticClinicalImpact", "OncogenicityClassification"],
+    },
+    // the RCV and the aggregate classifications share the name
+    Element {
+        name: "GermlineClassification",
+        attributes: &[
+            "NumberOfSubmissions",
+            "NumberOfSubmitters",
+            "DateLastEvaluated",
+            "DateCreated",
+            "MostRecentSubmission",
+        ],
+        children: &["ReviewStatus", "Description", "Citation"],
+        ignored: &[
+            "Explanation",
+            "XRef",
+            "Comment",
+            "ConditionList",
+            "HistoricalRecords",
+        ],
+    },
+    Element {
+        name: "SomaticClinicalImpact",
+        attributes: &[],
+        children: &["ReviewStatus", "Description", "Citation"],
+        ignored: &[],
+    },
+    Element {
+        name: "OncogenicityClassification",
+        attributes: &[],
+        children: &["ReviewStatus", "Description", "Citation"],
+        ignored: &[],
+    },
+    Element {
+        name: "Citation",
+        attributes: &["Type", "Abbrev"],
+        children: &["ID"],
+        ignored: &["URL", "CitationText"],
+    },
+    Element {
+        name: "ID",
+        attributes: &["Source", "curie"],
+        children: &[],
+        ignored: &[],
+    },
+    Element {
+        name: "Description",
+        attributes: &[
+            "SubmissionCount",
+            "DateLastEvaluated",
+            "ClinicalImpactAssertionType",
+            "ClinicalImpactClinicalSignificance",
+        ],
+        children: &[],
+        ignored: &[],
+    },
+    Element {
+        name: "ClinicalAssertionList",
+        attributes: &[],
+        children: &["ClinicalAssertion"],
+        ignored: &[],
+    },
+    Element {
+        name: "ClinicalAssertion",
+        attributes: &[
+            "ID",
+            "SubmissionDate",
+            "DateLastUpdated",
+            "DateCreated",
+            "FDARecognizedDatabase",
+            "ContributesToAggregateClassification",
+        ],
+        children: &["ObservedInList"],
+        ignored: &[
+            "ClinVarSubmissionID",
+            "ClinVarAccession",
+            "AdditionalSubmitters",
+            "RecordStatus",
+            "ReplacedList",
+            "Classification",
+            "Assertion",
+            "AttributeSet",
+            "SimpleAllele",
+            "Haplotype",
+            "Genotype",
+            "TraitSet",
+            "Citation",
+            "StudyName",
+            "StudyDescription",
+            "Comment",
+            "SubmissionNameList",
+        ],
+    },
+    Element {
+        name: "ObservedInList",
+        attributes: &[],
+        children: &["ObservedIn"],
+        ignored: &[],
+    },
+    Element {
+        name: "ObservedIn",
+        attributes: &[],
+        children: &["Sample"],
+        ignored: &[
+            "Method",
+            "ObservedData",
+            "Co-occurrenceSet",
+            "TraitSet",
+            "Citation",
+            "XRef",
+            "Comment",
+        ],
+    },
+    Element {
+        name: "Sample",
+        attributes: &[],
+        children: &["Origin"],
+        ignored: &[
+            "SampleDescription",
+            "Ethnicity",
+            "GeographicOrigin",
+            "Tissue",
+            "CellLine",
+            "Species",
+            "Age",
+            "Strain",
+            "AffectedStatus",
+            "NumberTested",
+            "NumberMales",
+            "NumberFemales",
+            "NumberChrTested",
+            "Gender",
+            "FamilyData",
+            "Proband",
+            "Indication",
+            "Citation",
+            "XRef",
+            "Comment",
+            "SourceType",
+        ],
+    },
+];
+
+/// Check a `VariationArchive` element, failing at the first unknown element or attribute
+///
+/// # Arguments
+///
+/// * `bytes`: the element
+/// * `position`: position in the input after its start tag, to report on failure
+///
+/// returns: Result<(), ClinvarError>
+pub fn validate(bytes: &[u8], position: usize) -> Result<(), ClinvarError> {
+    let mut reader = Reader::from_reader(bytes);
+    let mut accession = None;
+    let mut path: Vec<&Element> = Vec::new();
+
+    loop {
+        let event = reader
+            .read_event()
+            .map_err(|source| ClinvarError::Xml { position, source })?;
+        let (start, empty) = match event {
+            Event::Start(x) => (x, false),
+            Event::Empty(x) => (x, true),
+            Event::End(_) => {
+                path.pop();
+                continue;
+            }
+            Event::Eof => return Ok(()),
+            _ => continue,
+        };
+
+        let name = from_utf8(start.name().into_inner()).unwrap_or_default();
+        let known = match path.last() {
+            None => {
+                accession = attribute(&start, "Accession");
+                Some(name == "VariationArchive")
+            }
+            Some(parent) if parent.children.contains(&name) => Some(true),
+            Some(parent) if parent.ignored.contains(&name) => Some(false),
+            Some(_) => None,
+        };
+        let element = match known {
+            Some(true) => SCHEMA.iter().find(|x| x.name == name),
+            Some(false) => None,
+            None => {
+                return Err(violation(
+                    &accession,
+                    format!("unknown element {} in {}", name, join(&path)),
+                    position,
+                ))
+            }
+        };
+
+        let Some(element) = element else {
+            // text only or allowlisted
+            if !empty {
+                reader
+                    .read_to_end(start.name())
+                    .map_err(|source| ClinvarError::Xml { position, source })?;
+            }
+            continue;
+        };
+        for attribute in start.attributes().flatten() {
+            let key = from_utf8(attribute.key.into_inner()).unwrap_or_default();
+            if !element.attributes.contains(&key) {
+                return Err(violation(
+                    &accession,
+                    format!("unknown attribute {} of {}/{}", key, join(&path), name),
+                    position,
+                ));
+            }
+        }
+        if !empty {
+            path.push(element);
+        }
+    }
+}
+
+fn attribute(start: &BytesStart, name: &str) -> Option<String> {
+    start
+        .try_get_attribute(name)
+        .ok()
+        .flatten()
+        .and_then(|x| x.unescape_value().ok())
+        .map(|x| x.to_string())
+}
+
+fn join(path: &[&Element]) -> String {
+    path.iter().map(|x| x.name).collect::<Vec<&str>>().join("/")
+}
+
+fn violation(accession: &Option<String>, detail: String, position: usize) -> ClinvarError {
+    ClinvarError::InvalidRecord {
+        reason: SkipReason::SchemaViolation,
+        detail: format!(
+            "accession = {}, {} (position {})",
+            accession.as_deref().unwrap_or("-"),
+            detail,
+            position
+        ),
+    }
+}
@@ -0,0 +1,313 @@
+//! External merge sort of VCF records in the order of `bcftools sort`
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Error, ErrorKind, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Estimated memory used by a buffered record besides its text
+const RECORD_OVERHEAD: usize = 64;
+
+/// Sort key: contig rank, position, then alleles compared case-insensitively
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Key {
+    rank: usize,
+    pos: u64,
+    alleles: Vec<String>,
+}
+
+/// Ranks of contigs, in the order of the `##contig` lines followed by undeclared ones as they appear
+#[derive(Debug, Default)]
+struct ContigRanks(HashMap<String, usize>);
+
+impl ContigRanks {
+    fn rank(&mut self, name: &str) -> usize {
+        let n = self.0.len();
+        *self.0.entry(name.to_string()).or_insert(n)
+    }
+
+    fn key(&mut self, line: &str) -> io::Result<Key> {
+        let mut columns = line.split('\t');
+        match (
+            columns.next(),
+            columns.next(),
+            columns.nth(1),
+            columns.next(),
+        ) {
+            (Some(chrom), Some(pos), Some(reference), Some(alternate)) => Ok(Key {
+                rank: self.rank(chrom),
+                pos: pos.parse().map_err(|_| {
+                    Error::new(
+                        ErrorKind::InvalidData,
+                        format!("Invalid position: {}", line.trim_end()),
+                    )
+                })?,
+                alleles: std::iter::once(reference)
+                    .chain(alternate.split(','))
+                    .map(|x| x.to_ascii_lowercase())
+                    .collect(),
+            }),
+            _ => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Invalid VCF line: {}", line.trim_end()),
+            )),
+        }
+    }
+}
+
+/// Sort a VCF by contig order of the header and position, keeping the header as is
+///
+/// Records at the same position are ordered by REF and ALT, ties keep the input order. Records
+/// are spilled to sorted temporary files in `temp_dir` once the buffer exceeds `memory` bytes, and
+/// the files are merged into `writer`.
+///
+/// # Arguments
+///
+/// * `reader`: VCF
+/// * `writer`: output
+/// * `memory`: approximate size of the record buffer in bytes
+/// * `temp_dir`: directory for the temporary files
+///
+/// returns: number of records
+pub fn sort_vcf<R: BufRead, W: Write>(
+    reader: R,
+    writer: &mut W,
+    memory: usize,
+    temp_dir: &Path,
+) -> io::Result<u64> {
+    let mut contigs = ContigRanks::default();
+    let mut sorter = Sorter::new(memory, temp_dir);
+
+    for line in reader.lines() {
+        let line = line?;
+        if line.starts_with('#') {
+            if let Some(id) = contig_id(&line) {
+                contigs.rank(id);
+            }
+            writeln!(writer, "{}", line)?;
+            continue;
+        }
+
+        sorter.push(contigs.key(&line)?, line)?;
+    }
+
+    sorter.finish(writer, |x| contigs.key(x))
+}
+
+/// Lines sorted by a key, spilled to sorted temporary files once the buffer exceeds the memory
+///
+/// Ties keep the order the lines were pushed in.
+pub(crate) struct Sorter<'a, K> {
+    records: Vec<(K, String)>,
+    size: usize,
+    chunks: Vec<File>,
+    count: u64,
+    memory: usize,
+    temp_dir: &'a Path,
+}
+
+impl<'a, K: Ord> Sorter<'a, K> {
+    /// # Arguments
+    ///
+    /// * `memory`: approximate size of the buffer in bytes
+    /// * `temp_dir`: directory for the temporary files
+    pub(crate) fn new(memory: usize, temp_dir: &'a Path) -> Self {
+        Sorter {
+            records: Vec::new(),
+            size: 0,
+            chunks: Vec::new(),
+            count: 0,
+            memory,
+            temp_dir,
+        }
+    }
+
+    pub(crate) fn push(&mut self, key: K, line: String) -> io::Result<()> {
+        self.size += line.len() + RECORD_OVERHEAD;
+        self.records.push((key, line));
+        self.count += 1;
+
+        if self.size > self.memory {
+            self.chunks.push(spill(&mut self.records, self.temp_dir)?);
+            self.size = 0;
+        }
+
+        Ok(())
+    }
+
+    /// Write the lines in order
+    ///
+    /// # Arguments
+    ///
+    /// * `writer`: output
+    /// * `key`: key of a line read back from a temporary file, the same as it was pushed with
+    ///
+    /// returns: number of lines
+    pub(crate) fn finish<W, F>(mut self, writer: &mut W, mut key: F) -> io::Result<u64>
+    where
+        W: Write,
+        F: FnMut(&str) -> io::Result<K>,
+    {
+        // stable, so that ties keep the input order
+        self.records.sort_by(|a, b| a.0.cmp(&b.0));
+        if self.chunks.is_empty() {
+            for (_, line) in self.records {
+                writeln!(writer, "{}", line)?;
+            }
+            return Ok(self.count);
+        }
+        if !self.records.is_empty() {
+            self.chunks.push(spill(&mut self.records, self.temp_dir)?);
+        }
+
+        let mut readers = self
+            .chunks
+            .into_iter()
+            .map(|x| BufReader::new(x).lines())
+            .collect::<Vec<_>>();
+        let mut heap = BinaryHeap::with_capacity(readers.len());
+        for (i, reader) in readers.iter_mut().enumerate() {
+            if let Some(line) = reader.next() {
+                let line = line?;
+                heap.push(Reverse((key(&line)?, i, line)));
+            }
+        }
+        while let Some(Reverse((_, i, line))) = heap.pop() {
+            writeln!(writer, "{}", line)?;
+            if let Some(next) = readers[i].next() {
+                let next = next?;
+                heap.push(Reverse((key(&next)?, i, next)));
+            }
+        }
+
+        Ok(self.count)
+    }
+}
+
+/// Write the records sorted to a temporary file, rewound for reading
+fn spill<K: Ord>(records: &mut Vec<(K, String)>, temp_dir: &Path) -> io::Result<File> {
+    records.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut writer = BufWriter::new(tempfile::tempfile_in(temp_dir)?);
+    for (_, line) in records.drain(..) {
+        writeln!(writer, "{}", line)?;
+    }
+    let mut file = writer.into_inner().map_err(|e| e.into_error())?;
+    file.seek(SeekFrom::Start(0))?;
+
+    Ok(file)
+}
+
+/// ID in a `##contig=<ID=...,...>` line
+fn contig_id(line: &str) -> Option<&str> {
+    line.strip_prefix("##contig=<")?
+        .trim_end_matches('>')
+        .split(',')
+        .find_map(|x| x.strip_prefix("ID="))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "##fileformat=VCFv4.3
+##contig=<ID=2,length=1000000>
+##contig=<ID=1,length=1000000>
+##contig=<ID=10,length=1000000>
+##contig=<ID=X,length=1000000>
+#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO
+";
+
+    /// Records in the order of `bcftools sort`: contigs as declared, then undeclared ones as
+    /// they appear in the input, positions, then REF and ALT
+    fn sorted_records() -> Vec<String> {
+        let mut records = Vec::new();
+        for chrom in ["2", "1", "10", "X"] {
+            for pos in [1, 9, 10, 100, 1000, 99999] {
+                for (reference, alternate) in [("A", "C"), ("A", "G"), ("AC", "A"), ("C", "A,T")] {
+                    records.push(format!(
+                        "{}\t{}\t.\t{}\t{}\t.\t.\t.",
+                        chrom, pos, reference, alternate
+                    ));
+                }
+            }
+        }
+        // undeclared, so after the declared ones
+        records.push("MT\t5\t.\tA\tG\t.\t.\t.".to_string());
+
+        records
+    }
+
+    /// The records in a fixed pseudo-random order
+    fn shuffle(records: &[String]) -> Vec<String> {
+        // 89 is coprime with the number of records
+        assert_ne!(records.len() % 89, 0);
+        (0..records.len())
+            .map(|i| records[i * 89 % records.len()].clone())
+            .collect()
+    }
+
+    fn sort(records: &[String], memory: usize) -> String {
+        let input = format!("{}{}\n", HEADER, records.join("\n"));
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut output = Vec::new();
+
+        let count = sort_vcf(input.as_bytes(), &mut output, memory, temp_dir.path()).unwrap();
+
+        assert_eq!(count, records.len() as u64);
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn records_are_sorted_in_contig_order() {
+        let sorted = sorted_records();
+        let shuffled = shuffle(&sorted);
+        assert_ne!(shuffled, sorted);
+        let expected = format!("{}{}\n", HEADER, sorted.join("\n"));
+
+        // in memory, spilling every few records and spilling every record
+        for memory in [usize::MAX, 500, 0] {
+            assert_eq!(sort(&shuffled, memory), expected, "memory = {}", memory);
+        }
+    }
+
+    #[test]
+    fn small_memory_spills_to_temporary_files() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let mut sorter = Sorter::new(500, temp_dir.path());
+        for (i, line) in shuffle(&sorted_records()).into_iter().enumerate() {
+            sorter.push(i, line).unwrap();
+        }
+
+        assert!(sorter.chunks.len() > 10, "{}", sorter.chunks.len());
+    }
+
+    #[test]
+    fn ties_keep_the_input_order_across_spills() {
+        let records = (0..50)
+            .map(|i| {
+                let (reference, alternate) = if i % 2 == 0 { ("A", "G") } else { ("a", "g") };
+                format!("1\t100\tid{}\t{}\t{}\t.\t.\t.", i, reference, alternate)
+            })
+            .chain(["1\t99\tfirst\tT\tC\t.\t.\t.".to_string()])
+            .collect::<Vec<_>>();
+        let mut expected = vec![records[50].clone()];
+        expected.extend_from_slice(&records[..50]);
+        let expected = format!("{}{}\n", HEADER, expected.join("\n"));
+
+        for memory in [usize::MAX, 300, 0] {
+            assert_eq!(sort(&records, memory), expected, "memory = {}", memory);
+        }
+    }
+
+    #[test]
+    fn invalid_lines_are_rejected() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        for line in ["1\t100\t.\tA", "1\tpos\t.\tA\tG\t.\t.\t."] {
+            let input = format!("{}{}\n", HEADER, line);
+            let e = sort_vcf(input.as_bytes(), &mut Vec::new(), 0, temp_dir.path()).unwrap_err();
+            assert_eq!(e.kind(), ErrorKind::InvalidData, "{}", line);
+        }
+    }
+}
@@ -0,0 +1,675 @@
+//! Tabix (.tbi) index reader, and .tbi/.csi index writer for bgzipped VCF
+
+use crate::bgzf;
+use flate2::read::MultiGzDecoder;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Error, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+
+const MAGIC: &[u8; 4] = b"TBI\x01";
+const MAGIC_CSI: &[u8; 4] = b"CSI\x01";
+const PSEUDO_BIN: u32 = 37450;
+const LINEAR_SHIFT: u64 = 14;
+/// Binning depth of .tbi, the minimum of .csi
+const DEPTH: u32 = 5;
+
+/// Tabix header fields for VCF: format, col_seq, col_beg, col_end, meta ('#'), skip
+const VCF_CONFIG: [i32; 6] = [2, 1, 2, 0, b'#' as i32, 0];
+
+/// Chunk of virtual offsets `[start, end)`
+pub type Chunk = (u64, u64);
+
+#[derive(Debug, Default)]
+pub struct ReferenceIndex {
+    pub bins: HashMap<u32, Vec<Chunk>>,
+    pub intervals: Vec<u64>,
+}
+
+#[derive(Debug)]
+pub struct Index {
+    pub format: i32,
+    pub col_seq: i32,
+    pub col_beg: i32,
+    pub col_end: i32,
+    pub meta: u8,
+    pub skip: i32,
+    pub names: Vec<String>,
+    pub references: Vec<ReferenceIndex>,
+}
+
+impl Index {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut buf = Vec::new();
+        MultiGzDecoder::new(BufReader::new(File::open(path)?)).read_to_end(&mut buf)?;
+
+        Self::parse(&buf)
+    }
+
+    fn parse(buf: &[u8]) -> io::Result<Self> {
+        let mut r = buf;
+
+        let mut magic = [0u8; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            Err(Error::new(ErrorKind::InvalidData, "Invalid tabix magic"))?
+        }
+
+        let n_ref = read_i32(&mut r)?;
+        let format = read_i32(&mut r)?;
+        let col_seq = read_i32(&mut r)?;
+        let col_beg = read_i32(&mut r)?;
+        let col_end = read_i32(&mut r)?;
+        let meta = read_i32(&mut r)? as u8;
+        let skip = read_i32(&mut r)?;
+        let l_nm = read_i32(&mut r)? as usize;
+
+        let mut names = vec![0u8; l_nm];
+        r.read_exact(&mut names)?;
+        let names = names
+            .split(|x| *x == 0)
+            .filter(|x| !x.is_empty())
+            .map(|x| String::from_utf8_lossy(x).into_owned())
+            .collect::<Vec<String>>();
+
+        let mut references = Vec::with_capacity(n_ref as usize);
+        for _ in 0..n_ref {
+            let mut reference = ReferenceIndex::default();
+
+            for _ in 0..read_i32(&mut r)? {
+                let bin = read_u32(&mut r)?;
+                let n_chunk = read_i32(&mut r)?;
+                let mut chunks = Vec::with_capacity(n_chunk as usize);
+                for _ in 0..n_chunk {
+                    chunks.push((read_u64(&mut r)?, read_u64(&mut r)?));
+                }
+                reference.bins.insert(bin, chunks);
+            }
+
+            for _ in 0..read_i32(&mut r)? {
+                reference.intervals.push(read_u64(&mut r)?);
+            }
+
+            references.push(reference);
+        }
+
+        Ok(Index {
+            format,
+            col_seq,
+            col_beg,
+            col_end,
+            meta,
+            skip,
+            names,
+            references,
+        })
+    }
+
+    /// Chunks which may contain records overlapping `[start, end)` (0-based), sorted and merged
+    pub fn query(&self, name: &str, start: u64, end: u64) -> Vec<Chunk> {
+        let reference = match self.names.iter().position(|x| x == name) {
+            Some(i) => &self.references[i],
+            None => return Vec::new(),
+        };
+
+        let min_offset = reference
+            .intervals
+            .get((start >> LINEAR_SHIFT) as usize)
+            .or(reference.intervals.last())
+            .copied()
+            .unwrap_or(0);
+
+        let mut chunks = region_to_bins(start, end)
+            .into_iter()
+            .filter(|x| *x != PSEUDO_BIN)
+            .filter_map(|x| reference.bins.get(&x))
+            .flatten()
+            .filter(|x| x.1 > min_offset)
+            .map(|x| (x.0.max(min_offset), x.1))
+            .collect::<Vec<Chunk>>();
+        chunks.sort();
+
+        let mut merged: Vec<Chunk> = Vec::with_capacity(chunks.len());
+        for chunk in chunks {
+            match merged.last_mut() {
+                Some(last) if chunk.0 <= last.1 => last.1 = last.1.max(chunk.1),
+                _ => merged.push(chunk),
+            }
+        }
+
+        merged
+    }
+}
+
+/// Bins overlapping `[start, end)` in the UCSC binning scheme used by tabix
+pub fn region_to_bins(start: u64, end: u64) -> Vec<u32> {
+    let end = end.max(start + 1) - 1;
+    let mut bins = vec![0];
+
+    for (offset, shift) in [(1, 26), (9, 23), (73, 20), (585, 17), (4681, 14)] {
+        for k in (offset + (start >> shift))..=(offset + (end >> shift)) {
+            bins.push(k as u32);
+        }
+    }
+
+    bins
+}
+
+fn read_i32<R: Read>(r: &mut R) -> io::Result<i32> {
+    let mut buf = [0u8; 4];
+    r.read_exact(&mut buf)?;
+    Ok(i32::from_le_bytes(buf))
+}
+
+fn read_u32<R: Read>(r: &mut R) -> io::Result<u32> {
+    let mut buf = [0u8; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64<R: Read>(r: &mut R) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    r.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexFormat {
+    Tbi,
+    Csi,
+}
+
+impl IndexFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            IndexFormat::Tbi => "tbi",
+            IndexFormat::Csi => "csi",
+        }
+    }
+}
+
+/// Index under construction for one sequence
+#[derive(Debug, Default)]
+struct ReferenceBuilder {
+    bins: BTreeMap<u32, Vec<Chunk>>,
+    /// Offset of the first record in each 16 kb window
+    intervals: Vec<Option<u64>>,
+    start: u64,
+    end: u64,
+    records: u64,
+}
+
+/// Write the index of a sorted, bgzipped VCF to `<path>.tbi` or `<path>.csi`
+///
+/// # Arguments
+///
+/// * `path`: bgzipped VCF sorted by position within each contig
+/// * `format`: `IndexFormat::Tbi`, or `IndexFormat::Csi` for contigs longer than 2^29 bp
+///
+/// returns: path to the index
+pub fn build_index<P: AsRef<Path>>(path: P, format: IndexFormat) -> io::Result<PathBuf> {
+    let path = path.as_ref();
+    let mut reader = bgzf::Reader::new(BufReader::new(File::open(path)?));
+
+    let mut names: Vec<String> = Vec::new();
+    let mut references: Vec<ReferenceBuilder> = Vec::new();
+    let mut depth = DEPTH;
+    // (bin, chunk) of the records read since the bin last changed
+    let mut current: Option<(u32, Chunk)> = None;
+    let mut last_start = 0;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        let offset = reader.virtual_position();
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+        let end_offset = reader.virtual_position();
+        if line.starts_with('#') {
+            if format == IndexFormat::Csi {
+                // deep enough for the longest contig declared
+                if let Some(length) = contig_length(&line) {
+                    while length > 1 << (LINEAR_SHIFT + 3 * depth as u64) {
+                        depth += 1;
+                    }
+                }
+            }
+            continue;
+        }
+
+        let mut columns = line.split('\t');
+        let (chrom, pos, reference) = match (columns.next(), columns.next(), columns.nth(1)) {
+            (Some(chrom), Some(pos), Some(reference)) => (chrom, pos, reference),
+            _ => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Invalid VCF line: {}", line.trim_end()),
+            ))?,
+        };
+        let start = pos.parse::<u64>().map_err(|_| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("Invalid position: {}", line.trim_end()),
+            )
+        })? - 1;
+        let end = start + (reference.len() as u64).max(1);
+
+        if names.last().map(|x| x.as_str()) != Some(chrom) {
+            if names.iter().any(|x| x == chrom) {
+                Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "Records of {} are not contiguous in {}",
+                        chrom,
+                        path.display()
+                    ),
+                ))?
+            }
+            if let (Some((bin, chunk)), Some(r)) = (current.take(), references.last_mut()) {
+                r.bins.entry(bin).or_default().push(chunk);
+            }
+            names.push(chrom.to_string());
+            references.push(ReferenceBuilder {
+                start: offset,
+                ..Default::default()
+            });
+        } else if start < last_start {
+            Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Unsorted positions at {}:{} in {}",
+                    chrom,
+                    pos,
+                    path.display()
+                ),
+            ))?
+        }
+        last_start = start;
+
+        if end > 1 << (LINEAR_SHIFT + 3 * depth as u64) {
+            Err(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "Position beyond the range of the {} index at {}:{}",
+                    format.extension(),
+                    chrom,
+                    pos
+                ),
+            ))?
+        }
+        let bin = reg2bin(start, end, depth);
+
+        let r = references.last_mut().expect("reference is pushed");
+        match current.as_mut() {
+            Some((b, chunk)) if *b == bin => chunk.1 = end_offset,
+            _ => {
+                if let Some((b, chunk)) = current.replace((bin, (offset, end_offset))) {
+                    r.bins.entry(b).or_default().push(chunk);
+                }
+            }
+        }
+
+        let last_window = ((end - 1) >> LINEAR_SHIFT) as usize;
+        if r.intervals.len() <= last_window {
+            r.intervals.resize(last_window + 1, None);
+        }
+        for window in &mut r.intervals[(start >> LINEAR_SHIFT) as usize..=last_window] {
+            window.get_or_insert(offset);
+        }
+        r.end = end_offset;
+        r.records += 1;
+    }
+    if let (Some((bin, chunk)), Some(r)) = (current.take(), references.last_mut()) {
+        r.bins.entry(bin).or_default().push(chunk);
+    }
+
+    let mut index_path = path.as_os_str().to_owned();
+    index_path.push(".");
+    index_path.push(format.extension());
+    let index_path = PathBuf::from(index_path);
+
+    let mut w = bgzf::Writer::new(BufWriter::new(File::create(&index_path)?));
+    write_index(&mut w, format, depth, &names, &references)?;
+    w.finish()?.flush()?;
+
+    Ok(index_path)
+}
+
+fn write_index<W: Write>(
+    w: &mut W,
+    format: IndexFormat,
+    depth: u32,
+    names: &[String],
+    references: &[ReferenceBuilder],
+) -> io::Result<()> {
+    let names = names
+        .iter()
+        .flat_map(|x| x.bytes().chain([0]))
+        .collect::<Vec<u8>>();
+
+    let mut header = Vec::new();
+    for x in VCF_CONFIG {
+        header.extend(x.to_le_bytes());
+    }
+    header.extend((names.len() as i32).to_le_bytes());
+    header.extend(&names);
+
+    match format {
+        IndexFormat::Tbi => {
+            w.write_all(MAGIC)?;
+            w.write_all(&(references.len() as i32).to_le_bytes())?;
+            w.write_all(&header)?;
+        }
+        IndexFormat::Csi => {
+            w.write_all(MAGIC_CSI)?;
+            w.write_all(&(LINEAR_SHIFT as i32).to_le_bytes())?;
+            w.write_all(&(depth as i32).to_le_bytes())?;
+            w.write_all(&(header.len() as i32).to_le_bytes())?;
+            w.write_all(&header)?;
+            w.write_all(&(references.len() as i32).to_le_bytes())?;
+        }
+    }
+
+    let pseudo_bin = ((1u32 << (3 * (depth + 1))) - 1) / 7 + 1;
+    for r in references {
+        // fill windows without records with the offset of the preceding one
+        let mut intervals = Vec::with_capacity(r.intervals.len());
+        let mut previous = r.start;
+        for x in &r.intervals {
+            previous = x.unwrap_or(previous);
+            intervals.push(previous);
+        }
+
+        w.write_all(&(r.bins.len() as i32 + 1).to_le_bytes())?;
+        for (bin, chunks) in &r.bins {
+            w.write_all(&bin.to_le_bytes())?;
+            if format == IndexFormat::Csi {
+                let window = (bin_start(*bin, depth) >> LINEAR_SHIFT) as usize;
+                w.write_all(&intervals.get(window).copied().unwrap_or(0).to_le_bytes())?;
+            }
+            w.write_all(&(chunks.len() as i32).to_le_bytes())?;
+            for (start, end) in chunks {
+                w.write_all(&start.to_le_bytes())?;
+                w.write_all(&end.to_le_bytes())?;
+            }
+        }
+
+        // pseudo-bin: offsets of the sequence and the numbers of mapped/unmapped records
+        w.write_all(&pseudo_bin.to_le_bytes())?;
+        if format == IndexFormat::Csi {
+            w.write_all(&0u64.to_le_bytes())?;
+        }
+        w.write_all(&2i32.to_le_bytes())?;
+        for x in [r.start, r.end, r.records, 0] {
+            w.write_all(&x.to_le_bytes())?;
+        }
+
+        if format == IndexFormat::Tbi {
+            w.write_all(&(intervals.len() as i32).to_le_bytes())?;
+            for x in intervals {
+                w.write_all(&x.to_le_bytes())?;
+            }
+        }
+    }
+
+    // number of records without coordinates
+    w.write_all(&0u64.to_le_bytes())
+}
+
+/// Length in a `##contig=<ID=...,length=...>` line
+fn contig_length(line: &str) -> Option<u64> {
+    line.strip_prefix("##contig=<")?
+        .trim_end()
+        .trim_end_matches('>')
+        .split(',')
+        .find_map(|x| x.strip_prefix("length="))
+        .and_then(|x| x.parse().ok())
+}
+
+/// Smallest bin containing `[start, end)` (0-based) with 16 kb leaves and `depth` levels
+fn reg2bin(start: u64, end: u64, depth: u32) -> u32 {
+    let end = end - 1;
+    let mut shift = LINEAR_SHIFT;
+    let mut offset = ((1u64 << (3 * depth)) - 1) / 7;
+    for level in (1..=depth).rev() {
+        if start >> shift == end >> shift {
+            return (offset + (start >> shift)) as u32;
+        }
+        shift += 3;
+        offset -= 1 << (3 * (level - 1));
+    }
+
+    0
+}
+
+/// First position covered by a bin
+fn bin_start(bin: u32, depth: u32) -> u64 {
+    let mut offset = 0;
+    for level in 0..=depth {
+        let next = offset + (1u64 << (3 * level));
+        if (bin as u64) < next {
+            let shift = LINEAR_SHIFT + 3 * (depth - level) as u64;
+            return (bin as u64 - offset) << shift;
+        }
+        offset = next;
+    }
+
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Values of `reg2bin` in the SAM specification (section 5.3), with 0-based `[start, end)`
+    #[test]
+    fn bins_of_regions() {
+        for (start, end, depth, expected) in [
+            (0, 1, 5, 4681),
+            (16383, 16384, 5, 4681),
+            (16384, 16385, 5, 4682),
+            (16383, 16385, 5, 585),
+            (0, 1 << 17, 5, 585),
+            (0, (1 << 17) + 1, 5, 73),
+            (0, (1 << 20) + 1, 5, 9),
+            (0, (1 << 23) + 1, 5, 1),
+            (0, (1 << 26) + 1, 5, 0),
+            (1 << 26, (1 << 26) + 1, 5, 8777),
+            ((1 << 29) - 1, 1 << 29, 5, 37448),
+            // .csi with a level more
+            (0, 1, 6, 37449),
+            (0, 1 << 29, 6, 1),
+            (1 << 29, (1 << 29) + 1, 6, 37449 + 32768),
+            (0, (1 << 29) + 1, 6, 0),
+        ] {
+            assert_eq!(
+                reg2bin(start, end, depth),
+                expected,
+                "[{}, {}) at depth {}",
+                start,
+                end,
+                depth
+            );
+        }
+    }
+
+    #[test]
+    fn bins_start_at_their_first_window() {
+        for (bin, depth, expected) in [
+            (0, 5, 0),
+            (1, 5, 0),
+            (2, 5, 1 << 26),
+            (586, 5, 1 << 17),
+            (4681, 5, 0),
+            (4682, 5, 16384),
+            (37448, 5, (1 << 29) - 16384),
+            (37449, 6, 0),
+        ] {
+            assert_eq!(
+                bin_start(bin, depth),
+                expected,
+                "{} at depth {}",
+                bin,
+                depth
+            );
+        }
+    }
+
+    /// Values of `reg2bins` in the SAM specification
+    #[test]
+    fn bins_overlapping_regions() {
+        for (start, end, expected) in [
+            (0, 1, vec![0, 1, 9, 73, 585, 4681]),
+            (100, 100, vec![0, 1, 9, 73, 585, 4681]),
+            (16383, 16385, vec![0, 1, 9, 73, 585, 4681, 4682]),
+            (
+                (1 << 26) - 1,
+                (1 << 26) + 1,
+                vec![0, 1, 2, 16, 17, 136, 137, 1096, 1097, 8776, 8777],
+            ),
+            (0, 3 * 16384, vec![0, 1, 9, 73, 585, 4681, 4682, 4683]),
+        ] {
+            assert_eq!(region_to_bins(start, end), expected, "[{}, {})", start, end);
+        }
+    }
+
+    #[test]
+    fn contig_lengths_are_read_from_the_header() {
+        assert_eq!(
+            contig_length("##contig=<ID=1,length=248956422>\n"),
+            Some(248956422)
+        );
+        assert_eq!(
+            contig_length("##contig=<ID=1,length=248956422,assembly=GRCh38>"),
+            Some(248956422)
+        );
+        assert_eq!(contig_length("##contig=<ID=1>"), None);
+        assert_eq!(contig_length("##INFO=<ID=length=1>"), None);
+    }
+
+    /// Bgzipped VCF of records every 1 kb on chromosomes 1 and 2, and its lines
+    fn vcf(dir: &Path) -> (PathBuf, Vec<String>) {
+        let mut lines = Vec::new();
+        for chrom in ["1", "2"] {
+            for i in 1..=200 {
+                lines.push(format!("{}\t{}\t.\tAC\tA\t.\t.\t.\n", chrom, i * 1000));
+            }
+        }
+        let path = dir.join("test.vcf.gz");
+        let mut writer = bgzf::Writer::new(File::create(&path).unwrap());
+        writer
+            .write_all(b"##fileformat=VCFv4.3\n#CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n")
+            .unwrap();
+        for line in &lines {
+            writer.write_all(line.as_bytes()).unwrap();
+            // many blocks
+            writer.flush().unwrap();
+        }
+        writer.finish().unwrap();
+
+        (path, lines)
+    }
+
+    /// Records read from the chunks of a query overlapping `[start, end)`
+    fn query(path: &Path, index: &Index, name: &str, start: u64, end: u64) -> Vec<String> {
+        let mut reader = bgzf::Reader::new(File::open(path).unwrap());
+        let mut records = Vec::new();
+        for (chunk_start, chunk_end) in index.query(name, start, end) {
+            reader.seek_virtual(chunk_start).unwrap();
+            while reader.virtual_position() < chunk_end {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let columns = line.split('\t').collect::<Vec<_>>();
+                let pos = columns[1].parse::<u64>().unwrap() - 1;
+                if columns[0] == name && pos < end && pos + columns[3].len() as u64 > start {
+                    records.push(line);
+                }
+            }
+        }
+
+        records
+    }
+
+    #[test]
+    fn queries_of_the_written_index_find_the_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let (path, lines) = vcf(dir.path());
+
+        let index_path = build_index(&path, IndexFormat::Tbi).unwrap();
+        assert_eq!(index_path, dir.path().join("test.vcf.gz.tbi"));
+        let index = Index::from_path(&index_path).unwrap();
+        assert_eq!(index.names, vec!["1", "2"]);
+        assert_eq!(
+            (index.format, index.col_seq, index.col_beg, index.col_end),
+            (2, 1, 2, 0)
+        );
+
+        for (name, start, end) in [
+            ("1", 0, 1000),
+            ("1", 999, 1000),
+            ("1", 1000, 1001),
+            ("1", 15000, 50000),
+            ("1", 199_000, 300_000),
+            ("2", 0, 1000),
+            ("2", 0, 10_000_000),
+        ] {
+            let expected = lines
+                .iter()
+                .filter(|x| {
+                    let columns = x.split('\t').collect::<Vec<_>>();
+                    let pos = columns[1].parse::<u64>().unwrap() - 1;
+                    columns[0] == name && pos < end && pos + 2 > start
+                })
+                .cloned()
+                .collect::<Vec<_>>();
+            assert_eq!(
+                query(&path, &index, name, start, end),
+                expected,
+                "{}:[{}, {})",
+                name,
+                start,
+                end
+            );
+        }
+        assert!(index.query("3", 0, 1000).is_empty());
+    }
+
+    #[test]
+    fn unsorted_records_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.vcf.gz");
+        for records in [
+            "1\t200\t.\tA\tG\t.\t.\t.\n1\t100\t.\tA\tG\t.\t.\t.\n",
+            "1\t100\t.\tA\tG\t.\t.\t.\n2\t100\t.\tA\tG\t.\t.\t.\n1\t200\t.\tA\tG\t.\t.\t.\n",
+        ] {
+            let mut writer = bgzf::Writer::new(File::create(&path).unwrap());
+            writer.write_all(records.as_bytes()).unwrap();
+            writer.finish().unwrap();
+
+            let e = build_index(&path, IndexFormat::Tbi).unwrap_err();
+            assert_eq!(e.kind(), ErrorKind::InvalidData, "{}", records);
+        }
+    }
+
+    #[test]
+    fn long_contigs_need_csi() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.vcf.gz");
+        let mut writer = bgzf::Writer::new(File::create(&path).unwrap());
+        writer
+            .write_all(b"##contig=<ID=1,length=700000000>\n1\t600000000\t.\tA\tG\t.\t.\t.\n")
+            .unwrap();
+        writer.finish().unwrap();
+
+        let e = build_index(&path, IndexFormat::Tbi).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+
+        let index_path = build_index(&path, IndexFormat::Csi).unwrap();
+        let mut bytes = Vec::new();
+        MultiGzDecoder::new(File::open(index_path).unwrap())
+            .read_to_end(&mut bytes)
+            .unwrap();
+        assert_eq!(&bytes[..4], MAGIC_CSI);
+        // min_shift and depth
+        assert_eq!(bytes[4..12], [14, 0, 0, 0, 6, 0, 0, 0]);
+    }
+}
@@ -0,0 +1,77 @@
+//! Tab-separated output, one row per allele and CONDITIONS entry
+//!
+//! Rows are built from [`JsonRecord`] so that they carry the same values as the other outputs.
+//! Tabs, line breaks and backslashes in values are escaped as `\t`, `\n`, `\r` and `\\`, and
+//! missing values are empty.
+
+use crate::json::JsonRecord;
+
+pub const COLUMNS: [&str; 12] = [
+    "chrom",
+    "pos",
+    "ref",
+    "alt",
+    "variation_id",
+    "allele_id",
+    "rcv_accession",
+    "condition_db",
+    "condition_ids",
+    "classification",
+    "submission_count",
+    "variant_type",
+];
+
+/// Header row
+pub fn header() -> String {
+    COLUMNS.join("\t")
+}
+
+/// Rows of a record, a single one with empty RCV columns if it has no germline classification
+pub fn rows(record: &JsonRecord) -> Vec<String> {
+    let allele = [
+        escape(&record.chrom),
+        record.pos.to_string(),
+        escape(&record.reference),
+        escape(&record.alt),
+        record.variation_id.to_string(),
+        record.allele_id.to_string(),
+    ]
+    .join("\t");
+
+    let variant_type = escape(record.variant_type.as_deref().unwrap_or_default());
+    if record.conditions.is_empty() {
+        return vec![format!("{}\t\t\t\t\t\t{}", allele, variant_type)];
+    }
+
+    record
+        .conditions
+        .iter()
+        .map(|x| {
+            format!(
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                allele,
+                escape(&x.rcv),
+                escape(&x.db),
+                escape(&x.ids.join("/")),
+                escape(&x.classifications.join("/")),
+                x.submission_count,
+                variant_type
+            )
+        })
+        .collect()
+}
+
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\\' => escaped.push_str("\\\\"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
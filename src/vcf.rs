@@ -0,0 +1,2458 @@
+//! Formatting of `VariationArchive` into VCF records
+
+use crate::*;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::str::FromStr;
+
+/// Options controlling how records are formatted
+#[derive(Debug, Clone, Default)]
+pub struct FormatConfig {
+    /// Emit at most this many RCVs per record (see [`extract_classifications`])
+    pub max_rcvs: Option<usize>,
+    /// Skip records that only have sentinel classifications
+    pub exclude_unclassified: bool,
+    /// Write soft issues of each record into the FLAGS INFO field
+    pub emit_flags: bool,
+    /// Output format
+    pub format: OutputFormat,
+    /// Layout of the CONDITIONS field of the togovar format
+    pub conditions_format: ConditionsFormat,
+    /// Databases to take condition IDs from, in output order (MedGen if empty)
+    pub condition_dbs: Vec<String>,
+    /// Use condition names for RCVs without conditions from the condition databases
+    pub keep_unmapped_conditions: bool,
+    /// Keep only records with any of these record statuses, compared case-insensitively (current
+    /// only if empty)
+    pub record_status: Vec<String>,
+    /// Keep only records with at least one of these germline classifications (all if empty)
+    pub include_significance: Vec<ClinicalSignificance>,
+    /// Keep only records located in any of these regions (all if empty)
+    pub regions: Vec<Region>,
+    /// Keep only records with any of these gene symbols, compared case-insensitively (all if empty)
+    pub genes: Vec<String>,
+    /// Accept IUPAC ambiguity codes (including N) in alleles
+    pub allow_iupac: bool,
+    /// Contigs accepted besides 1-22, X, Y and MT (only those if `None`)
+    pub contigs: Option<BTreeSet<String>>,
+    /// Write the submitted records into the SCV INFO field of the togovar format and their
+    /// origins into ORIGIN, which requires them to be read (see
+    /// [`crate::reader::VariationArchiveReader::include_scv`])
+    pub include_scv: bool,
+    /// Write the PubMed IDs cited by the classifications into the PMIDS INFO field of the
+    /// togovar format
+    pub include_citations: bool,
+    /// Write only these INFO fields, skipping the extraction of the others (see
+    /// [`FormatConfig::emits`])
+    pub info_fields: Option<BTreeSet<String>>,
+}
+
+impl FormatConfig {
+    pub fn condition_databases(&self) -> &[String] {
+        static DEFAULT: Lazy<Vec<String>> = Lazy::new(|| vec![DB_MEDGEN.to_string()]);
+
+        if self.condition_dbs.is_empty() {
+            &DEFAULT
+        } else {
+            &self.condition_dbs
+        }
+    }
+
+    /// Whether an INFO field is written
+    ///
+    /// SCV and ORIGIN also require `include_scv`, PMIDS `include_citations`, and the others are
+    /// written unless `info_fields` leaves them out.
+    pub fn emits(&self, id: &str) -> bool {
+        match id {
+            "SCV" | "ORIGIN" if !self.include_scv => false,
+            "PMIDS" if !self.include_citations => false,
+            _ => self.info_fields.as_ref().is_none_or(|x| x.contains(id)),
+        }
+    }
+
+    /// Value of an INFO field by `f`, which is not called if the field is not written
+    pub fn extract<T, F: FnOnce() -> Option<T>>(&self, id: &str, f: F) -> Option<T> {
+        if self.emits(id) {
+            f()
+        } else {
+            None
+        }
+    }
+
+    pub fn record_statuses(&self) -> &[String] {
+        static DEFAULT: Lazy<Vec<String>> = Lazy::new(|| vec![RECORD_STATUS_CURRENT.to_string()]);
+
+        if self.record_status.is_empty() {
+            &DEFAULT
+        } else {
+            &self.record_status
+        }
+    }
+}
+
+/// Output format, selecting the set of INFO fields and their header
+///
+/// Each format has its INFO builder here and its header block in the binary.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Fields of this tool (CONDITIONS, ONC, ...)
+    #[default]
+    Togovar,
+    /// Subset of the fields of the official ClinVar VCF (clinvar.vcf), with their names and formats
+    Ncbi,
+}
+
+impl OutputFormat {
+    pub const VARIANTS: [&'static str; 2] = ["togovar", "ncbi"];
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "togovar" => Ok(OutputFormat::Togovar),
+            "ncbi" => Ok(OutputFormat::Ncbi),
+            _ => Err(format!("Unknown output format: {}", s)),
+        }
+    }
+}
+
+/// Layout of the CONDITIONS field
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConditionsFormat {
+    /// A single value of `|` separated entries of `:` separated components (`Number=1`)
+    #[default]
+    V1,
+    /// A value per entry of `|` separated components (`Number=.`), see [`ConditionEntry::v2`]
+    V2,
+}
+
+impl ConditionsFormat {
+    pub const VARIANTS: [&'static str; 2] = ["v1", "v2"];
+}
+
+impl FromStr for ConditionsFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "v1" => Ok(ConditionsFormat::V1),
+            "v2" => Ok(ConditionsFormat::V2),
+            _ => Err(format!("Unknown conditions format: {}", s)),
+        }
+    }
+}
+
+/// Soft issue found while formatting a record which did not cause it to be skipped
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum QcFlag {
+    ConditionIdNormalized,
+    SubmissionCountClamped,
+    ReviewStatusMissing,
+    SentinelClassification,
+    ConditionsTruncated,
+    UnknownClassification,
+}
+
+impl QcFlag {
+    /// Stable machine-readable code
+    pub fn code(&self) -> &'static str {
+        match self {
+            QcFlag::ConditionIdNormalized => "condition_id_normalized",
+            QcFlag::SubmissionCountClamped => "submission_count_clamped",
+            QcFlag::ReviewStatusMissing => "review_status_missing",
+            QcFlag::SentinelClassification => "sentinel_classification",
+            QcFlag::ConditionsTruncated => "conditions_truncated",
+            QcFlag::UnknownClassification => "unknown_classification",
+        }
+    }
+}
+
+/// Soft issues collected while formatting the current record
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    pub flags: BTreeSet<QcFlag>,
+}
+
+impl Diagnostics {
+    pub fn flag(&mut self, flag: QcFlag) {
+        self.flags.insert(flag);
+    }
+}
+
+/// Value of the FILTER column
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Filter {
+    /// Non-conflicting classification
+    #[default]
+    Pass,
+    /// The aggregate germline classification is conflicting, or RCVs are on both the pathogenic
+    /// and the benign side
+    Conflicting,
+    /// No assertion criteria were provided for any classification
+    NoAssertion,
+}
+
+impl Filter {
+    /// ID in the FILTER column
+    pub fn id(&self) -> &'static str {
+        match self {
+            Filter::Pass => "PASS",
+            Filter::Conflicting => "conflicting",
+            Filter::NoAssertion => "no_assertion",
+        }
+    }
+}
+
+/// A VCF data line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VcfRecord {
+    pub chrom: String,
+    pub pos: u64,
+    pub id: String,
+    pub reference: String,
+    pub alternate: String,
+    pub filter: Filter,
+    pub info: Vec<String>,
+    /// Soft issues found while formatting (not written)
+    pub flags: BTreeSet<QcFlag>,
+}
+
+impl VcfRecord {
+    /// Whether the INFO column contains the flag or key
+    pub fn has_info(&self, key: &str) -> bool {
+        self.info
+            .iter()
+            .any(|x| x == key || x.split_once('=').map(|x| x.0) == Some(key))
+    }
+}
+
+/// A record skipped after parsing, as a VCF data line with the reason in SKIP_REASON
+///
+/// CHROM, POS, REF and ALT are taken from the XML without any check, and are `.` if missing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RejectedRecord {
+    pub chrom: Option<String>,
+    pub pos: Option<u64>,
+    pub id: String,
+    pub reference: Option<String>,
+    pub alternate: Option<String>,
+    pub reason: SkipReason,
+}
+
+impl RejectedRecord {
+    /// Record of a variant skipped before being converted
+    ///
+    /// # Arguments
+    ///
+    /// * `archive`: `VariationArchive`
+    /// * `assembly`: `Assembly` to take the location of
+    /// * `reason`: `SkipReason`
+    ///
+    /// returns: RejectedRecord
+    pub fn new(archive: &VariationArchive, assembly: Assembly, reason: SkipReason) -> Self {
+        let location = archive
+            .classified_record
+            .as_ref()
+            .and_then(|x| x.simple_allele.as_ref())
+            .and_then(|x| select_location(x, assembly));
+        let alleles = location.and_then(|x| x.vcf_alleles());
+
+        RejectedRecord {
+            chrom: location.map(|x| x.chr.clone()),
+            pos: alleles.map(|x| x.0).or(location.and_then(|x| x.start)),
+            id: archive.variation_id.to_string(),
+            reference: alleles.map(|x| x.1.clone()),
+            alternate: alleles.map(|x| x.2.clone()),
+            reason,
+        }
+    }
+
+    /// Record of a converted variant skipped afterwards (e.g. for a REF mismatch)
+    pub fn from_record(record: &VcfRecord, reason: SkipReason) -> Self {
+        RejectedRecord {
+            chrom: Some(record.chrom.clone()),
+            pos: Some(record.pos),
+            id: record.id.clone(),
+            reference: Some(record.reference.clone()),
+            alternate: Some(record.alternate.clone()),
+            reason,
+        }
+    }
+}
+
+impl fmt::Display for RejectedRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = |x: &Option<String>| match x.as_deref() {
+            Some(x) if !x.is_empty() => encode_info_value(x, &[]),
+            _ => ".".to_string(),
+        };
+
+        write!(
+            f,
+            "{}\t{}\t{}\t{}\t{}\t.\t.\tSKIP_REASON={}",
+            value(&self.chrom),
+            self.pos.map_or_else(|| ".".to_string(), |x| x.to_string()),
+            self.id,
+            value(&self.reference),
+            value(&self.alternate),
+            self.reason.code()
+        )
+    }
+}
+
+impl fmt::Display for VcfRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}\t{}\t{}\t{}\t{}\t.\t{}\t{}",
+            self.chrom,
+            self.pos,
+            self.id,
+            self.reference,
+            self.alternate,
+            self.filter.id(),
+            if self.info.is_empty() {
+                ".".to_string()
+            } else {
+                self.info.join(";")
+            }
+        )
+    }
+}
+
+/// Format a `VariationArchive` into VCF lines
+///
+/// # Arguments
+///
+/// * `archive`: `VariationArchive`
+/// * `assembly`: `Assembly`
+/// * `config`: `FormatConfig`
+///
+/// returns: Vec<Result<String, SkipReason>>
+///          one entry per allele, either the VCF line or the reason why it was skipped
+pub fn to_vcf_lines(
+    archive: &VariationArchive,
+    assembly: Assembly,
+    config: &FormatConfig,
+) -> Vec<Result<String, SkipReason>> {
+    to_vcf_records(archive, assembly, config)
+        .into_iter()
+        .map(|x| x.map(|x| x.to_string()))
+        .collect()
+}
+
+/// Format a `VariationArchive` into VCF records
+///
+/// # Arguments
+///
+/// * `archive`: `VariationArchive`
+/// * `assembly`: `Assembly`
+/// * `config`: `FormatConfig`
+///
+/// returns: Vec<Result<VcfRecord, SkipReason>>
+///          one entry per allele, either the record or the reason why it was skipped
+pub fn to_vcf_records(
+    archive: &VariationArchive,
+    assembly: Assembly,
+    config: &FormatConfig,
+) -> Vec<Result<VcfRecord, SkipReason>> {
+    vec![to_vcf_record(archive, assembly, config)]
+}
+
+fn to_vcf_record(
+    archive: &VariationArchive,
+    assembly: Assembly,
+    config: &FormatConfig,
+) -> Result<VcfRecord, SkipReason> {
+    // records without the status are taken as current
+    let status = archive.record_status().unwrap_or(RECORD_STATUS_CURRENT);
+    if !config
+        .record_statuses()
+        .iter()
+        .any(|x| x.eq_ignore_ascii_case(status))
+    {
+        debug!(
+            reason = SkipReason::RecordStatusFiltered;
+            "{}: variation_id = {}, record_status = {}",
+            SkipReason::RecordStatusFiltered,
+            archive.variation_id,
+            status
+        );
+        return Err(SkipReason::RecordStatusFiltered);
+    }
+
+    if let Some(species) = archive.species.as_ref().filter(|x| x.text != SPECIES_HUMAN) {
+        debug!(
+            reason = SkipReason::SpeciesFiltered;
+            "{}: variation_id = {}, species = {}",
+            SkipReason::SpeciesFiltered,
+            archive.variation_id,
+            species.text
+        );
+        return Err(SkipReason::SpeciesFiltered);
+    }
+
+    let record = archive.classified_record.as_ref().ok_or_else(|| {
+        debug!(
+            reason = SkipReason::NoClassifiedRecord;
+            "{}: variation_id = {}",
+            SkipReason::NoClassifiedRecord,
+            archive.variation_id
+        );
+        SkipReason::NoClassifiedRecord
+    })?;
+
+    let allele = record.simple_allele.as_ref().ok_or_else(|| {
+        debug!(
+            reason = SkipReason::NoSimpleAllele;
+            "{}: variation_id = {}",
+            SkipReason::NoSimpleAllele,
+            archive.variation_id
+        );
+        SkipReason::NoSimpleAllele
+    })?;
+
+    let VcfCoordinates {
+        chrom,
+        pos,
+        reference,
+        alternate,
+    } = try_extract_location(allele, assembly, config)?;
+
+    if !config.regions.is_empty() && !config.regions.iter().any(|x| x.contains(chrom, pos)) {
+        return Err(SkipReason::RegionFiltered);
+    }
+
+    if !config.genes.is_empty() && !has_gene(allele, &config.genes) {
+        return Err(SkipReason::GeneFiltered);
+    }
+
+    let mut diagnostics = Diagnostics::default();
+    let conditions = extract_conditions(record, config, &mut diagnostics);
+    let oncogenicity = extract_oncogenicity(record, config, &mut diagnostics);
+
+    if conditions.is_empty() && oncogenicity.is_empty() {
+        debug!(
+            reason = SkipReason::NoMedgenCondition;
+            "{}: variation_id = {}",
+            SkipReason::NoMedgenCondition,
+            archive.variation_id
+        );
+        return Err(SkipReason::NoMedgenCondition);
+    }
+
+    if config.exclude_unclassified && is_unclassified(record) {
+        return Err(SkipReason::Unclassified);
+    }
+
+    if !config.include_significance.is_empty()
+        && !includes_significance(&conditions, &config.include_significance)
+    {
+        return Err(SkipReason::SignificanceFiltered);
+    }
+
+    let mut vcf_record = VcfRecord {
+        chrom: chrom.to_string(),
+        pos,
+        id: allele.variation_id.to_string(),
+        reference: reference.into_owned(),
+        alternate: alternate.into_owned(),
+        filter: extract_filter(record),
+        info: Vec::new(),
+        flags: BTreeSet::new(),
+    };
+
+    if config.format == OutputFormat::Ncbi {
+        vcf_record.info = ncbi_info(record, allele, assembly, config);
+        vcf_record.flags = diagnostics.flags;
+        return Ok(vcf_record);
+    }
+
+    let mut info = Vec::new();
+    if config.emits("ALLELEID") {
+        info.push(format!("ALLELEID={}", allele.allele_id));
+    }
+    if let Some(rs) = config.extract("RS", || extract_rs(allele)) {
+        info.push(format!("RS={}", rs));
+    }
+    if let Some(xrefs) = config.extract("CLNVI", || extract_clinical_xrefs(allele)) {
+        info.push(format!("CLNVI={}", xrefs));
+    }
+    if let Some(genes) = config.extract("GENEINFO", || extract_gene_info(allele)) {
+        info.push(format!("GENEINFO={}", genes));
+    }
+    if let Some(consequences) = config.extract("MC", || extract_molecular_consequences(allele)) {
+        info.push(format!("MC={}", consequences));
+    }
+    if let Some(hgvs) = config.extract("CLNHGVS", || extract_genomic_hgvs(allele, assembly)) {
+        info.push(format!("CLNHGVS={}", encode_info_value(&hgvs, &[])));
+    }
+    if let Some(hgvs) = config.extract("HGVSP", || extract_protein_hgvs(allele)) {
+        info.push(format!("HGVSP={}", encode_info_value(&hgvs, &[])));
+    }
+    if let Some(variant_type) = config.extract("CLNVC", || extract_variant_type(allele)) {
+        info.push(format!("CLNVC={}", variant_type));
+    }
+    if !conditions.is_empty() {
+        if config.emits("CONDITIONS") {
+            info.push(format!(
+                "CONDITIONS={}",
+                conditions.format(config.conditions_format)
+            ));
+        }
+        if config.emits("CLNDN") {
+            info.push(format!("CLNDN={}", conditions.names.join("|")));
+        }
+        if config.emits("RCV") {
+            info.push(format!("RCV={}", conditions.rcvs.join("|")));
+        }
+    }
+    if let Some(significance) =
+        config.extract("CLNSIG", || extract_significance(record, &mut diagnostics))
+    {
+        info.push(format!("CLNSIG={}", significance));
+    }
+    if let Some(conflicts) = config.extract("CLNSIGCONF", || extract_significance_conflicts(record))
+    {
+        info.push(format!("CLNSIGCONF={}", conflicts));
+    }
+    if let Some(review_status) = config.extract("CLNREVSTAT", || extract_review_status(record)) {
+        info.push(format!(
+            "CLNREVSTAT={}",
+            encode_info_value(&review_status, &[])
+        ));
+    }
+    if let Some(origin) = config.extract("ORIGIN", || extract_origin(record)) {
+        info.push(format!("ORIGIN={}", origin));
+    }
+    if let Some(date) = config.extract("CLNDATELASTEVAL", || extract_date_last_evaluated(record)) {
+        info.push(format!("CLNDATELASTEVAL={}", date));
+    }
+    if let Some(date) = config.extract("DATECREATED", || {
+        archive.date_created.as_deref().and_then(normalize_date)
+    }) {
+        info.push(format!("DATECREATED={}", date));
+    }
+    if let Some(date) = config.extract("DATELASTUPDATED", || {
+        archive
+            .date_last_updated
+            .as_deref()
+            .and_then(normalize_date)
+    }) {
+        info.push(format!("DATELASTUPDATED={}", date));
+    }
+    if !oncogenicity.is_empty() && config.emits("ONC") {
+        info.push(format!("ONC={}", oncogenicity));
+    }
+    let submissions = selected_submission_counts(record, config);
+    for (key, count) in [
+        ("GERMLINE_SUBMISSIONS", submissions.germline),
+        ("SOMATIC_SUBMISSIONS", submissions.somatic),
+        ("ONC_SUBMISSIONS", submissions.oncogenicity),
+        (
+            "SCV_COUNT",
+            config
+                .extract("SCV_COUNT", || Some(extract_scv_count(record)))
+                .unwrap_or_default(),
+        ),
+    ] {
+        if count != 0 && config.emits(key) {
+            info.push(format!("{}={}", key, count));
+        }
+    }
+    if let Some(scvs) = config.extract("SCV", || extract_scvs(archive)) {
+        info.push(format!("SCV={}", scvs));
+    }
+    if let Some(pmids) = config.extract("PMIDS", || {
+        Some(extract_pmids(archive)).filter(|x| !x.is_empty())
+    }) {
+        info.push(format!(
+            "PMIDS={}",
+            pmids
+                .iter()
+                .map(|x| x.to_string())
+                .collect::<Vec<String>>()
+                .join("|")
+        ));
+    }
+    let omitted = conditions.omitted + oncogenicity.omitted;
+    if omitted != 0 && config.emits("RCV_TRUNCATED") {
+        info.push(format!("RCV_TRUNCATED={}", omitted));
+    }
+    if conditions.mapped == 0 && oncogenicity.mapped == 0 && config.emits("CONDITIONS_UNMAPPED") {
+        info.push("CONDITIONS_UNMAPPED".to_string());
+    }
+    if config.emit_flags && !diagnostics.flags.is_empty() && config.emits("FLAGS") {
+        info.push(format!(
+            "FLAGS={}",
+            diagnostics
+                .flags
+                .iter()
+                .map(|x| x.code())
+                .collect::<Vec<&str>>()
+                .join(",")
+        ));
+    }
+
+    vcf_record.info = info;
+    vcf_record.flags = diagnostics.flags;
+
+    Ok(vcf_record)
+}
+
+/// INFO fields in the names and formats of the official ClinVar VCF
+///
+/// Fields which cannot be reproduced from the XML (e.g. CLNVCSO, numeric ORIGIN) are omitted.
+fn ncbi_info(
+    record: &ClassifiedRecord,
+    allele: &SimpleAllele,
+    assembly: Assembly,
+    config: &FormatConfig,
+) -> Vec<String> {
+    let mut info = Vec::new();
+    if config.emits("ALLELEID") {
+        info.push(format!("ALLELEID={}", allele.allele_id));
+    }
+
+    let diseases = if config.emits("CLNDISDB") || config.emits("CLNDN") {
+        extract_diseases(record, config.condition_databases())
+    } else {
+        Vec::new()
+    };
+    if !diseases.is_empty() && config.emits("CLNDISDB") {
+        info.push(format!(
+            "CLNDISDB={}",
+            diseases
+                .iter()
+                .map(|x| format!("{}:{}", x.0, x.1))
+                .collect::<Vec<String>>()
+                .join("|")
+        ));
+    }
+    if !diseases.is_empty() && config.emits("CLNDN") {
+        info.push(format!(
+            "CLNDN={}",
+            diseases
+                .iter()
+                .map(|x| x.2.as_str())
+                .collect::<Vec<&str>>()
+                .join("|")
+        ));
+    }
+    if let Some(hgvs) = config.extract("CLNHGVS", || extract_genomic_hgvs(allele, assembly)) {
+        info.push(format!("CLNHGVS={}", encode_info_value(&hgvs, &[])));
+    }
+    if let Some(review_status) = config.extract("CLNREVSTAT", || extract_review_status(record)) {
+        info.push(format!(
+            "CLNREVSTAT={}",
+            encode_info_value(&review_status, &[])
+        ));
+    }
+    if let Some(x) = config.extract("CLNSIG", || {
+        record
+            .classifications
+            .as_ref()
+            .and_then(|x| x.germline_classification.as_ref())
+            .map(|x| encode_info_value(&x.description.text.trim().replace(' ', "_"), &['|']))
+            .filter(|x| !x.is_empty())
+    }) {
+        info.push(format!("CLNSIG={}", x));
+    }
+    if let Some(conflicts) = config.extract("CLNSIGCONF", || extract_significance_conflicts(record))
+    {
+        info.push(format!("CLNSIGCONF={}", conflicts));
+    }
+    if let Some(variant_type) = config.extract("CLNVC", || extract_variant_type(allele)) {
+        info.push(format!("CLNVC={}", variant_type));
+    }
+    if let Some(xrefs) = config.extract("CLNVI", || extract_clinical_xrefs(allele)) {
+        info.push(format!("CLNVI={}", xrefs));
+    }
+    if let Some(genes) = config.extract("GENEINFO", || extract_gene_info(allele)) {
+        info.push(format!("GENEINFO={}", genes));
+    }
+    if let Some(consequences) = config.extract("MC", || extract_molecular_consequences(allele)) {
+        info.push(format!("MC={}", consequences));
+    }
+    if let Some(rs) = config.extract("RS", || extract_rs(allele)) {
+        info.push(format!("RS={}", rs));
+    }
+
+    info
+}
+
+/// Variant type with spaces replaced by underscores as in the CLNVC field of the official ClinVar
+/// VCF (e.g. `single_nucleotide_variant`, `copy_number_loss`), other special characters
+/// percent-encoded
+pub fn extract_variant_type(allele: &SimpleAllele) -> Option<String> {
+    allele
+        .variant_type
+        .as_deref()
+        .map(|x| x.split_whitespace().collect::<Vec<&str>>().join("_"))
+        .filter(|x| !x.is_empty())
+        .map(|x| encode_info_value(&x, &[]))
+}
+
+/// Conditions of all RCVs with a germline classification as (DB, ID, name)
+///
+/// Ordered by the position of the DB in `databases`, then by ID. Names have spaces replaced by
+/// underscores as in the CLNDN field of the official ClinVar VCF.
+pub fn extract_diseases<'a>(
+    record: &'a ClassifiedRecord,
+    databases: &'a [String],
+) -> Vec<(&'a str, &'a str, String)> {
+    let mut diseases = record
+        .rcv_list
+        .rcv_accession
+        .iter()
+        .filter(|x| x.rcv_classifications.germline_classification.is_some())
+        .flat_map(|x| x.classified_condition_list.classified_condition.iter())
+        .filter_map(|x| {
+            let db = x.db.as_deref()?;
+            let position = databases.iter().position(|x| x.eq_ignore_ascii_case(db))?;
+            let id = x.id.as_deref()?.trim();
+            if id.is_empty() {
+                return None;
+            }
+            let name = encode_info_value(&x.text.trim().replace(' ', "_"), &['|']);
+            Some((position, databases[position].as_str(), id, name))
+        })
+        .collect::<Vec<_>>();
+    diseases.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| compare_ids(a.2, b.2)));
+    diseases.dedup_by(|a, b| a.0 == b.0 && a.2 == b.2);
+
+    diseases
+        .into_iter()
+        .map(|(_, db, id, name)| (db, id, name))
+        .collect()
+}
+
+const DB_MEDGEN: &str = "MedGen";
+const RECORD_STATUS_CURRENT: &str = "current";
+const SPECIES_HUMAN: &str = "Homo sapiens";
+const DB_DBSNP: &str = "dbSNP";
+const DB_CLINGEN: &str = "ClinGen";
+const DB_UNMAPPED: &str = "NA";
+const NOT_PROVIDED: &str = "not_provided";
+
+/// Sub-delimiters of CONDITIONS and ONC entries
+const CONDITIONS_DELIMITERS: [char; 3] = [':', '/', '|'];
+/// Sub-delimiters of CONDITIONS entries of `--conditions-format v2`
+const CONDITIONS_V2_DELIMITERS: [char; 3] = [',', '/', '|'];
+/// Sub-delimiters of CLNDN in the togovar format
+const CLNDN_DELIMITERS: [char; 2] = ['/', '|'];
+/// Sub-delimiters of SCV entries
+const SCV_DELIMITERS: [char; 2] = [':', '|'];
+
+/// The lowest dbSNP rs number, so that the choice does not depend on the XML document order
+pub fn extract_rs(allele: &SimpleAllele) -> Option<u64> {
+    allele
+        .xref_list
+        .as_ref()?
+        .xref
+        .iter()
+        .filter(|x| x.db == DB_DBSNP)
+        .filter_map(|x| x.id.trim_start_matches("rs").parse::<u64>().ok())
+        .min()
+}
+
+/// Cross references other than dbSNP and ClinGen as `DB:ID|DB:ID`, sorted and deduplicated
+///
+/// `:` and `|` inside DB names or IDs are percent-encoded so that they cannot be mistaken for the
+/// delimiters.
+pub fn extract_clinical_xrefs(allele: &SimpleAllele) -> Option<String> {
+    let mut xrefs = allele
+        .xref_list
+        .as_ref()?
+        .xref
+        .iter()
+        .filter(|x| x.db != DB_DBSNP && x.db != DB_CLINGEN)
+        .map(|x| {
+            format!(
+                "{}:{}",
+                encode_info_value(x.db.trim(), &[':', '|']),
+                encode_info_value(x.id.trim(), &[':', '|'])
+            )
+        })
+        .collect::<Vec<String>>();
+    xrefs.sort();
+    xrefs.dedup();
+
+    if xrefs.is_empty() {
+        return None;
+    }
+
+    Some(xrefs.join("|"))
+}
+
+/// Whether the `GeneList` contains any of the symbols, compared case-insensitively
+pub fn has_gene(allele: &SimpleAllele, symbols: &[String]) -> bool {
+    allele.gene_list.as_ref().is_some_and(|x| {
+        x.gene.iter().any(|x| {
+            x.symbol
+                .as_deref()
+                .is_some_and(|x| symbols.iter().any(|y| x.trim().eq_ignore_ascii_case(y)))
+        })
+    })
+}
+
+pub fn extract_gene_info(allele: &SimpleAllele) -> Option<String> {
+    let mut genes = allele
+        .gene_list
+        .as_ref()?
+        .gene
+        .iter()
+        .filter_map(|x| {
+            x.symbol
+                .as_ref()
+                .map(|symbol| (symbol, x.gene_id.map(|x| x.to_string()).unwrap_or_default()))
+        })
+        .collect::<Vec<_>>();
+    genes.sort();
+    genes.dedup();
+
+    if genes.is_empty() {
+        return None;
+    }
+
+    Some(
+        genes
+            .into_iter()
+            .map(|(symbol, id)| format!("{}:{}", encode_info_value(symbol, &[':', '|']), id))
+            .collect::<Vec<String>>()
+            .join("|"),
+    )
+}
+
+pub fn extract_molecular_consequences(allele: &SimpleAllele) -> Option<String> {
+    let mut consequences = allele
+        .hgvs_list
+        .as_ref()?
+        .hgvs
+        .iter()
+        .flat_map(|x| x.molecular_consequence.iter())
+        .map(|x| {
+            (
+                encode_info_value(x.id.trim(), &['|']),
+                encode_info_value(&x.r#type.trim().replace(' ', "_"), &['|']),
+            )
+        })
+        .collect::<Vec<_>>();
+    consequences.sort();
+    consequences.dedup();
+
+    if consequences.is_empty() {
+        return None;
+    }
+
+    Some(
+        consequences
+            .into_iter()
+            .map(|(id, consequence)| format!("{}|{}", id, consequence))
+            .collect::<Vec<String>>()
+            .join(","),
+    )
+}
+
+/// Extract the genomic HGVS expression on the NC_ accession of the given assembly
+///
+/// Expressions whose `@Assembly` matches are preferred; expressions without an assembly are used
+/// as a fallback, and expressions of other assemblies are never used.
+///
+/// # Arguments
+///
+/// * `allele`: `SimpleAllele`
+/// * `assembly`: `Assembly`
+///
+/// returns: Option<String>
+pub fn extract_genomic_hgvs(allele: &SimpleAllele, assembly: Assembly) -> Option<String> {
+    let mut candidates = allele
+        .hgvs_list
+        .as_ref()?
+        .hgvs
+        .iter()
+        .filter(|x| {
+            x.r#type
+                .as_deref()
+                .is_some_and(|t| t.starts_with("genomic"))
+        })
+        .filter_map(|x| {
+            let nucleotide = x.nucleotide_expression.as_ref()?;
+            let expression = nucleotide.expression.as_deref()?.trim();
+            if !expression.starts_with("NC_") {
+                return None;
+            }
+            match x.assembly.as_deref().or(nucleotide.assembly.as_deref()) {
+                Some(x) if assembly.matches(x) => Some((0, expression)),
+                Some(_) => None,
+                None => Some((1, expression)),
+            }
+        })
+        .collect::<Vec<_>>();
+    candidates.sort();
+
+    candidates.first().map(|x| x.1.to_string())
+}
+
+/// Extract the protein HGVS expression of the MANE Select transcript
+///
+/// # Arguments
+///
+/// * `allele`: `SimpleAllele`
+///
+/// returns: Option<String>
+pub fn extract_protein_hgvs(allele: &SimpleAllele) -> Option<String> {
+    let mut candidates = allele
+        .hgvs_list
+        .as_ref()?
+        .hgvs
+        .iter()
+        .filter(|x| {
+            x.nucleotide_expression
+                .as_ref()
+                .and_then(|x| x.mane_select.as_deref())
+                .is_some_and(|x| x.eq_ignore_ascii_case("true"))
+        })
+        .filter_map(|x| x.protein_expression.as_ref()?.expression.as_deref())
+        .map(|x| x.trim())
+        .collect::<Vec<&str>>();
+    candidates.sort();
+
+    candidates.first().map(|x| x.to_string())
+}
+
+pub fn extract_conditions(
+    record: &ClassifiedRecord,
+    config: &FormatConfig,
+    diagnostics: &mut Diagnostics,
+) -> RcvEntries {
+    extract_classifications(record, config, diagnostics, |x| {
+        x.germline_classification
+            .as_ref()
+            .map(|x| x as &dyn Classification)
+    })
+}
+
+/// Extract the aggregate germline classification
+///
+/// Records without an aggregate classification fall back to [`aggregate_significance`] over the
+/// RCV classifications.
+pub fn extract_significance(
+    record: &ClassifiedRecord,
+    diagnostics: &mut Diagnostics,
+) -> Option<String> {
+    match record
+        .classifications
+        .as_ref()
+        .and_then(|x| x.germline_classification.as_ref())
+    {
+        Some(x) => match sentinel_classification(&x.description.text) {
+            Some(token) => Some(token.to_string()),
+            None => Some(controlled_significance(
+                &normalize_classification(&x.description.text),
+                diagnostics,
+            )),
+        },
+        None => aggregate_significance(record).map(|x| {
+            controlled_significance(
+                &x.split('/').map(|x| x.to_string()).collect::<Vec<_>>(),
+                diagnostics,
+            )
+        }),
+    }
+    .filter(|x| !x.is_empty())
+}
+
+/// Map classification tokens onto the [`ClinicalSignificance`] vocabulary, joined with `/`
+///
+/// Tokens outside the vocabulary are written as `other:<token>` and flagged.
+fn controlled_significance(tokens: &[String], diagnostics: &mut Diagnostics) -> String {
+    tokens
+        .iter()
+        .filter(|x| !x.is_empty())
+        .map(|x| match x.parse::<ClinicalSignificance>() {
+            Ok(x) => x.to_string(),
+            Err(x) => {
+                diagnostics.flag(QcFlag::UnknownClassification);
+                format!("other:{}", x)
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("/")
+}
+
+/// Tally submissions per germline classification for records whose aggregate is conflicting
+///
+/// e.g. `Pathogenic(3)|Uncertain_significance(2)`. Tallies are computed from the submission count
+/// of each RCV over the terms of the vocabulary of [`ClinicalSignificance`]. RCVs classified as a
+/// range (e.g. `Pathogenic/Likely pathogenic`) or as conflicting themselves cannot be attributed
+/// to a single term and are not counted, nor are terms outside the vocabulary.
+///
+/// # Arguments
+///
+/// * `record`: `ClassifiedRecord`
+///
+/// returns: Option<String>
+///          `None` unless the aggregate germline classification is conflicting
+pub fn extract_significance_conflicts(record: &ClassifiedRecord) -> Option<String> {
+    if !extract_significance(record, &mut Diagnostics::default())?
+        .to_lowercase()
+        .starts_with("conflicting")
+    {
+        return None;
+    }
+
+    let mut tallies: Vec<(ClinicalSignificance, i64)> = Vec::new();
+    for description in record
+        .rcv_list
+        .rcv_accession
+        .iter()
+        .filter_map(|x| x.rcv_classifications.germline_classification.as_ref())
+        .map(|x| &x.description)
+        .filter(|x| sentinel_classification(&x.text).is_none())
+    {
+        for token in normalize_classification(&description.text) {
+            let significance = match token.parse::<ClinicalSignificance>() {
+                Ok(x) if is_single_term(x) => x,
+                _ => continue,
+            };
+            let count = description.submission_count.max(0) as i64;
+            match tallies.iter_mut().find(|x| x.0 == significance) {
+                Some(x) => x.1 += count,
+                None => tallies.push((significance, count)),
+            }
+        }
+    }
+    if tallies.is_empty() {
+        return None;
+    }
+
+    tallies.sort_by(|a, b| {
+        significance_rank(a.0)
+            .cmp(&significance_rank(b.0))
+            .then_with(|| a.0.as_str().cmp(b.0.as_str()))
+    });
+
+    Some(
+        tallies
+            .into_iter()
+            .map(|(significance, count)| {
+                // capitalized as in the official ClinVar VCF, e.g. Uncertain_significance
+                let token = significance.as_str();
+                format!("{}{}({})", token[..1].to_uppercase(), &token[1..], count)
+            })
+            .collect::<Vec<String>>()
+            .join("|"),
+    )
+}
+
+/// Whether a classification is a single term, rather than a range of terms (e.g.
+/// `pathogenic_or_likely_pathogenic`) or a conflict
+fn is_single_term(significance: ClinicalSignificance) -> bool {
+    !matches!(
+        significance,
+        ClinicalSignificance::Conflicting | ClinicalSignificance::ConflictingDataFromSubmitters
+    ) && !significance.as_str().contains("_or_")
+}
+
+/// Position of a classification from pathogenic to benign, others last
+fn significance_rank(significance: ClinicalSignificance) -> usize {
+    const ORDER: [ClinicalSignificance; 7] = [
+        ClinicalSignificance::Pathogenic,
+        ClinicalSignificance::PathogenicLowPenetrance,
+        ClinicalSignificance::LikelyPathogenic,
+        ClinicalSignificance::LikelyPathogenicLowPenetrance,
+        ClinicalSignificance::UncertainSignificance,
+        ClinicalSignificance::LikelyBenign,
+        ClinicalSignificance::Benign,
+    ];
+
+    ORDER
+        .iter()
+        .position(|x| *x == significance)
+        .unwrap_or(ORDER.len())
+}
+
+/// Aggregate the germline classifications of all RCVs into one token
+///
+/// A compound phrase within one RCV (e.g. `Pathogenic/Likely pathogenic`) is a range on one side
+/// of the pathogenicity scale, whereas RCVs on different sides conflict. Tokens outside the scale
+/// are only reported when no RCV is on the scale.
+///
+/// # Arguments
+///
+/// * `record`: `ClassifiedRecord`
+///
+/// returns: Option<String>
+pub fn aggregate_significance(record: &ClassifiedRecord) -> Option<String> {
+    const CONFLICTING: &str = "conflicting_classifications_of_pathogenicity";
+
+    let mut tokens = record
+        .rcv_list
+        .rcv_accession
+        .iter()
+        .filter_map(|x| x.rcv_classifications.germline_classification.as_ref())
+        .filter(|x| sentinel_classification(&x.description.text).is_none())
+        .flat_map(|x| normalize_classification(&x.description.text))
+        .filter(|x| !x.is_empty())
+        .collect::<Vec<String>>();
+    tokens.sort();
+    tokens.dedup();
+
+    if tokens.iter().any(|x| x.starts_with("conflicting_")) {
+        return Some(CONFLICTING.to_string());
+    }
+
+    let scaled = tokens
+        .iter()
+        .filter_map(|x| pathogenicity(x).map(|p| (p, x)))
+        .collect::<Vec<_>>();
+    if scaled.is_empty() {
+        return if tokens.is_empty() {
+            None
+        } else {
+            Some(tokens.join("/"))
+        };
+    }
+
+    let side = scaled[0].0;
+    if scaled.iter().any(|x| x.0 != side) {
+        return Some(CONFLICTING.to_string());
+    }
+    if scaled.len() == 1 {
+        return Some(scaled[0].1.to_string());
+    }
+
+    Some(
+        match side {
+            Pathogenicity::Pathogenic => "pathogenic_or_likely_pathogenic",
+            Pathogenicity::Uncertain => "uncertain_significance",
+            Pathogenicity::Benign => "benign_or_likely_benign",
+        }
+        .to_string(),
+    )
+}
+
+/// Compute the FILTER of a record
+///
+/// A record is `conflicting` if its aggregate germline classification is conflicting or if its
+/// RCVs have germline classifications on both the pathogenic and the benign side (uncertain
+/// significance conflicts with neither). Otherwise it is `no_assertion` if the review status of
+/// every aggregate and RCV classification is `no assertion criteria provided`, and `PASS` if not.
+///
+/// # Arguments
+///
+/// * `record`: `ClassifiedRecord`
+///
+/// returns: Filter
+pub fn extract_filter(record: &ClassifiedRecord) -> Filter {
+    const NO_ASSERTION: &str = "no_assertion_criteria_provided";
+
+    let aggregate = record
+        .classifications
+        .as_ref()
+        .and_then(|x| x.germline_classification.as_ref());
+    if aggregate.is_some_and(|x| x.description.text.to_lowercase().contains("conflicting")) {
+        return Filter::Conflicting;
+    }
+
+    let sides = record
+        .rcv_list
+        .rcv_accession
+        .iter()
+        .filter_map(|x| x.rcv_classifications.germline_classification.as_ref())
+        .filter(|x| sentinel_classification(&x.description.text).is_none())
+        .flat_map(|x| normalize_classification(&x.description.text))
+        .filter_map(|x| pathogenicity(&x))
+        .collect::<BTreeSet<Pathogenicity>>();
+    if sides.contains(&Pathogenicity::Pathogenic) && sides.contains(&Pathogenicity::Benign) {
+        return Filter::Conflicting;
+    }
+
+    let mut review_statuses = aggregate
+        .and_then(|x| x.review_status.as_deref())
+        .into_iter()
+        .chain(record.rcv_list.rcv_accession.iter().flat_map(|x| {
+            let classifications = &x.rcv_classifications;
+            [
+                classifications
+                    .germline_classification
+                    .as_ref()
+                    .and_then(|x| x.review_status()),
+                classifications
+                    .somatic_clinical_impact
+                    .as_ref()
+                    .and_then(|x| x.review_status()),
+                classifications
+                    .oncogenicity_classification
+                    .as_ref()
+                    .and_then(|x| x.review_status()),
+            ]
+            .into_iter()
+            .flatten()
+        }))
+        .map(|x| normalize_review_status(x).to_lowercase())
+        .peekable();
+    if review_statuses.peek().is_some() && review_statuses.all(|x| x == NO_ASSERTION) {
+        return Filter::NoAssertion;
+    }
+
+    Filter::Pass
+}
+
+pub fn extract_review_status(record: &ClassifiedRecord) -> Option<String> {
+    record
+        .classifications
+        .as_ref()
+        .and_then(|x| x.germline_classification.as_ref())
+        .and_then(|x| x.review_status.as_deref())
+        .map(normalize_review_status)
+        .filter(|x| !x.is_empty())
+}
+
+/// Extract the allele origins observed by all submitters
+///
+/// The submitted records are only read with `include_scv` (`None` otherwise). Origins are
+/// lowercased with spaces replaced by underscores, deduplicated, sorted and joined
+/// with `/` (e.g. `de_novo/germline`).
+///
+/// # Arguments
+///
+/// * `record`: `ClassifiedRecord`
+///
+/// returns: Option<String>
+pub fn extract_origin(record: &ClassifiedRecord) -> Option<String> {
+    let mut origins = record
+        .clinical_assertion_list
+        .as_ref()?
+        .clinical_assertion
+        .iter()
+        .filter_map(|x| x.observed_in_list.as_ref())
+        .flat_map(|x| x.observed_in.iter())
+        .filter_map(|x| x.sample.as_ref()?.origin.as_deref())
+        .map(|x| encode_info_value(&x.trim().to_lowercase().replace(' ', "_"), &['/']))
+        .filter(|x| !x.is_empty())
+        .collect::<Vec<String>>();
+    origins.sort();
+    origins.dedup();
+
+    if origins.is_empty() {
+        return None;
+    }
+
+    Some(origins.join("/"))
+}
+
+/// Sums of the submission counts of the RCV classifications of each type
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SubmissionCounts {
+    pub germline: u64,
+    pub somatic: u64,
+    pub oncogenicity: u64,
+}
+
+impl SubmissionCounts {
+    pub fn total(&self) -> u64 {
+        self.germline + self.somatic + self.oncogenicity
+    }
+}
+
+/// Sum the submission counts of the RCV classifications by type, counting negative ones as 0
+///
+/// # Arguments
+///
+/// * `record`: `ClassifiedRecord`
+///
+/// returns: SubmissionCounts
+pub fn extract_submission_counts(record: &ClassifiedRecord) -> SubmissionCounts {
+    let sum = |f: fn(&RCVClassifications) -> Option<&dyn Classification>| {
+        record
+            .rcv_list
+            .rcv_accession
+            .iter()
+            .filter_map(|x| f(&x.rcv_classifications))
+            .map(|x| x.description().submission_count.max(0) as u64)
+            .sum()
+    };
+
+    SubmissionCounts {
+        germline: sum(|x| x.germline_classification.as_ref().map(|x| x as _)),
+        somatic: sum(|x| x.somatic_clinical_impact.as_ref().map(|x| x as _)),
+        oncogenicity: sum(|x| x.oncogenicity_classification.as_ref().map(|x| x as _)),
+    }
+}
+
+/// Submission counts of the record if any of GERMLINE_SUBMISSIONS, SOMATIC_SUBMISSIONS and
+/// ONC_SUBMISSIONS is written, otherwise zeros without extracting them
+fn selected_submission_counts(
+    record: &ClassifiedRecord,
+    config: &FormatConfig,
+) -> SubmissionCounts {
+    let fields = [
+        "GERMLINE_SUBMISSIONS",
+        "SOMATIC_SUBMISSIONS",
+        "ONC_SUBMISSIONS",
+    ];
+    if fields.iter().any(|x| config.emits(x)) {
+        extract_submission_counts(record)
+    } else {
+        SubmissionCounts::default()
+    }
+}
+
+/// Count the submitted records (SCVs) by the `ClinicalAssertion` elements, or by the submission
+/// counts of the RCV classifications if the record has no `ClinicalAssertionList` or it is not
+/// read (without `include_scv`)
+///
+/// # Arguments
+///
+/// * `record`: `ClassifiedRecord`
+///
+/// returns: u64
+pub fn extract_scv_count(record: &ClassifiedRecord) -> u64 {
+    match record.clinical_assertion_list.as_ref() {
+        Some(x) => x.clinical_assertion.len() as u64,
+        None => extract_submission_counts(record).total(),
+    }
+}
+
+/// Format the submitted records as `<SCV>.<version>:<SubmitterName>:<Classification>|...`
+///
+/// Spaces in submitter names and classifications are replaced by `_` and the other special
+/// characters are percent-encoded, e.g. `SCV000012345.2:Lab_A%2C_Inc.:Pathogenic`. Missing values
+/// are written as `.`.
+///
+/// # Arguments
+///
+/// * `archive`: `VariationArchive` read with its submitted records
+///
+/// returns: Option<String>
+///          `None` if the submitted records were not read or there are none
+pub fn extract_scvs(archive: &VariationArchive) -> Option<String> {
+    let encode = |x: Option<&str>| match x {
+        Some(x) => encode_info_value(&x.replace(' ', "_"), &SCV_DELIMITERS),
+        None => ".".to_string(),
+    };
+
+    let scvs = archive.scvs.as_ref().filter(|x| !x.is_empty())?;
+    Some(
+        scvs.iter()
+            .map(|x| {
+                format!(
+                    "{}:{}:{}",
+                    encode(Some(&x.accession)),
+                    encode(x.submitter_name.as_deref()),
+                    encode(x.classification.as_deref())
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("|"),
+    )
+}
+
+/// Extract the PubMed IDs cited by the aggregate and the RCV classifications, and by the
+/// submitted records if they were read, sorted and deduplicated
+///
+/// # Arguments
+///
+/// * `archive`: `VariationArchive`
+///
+/// returns: Vec<u64>
+pub fn extract_pmids(archive: &VariationArchive) -> Vec<u64> {
+    let Some(record) = archive.classified_record.as_ref() else {
+        return Vec::new();
+    };
+
+    let aggregate = record
+        .classifications
+        .iter()
+        .flat_map(|x| x.germline_classification.iter())
+        .flat_map(|x| x.citation.iter());
+    let rcvs = record.rcv_list.rcv_accession.iter().flat_map(|x| {
+        let classifications = &x.rcv_classifications;
+        let germline = classifications
+            .germline_classification
+            .iter()
+            .flat_map(|x| x.citations());
+        let somatic = classifications
+            .somatic_clinical_impact
+            .iter()
+            .flat_map(|x| x.citations());
+        let oncogenicity = classifications
+            .oncogenicity_classification
+            .iter()
+            .flat_map(|x| x.citations());
+        germline.chain(somatic).chain(oncogenicity)
+    });
+    let mut pmids = aggregate
+        .chain(rcvs)
+        .flat_map(|x| x.pmids())
+        .chain(
+            archive
+                .scvs
+                .iter()
+                .flatten()
+                .flat_map(|x| x.pmids.iter().copied()),
+        )
+        .collect::<Vec<u64>>();
+    pmids.sort_unstable();
+    pmids.dedup();
+
+    pmids
+}
+
+/// Extract the most recent `DateLastEvaluated` across the aggregate and all RCV classifications
+///
+/// # Arguments
+///
+/// * `record`: `ClassifiedRecord`
+///
+/// returns: Option<String>
+pub fn extract_date_last_evaluated(record: &ClassifiedRecord) -> Option<String> {
+    let aggregate = record
+        .classifications
+        .as_ref()
+        .and_then(|x| x.germline_classification.as_ref())
+        .and_then(|x| x.description.date_last_evaluated.as_deref());
+
+    let rcvs = record.rcv_list.rcv_accession.iter().flat_map(|x| {
+        let c = &x.rcv_classifications;
+        [
+            c.germline_classification
+                .as_ref()
+                .map(|x| x as &dyn Classification),
+            c.somatic_clinical_impact
+                .as_ref()
+                .map(|x| x as &dyn Classification),
+            c.oncogenicity_classification
+                .as_ref()
+                .map(|x| x as &dyn Classification),
+        ]
+        .into_iter()
+        .flatten()
+        .filter_map(|x| x.description().date_last_evaluated.as_deref())
+    });
+
+    aggregate
+        .into_iter()
+        .chain(rcvs)
+        .filter_map(normalize_date)
+        .max()
+        .map(|x| x.to_string())
+}
+
+/// Whether any RCV has one of the classifications
+///
+/// A compound classification matches each of its terms, e.g. `pathogenic_or_likely_pathogenic`
+/// matches both `pathogenic` and `likely_pathogenic`. It is enough for one of several RCVs to
+/// match.
+///
+/// # Arguments
+///
+/// * `conditions`: `RcvEntries` of germline classifications
+/// * `significances`: classifications to look for
+///
+/// returns: bool
+pub fn includes_significance(
+    conditions: &RcvEntries,
+    significances: &[ClinicalSignificance],
+) -> bool {
+    conditions.significances.iter().any(|x| {
+        significances.contains(x)
+            || x.as_str()
+                .split("_or_")
+                .filter_map(|x| x.parse::<ClinicalSignificance>().ok())
+                .any(|x| significances.contains(&x))
+    })
+}
+
+/// A record is unclassified if its aggregate germline classification is a sentinel, or if it has
+/// no aggregate and every RCV germline classification is a sentinel
+pub fn is_unclassified(record: &ClassifiedRecord) -> bool {
+    if let Some(x) = record
+        .classifications
+        .as_ref()
+        .and_then(|x| x.germline_classification.as_ref())
+    {
+        return sentinel_classification(&x.description.text).is_some();
+    }
+
+    let mut descriptions = record
+        .rcv_list
+        .rcv_accession
+        .iter()
+        .filter_map(|x| x.rcv_classifications.germline_classification.as_ref())
+        .peekable();
+
+    descriptions.peek().is_some()
+        && descriptions.all(|x| sentinel_classification(&x.description.text).is_some())
+}
+
+pub fn extract_oncogenicity(
+    record: &ClassifiedRecord,
+    config: &FormatConfig,
+    diagnostics: &mut Diagnostics,
+) -> RcvEntries {
+    extract_classifications(record, config, diagnostics, |x| {
+        x.oncogenicity_classification
+            .as_ref()
+            .map(|x| x as &dyn Classification)
+    })
+}
+
+/// RCV classifications for the CONDITIONS style fields
+#[derive(Debug, Default)]
+pub struct RcvEntries {
+    /// One entry per RCV and condition database, ordered by accession
+    pub conditions: Vec<ConditionEntry>,
+    /// `<accession>.<version>` of the RCV of each entry
+    pub rcvs: Vec<String>,
+    /// Condition names of each entry, joined by `/` in the same order as the IDs
+    pub names: Vec<String>,
+    /// Number of RCVs omitted by `max_rcvs`
+    pub omitted: usize,
+    /// Number of entries (including omitted ones) with IDs from the condition databases
+    pub mapped: usize,
+    /// Classifications of all entries (including omitted ones) within the vocabulary
+    pub significances: BTreeSet<ClinicalSignificance>,
+}
+
+impl RcvEntries {
+    pub fn is_empty(&self) -> bool {
+        self.conditions.is_empty()
+    }
+
+    /// Value of a CONDITIONS style field, with an entry per element of `rcvs` and `names`
+    pub fn format(&self, format: ConditionsFormat) -> String {
+        match format {
+            ConditionsFormat::V1 => self
+                .conditions
+                .iter()
+                .map(|x| x.to_string())
+                .collect::<Vec<String>>()
+                .join("|"),
+            ConditionsFormat::V2 => self
+                .conditions
+                .iter()
+                .map(|x| x.v2())
+                .collect::<Vec<String>>()
+                .join(","),
+        }
+    }
+}
+
+impl fmt::Display for RcvEntries {
+    /// CONDITIONS style of [`ConditionsFormat::V1`]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.format(ConditionsFormat::V1))
+    }
+}
+
+/// Classification of an RCV with its conditions from a condition database
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConditionEntry {
+    /// `<accession>.<version>` of the RCV
+    pub rcv: String,
+    /// Condition database, or `NA` for condition names kept by `keep_unmapped_conditions`
+    pub db: String,
+    /// Condition IDs in the database, sorted (the names if `db` is `NA`)
+    pub ids: Vec<String>,
+    /// Condition names in the same order as the IDs
+    pub names: Vec<String>,
+    /// Classification tokens of the vocabulary of [`ClinicalSignificance`], `other:<token>`
+    /// outside it, or a single sentinel token
+    pub classifications: Vec<String>,
+    pub submission_count: i32,
+    /// Normalized review status
+    pub review_status: Option<String>,
+}
+
+impl fmt::Display for ConditionEntry {
+    /// CONDITIONS style, e.g. `MedGen:C0001/C0002:pathogenic:2:criteria_provided,_single_submitter`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let encode = |x: &[String]| {
+            x.iter()
+                .map(|x| encode_info_value(x, &CONDITIONS_DELIMITERS))
+                .collect::<Vec<String>>()
+                .join("/")
+        };
+        // unmapped names as IDs have spaces replaced by underscores as in CLNDN
+        let ids = self
+            .ids
+            .iter()
+            .map(|x| match self.db.as_str() {
+                DB_UNMAPPED => encode_info_value(&x.replace(' ', "_"), &CONDITIONS_DELIMITERS),
+                _ => encode_info_value(x, &CONDITIONS_DELIMITERS),
+            })
+            .collect::<Vec<String>>()
+            .join("/");
+
+        write!(
+            f,
+            "{}:{}:{}:{}:{}",
+            self.db,
+            ids,
+            encode(&self.classifications),
+            self.submission_count,
+            match &self.review_status {
+                Some(x) => encode_info_value(x, &CONDITIONS_DELIMITERS),
+                None => ".".to_string(),
+            }
+        )
+    }
+}
+
+impl ConditionEntry {
+    /// CONDITIONS style of [`ConditionsFormat::V2`] as
+    /// `<DB>|<ID1>/<ID2>/...|<Classification1>/...|<ReviewStatus>|<SubmissionCount>`, with all the
+    /// components percent-encoded, e.g.
+    /// `MedGen|C0001/C0002|pathogenic|criteria_provided%2C_single_submitter|2`
+    pub fn v2(&self) -> String {
+        let encode = |x: &[String]| {
+            x.iter()
+                .map(|x| encode_info_value(x, &CONDITIONS_V2_DELIMITERS))
+                .collect::<Vec<String>>()
+                .join("/")
+        };
+
+        format!(
+            "{}|{}|{}|{}|{}",
+            encode_info_value(&self.db, &CONDITIONS_V2_DELIMITERS),
+            encode(&self.ids),
+            encode(&self.classifications),
+            match &self.review_status {
+                Some(x) => encode_info_value(x, &CONDITIONS_V2_DELIMITERS),
+                None => ".".to_string(),
+            },
+            self.submission_count
+        )
+    }
+}
+
+/// Format RCV classifications in CONDITIONS style
+///
+/// Each RCV yields one entry per condition database (in configured order) which it has conditions
+/// from, e.g. `MedGen:C0001:...|OMIM:600185:...`. With `keep_unmapped_conditions`, an RCV without
+/// any yields a single `NA:<name1>/<name2>/...:...` entry instead (`NA:not_provided:...` if it has
+/// no condition names either).
+///
+/// If `max_rcvs` is given and exceeded, only the entries of the RCVs with the most submissions
+/// (ties broken by accession) are kept, and the number of the other RCVs is recorded in `omitted`.
+/// Soft issues are recorded into `diagnostics`.
+///
+/// returns: RcvEntries
+pub fn extract_classifications<F>(
+    record: &ClassifiedRecord,
+    config: &FormatConfig,
+    diagnostics: &mut Diagnostics,
+    classification: F,
+) -> RcvEntries
+where
+    F: Fn(&RCVClassifications) -> Option<&dyn Classification>,
+{
+    let mut entries = Vec::new();
+    let mut mapped = 0;
+    let mut significances = BTreeSet::new();
+    for rcv in record.rcv_list.sorted() {
+        let c = match classification(&rcv.rcv_classifications) {
+            Some(x) => x,
+            None => continue,
+        };
+
+        let mut groups = config
+            .condition_databases()
+            .iter()
+            .filter_map(|db| {
+                let raw = rcv
+                    .classified_condition_list
+                    .classified_condition
+                    .iter()
+                    .filter(|x| x.db.as_deref().is_some_and(|x| x.eq_ignore_ascii_case(db)))
+                    .filter_map(|x| x.id.as_deref().map(|id| (id, x.text.as_str())))
+                    .collect::<Vec<(&str, &str)>>();
+                let mut conditions = raw
+                    .iter()
+                    .map(|(id, name)| (id.trim(), *name))
+                    .filter(|(id, _)| !id.is_empty())
+                    .collect::<Vec<(&str, &str)>>();
+                conditions.sort_by(|a, b| compare_ids(a.0, b.0));
+                conditions.dedup_by(|a, b| a.0 == b.0);
+                if conditions.len() != raw.len() || raw.iter().any(|(id, _)| id.trim() != *id) {
+                    diagnostics.flag(QcFlag::ConditionIdNormalized);
+                }
+                if conditions.is_empty() {
+                    return None;
+                }
+
+                let ids = conditions.iter().map(|x| x.0).collect::<Vec<&str>>();
+                let names = conditions.iter().map(|x| x.1.trim()).collect::<Vec<&str>>();
+                Some((db.as_str(), ids, names))
+            })
+            .collect::<Vec<_>>();
+        mapped += groups.len();
+        if groups.is_empty() && config.keep_unmapped_conditions {
+            let names = rcv
+                .classified_condition_list
+                .classified_condition
+                .iter()
+                .map(|x| x.text.trim())
+                .filter(|x| !x.is_empty())
+                .collect::<Vec<&str>>();
+            let names = if names.is_empty() {
+                vec![NOT_PROVIDED]
+            } else {
+                names
+            };
+            groups.push((DB_UNMAPPED, names.clone(), names));
+        }
+        if groups.is_empty() {
+            continue;
+        }
+
+        let x = c.description();
+        let submission_count = if x.submission_count < 0 {
+            diagnostics.flag(QcFlag::SubmissionCountClamped);
+            0
+        } else {
+            x.submission_count
+        };
+        let classifications = match sentinel_classification(&x.text) {
+            Some(token) => {
+                diagnostics.flag(QcFlag::SentinelClassification);
+                vec![token.to_string()]
+            }
+            None => normalize_classification(&x.text)
+                .iter()
+                .map(|x| {
+                    if let Ok(x) = x.parse::<ClinicalSignificance>() {
+                        significances.insert(x);
+                    }
+                    controlled_significance(std::slice::from_ref(x), diagnostics)
+                })
+                .collect::<Vec<String>>(),
+        };
+        let review_status = c.review_status().map(normalize_review_status);
+        if review_status.is_none() {
+            diagnostics.flag(QcFlag::ReviewStatusMissing);
+        }
+
+        for (db, ids, names) in groups {
+            entries.push((
+                rcv,
+                ConditionEntry {
+                    rcv: format!("{}.{}", rcv.accession, rcv.version),
+                    db: db.to_string(),
+                    ids: ids.iter().map(|x| x.to_string()).collect(),
+                    names: names.iter().map(|x| x.to_string()).collect(),
+                    classifications: classifications.clone(),
+                    submission_count,
+                    review_status: review_status.clone(),
+                },
+            ));
+        }
+    }
+
+    let mut omitted = 0;
+    if let Some(max) = config.max_rcvs {
+        // the entries of an RCV are adjacent and share its submission count
+        let mut ranked = entries
+            .iter()
+            .map(|x| (x.0, x.1.submission_count))
+            .collect::<Vec<_>>();
+        ranked.dedup_by(|a, b| std::ptr::eq(a.0, b.0));
+        if ranked.len() > max {
+            omitted = ranked.len() - max;
+            ranked.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.accession.cmp(&b.0.accession)));
+            let kept = &ranked[..max];
+            entries.retain(|x| kept.iter().any(|y| std::ptr::eq(x.0, y.0)));
+        }
+    }
+
+    if omitted != 0 {
+        diagnostics.flag(QcFlag::ConditionsTruncated);
+    }
+
+    let conditions = entries.into_iter().map(|x| x.1).collect::<Vec<_>>();
+
+    RcvEntries {
+        rcvs: conditions.iter().map(|x| x.rcv.clone()).collect(),
+        names: conditions
+            .iter()
+            .map(|x| {
+                // unmapped names are also the IDs of CONDITIONS
+                let delimiters: &[char] = if x.db == DB_UNMAPPED {
+                    &CONDITIONS_DELIMITERS
+                } else {
+                    &CLNDN_DELIMITERS
+                };
+                x.names
+                    .iter()
+                    .map(|x| encode_info_value(&x.replace(' ', "_"), delimiters))
+                    .collect::<Vec<String>>()
+                    .join("/")
+            })
+            .collect(),
+        conditions,
+        omitted,
+        mapped,
+        significances,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rcv(accession: &str, version: u32, medgen: &[&str], classifications: &str) -> String {
+        let conditions = medgen
+            .iter()
+            .map(|x| {
+                format!(
+                    r#"<ClassifiedCondition DB="MedGen" ID="{}">Disease</ClassifiedCondition>"#,
+                    x
+                )
+            })
+            .collect::<String>();
+
+        format!(
+            r#"<RCVAccession Title="title" Accession="{}" Version="{}">
+  <ClassifiedConditionList>{}</ClassifiedConditionList>
+  <RCVClassifications>{}</RCVClassifications>
+</RCVAccession>"#,
+            accession, version, conditions, classifications
+        )
+    }
+
+    fn archive(rcvs: &[String]) -> VariationArchive {
+        quick_xml::de::from_str(&format!(
+            r#"<VariationArchive VariationID="1" Accession="VCV000000001">
+  <ClassifiedRecord>
+    <SimpleAllele AlleleID="11" VariationID="1">
+      <Location>
+        <SequenceLocation Assembly="GRCh38" Chr="1" positionVCF="100" referenceAlleleVCF="A" alternateAlleleVCF="G"/>
+      </Location>
+    </SimpleAllele>
+    <RCVList>{}</RCVList>
+  </ClassifiedRecord>
+</VariationArchive>"#,
+            rcvs.concat()
+        ))
+        .unwrap()
+    }
+
+    fn convert(archive: &VariationArchive, config: &FormatConfig) -> Result<VcfRecord, SkipReason> {
+        let mut records = to_vcf_records(archive, Assembly::GRCh38, config);
+        assert_eq!(records.len(), 1);
+
+        records.remove(0)
+    }
+
+    /// Value of an INFO field (empty for a flag)
+    fn info<'a>(record: &'a VcfRecord, key: &str) -> Option<&'a str> {
+        record.info.iter().find_map(|x| match x.split_once('=') {
+            Some((k, v)) if k == key => Some(v),
+            None if x == key => Some(""),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn oncogenicity_is_written_into_onc() {
+        let archive = archive(&[rcv(
+            "RCV000000001",
+            1,
+            &["C0002"],
+            r#"<GermlineClassification><ReviewStatus>criteria provided, single submitter</ReviewStatus><Description SubmissionCount="1">Pathogenic</Description></GermlineClassification>
+<OncogenicityClassification><ReviewStatus>criteria provided, single submitter</ReviewStatus><Description SubmissionCount="2">Oncogenic</Description></OncogenicityClassification>"#,
+        )]);
+
+        let vcf = convert(&archive, &FormatConfig::default()).unwrap();
+        assert_eq!(
+            info(&vcf, "ONC"),
+            Some("MedGen:C0002:oncogenic:2:criteria_provided%2C_single_submitter")
+        );
+        assert_eq!(
+            info(&vcf, "CONDITIONS"),
+            Some("MedGen:C0002:pathogenic:1:criteria_provided%2C_single_submitter")
+        );
+    }
+
+    #[test]
+    fn record_with_only_oncogenicity_is_emitted() {
+        let archive = archive(&[rcv(
+            "RCV000000001",
+            1,
+            &["C0002"],
+            r#"<OncogenicityClassification><ReviewStatus>no assertion criteria provided</ReviewStatus><Description SubmissionCount="1">Likely oncogenic</Description></OncogenicityClassification>"#,
+        )]);
+
+        let vcf = convert(&archive, &FormatConfig::default()).unwrap();
+        assert_eq!(
+            info(&vcf, "ONC"),
+            Some("MedGen:C0002:likely_oncogenic:1:no_assertion_criteria_provided")
+        );
+        assert_eq!(info(&vcf, "CONDITIONS"), None);
+    }
+
+    #[test]
+    fn onc_is_not_written_unless_selected() {
+        let archive = archive(&[
+            rcv("RCV000000001", 1, &["C0001"], &germline("Pathogenic")),
+            rcv(
+                "RCV000000002",
+                1,
+                &["C0002"],
+                r#"<OncogenicityClassification><ReviewStatus>criteria provided, single submitter</ReviewStatus><Description SubmissionCount="1">Oncogenic</Description></OncogenicityClassification>"#,
+            ),
+        ]);
+        let config = FormatConfig {
+            info_fields: Some(["CONDITIONS".to_string()].into_iter().collect()),
+            ..Default::default()
+        };
+
+        let vcf = convert(&archive, &config).unwrap();
+
+        assert_eq!(info(&vcf, "ONC"), None);
+        assert!(info(&vcf, "CONDITIONS").is_some());
+    }
+
+    #[test]
+    fn submission_counts_are_only_extracted_if_selected() {
+        let archive = archive(&[
+            rcv("RCV000000001", 1, &["C0001"], &germline("Pathogenic")),
+            rcv(
+                "RCV000000002",
+                1,
+                &["C0002"],
+                r#"<SomaticClinicalImpact><ReviewStatus>criteria provided, single submitter</ReviewStatus><Description SubmissionCount="3">Tier I - Strong</Description></SomaticClinicalImpact>"#,
+            ),
+        ]);
+        let record = archive.classified_record.as_ref().unwrap();
+        let config = |fields: &[&str]| FormatConfig {
+            info_fields: Some(fields.iter().map(|x| x.to_string()).collect()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            selected_submission_counts(record, &config(&["CLNSIG", "SCV_COUNT"])),
+            SubmissionCounts::default()
+        );
+        assert_eq!(
+            selected_submission_counts(record, &config(&["SOMATIC_SUBMISSIONS"])),
+            SubmissionCounts {
+                germline: 1,
+                somatic: 3,
+                oncogenicity: 0,
+            }
+        );
+
+        let vcf = to_vcf_records(
+            &archive,
+            Assembly::GRCh38,
+            &config(&["CLNSIG", "SCV_COUNT"]),
+        )
+        .pop()
+        .unwrap()
+        .unwrap();
+        let keys = vcf
+            .info
+            .iter()
+            .map(|x| x.split('=').next().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(keys, vec!["CLNSIG", "SCV_COUNT"]);
+        assert_eq!(info(&vcf, "SCV_COUNT"), Some("4"));
+    }
+
+    /// All orders of `items`
+    fn permutations<T: Clone>(items: &[T]) -> Vec<Vec<T>> {
+        if items.len() <= 1 {
+            return vec![items.to_vec()];
+        }
+        (0..items.len())
+            .flat_map(|i| {
+                let mut rest = items.to_vec();
+                let first = rest.remove(i);
+                permutations(&rest).into_iter().map(move |mut x| {
+                    x.insert(0, first.clone());
+                    x
+                })
+            })
+            .collect()
+    }
+
+    fn germline(text: &str) -> String {
+        format!(
+            r#"<GermlineClassification><ReviewStatus>criteria provided, single submitter</ReviewStatus><Description SubmissionCount="1">{}</Description></GermlineClassification>"#,
+            text
+        )
+    }
+
+    #[test]
+    fn rcv_order_does_not_change_output() {
+        let rcvs = vec![
+            rcv(
+                "RCV000000003",
+                1,
+                &["C0003", "C0002"],
+                &germline("Pathogenic"),
+            ),
+            rcv("RCV000000001", 2, &["C0001"], &germline("Benign")),
+            rcv(
+                "RCV000000002",
+                1,
+                &["C0010"],
+                &germline("Uncertain significance"),
+            ),
+            rcv("RCV000000001", 1, &["C0001"], &germline("Likely benign")),
+        ];
+
+        let config = FormatConfig::default();
+        let line = |rcvs: &[String]| convert(&archive(rcvs), &config).unwrap().to_string();
+
+        let expected = line(&rcvs);
+        assert!(expected.contains(
+            "CONDITIONS=MedGen:C0001:likely_benign:1:criteria_provided%2C_single_submitter|\
+             MedGen:C0001:benign:1:criteria_provided%2C_single_submitter|\
+             MedGen:C0010:uncertain_significance:1:criteria_provided%2C_single_submitter|\
+             MedGen:C0002/C0003:pathogenic:1:criteria_provided%2C_single_submitter"
+        ));
+        for rcvs in permutations(&rcvs) {
+            assert_eq!(line(&rcvs), expected);
+        }
+    }
+
+    fn submitted(text: &str, submissions: u32) -> String {
+        format!(
+            r#"<GermlineClassification><Description SubmissionCount="{}">{}</Description></GermlineClassification>"#,
+            submissions, text
+        )
+    }
+
+    #[test]
+    fn max_rcvs_keeps_the_most_submitted_rcvs() {
+        let rcvs = (1..=50)
+            .map(|i| {
+                rcv(
+                    &format!("RCV{:09}", i),
+                    1,
+                    &[&format!("C{:07}", i)],
+                    &submitted("Pathogenic", if i > 45 { 10 } else { 1 }),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let config = FormatConfig {
+            max_rcvs: Some(5),
+            ..Default::default()
+        };
+
+        let vcf = convert(&archive(&rcvs), &config).unwrap();
+
+        let conditions = info(&vcf, "CONDITIONS").unwrap().split('|');
+        let expected = (46..=50)
+            .map(|i| format!("MedGen:C{:07}:pathogenic:10:.", i))
+            .collect::<Vec<_>>();
+        assert_eq!(conditions.collect::<Vec<_>>(), expected);
+        let rcvs = (46..=50)
+            .map(|i| format!("RCV{:09}.1", i))
+            .collect::<Vec<_>>();
+        assert_eq!(info(&vcf, "RCV"), Some(rcvs.join("|").as_str()));
+        assert_eq!(info(&vcf, "RCV_TRUNCATED"), Some("45"));
+    }
+
+    #[test]
+    fn max_rcvs_keeps_whole_rcvs() {
+        // RCVs 1-50 with 2 entries each (MedGen and OMIM), RCVs 46-50 with the most submissions
+        let rcvs = (1..=50)
+            .map(|i| {
+                rcv(
+                    &format!("RCV{:09}", i),
+                    1,
+                    &[&format!("C{:07}", i)],
+                    &submitted("Pathogenic", if i > 45 { 10 } else { 1 }),
+                )
+                .replace(
+                    "</ClassifiedConditionList>",
+                    &format!(
+                        r#"<ClassifiedCondition DB="OMIM" ID="{}">Disease</ClassifiedCondition></ClassifiedConditionList>"#,
+                        100000 + i
+                    ),
+                )
+            })
+            .collect::<Vec<_>>();
+        let config = FormatConfig {
+            max_rcvs: Some(5),
+            condition_dbs: vec!["MedGen".to_string(), "OMIM".to_string()],
+            ..Default::default()
+        };
+
+        let vcf = convert(&archive(&rcvs), &config).unwrap();
+
+        let values = |key| info(&vcf, key).unwrap().split('|').collect::<Vec<_>>();
+        let expected = (46..=50)
+            .flat_map(|i| [format!("RCV{:09}.1", i), format!("RCV{:09}.1", i)])
+            .collect::<Vec<_>>();
+        assert_eq!(values("RCV"), expected);
+        assert_eq!(values("CONDITIONS").len(), expected.len());
+        assert_eq!(values("CLNDN").len(), expected.len());
+        assert!(values("CONDITIONS")[0].starts_with("MedGen:C0000046:"));
+        assert!(values("CONDITIONS")[1].starts_with("OMIM:100046:"));
+        assert_eq!(info(&vcf, "RCV_TRUNCATED"), Some("45"));
+    }
+
+    #[test]
+    fn max_rcvs_ties_are_broken_by_accession() {
+        let rcvs = (1..=3)
+            .rev()
+            .map(|i| {
+                rcv(
+                    &format!("RCV{:09}", i),
+                    1,
+                    &[&format!("C{:07}", i)],
+                    &submitted("Benign", 1),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let config = FormatConfig {
+            max_rcvs: Some(2),
+            ..Default::default()
+        };
+
+        let vcf = convert(&archive(&rcvs), &config).unwrap();
+
+        assert_eq!(
+            info(&vcf, "CONDITIONS"),
+            Some("MedGen:C0000001:benign:1:.|MedGen:C0000002:benign:1:.")
+        );
+        assert_eq!(info(&vcf, "RCV"), Some("RCV000000001.1|RCV000000002.1"));
+        assert_eq!(info(&vcf, "RCV_TRUNCATED"), Some("1"));
+        let vcf = convert(&archive(&rcvs), &FormatConfig::default()).unwrap();
+        assert_eq!(info(&vcf, "RCV_TRUNCATED"), None);
+    }
+
+    /// One row per entry of `COMPOUND_CLASSIFICATIONS`: the phrase as ClinVar writes it and its
+    /// aggregate with a single RCV
+    #[test]
+    fn compound_classifications_stay_one_assertion() {
+        use ClinicalSignificance::*;
+
+        const CONFLICTING: &str = "conflicting_classifications_of_pathogenicity";
+        let rows = [
+            (
+                "Conflicting data from submitters",
+                CONFLICTING,
+                ConflictingDataFromSubmitters,
+            ),
+            (
+                "Conflicting interpretations of pathogenicity",
+                CONFLICTING,
+                Conflicting,
+            ),
+            (
+                "Conflicting classifications of pathogenicity",
+                CONFLICTING,
+                Conflicting,
+            ),
+            (
+                "Pathogenic/Likely pathogenic/Pathogenic, low penetrance",
+                "pathogenic_or_likely_pathogenic_or_pathogenic_low_penetrance",
+                PathogenicLowPenetrance,
+            ),
+            (
+                "Pathogenic/Likely pathogenic/Likely risk allele",
+                "pathogenic_or_likely_pathogenic_or_likely_risk_allele",
+                LikelyRiskAllele,
+            ),
+            (
+                "Pathogenic/Likely pathogenic",
+                "pathogenic_or_likely_pathogenic",
+                LikelyPathogenic,
+            ),
+            (
+                "Pathogenic/Pathogenic, low penetrance",
+                "pathogenic_or_pathogenic_low_penetrance",
+                Pathogenic,
+            ),
+            (
+                "Pathogenic/Likely risk allele",
+                "pathogenic_or_likely_risk_allele",
+                LikelyRiskAllele,
+            ),
+            (
+                "Likely pathogenic/Likely pathogenic, low penetrance",
+                "likely_pathogenic_or_likely_pathogenic_low_penetrance",
+                LikelyPathogenicLowPenetrance,
+            ),
+            (
+                "Likely pathogenic/Likely risk allele",
+                "likely_pathogenic_or_likely_risk_allele",
+                LikelyPathogenic,
+            ),
+            (
+                "Pathogenic, low penetrance",
+                "pathogenic_low_penetrance",
+                PathogenicLowPenetrance,
+            ),
+            (
+                "Likely pathogenic, low penetrance",
+                "likely_pathogenic_low_penetrance",
+                LikelyPathogenicLowPenetrance,
+            ),
+            (
+                "Benign/Likely benign",
+                "benign_or_likely_benign",
+                LikelyBenign,
+            ),
+            (
+                "Uncertain significance/Uncertain risk allele",
+                "uncertain_significance_or_uncertain_risk_allele",
+                UncertainRiskAllele,
+            ),
+        ];
+        assert_eq!(rows.len(), COMPOUND_CLASSIFICATIONS.len());
+
+        for ((phrase, aggregate, included), (entry, token)) in
+            rows.into_iter().zip(COMPOUND_CLASSIFICATIONS)
+        {
+            assert!(phrase.eq_ignore_ascii_case(entry), "{}", phrase);
+            assert_eq!(normalize_classification(phrase), vec![token], "{}", phrase);
+            assert_eq!(compound_classification(phrase), Some(token));
+            assert_eq!(
+                phrase.parse::<ClinicalSignificance>().unwrap().as_str(),
+                token
+            );
+
+            let rcvs = [rcv("RCV000000001", 1, &["C0001"], &germline(phrase))];
+            let archive = archive(&rcvs);
+            let classified = archive.classified_record.as_ref().unwrap();
+            assert_eq!(
+                aggregate_significance(classified).as_deref(),
+                Some(aggregate),
+                "{}",
+                phrase
+            );
+
+            // a compound is selected by itself or by any of its terms
+            for significance in [phrase.parse().unwrap(), included] {
+                let config = FormatConfig {
+                    include_significance: vec![significance],
+                    ..Default::default()
+                };
+                assert!(
+                    convert(&archive, &config).is_ok(),
+                    "{} with {}",
+                    phrase,
+                    significance
+                );
+            }
+            let config = FormatConfig {
+                include_significance: vec![Other],
+                ..Default::default()
+            };
+            assert_eq!(
+                convert(&archive, &config).err(),
+                Some(SkipReason::SignificanceFiltered),
+                "{}",
+                phrase
+            );
+        }
+    }
+
+    #[test]
+    fn clnsigconf_tallies_single_terms() {
+        let rcvs = vec![
+            rcv("RCV000000001", 1, &["C0001"], &submitted("Pathogenic", 3)),
+            rcv(
+                "RCV000000002",
+                1,
+                &["C0002"],
+                &submitted("Uncertain significance", 2),
+            ),
+            rcv(
+                "RCV000000003",
+                1,
+                &["C0003"],
+                &submitted("Conflicting classifications of pathogenicity", 4),
+            ),
+            rcv(
+                "RCV000000004",
+                1,
+                &["C0004"],
+                &submitted("Pathogenic/Likely pathogenic", 5),
+            ),
+            rcv(
+                "RCV000000005",
+                1,
+                &["C0005"],
+                &submitted("Likely benign; risk factor", 1),
+            ),
+        ];
+
+        let vcf = convert(&archive(&rcvs), &FormatConfig::default()).unwrap();
+        let ncbi = convert(
+            &archive(&rcvs),
+            &FormatConfig {
+                format: OutputFormat::Ncbi,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let expected = "Pathogenic(3)|Uncertain_significance(2)|Likely_benign(1)|Risk_factor(1)";
+        assert_eq!(info(&vcf, "CLNSIGCONF"), Some(expected));
+        assert_eq!(info(&ncbi, "CLNSIGCONF"), Some(expected));
+    }
+
+    #[test]
+    fn clnsigconf_is_only_written_for_conflicts() {
+        let rcvs = vec![rcv("RCV000000001", 1, &["C0001"], &germline("Pathogenic"))];
+
+        let vcf = convert(&archive(&rcvs), &FormatConfig::default()).unwrap();
+
+        assert_eq!(info(&vcf, "CLNSIGCONF"), None);
+    }
+
+    #[test]
+    fn condition_ids_are_encoded() {
+        let rcvs = vec![rcv(
+            "RCV000000001",
+            1,
+            &["C0001"],
+            &germline("Pathogenic"),
+        )
+        .replace(
+            "<ClassifiedConditionList>",
+            r#"<ClassifiedConditionList><ClassifiedCondition DB="MONDO" ID="MONDO:0008840">Disease</ClassifiedCondition><ClassifiedCondition DB="MONDO" ID="MONDO:0007254">Disease</ClassifiedCondition>"#,
+        )];
+        let config = |format| FormatConfig {
+            condition_dbs: vec!["MONDO".to_string(), "MedGen".to_string()],
+            conditions_format: format,
+            ..Default::default()
+        };
+
+        let v1 = convert(&archive(&rcvs), &config(ConditionsFormat::V1)).unwrap();
+        let v2 = convert(&archive(&rcvs), &config(ConditionsFormat::V2)).unwrap();
+
+        let conditions = info(&v1, "CONDITIONS").unwrap();
+        assert_eq!(
+            conditions,
+            "MONDO:MONDO%3A0007254/MONDO%3A0008840:pathogenic:1:criteria_provided%2C_single_submitter|\
+             MedGen:C0001:pathogenic:1:criteria_provided%2C_single_submitter"
+        );
+        // the components are found by splitting on the delimiters
+        let entry = conditions.split('|').next().unwrap();
+        assert_eq!(entry.split(':').count(), 5);
+        assert_eq!(
+            info(&v2, "CONDITIONS"),
+            Some(
+                "MONDO|MONDO:0007254/MONDO:0008840|pathogenic|criteria_provided%2C_single_submitter|1,\
+                 MedGen|C0001|pathogenic|criteria_provided%2C_single_submitter|1"
+            )
+        );
+    }
+
+    #[test]
+    fn conditions_follow_the_database_order_in_every_style() {
+        let rcv = |accession: &str, conditions: &[(&str, &str)], classification: &str| {
+            let conditions = conditions
+                .iter()
+                .map(|(db, id)| {
+                    format!(
+                        r#"<ClassifiedCondition DB="{}" ID="{}">Disease</ClassifiedCondition>"#,
+                        db, id
+                    )
+                })
+                .collect::<String>();
+            rcv(accession, 1, &[], &germline(classification)).replace(
+                "<ClassifiedConditionList>",
+                &format!("<ClassifiedConditionList>{}", conditions),
+            )
+        };
+        let archive = archive(&[
+            rcv(
+                "RCV000000002",
+                &[
+                    ("OMIM", "100100"),
+                    ("MONDO", "MONDO:0000002"),
+                    ("MedGen", "C0002"),
+                ],
+                "Benign",
+            ),
+            rcv(
+                "RCV000000001",
+                &[("MedGen", "C0001"), ("OMIM", "600185")],
+                "Pathogenic",
+            ),
+        ]);
+        let styles = |databases: &[&str]| {
+            let config = |format, conditions_format| FormatConfig {
+                format,
+                conditions_format,
+                condition_dbs: databases.iter().map(|x| x.to_string()).collect(),
+                ..Default::default()
+            };
+            [
+                (OutputFormat::Togovar, ConditionsFormat::V1, "CONDITIONS"),
+                (OutputFormat::Togovar, ConditionsFormat::V2, "CONDITIONS"),
+                (OutputFormat::Ncbi, ConditionsFormat::V1, "CLNDISDB"),
+            ]
+            .map(|(format, conditions_format, key)| {
+                let vcf = convert(&archive, &config(format, conditions_format)).unwrap();
+                info(&vcf, key).unwrap().to_string()
+            })
+        };
+
+        assert_eq!(
+            styles(&["OMIM", "MedGen"]),
+            [
+                "OMIM:600185:pathogenic:1:criteria_provided%2C_single_submitter|\
+                 MedGen:C0001:pathogenic:1:criteria_provided%2C_single_submitter|\
+                 OMIM:100100:benign:1:criteria_provided%2C_single_submitter|\
+                 MedGen:C0002:benign:1:criteria_provided%2C_single_submitter"
+                    .to_string(),
+                "OMIM|600185|pathogenic|criteria_provided%2C_single_submitter|1,\
+                 MedGen|C0001|pathogenic|criteria_provided%2C_single_submitter|1,\
+                 OMIM|100100|benign|criteria_provided%2C_single_submitter|1,\
+                 MedGen|C0002|benign|criteria_provided%2C_single_submitter|1"
+                    .to_string(),
+                "OMIM:100100|OMIM:600185|MedGen:C0001|MedGen:C0002".to_string(),
+            ]
+        );
+        assert_eq!(
+            styles(&["MedGen", "OMIM"]),
+            [
+                "MedGen:C0001:pathogenic:1:criteria_provided%2C_single_submitter|\
+                 OMIM:600185:pathogenic:1:criteria_provided%2C_single_submitter|\
+                 MedGen:C0002:benign:1:criteria_provided%2C_single_submitter|\
+                 OMIM:100100:benign:1:criteria_provided%2C_single_submitter"
+                    .to_string(),
+                "MedGen|C0001|pathogenic|criteria_provided%2C_single_submitter|1,\
+                 OMIM|600185|pathogenic|criteria_provided%2C_single_submitter|1,\
+                 MedGen|C0002|benign|criteria_provided%2C_single_submitter|1,\
+                 OMIM|100100|benign|criteria_provided%2C_single_submitter|1"
+                    .to_string(),
+                "MedGen:C0001|MedGen:C0002|OMIM:100100|OMIM:600185".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn genes_are_matched_ignoring_case() {
+        let allele: SimpleAllele = quick_xml::de::from_str(
+            r#"<SimpleAllele AlleleID="1" VariationID="1">
+                 <GeneList><Gene Symbol="BRCA1"/><Gene Symbol=" Nbr2 "/></GeneList>
+               </SimpleAllele>"#,
+        )
+        .unwrap();
+        let genes = |x: &[&str]| x.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+
+        assert!(has_gene(&allele, &genes(&["brca1"])));
+        assert!(has_gene(&allele, &genes(&["TP53", "NBR2"])));
+        assert!(!has_gene(&allele, &genes(&["BRCA2"])));
+        assert!(!has_gene(&allele, &genes(&["BRCA"])));
+        assert!(!has_gene(&allele, &[]));
+    }
+}
@@ -0,0 +1,183 @@
+//! Retries and exit statuses of the bcftools stages, run with a fake bcftools
+#![cfg(unix)]
+
+mod common;
+
+use common::*;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::process::Output;
+
+/// Fake bcftools failing the first `$FAIL_<COMMAND>` runs of each command (all if unset), and
+/// otherwise copying the input to `--output` as if it were already sorted and normalized
+const FAKE_BCFTOOLS: &str = r#"#!/bin/sh
+if [ "$1" = "--version" ]; then
+    echo "bcftools 1.17"
+    exit 0
+fi
+command=$1
+count=$(cat "$FAKE_DIR/$command.count" 2>/dev/null || echo 0)
+count=$((count + 1))
+echo $count > "$FAKE_DIR/$command.count"
+fail=$(eval echo "\${FAIL_$(echo $command | tr a-z A-Z):-1000}")
+if [ $count -le $fail ]; then
+    echo "fake $command failure $count" >&2
+    exit 1
+fi
+output=
+input=
+while [ $# -gt 0 ]; do
+    case "$1" in
+        --output) output=$2; shift ;;
+        *) input=$1 ;;
+    esac
+    shift
+done
+if [ -n "$output" ]; then
+    cp "$input" "$output"
+fi
+"#;
+
+struct Fake {
+    workspace: Workspace,
+    bcftools: PathBuf,
+}
+
+impl Fake {
+    fn new() -> Self {
+        let workspace = Workspace::new();
+        let bcftools = workspace.path("bcftools");
+        fs::write(&bcftools, FAKE_BCFTOOLS).unwrap();
+        fs::set_permissions(&bcftools, fs::Permissions::from_mode(0o755)).unwrap();
+
+        Fake {
+            workspace,
+            bcftools,
+        }
+    }
+
+    /// Convert records at the positions with the fake bcftools, failing `fail` runs of each command
+    fn convert(&self, positions: &[u64], fail: &[(&str, u32)], args: &[&str]) -> Output {
+        let records = positions
+            .iter()
+            .enumerate()
+            .map(|(i, x)| snv(i as u64 + 1, *x))
+            .collect();
+        let input = self.workspace.write_xml("input.xml", &spec(records));
+        let output = self.workspace.path("output.vcf.gz");
+
+        let mut command = self.workspace.command(&input, &output, args);
+        command
+            .arg("--use-bcftools")
+            .arg("--bcftools-path")
+            .arg(&self.bcftools)
+            .env("FAKE_DIR", self.workspace.dir.path());
+        for (name, count) in fail {
+            command.env(format!("FAIL_{}", name.to_uppercase()), count.to_string());
+        }
+
+        command.output().unwrap()
+    }
+
+    /// Number of runs of a bcftools command
+    fn runs(&self, command: &str) -> u32 {
+        fs::read_to_string(self.workspace.path(&format!("{}.count", command)))
+            .map(|x| x.trim().parse().unwrap())
+            .unwrap_or(0)
+    }
+}
+
+#[test]
+fn sort_succeeds_after_retries() {
+    let fake = Fake::new();
+
+    let result = fake.convert(
+        &[100, 200],
+        &[("sort", 2), ("norm", 0), ("index", 0)],
+        &["--retries", "2"],
+    );
+
+    assert_success(&result);
+    assert_eq!(fake.runs("sort"), 3);
+    assert_eq!(fake.runs("norm"), 1);
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert!(stderr.contains("retrying (1/2)"), "{}", stderr);
+    assert!(stderr.contains("retrying (2/2)"), "{}", stderr);
+    let vcf = read_text(&fake.workspace.path("output.vcf.gz"));
+    assert_eq!(records(&vcf).len(), 2);
+}
+
+#[test]
+fn sort_failure_exits_with_5() {
+    let fake = Fake::new();
+
+    let result = fake.convert(&[100], &[], &["--retries", "2"]);
+
+    assert_eq!(result.status.code(), Some(5));
+    assert_eq!(fake.runs("sort"), 3);
+    assert_eq!(fake.runs("norm"), 0);
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert!(stderr.contains("fake sort failure 3"), "{}", stderr);
+}
+
+#[test]
+fn normalize_failure_exits_with_6() {
+    let fake = Fake::new();
+
+    let result = fake.convert(&[100], &[("sort", 0)], &["--retries", "1"]);
+
+    assert_eq!(result.status.code(), Some(6));
+    assert_eq!(fake.runs("sort"), 1);
+    assert_eq!(fake.runs("norm"), 2);
+}
+
+#[test]
+fn no_retries_by_default() {
+    let fake = Fake::new();
+
+    let result = fake.convert(&[100], &[("sort", 1)], &[]);
+
+    assert_eq!(result.status.code(), Some(5));
+    assert_eq!(fake.runs("sort"), 1);
+}
+
+#[test]
+fn index_failure_exits_with_7() {
+    let fake = Fake::new();
+
+    // the fake sort leaves the records unsorted, so the built-in indexer fails and bcftools index
+    // is run in its place
+    let result = fake.convert(
+        &[200, 100],
+        &[("sort", 0), ("norm", 0)],
+        &["--retries", "1"],
+    );
+
+    assert_eq!(result.status.code(), Some(7));
+    assert_eq!(fake.runs("index"), 2);
+}
+
+#[test]
+fn missing_bcftools_is_reported() {
+    let fake = Fake::new();
+    fs::remove_file(&fake.bcftools).unwrap();
+
+    let result = fake.convert(&[100], &[], &[]);
+
+    assert!(!result.status.success());
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    assert!(stderr.contains("not found"), "{}", stderr);
+}
+
+#[test]
+fn failure_keeps_existing_output() {
+    let fake = Fake::new();
+    let output = fake.workspace.path("output.vcf.gz");
+    fs::write(&output, "previous").unwrap();
+
+    let result = fake.convert(&[100], &[("sort", 0)], &["--force"]);
+
+    assert_eq!(result.status.code(), Some(6));
+    assert_eq!(fs::read_to_string(&output).unwrap(), "previous");
+}
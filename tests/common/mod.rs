@@ -0,0 +1,191 @@
+//! Inputs generated by `clinvar::fixture_gen`, a synthetic reference and runs of the binary
+//! shared by the integration tests
+
+#![allow(dead_code)]
+
+use clinvar::bgzf;
+use clinvar::fixture_gen::{
+    self, AggregateSpec, ConditionSpec, LocationSpec, RcvSpec, RecordSpec, Spec,
+};
+use flate2::read::MultiGzDecoder;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use tempfile::TempDir;
+
+/// Length of chromosome 1 of the synthetic reference
+pub const LENGTH: u64 = 2000;
+
+/// Base of the synthetic reference at a 1-based position (`ACGT` repeated)
+pub fn base(position: u64) -> char {
+    b"ACGT"[((position - 1) % 4) as usize] as char
+}
+
+/// Record of an SNV on chromosome 1 (GRCh38) matching the reference, with a pathogenic aggregate
+/// classification and a single RCV
+pub fn snv(variation_id: u64, position: u64) -> RecordSpec {
+    let reference = base(position);
+    let alternate = if reference == 'A' { 'G' } else { 'A' };
+
+    RecordSpec {
+        variation_id,
+        locations: vec![LocationSpec {
+            assembly: "GRCh38".to_string(),
+            chr: "1".to_string(),
+            position,
+            reference: reference.to_string(),
+            alternate: alternate.to_string(),
+        }],
+        rcvs: vec![rcv(
+            &format!("RCV{:09}", variation_id),
+            &format!("C{:07}", variation_id),
+            "Pathogenic",
+        )],
+        aggregate: Some(AggregateSpec {
+            classification: "Pathogenic".to_string(),
+            review_status: Some("criteria provided, single submitter".to_string()),
+            submission_count: 1,
+            date_last_evaluated: None,
+        }),
+        ..Default::default()
+    }
+}
+
+/// Germline classification of an RCV with a MedGen condition
+pub fn rcv(accession: &str, medgen: &str, classification: &str) -> RcvSpec {
+    RcvSpec {
+        accession: accession.to_string(),
+        version: 1,
+        conditions: vec![ConditionSpec {
+            db: "MedGen".to_string(),
+            id: medgen.to_string(),
+            name: format!("Disease {}", medgen),
+        }],
+        classification: classification.to_string(),
+        review_status: Some("criteria provided, single submitter".to_string()),
+        submission_count: 1,
+        ..Default::default()
+    }
+}
+
+/// Document of the records
+pub fn spec(records: Vec<RecordSpec>) -> Spec {
+    Spec {
+        release_date: Some("2024-05-01".to_string()),
+        records,
+    }
+}
+
+/// Temporary directory with the synthetic reference (`ref.fa.gz` with its .fai and .gzi)
+pub struct Workspace {
+    pub dir: TempDir,
+    pub reference: PathBuf,
+}
+
+impl Workspace {
+    pub fn new() -> Self {
+        let dir = tempfile::tempdir().expect("temporary directory");
+        let reference = dir.path().join("ref.fa.gz");
+
+        let sequence = (1..=LENGTH).map(base).collect::<String>();
+        let mut fasta = String::from(">1\n");
+        for line in sequence.as_bytes().chunks(60) {
+            fasta.push_str(std::str::from_utf8(line).expect("ASCII"));
+            fasta.push('\n');
+        }
+
+        // a single block, so the .gzi lists no block besides the first one
+        let mut writer = bgzf::Writer::new(File::create(&reference).expect("reference"));
+        writer.write_all(fasta.as_bytes()).expect("reference");
+        writer.finish().expect("reference");
+        fs::write(
+            dir.path().join("ref.fa.gz.fai"),
+            format!("1\t{}\t3\t60\t61\n", LENGTH),
+        )
+        .expect("fai");
+        fs::write(dir.path().join("ref.fa.gz.gzi"), 0u64.to_le_bytes()).expect("gzi");
+
+        Workspace { dir, reference }
+    }
+
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.path().join(name)
+    }
+
+    /// Write the generated document of `spec` as `name`
+    pub fn write_xml(&self, name: &str, spec: &Spec) -> PathBuf {
+        let path = self.path(name);
+        fs::write(&path, fixture_gen::generate(spec)).expect("fixture");
+
+        path
+    }
+
+    /// Run the converter on GRCh38 with the synthetic reference and the built-in tools
+    pub fn convert(&self, input: &Path, output: &Path, args: &[&str]) -> Output {
+        self.command(input, output, args)
+            .arg("--no-external-tools")
+            .output()
+            .expect("clinvar_xml2vcf")
+    }
+
+    /// Command of the converter on GRCh38 with the synthetic reference
+    pub fn command(&self, input: &Path, output: &Path, args: &[&str]) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_clinvar_xml2vcf"));
+        command
+            .arg(input)
+            .arg("--assembly")
+            .arg("GRCh38")
+            .arg("--reference")
+            .arg(&self.reference)
+            .arg("--output")
+            .arg(output)
+            .args(args);
+
+        command
+    }
+}
+
+/// Contents of a plain or gzipped (including BGZF) text file
+pub fn read_text(path: &Path) -> String {
+    let bytes = fs::read(path).expect("output");
+    if bytes.starts_with(&[0x1f, 0x8b]) {
+        let mut text = String::new();
+        MultiGzDecoder::new(bytes.as_slice())
+            .read_to_string(&mut text)
+            .expect("gzip");
+        text
+    } else {
+        String::from_utf8(bytes).expect("UTF-8")
+    }
+}
+
+/// Data lines of a VCF split into columns
+pub fn records(vcf: &str) -> Vec<Vec<&str>> {
+    vcf.lines()
+        .filter(|x| !x.starts_with('#'))
+        .map(|x| x.split('\t').collect())
+        .collect()
+}
+
+/// INFO column as a map of keys to values (empty for flags)
+pub fn info(record: &[&str]) -> BTreeMap<String, String> {
+    record[7]
+        .split(';')
+        .map(|x| match x.split_once('=') {
+            Some((k, v)) => (k.to_string(), v.to_string()),
+            None => (x.to_string(), String::new()),
+        })
+        .collect()
+}
+
+/// Assert that the run succeeded, showing its stderr otherwise
+pub fn assert_success(output: &Output) {
+    assert!(
+        output.status.success(),
+        "exit status {:?}: {}",
+        output.status.code(),
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
@@ -0,0 +1,181 @@
+//! Reports of the `diff` subcommand between generated releases
+
+mod common;
+
+use clinvar::fixture_gen::{RecordSpec, Spec};
+use common::*;
+use serde_json::Value;
+use std::path::Path;
+use std::process::{Command, Output};
+
+/// Record of `snv` with another classification
+fn benign(variation_id: u64, position: u64) -> RecordSpec {
+    let mut record = snv(variation_id, position);
+    record.rcvs[0].classification = "Benign".to_string();
+    record.aggregate.as_mut().unwrap().classification = "Benign".to_string();
+    record
+}
+
+fn old_release() -> Spec {
+    spec(vec![
+        snv(1, 101),
+        snv(2, 201),
+        snv(3, 301),
+        snv(5, 501),
+        snv(6, 701),
+    ])
+}
+
+/// 1 is unchanged, 2 is relocated, 3 is reclassified, 4 is added, 5 is removed and 6 is both
+/// relocated and reclassified
+fn new_release() -> Spec {
+    spec(vec![
+        snv(1, 101),
+        snv(2, 401),
+        benign(3, 301),
+        snv(4, 601),
+        benign(6, 801),
+    ])
+}
+
+fn diff(old: &Path, new: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_clinvar_xml2vcf"))
+        .arg("diff")
+        .arg(old)
+        .arg(new)
+        .args(args)
+        .output()
+        .expect("clinvar_xml2vcf")
+}
+
+/// Rows of a TSV report after the header
+fn rows(output: &Output) -> Vec<String> {
+    assert_success(output);
+    let stdout = String::from_utf8(output.stdout.clone()).unwrap();
+    let mut lines = stdout.lines();
+    assert_eq!(
+        lines.next(),
+        Some(clinvar::diff::COLUMNS.join("\t").as_str())
+    );
+
+    lines.map(|x| x.to_string()).collect()
+}
+
+/// VariationID, change, positions before and after of a TSV report
+fn changes(rows: &[String]) -> Vec<(String, String, String, String)> {
+    rows.iter()
+        .map(|x| {
+            let columns = x.split('\t').collect::<Vec<_>>();
+            (
+                columns[0].to_string(),
+                columns[1].to_string(),
+                columns[2].to_string(),
+                columns[3].to_string(),
+            )
+        })
+        .collect()
+}
+
+fn expected_changes() -> Vec<(String, String, String, String)> {
+    let position = |pos: u64| {
+        let reference = base(pos);
+        let alternate = if reference == 'A' { 'G' } else { 'A' };
+        format!("1:{}:{}:{}", pos, reference, alternate)
+    };
+
+    [
+        ("2", "relocated", position(201), position(401)),
+        ("3", "reclassified", position(301), position(301)),
+        ("4", "added", String::new(), position(601)),
+        ("5", "removed", position(501), String::new()),
+        ("6", "relocated", position(701), position(801)),
+        ("6", "reclassified", position(701), position(801)),
+    ]
+    .into_iter()
+    .map(|(id, change, before, after)| (id.to_string(), change.to_string(), before, after))
+    .collect()
+}
+
+#[test]
+fn xml_releases_are_compared_in_tsv() {
+    let workspace = Workspace::new();
+    let old = workspace.write_xml("old.xml", &old_release());
+    let new = workspace.write_xml("new.xml", &new_release());
+
+    let result = diff(&old, &new, &[]);
+
+    let rows = rows(&result);
+    assert_eq!(changes(&rows), expected_changes());
+    let reclassified = rows[1].split('\t').collect::<Vec<_>>();
+    assert_eq!((reclassified[4], reclassified[5]), ("pathogenic", "benign"));
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    for line in ["added: 1", "removed: 1", "reclassified: 2", "relocated: 2"] {
+        assert!(stderr.contains(line), "{}", stderr);
+    }
+}
+
+#[test]
+fn changes_are_written_as_json_lines() {
+    let workspace = Workspace::new();
+    let old = workspace.write_xml("old.xml", &old_release());
+    let new = workspace.write_xml("new.xml", &new_release());
+    let report = workspace.path("report.jsonl");
+
+    let result = diff(
+        &old,
+        &new,
+        &["--format", "jsonl", "--output", report.to_str().unwrap()],
+    );
+
+    assert_success(&result);
+    let entries = read_text(&report)
+        .lines()
+        .map(|x| serde_json::from_str::<Value>(x).unwrap())
+        .collect::<Vec<_>>();
+    let changes = entries
+        .iter()
+        .map(|x| {
+            (
+                x["variation_id"].as_u64().unwrap(),
+                x["change"].as_str().unwrap().to_string(),
+            )
+        })
+        .collect::<Vec<_>>();
+    let expected = expected_changes()
+        .into_iter()
+        .map(|(id, change, _, _)| (id.parse().unwrap(), change))
+        .collect::<Vec<_>>();
+    assert_eq!(changes, expected);
+
+    let added = &entries[2];
+    assert_eq!(added["before"], Value::Array(Vec::new()));
+    assert_eq!(added["after"][0]["pos"], 601);
+    assert_eq!(added["after"][0]["clnsig"], "pathogenic");
+    let reclassified = &entries[1];
+    assert_eq!(reclassified["before"][0]["clnsig"], "pathogenic");
+    assert_eq!(reclassified["after"][0]["clnsig"], "benign");
+}
+
+#[test]
+fn converted_vcf_is_compared_with_xml() {
+    let workspace = Workspace::new();
+    let xml = workspace.write_xml("old.xml", &old_release());
+    let old = workspace.path("old.vcf.gz");
+    assert_success(&workspace.convert(&xml, &old, &[]));
+    let new = workspace.write_xml("new.xml", &new_release());
+
+    let result = diff(&old, &new, &[]);
+
+    assert_eq!(changes(&rows(&result)), expected_changes());
+}
+
+#[test]
+fn same_release_has_no_change() {
+    let workspace = Workspace::new();
+    let xml = workspace.write_xml("release.xml", &new_release());
+    let vcf = workspace.path("release.vcf.gz");
+    assert_success(&workspace.convert(&xml, &vcf, &[]));
+
+    assert!(rows(&diff(&xml, &xml, &[])).is_empty());
+    assert!(rows(&diff(&vcf, &xml, &[])).is_empty());
+}
@@ -0,0 +1,150 @@
+//! Exit statuses of runs failing, ignoring errors and rejecting invalid input
+
+mod common;
+
+use clinvar::fixture_gen::{self, Malformation, Spec};
+use common::*;
+use std::fs;
+use std::process::Output;
+
+/// Two records, the second of which cannot be deserialized
+fn undeserializable() -> Spec {
+    let mut broken = snv(2, 201);
+    broken.malformations = vec![Malformation::MissingAlleleId];
+    spec(vec![snv(1, 101), broken])
+}
+
+/// Output of converting `xml`, and the VCF written if any
+fn run_xml(xml: &str, args: &[&str]) -> (Output, String) {
+    let workspace = Workspace::new();
+    let input = workspace.path("input.xml");
+    fs::write(&input, xml).unwrap();
+    let output = workspace.path("output.vcf.gz");
+
+    let result = workspace.convert(&input, &output, args);
+    let vcf = if output.exists() {
+        read_text(&output)
+    } else {
+        String::new()
+    };
+
+    (result, vcf)
+}
+
+fn run(spec: &Spec, args: &[&str]) -> (Output, String) {
+    run_xml(&fixture_gen::generate(spec), args)
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn ignored_errors_exit_with_2() {
+    let (result, vcf) = run(&undeserializable(), &["--ignore-error"]);
+
+    assert_eq!(result.status.code(), Some(2), "{}", stderr(&result));
+    assert!(stderr(&result).contains("Exit status 2: completed, but errors were ignored"));
+    let ids = records(&vcf).iter().map(|x| x[2]).collect::<Vec<_>>();
+    assert_eq!(ids, vec!["1"]);
+}
+
+#[test]
+fn too_many_ignored_errors_exit_with_1() {
+    let (result, _) = run(
+        &undeserializable(),
+        &["--ignore-error", "--max-errors", "0"],
+    );
+
+    assert_eq!(result.status.code(), Some(1), "{}", stderr(&result));
+    assert!(
+        stderr(&result).contains("Too many errors: 1 (--max-errors 0)"),
+        "{}",
+        stderr(&result)
+    );
+}
+
+#[test]
+fn missing_required_fields_exit_with_1() {
+    let (result, _) = run(&spec(vec![snv(1, 101)]), &["--require-fields", "GENEINFO"]);
+
+    assert_eq!(result.status.code(), Some(1), "{}", stderr(&result));
+}
+
+#[test]
+fn missing_input_exits_with_1() {
+    let workspace = Workspace::new();
+
+    let result = workspace.convert(
+        &workspace.path("missing.xml"),
+        &workspace.path("output.vcf.gz"),
+        &[],
+    );
+
+    assert_eq!(result.status.code(), Some(1), "{}", stderr(&result));
+}
+
+#[test]
+fn undeserializable_records_exit_with_3() {
+    let (result, _) = run(&undeserializable(), &[]);
+
+    assert_eq!(result.status.code(), Some(3), "{}", stderr(&result));
+    assert!(
+        stderr(&result).contains("Error: Failed to deserialize"),
+        "{}",
+        stderr(&result)
+    );
+}
+
+#[test]
+fn ref_mismatches_exit_with_3_on_fail() {
+    let mut record = snv(1, 101);
+    record.locations[0].reference = "C".to_string();
+
+    let (result, _) = run(&spec(vec![record]), &["--on-ref-mismatch", "fail"]);
+
+    assert_eq!(result.status.code(), Some(3), "{}", stderr(&result));
+}
+
+#[test]
+fn document_truncated_between_records_exits_with_1() {
+    let workspace = Workspace::new();
+    let xml = clinvar::fixture_gen::generate(&spec(vec![snv(1, 101), snv(2, 201)]));
+    let input = workspace.path("input.xml");
+    std::fs::write(
+        &input,
+        &xml[..xml.rfind("</ClinVarVariationRelease>").unwrap()],
+    )
+    .unwrap();
+
+    let result = workspace.convert(&input, &workspace.path("output.vcf.gz"), &[]);
+
+    assert_eq!(result.status.code(), Some(1), "{}", stderr(&result));
+    assert!(
+        stderr(&result).contains("(truncated?)"),
+        "{}",
+        stderr(&result)
+    );
+}
+
+#[test]
+fn strict_fails_on_unknown_elements_even_ignoring_errors() {
+    let xml = fixture_gen::generate(&spec(vec![snv(1, 101), snv(2, 201)])).replacen(
+        "</SimpleAllele>",
+        "<UnknownElement/></SimpleAllele>",
+        1,
+    );
+
+    let (result, vcf) = run_xml(&xml, &[]);
+    assert_success(&result);
+    assert_eq!(records(&vcf).len(), 2);
+
+    for args in [&["--strict"][..], &["--strict", "--ignore-error"][..]] {
+        let (result, _) = run_xml(&xml, args);
+
+        assert_eq!(result.status.code(), Some(3), "{:?}", args);
+        let stderr = stderr(&result);
+        assert!(stderr.contains("schema_violation"), "{}", stderr);
+        assert!(stderr.contains("UnknownElement"), "{}", stderr);
+    }
+}
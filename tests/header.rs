@@ -0,0 +1,174 @@
+//! Headers of the output formats
+
+mod common;
+
+use common::*;
+
+/// Header of `--format ncbi` on the synthetic reference, without the lines of the run
+/// (`##fileDate`, `##reference` and `##clinvar_xml2vcf_command`)
+const HEADER_NCBI: &str = r#"##fileformat=VCFv4.3
+##FILTER=<ID=PASS,Description="All filters passed">
+##FILTER=<ID=conflicting,Description="Conflicting germline classifications, in the aggregate or between pathogenic and benign RCVs">
+##FILTER=<ID=no_assertion,Description="No assertion criteria provided for any classification">
+##clinvar_id_description=ClinVar Variation ID
+##source=clinvar_xml2vcf 0.1.0
+##clinvar_release=2024-05-01
+##INFO=<ID=ALLELEID,Number=1,Type=Integer,Description="the ClinVar Allele ID">
+##INFO=<ID=CLNDISDB,Number=.,Type=String,Description="Tag-value pairs of disease database name and identifier, e.g. MedGen:NNNNNN">
+##INFO=<ID=CLNDN,Number=.,Type=String,Description="ClinVar's preferred disease name for the concept specified by disease identifiers in CLNDISDB">
+##INFO=<ID=CLNHGVS,Number=.,Type=String,Description="Top-level (primary assembly, alt, or patch) HGVS expression.">
+##INFO=<ID=CLNREVSTAT,Number=.,Type=String,Description="ClinVar review status for the Variation ID">
+##INFO=<ID=CLNSIG,Number=.,Type=String,Description="Clinical significance for this single variant; multiple values are separated by a vertical bar">
+##INFO=<ID=CLNSIGCONF,Number=.,Type=String,Description="Conflicting clinical significance for this single variant; multiple values are separated by a vertical bar">
+##INFO=<ID=CLNVC,Number=1,Type=String,Description="Variant type">
+##INFO=<ID=CLNVI,Number=.,Type=String,Description="the variant's clinical sources reported as tag-value pairs of database and variant identifier">
+##INFO=<ID=GENEINFO,Number=1,Type=String,Description="Gene(s) for the variant reported as gene symbol:gene id. The gene symbol and id are delimited by a colon (:) and each pair is delimited by a vertical bar (|)">
+##INFO=<ID=MC,Number=.,Type=String,Description="comma separated list of molecular consequence in the form of Sequence Ontology ID|molecular_consequence">
+##INFO=<ID=RS,Number=.,Type=String,Description="dbSNP ID (i.e. rs number)">
+##contig=<ID=1,length=2000>
+##contig=<ID=2>
+##contig=<ID=3>
+##contig=<ID=4>
+##contig=<ID=5>
+##contig=<ID=6>
+##contig=<ID=7>
+##contig=<ID=8>
+##contig=<ID=9>
+##contig=<ID=10>
+##contig=<ID=11>
+##contig=<ID=12>
+##contig=<ID=13>
+##contig=<ID=14>
+##contig=<ID=15>
+##contig=<ID=16>
+##contig=<ID=17>
+##contig=<ID=18>
+##contig=<ID=19>
+##contig=<ID=20>
+##contig=<ID=21>
+##contig=<ID=22>
+##contig=<ID=X>
+##contig=<ID=Y>
+##contig=<ID=MT>
+"#;
+
+fn header(args: &[&str]) -> String {
+    let workspace = Workspace::new();
+    let input = workspace.write_xml("input.xml", &spec(vec![snv(1, 100)]));
+    let output = workspace.path("output.vcf.gz");
+
+    let result = workspace.convert(&input, &output, args);
+
+    assert_success(&result);
+    read_text(&output)
+        .lines()
+        .filter(|x| x.starts_with("##"))
+        .filter(|x| {
+            !["##fileDate=", "##reference=", "##clinvar_xml2vcf_command="]
+                .iter()
+                .any(|prefix| x.starts_with(prefix))
+        })
+        .map(|x| format!("{}\n", x))
+        .collect()
+}
+
+#[test]
+fn ncbi_header() {
+    assert_eq!(header(&["--format", "ncbi"]), HEADER_NCBI);
+}
+
+#[test]
+fn field_names_is_an_alias_of_format() {
+    assert_eq!(header(&["--field-names", "clinvar"]), HEADER_NCBI);
+    assert_eq!(
+        header(&["--field-names", "native"]),
+        header(&["--format", "togovar"])
+    );
+}
+
+#[test]
+fn field_names_conflicts_with_format() {
+    let workspace = Workspace::new();
+    let input = workspace.write_xml("input.xml", &spec(vec![snv(1, 100)]));
+    let output = workspace.path("output.vcf.gz");
+
+    let result = workspace.convert(
+        &input,
+        &output,
+        &["--field-names", "clinvar", "--format", "togovar"],
+    );
+
+    assert!(!result.status.success());
+    assert!(!output.exists());
+}
+
+/// Arguments of a command line quoted by `shell_quote` (plain words and `'...'` with `'\''`)
+fn split_command_line(line: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut arg = None::<String>;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' => args.extend(arg.take()),
+            '\'' => {
+                let arg = arg.get_or_insert_with(String::new);
+                arg.extend(chars.by_ref().take_while(|x| *x != '\''));
+            }
+            '\\' => arg.get_or_insert_with(String::new).extend(chars.next()),
+            c => arg.get_or_insert_with(String::new).push(c),
+        }
+    }
+    args.extend(arg);
+
+    args
+}
+
+#[test]
+fn provenance_lines_record_the_run() {
+    let workspace = Workspace::new();
+    let input = workspace.write_xml("ClinVar release's.xml", &spec(vec![snv(1, 100)]));
+    let output = workspace.path("output file.vcf.gz");
+
+    let result = workspace.convert(&input, &output, &["--format", "ncbi"]);
+
+    assert_success(&result);
+    let vcf = read_text(&output);
+    let header = vcf
+        .lines()
+        .take_while(|x| !x.starts_with("#CHROM"))
+        .collect::<Vec<_>>();
+    let value = |key: &str| {
+        let prefix = format!("##{}=", key);
+        let values = header
+            .iter()
+            .filter_map(|x| x.strip_prefix(prefix.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(values.len(), 1, "{}: {:?}", key, values);
+        values[0]
+    };
+
+    assert_eq!(
+        value("source"),
+        format!("clinvar_xml2vcf {}", env!("CARGO_PKG_VERSION"))
+    );
+    assert_eq!(value("clinvar_release"), "2024-05-01");
+    assert_eq!(
+        value("reference"),
+        format!("file://{}", workspace.reference.to_str().unwrap())
+    );
+    let command_line = split_command_line(value("clinvar_xml2vcf_command"));
+    let expected = [
+        env!("CARGO_BIN_EXE_clinvar_xml2vcf"),
+        input.to_str().unwrap(),
+        "--assembly",
+        "GRCh38",
+        "--reference",
+        workspace.reference.to_str().unwrap(),
+        "--output",
+        output.to_str().unwrap(),
+        "--format",
+        "ncbi",
+        "--no-external-tools",
+    ];
+    assert_eq!(command_line, expected);
+}
@@ -0,0 +1,203 @@
+//! Acceptance of the BGZF files and indexes written natively by bgzip, tabix and bcftools,
+//! skipped if they are not installed
+
+use clinvar::bgzf;
+use clinvar::tabix::{self, IndexFormat};
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Whether the tool can be run, reporting it as skipped otherwise
+fn installed(tool: &str) -> bool {
+    let installed = Command::new(tool)
+        .arg("--version")
+        .output()
+        .is_ok_and(|x| x.status.success());
+    if !installed {
+        eprintln!("{} is not installed, skipping", tool);
+    }
+
+    installed
+}
+
+/// VCF of a few hundred thousand bytes, so that it spans several blocks
+fn vcf() -> String {
+    let mut vcf = String::from(
+        "##fileformat=VCFv4.3\n##contig=<ID=1,length=10000000>\n\
+         #CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n",
+    );
+    for i in 1..=20000 {
+        vcf.push_str(&format!("1\t{}\t{}\tA\tG\t.\t.\tN={}\n", i * 100, i, i));
+    }
+
+    vcf
+}
+
+fn write_bgzf(path: &Path, text: &str) {
+    let mut writer = bgzf::Writer::new(File::create(path).unwrap());
+    writer.write_all(text.as_bytes()).unwrap();
+    writer.finish().unwrap();
+}
+
+#[test]
+fn bgzip_reads_written_blocks() {
+    if !installed("bgzip") {
+        return;
+    }
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("test.vcf.gz");
+    let text = vcf();
+    write_bgzf(&path, &text);
+
+    let output = Command::new("bgzip")
+        .arg("--test")
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let output = Command::new("bgzip")
+        .args(["-d", "-c"])
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert!(output.stdout == text.as_bytes());
+}
+
+#[test]
+fn blocks_of_bgzip_are_read() {
+    if !installed("bgzip") {
+        return;
+    }
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("test.vcf");
+    let text = vcf();
+    fs::write(&path, &text).unwrap();
+
+    let status = Command::new("bgzip").arg(&path).status().unwrap();
+    assert!(status.success());
+
+    let mut read = String::new();
+    bgzf::Reader::new(File::open(dir.path().join("test.vcf.gz")).unwrap())
+        .read_to_string(&mut read)
+        .unwrap();
+    assert_eq!(read, text);
+}
+
+#[test]
+fn tabix_indexes_and_queries_written_files() {
+    if !installed("tabix") {
+        return;
+    }
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("test.vcf.gz");
+    write_bgzf(&path, &vcf());
+
+    let status = Command::new("tabix")
+        .args(["-p", "vcf"])
+        .arg(&path)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let output = Command::new("tabix")
+        .arg(&path)
+        .arg("1:1000000-1000200")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "1\t1000000\t10000\tA\tG\t.\t.\tN=10000\n\
+         1\t1000100\t10001\tA\tG\t.\t.\tN=10001\n\
+         1\t1000200\t10002\tA\tG\t.\t.\tN=10002\n"
+    );
+}
+
+/// Records of `path` in the regions, by bcftools with the index next to it
+fn view(path: &Path, regions: &str) -> String {
+    let output = Command::new("bcftools")
+        .args(["view", "--no-header", "--regions", regions])
+        .arg(path)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// Copies of the bgzipped VCF indexed natively and by `bcftools index`
+fn index_both(dir: &Path, vcf: &str, format: IndexFormat) -> (PathBuf, PathBuf) {
+    let native = dir.join("native.vcf.gz");
+    write_bgzf(&native, vcf);
+    tabix::build_index(&native, format).unwrap();
+
+    let bcftools = dir.join("bcftools.vcf.gz");
+    fs::copy(&native, &bcftools).unwrap();
+    let flag = match format {
+        IndexFormat::Tbi => "--tbi",
+        IndexFormat::Csi => "--csi",
+    };
+    let status = Command::new("bcftools")
+        .args(["index", flag])
+        .arg(&bcftools)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    (native, bcftools)
+}
+
+#[test]
+fn native_tbi_finds_the_records_of_bcftools() {
+    if !installed("bcftools") {
+        return;
+    }
+    let dir = tempfile::tempdir().unwrap();
+    let mut vcf = vcf().replace(
+        "##contig=<ID=1,length=10000000>\n",
+        "##contig=<ID=1,length=10000000>\n##contig=<ID=2,length=10000000>\n",
+    );
+    for i in 1..=100 {
+        vcf.push_str(&format!("2\t{}\t.\tACGT\tA\t.\t.\t.\n", i * 16384 - 2));
+    }
+    let (native, bcftools) = index_both(dir.path(), &vcf, IndexFormat::Tbi);
+
+    for regions in [
+        "1:1000-5000",
+        "1:16300-16500",
+        "1:999950-1000150",
+        // the boundary of the contigs
+        "1:1999900-2000000,2:1-16384",
+        "2:16381-16383",
+        "2:16383-16384",
+        "2:1638300-",
+        "2",
+    ] {
+        let expected = view(&bcftools, regions);
+        assert_eq!(view(&native, regions), expected, "{}", regions);
+    }
+}
+
+#[test]
+fn native_csi_finds_the_records_of_bcftools() {
+    if !installed("bcftools") {
+        return;
+    }
+    let dir = tempfile::tempdir().unwrap();
+    let mut vcf = String::from(
+        "##fileformat=VCFv4.3\n##contig=<ID=1,length=700000000>\n\
+         #CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n",
+    );
+    for i in 1..=1000 {
+        vcf.push_str(&format!("1\t{}\t{}\tA\tG\t.\t.\t.\n", i * 650_000, i));
+    }
+    let (native, bcftools) = index_both(dir.path(), &vcf, IndexFormat::Csi);
+
+    for regions in ["1:650000", "1:536000000-537500000", "1:649000000-", "1"] {
+        let expected = view(&bcftools, regions);
+        assert!(!expected.is_empty(), "{}", regions);
+        assert_eq!(view(&native, regions), expected, "{}", regions);
+    }
+}
@@ -0,0 +1,100 @@
+//! Levels and per-reason limits of the events written on stderr, and the progress bar
+
+mod common;
+
+use common::*;
+use std::process::Output;
+
+/// A record removed from ClinVar, filtered at debug level, and records whose REF does not match
+/// the reference, warned about when writing to stdout
+fn run(args: &[&str]) -> Output {
+    let workspace = Workspace::new();
+    let mut removed = snv(1, 100);
+    removed.record_status = Some("removed".to_string());
+    let mut records = vec![removed];
+    for (id, position) in [(2, 201), (3, 301), (4, 401)] {
+        let mut record = snv(id, position);
+        record.locations[0].reference = "C".to_string();
+        records.push(record);
+    }
+    let input = workspace.write_xml("input.xml", &spec(records));
+
+    workspace
+        .command(&input, "-".as_ref(), args)
+        .arg("--no-external-tools")
+        .output()
+        .expect("clinvar_xml2vcf")
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn warnings_are_written_by_default() {
+    let output = run(&[]);
+
+    assert_success(&output);
+    let stderr = stderr(&output);
+    assert_eq!(
+        stderr.matches("[WARN] REF mismatch").count(),
+        3,
+        "{}",
+        stderr
+    );
+    assert!(!stderr.contains("[DEBUG]"), "{}", stderr);
+}
+
+#[test]
+fn quiet_drops_warnings() {
+    let output = run(&["--quiet"]);
+
+    assert_success(&output);
+    let stderr = stderr(&output);
+    assert!(!stderr.contains("[WARN]"), "{}", stderr);
+}
+
+#[test]
+fn verbose_writes_debug_events() {
+    let info = stderr(&run(&["-v"]));
+    assert!(info.contains("[WARN] REF mismatch"), "{}", info);
+    assert!(!info.contains("[DEBUG]"), "{}", info);
+
+    let debug = stderr(&run(&["-vv"]));
+    assert!(
+        debug.contains("[DEBUG] Skip by record status: variation_id = 1"),
+        "{}",
+        debug
+    );
+}
+
+#[test]
+fn events_are_limited_per_reason() {
+    let output = run(&["--log-max-per-reason", "1"]);
+
+    let stderr = stderr(&output);
+    assert_eq!(
+        stderr.matches("[WARN] REF mismatch").count(),
+        1,
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.contains(
+            "[WARN] ...suppressed 2 further 'Skip REF mismatching the reference' messages"
+        ),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn progress_is_hidden_without_a_terminal() {
+    let output = run(&["-v"]);
+
+    assert_success(&output);
+    let stderr = stderr(&output);
+    assert!(!stderr.contains('\r'), "{}", stderr);
+    assert!(!stderr.contains("\x1b["), "{}", stderr);
+    assert!(!stderr.contains(" emitted, "), "{}", stderr);
+}
@@ -0,0 +1,225 @@
+//! Left-alignment and trimming of messy indels against a golden set, checked against
+//! `bcftools norm` if it is installed, and resolution of records colliding after normalization
+
+mod common;
+
+use clinvar::bgzf;
+use clinvar::faidx;
+use clinvar::normalize::normalize;
+use clinvar::vcf::Filter;
+use clinvar::VcfRecord;
+use common::*;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Chromosome 1 with a poly-C at 5-9, an AGT repeat at 10-18, an AT repeat at 23-30 and a poly-A
+/// at 34-36
+const SEQUENCE: &str = "TTGACCCCCAGTAGTAGTACGGATATATATCGCAAAG";
+
+/// POS, REF and ALT
+type Alleles = (u64, &'static str, &'static str);
+
+/// Alleles of the input and as normalized by `bcftools norm`
+const GOLDEN: [(Alleles, Alleles); 12] = [
+    // deletion and insertion in a homopolymer, given at its end
+    ((9, "CA", "A"), (4, "AC", "A")),
+    ((9, "C", "CC"), (4, "A", "AC")),
+    // deletion and insertion of a unit of a repeat
+    ((15, "TAGT", "T"), (9, "CAGT", "C")),
+    ((30, "T", "TAT"), (22, "G", "GAT")),
+    ((25, "ATAT", "AT"), (22, "GAT", "G")),
+    // padded substitutions
+    ((31, "CGCA", "CTCA"), (32, "G", "T")),
+    ((20, "CGG", "CAG"), (21, "G", "A")),
+    ((20, "CGGA", "CTTA"), (21, "GG", "TT")),
+    // deletion at the end of a homopolymer near the end of the sequence
+    ((35, "AA", "A"), (33, "CA", "C")),
+    // insertion at the start of the sequence, which cannot be anchored before it
+    ((2, "TG", "TTG"), (1, "T", "TT")),
+    // already normalized
+    ((4, "A", "G"), (4, "A", "G")),
+    ((9, "CAGT", "C"), (9, "CAGT", "C")),
+];
+
+/// Bgzipped FASTA of `SEQUENCE` with its .fai and .gzi
+fn write_fasta(dir: &Path) -> PathBuf {
+    let path = dir.join("ref.fa.gz");
+    let mut writer = bgzf::Writer::new(File::create(&path).unwrap());
+    write!(writer, ">1\n{}\n", SEQUENCE).unwrap();
+    writer.finish().unwrap();
+    let length = SEQUENCE.len();
+    fs::write(
+        dir.join("ref.fa.gz.fai"),
+        format!("1\t{}\t3\t{}\t{}\n", length, length, length + 1),
+    )
+    .unwrap();
+    fs::write(dir.join("ref.fa.gz.gzi"), 0u64.to_le_bytes()).unwrap();
+
+    path
+}
+
+#[test]
+fn messy_indels_are_normalized_as_golden() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut fasta = faidx::Reader::from_path(write_fasta(dir.path())).unwrap();
+
+    for ((pos, reference, alternate), expected) in GOLDEN {
+        let mut record = VcfRecord {
+            chrom: "1".to_string(),
+            pos,
+            id: ".".to_string(),
+            reference: reference.to_string(),
+            alternate: alternate.to_string(),
+            filter: Filter::Pass,
+            info: Vec::new(),
+            flags: Default::default(),
+        };
+
+        let changed = normalize(&mut record, &mut fasta).unwrap();
+
+        assert_eq!(
+            (
+                record.pos,
+                record.reference.as_str(),
+                record.alternate.as_str()
+            ),
+            expected,
+            "{}:{}>{}",
+            pos,
+            reference,
+            alternate
+        );
+        assert_eq!(changed, expected != (pos, reference, alternate));
+    }
+}
+
+#[test]
+fn golden_is_what_bcftools_norm_writes() {
+    let installed = Command::new("bcftools")
+        .arg("--version")
+        .output()
+        .is_ok_and(|x| x.status.success());
+    if !installed {
+        eprintln!("bcftools is not installed, skipping");
+        return;
+    }
+    let dir = tempfile::tempdir().unwrap();
+    let fasta = write_fasta(dir.path());
+    let mut vcf = format!(
+        "##fileformat=VCFv4.3\n##contig=<ID=1,length={}>\n\
+         #CHROM\tPOS\tID\tREF\tALT\tQUAL\tFILTER\tINFO\n",
+        SEQUENCE.len()
+    );
+    for (i, ((pos, reference, alternate), _)) in GOLDEN.iter().enumerate() {
+        vcf.push_str(&format!(
+            "1\t{}\t{}\t{}\t{}\t.\t.\t.\n",
+            pos, i, reference, alternate
+        ));
+    }
+    let input = dir.path().join("input.vcf");
+    fs::write(&input, vcf).unwrap();
+
+    let output = Command::new("bcftools")
+        .args(["norm", "--no-version", "--check-ref", "e", "--fasta-ref"])
+        .arg(&fasta)
+        .arg(&input)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let mut normalized = records(&stdout)
+        .into_iter()
+        .map(|x| {
+            (
+                x[2].parse::<usize>().unwrap(),
+                x[1].parse::<u64>().unwrap(),
+                x[3],
+                x[4],
+            )
+        })
+        .collect::<Vec<_>>();
+    normalized.sort();
+    let normalized = normalized
+        .into_iter()
+        .map(|(_, pos, reference, alternate)| (pos, reference, alternate))
+        .collect::<Vec<_>>();
+    let expected = GOLDEN.iter().map(|x| x.1).collect::<Vec<_>>();
+    assert_eq!(normalized, expected);
+}
+
+/// Deletions of `CGTA` at 98 and 202, both left-aligned to 1 in the `ACGT` repeat of the
+/// synthetic reference
+fn colliding_deletions() -> clinvar::fixture_gen::Spec {
+    let records = [(1, 97), (2, 201)]
+        .into_iter()
+        .map(|(id, position)| {
+            let mut record = snv(id, position);
+            record.locations[0].reference = "ACGTA".to_string();
+            record.locations[0].alternate = "A".to_string();
+            record
+        })
+        .collect();
+
+    spec(records)
+}
+
+#[test]
+fn records_colliding_after_normalization_are_merged() {
+    let workspace = Workspace::new();
+    let input = workspace.write_xml("input.xml", &colliding_deletions());
+
+    // VariationIDs of the RCVs and conditions of each record
+    for (duplicates, ids, entries) in [
+        ("keep", vec!["1", "2"], vec![vec![1], vec![2]]),
+        ("first", vec!["1"], vec![vec![1]]),
+        ("merge", vec!["1;2"], vec![vec![1, 2]]),
+    ] {
+        let output = workspace.path(&format!("{}.vcf.gz", duplicates));
+        let result = workspace.convert(&input, &output, &["--duplicates", duplicates]);
+
+        assert_success(&result);
+        let vcf = read_text(&output);
+        let records = records(&vcf);
+        for record in &records {
+            assert_eq!(
+                (record[0], record[1], record[3], record[4]),
+                ("1", "1", "ACGTA", "A")
+            );
+        }
+        assert_eq!(
+            records.iter().map(|x| x[2]).collect::<Vec<_>>(),
+            ids,
+            "--duplicates {}",
+            duplicates
+        );
+        // entries of CONDITIONS and RCV are concatenated by `|` on merge
+        let joined = |f: fn(u64) -> String| {
+            entries
+                .iter()
+                .map(|x| x.iter().map(|x| f(*x)).collect::<Vec<_>>().join("|"))
+                .collect::<Vec<_>>()
+        };
+        let infos = records.iter().map(|x| info(x)).collect::<Vec<_>>();
+        assert_eq!(
+            infos
+                .iter()
+                .map(|x| x["CONDITIONS"].clone())
+                .collect::<Vec<_>>(),
+            joined(|x| format!(
+                "MedGen:C{:07}:pathogenic:1:criteria_provided%2C_single_submitter",
+                x
+            )),
+            "--duplicates {}",
+            duplicates
+        );
+        assert_eq!(
+            infos.iter().map(|x| x["RCV"].clone()).collect::<Vec<_>>(),
+            joined(|x| format!("RCV{:09}.1", x)),
+            "--duplicates {}",
+            duplicates
+        );
+    }
+}
@@ -0,0 +1,218 @@
+//! Skipped records written to `--report` and `--rejects` with the code of their skip reason
+
+mod common;
+
+use clinvar::fixture_gen::{self, GeneSpec, Malformation, RecordSpec, Structure};
+use clinvar::SkipReason;
+use common::*;
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::fs;
+
+/// Input of a single record skipped for `reason` by a run with `args`
+struct Case {
+    reason: SkipReason,
+    record: RecordSpec,
+    /// Edit of the generated document, for structures the generator does not write
+    edit: fn(String) -> String,
+    args: &'static [&'static str],
+}
+
+impl Case {
+    fn new(reason: SkipReason, record: RecordSpec) -> Self {
+        Case {
+            reason,
+            record,
+            edit: |x| x,
+            args: &[],
+        }
+    }
+
+    fn edit(mut self, edit: fn(String) -> String) -> Self {
+        self.edit = edit;
+        self
+    }
+
+    fn args(mut self, args: &'static [&'static str]) -> Self {
+        self.args = args;
+        self
+    }
+
+    /// Whether the record is skipped before being parsed, so that it is only reported
+    fn is_error(&self) -> bool {
+        matches!(
+            self.reason,
+            SkipReason::XmlError | SkipReason::DeserializationError
+        )
+    }
+}
+
+/// SNV of VariationID 1 at a position of `A` with `f` applied
+fn record(f: impl FnOnce(&mut RecordSpec)) -> RecordSpec {
+    let mut record = snv(1, 101);
+    f(&mut record);
+    record
+}
+
+/// A case of every reason the conversion can skip a record for
+///
+/// `SchemaViolation` is left out as it fails the conversion even with `--ignore-error`.
+fn cases() -> Vec<Case> {
+    vec![
+        // quick-xml stops at the first error, so that only malformed XML after the last record
+        // can be skipped; the record itself is written
+        Case::new(SkipReason::XmlError, record(|_| {})).edit(|x| x + "</Trailer>\n"),
+        Case::new(
+            SkipReason::DeserializationError,
+            record(|x| x.malformations = vec![Malformation::MissingAlleleId]),
+        ),
+        Case::new(SkipReason::NoClassifiedRecord, record(|_| {}))
+            .edit(|x| x.replace("ClassifiedRecord>", "IncludedRecord>")),
+        Case::new(
+            SkipReason::NoSimpleAllele,
+            record(|x| x.structure = Structure::Haplotype),
+        ),
+        Case::new(
+            SkipReason::NoLocation,
+            record(|x| x.locations[0].assembly = "GRCh37".to_string()),
+        ),
+        Case::new(
+            SkipReason::IncompleteLocation,
+            record(|x| x.malformations = vec![Malformation::MissingPositionVcf]),
+        ),
+        Case::new(
+            SkipReason::CoordinateOnly,
+            record(|x| x.malformations = vec![Malformation::MissingPositionVcf]),
+        )
+        .edit(|x| x.replace(r#"Chr="1" "#, r#"Chr="1" start="101" stop="101" "#)),
+        Case::new(
+            SkipReason::NonstandardChromosome,
+            record(|x| x.locations[0].chr = "Un".to_string()),
+        ),
+        Case::new(
+            SkipReason::UnknownContig,
+            record(|x| x.locations[0].chr = "Un".to_string()),
+        )
+        .args(&["--keep-nonstandard-chr"]),
+        Case::new(
+            SkipReason::NonAcgtRef,
+            record(|x| x.locations[0].reference = "R".to_string()),
+        ),
+        Case::new(
+            SkipReason::NonAcgtAlt,
+            record(|x| x.locations[0].alternate = "R".to_string()),
+        ),
+        Case::new(
+            SkipReason::RefContainsN,
+            record(|x| x.locations[0].reference = "AN".to_string()),
+        ),
+        Case::new(
+            SkipReason::AltContainsN,
+            record(|x| x.locations[0].alternate = "N".to_string()),
+        ),
+        Case::new(
+            SkipReason::RefEqualsAlt,
+            record(|x| x.locations[0].alternate = "A".to_string()),
+        ),
+        Case::new(
+            SkipReason::RefMismatch,
+            record(|x| x.locations[0].reference = "C".to_string()),
+        ),
+        Case::new(
+            SkipReason::NoMedgenCondition,
+            record(|x| x.rcvs[0].conditions[0].db = "OMIM".to_string()),
+        ),
+        Case::new(
+            SkipReason::Unclassified,
+            record(|x| {
+                let text = "no classifications from unflagged records";
+                x.rcvs[0].classification = text.to_string();
+                x.aggregate.as_mut().unwrap().classification = text.to_string();
+            }),
+        )
+        .args(&["--exclude-unclassified"]),
+        Case::new(
+            SkipReason::RecordStatusFiltered,
+            record(|x| x.record_status = Some("removed".to_string())),
+        ),
+        Case::new(
+            SkipReason::SpeciesFiltered,
+            record(|x| x.species = Some("Mus musculus".to_string())),
+        ),
+        Case::new(SkipReason::SignificanceFiltered, record(|_| {}))
+            .args(&["--include-significance", "benign"]),
+        Case::new(SkipReason::RegionFiltered, record(|_| {})).args(&["--regions", "1:1000-2000"]),
+        Case::new(
+            SkipReason::GeneFiltered,
+            record(|x| {
+                x.genes = vec![GeneSpec {
+                    symbol: "BRCA2".to_string(),
+                    id: None,
+                }]
+            }),
+        )
+        .args(&["--genes", "BRCA1"]),
+    ]
+}
+
+#[test]
+fn every_reason_has_a_case() {
+    let reasons = cases().iter().map(|x| x.reason).collect::<BTreeSet<_>>();
+    let expected = SkipReason::ALL
+        .into_iter()
+        .filter(|x| *x != SkipReason::SchemaViolation)
+        .collect::<BTreeSet<_>>();
+
+    assert_eq!(reasons, expected);
+}
+
+#[test]
+fn skipped_records_are_reported_with_the_code() {
+    for case in cases() {
+        let code = case.reason.code();
+        let workspace = Workspace::new();
+        let xml = (case.edit)(fixture_gen::generate(&spec(vec![case.record.clone()])));
+        let input = workspace.path("input.xml");
+        fs::write(&input, xml).unwrap();
+        let output = workspace.path("output.vcf.gz");
+        let report = workspace.path("report.jsonl");
+        let rejects = workspace.path("rejects.vcf");
+
+        let mut args = vec!["--ignore-error"];
+        args.extend(["--report", report.to_str().unwrap()]);
+        args.extend(["--rejects", rejects.to_str().unwrap()]);
+        args.extend(case.args);
+
+        let result = workspace.convert(&input, &output, &args);
+
+        let stderr = String::from_utf8_lossy(&result.stderr);
+        let status = if case.is_error() { 2 } else { 0 };
+        assert_eq!(result.status.code(), Some(status), "{}: {}", code, stderr);
+        let written = records(&read_text(&output)).len();
+        let expected = (case.reason == SkipReason::XmlError) as usize;
+        assert_eq!(written, expected, "{}", code);
+
+        let lines = fs::read_to_string(&report).unwrap();
+        let lines = lines
+            .lines()
+            .map(|x| serde_json::from_str::<Value>(x).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(lines.len(), 1, "{}: {:?}", code, lines);
+        assert_eq!(lines[0]["reason"], code);
+        if case.reason == SkipReason::XmlError {
+            assert_eq!(lines[0]["variation_id"], Value::Null);
+        } else {
+            assert_eq!(lines[0]["variation_id"], 1, "{}", code);
+        }
+
+        let vcf = read_text(&rejects);
+        let rejected = records(&vcf);
+        if case.is_error() {
+            assert!(rejected.is_empty(), "{}: {}", code, vcf);
+        } else {
+            assert_eq!(rejected.len(), 1, "{}: {}", code, vcf);
+            assert_eq!(rejected[0][2], "1", "{}", code);
+            assert_eq!(rejected[0][7], format!("SKIP_REASON={}", code));
+        }
+    }
+}
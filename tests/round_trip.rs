@@ -0,0 +1,351 @@
+//! Conversion of documents generated by `clinvar::fixture_gen`
+
+mod common;
+
+use common::*;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+use xz2::write::XzEncoder;
+
+fn three_records() -> clinvar::fixture_gen::Spec {
+    spec(vec![snv(3, 300), snv(1, 100), snv(2, 50)])
+}
+
+/// Positions, IDs and alleles of the records of `three_records`, in the sorted order
+fn assert_three_records(vcf: &str) {
+    let records = records(vcf);
+    let columns = records
+        .iter()
+        .map(|x| (x[0], x[1], x[2], x[3], x[4], x[6]))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        columns,
+        vec![
+            ("1", "50", "2", "C", "A", "PASS"),
+            ("1", "100", "1", "T", "A", "PASS"),
+            ("1", "300", "3", "T", "A", "PASS"),
+        ]
+    );
+
+    let info = info(&records[0]);
+    assert_eq!(info["ALLELEID"], "2");
+    assert_eq!(
+        info["CONDITIONS"],
+        "MedGen:C0000002:pathogenic:1:criteria_provided%2C_single_submitter"
+    );
+    assert_eq!(info["CLNDN"], "Disease_C0000002");
+    assert_eq!(info["RCV"], "RCV000000002.1");
+    assert_eq!(info["CLNSIG"], "pathogenic");
+    assert_eq!(info["CLNREVSTAT"], "criteria_provided%2C_single_submitter");
+}
+
+#[test]
+fn converts_generated_document() {
+    let workspace = Workspace::new();
+    let input = workspace.write_xml("input.xml", &three_records());
+    let output = workspace.path("output.vcf.gz");
+
+    let result = workspace.convert(&input, &output, &[]);
+
+    assert_success(&result);
+    let vcf = read_text(&output);
+    assert!(vcf.starts_with("##fileformat=VCFv4."));
+    assert!(vcf.contains("##contig=<ID=1,length=2000"));
+    assert_three_records(&vcf);
+    assert!(workspace.path("output.vcf.gz.tbi").exists());
+}
+
+/// Generated document of `three_records` compressed by the format of the extension
+fn compress(extension: &str) -> Vec<u8> {
+    let xml = clinvar::fixture_gen::generate(&three_records());
+    match extension {
+        "gz" => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(xml.as_bytes()).unwrap();
+            encoder.finish().unwrap()
+        }
+        "zst" => zstd::encode_all(xml.as_bytes(), 0).unwrap(),
+        "xz" => {
+            let mut encoder = XzEncoder::new(Vec::new(), 6);
+            encoder.write_all(xml.as_bytes()).unwrap();
+            encoder.finish().unwrap()
+        }
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+fn converts_compressed_documents() {
+    let workspace = Workspace::new();
+    let input = workspace.write_xml("input.xml", &three_records());
+    let output = workspace.path("output.vcf.gz");
+    assert_success(&workspace.convert(&input, &output, &[]));
+    let expected = read_text(&output);
+
+    for extension in ["gz", "zst", "xz"] {
+        let input = workspace.path(&format!("input.xml.{}", extension));
+        fs::write(&input, compress(extension)).unwrap();
+        let output = workspace.path(&format!("output.{}.vcf.gz", extension));
+
+        let result = workspace.convert(&input, &output, &[]);
+
+        assert_success(&result);
+        let vcf = read_text(&output);
+        assert_eq!(records(&vcf), records(&expected), "{}", extension);
+    }
+}
+
+#[test]
+fn detects_compression_by_magic_bytes() {
+    let workspace = Workspace::new();
+
+    for extension in ["gz", "zst", "xz"] {
+        let input = workspace.path(&format!("{}.xml", extension));
+        fs::write(&input, compress(extension)).unwrap();
+        let output = workspace.path(&format!("output.{}.vcf.gz", extension));
+
+        let result = workspace.convert(&input, &output, &[]);
+
+        assert_success(&result);
+        assert_three_records(&read_text(&output));
+    }
+}
+
+/// Output of converting `input` piped to stdin, with `args` in place of `--output`
+fn convert_stdin(workspace: &Workspace, input: &[u8], args: &[&str]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_clinvar_xml2vcf"))
+        .arg("-")
+        .arg("--assembly")
+        .arg("GRCh38")
+        .arg("--reference")
+        .arg(&workspace.reference)
+        .arg("--no-external-tools")
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // the input is rejected before stdin is read if --output is missing
+    let _ = child.stdin.take().unwrap().write_all(input);
+
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn reads_stdin() {
+    let workspace = Workspace::new();
+    let xml = clinvar::fixture_gen::generate(&three_records());
+
+    for (name, input) in [("xml", xml.into_bytes()), ("gz", compress("gz"))] {
+        let output = workspace.path(&format!("output.{}.vcf.gz", name));
+
+        let result = convert_stdin(&workspace, &input, &["--output", output.to_str().unwrap()]);
+
+        assert_success(&result);
+        assert_three_records(&read_text(&output));
+    }
+}
+
+#[test]
+fn stdin_requires_output_file() {
+    let workspace = Workspace::new();
+    let xml = clinvar::fixture_gen::generate(&three_records());
+    let directory = workspace.path("");
+
+    for args in [vec![], vec!["--output", directory.to_str().unwrap()]] {
+        let result = convert_stdin(&workspace, xml.as_bytes(), &args);
+
+        assert_eq!(result.status.code(), Some(1), "{:?}", args);
+        let stderr = String::from_utf8_lossy(&result.stderr);
+        assert!(
+            stderr.contains("--output <file> is required to read from stdin"),
+            "{}",
+            stderr
+        );
+    }
+}
+
+#[test]
+fn fails_on_truncated_compressed_documents() {
+    let workspace = Workspace::new();
+
+    for extension in ["gz", "zst", "xz"] {
+        let compressed = compress(extension);
+        let input = workspace.path(&format!("input.xml.{}", extension));
+        fs::write(&input, &compressed[..compressed.len() / 2]).unwrap();
+        let output = workspace.path(&format!("output.{}.vcf.gz", extension));
+
+        let result = workspace.convert(&input, &output, &[]);
+
+        assert_eq!(result.status.code(), Some(1), "{}", extension);
+        assert!(!output.exists(), "{}", extension);
+    }
+}
+
+#[test]
+fn fails_on_truncated_document() {
+    let workspace = Workspace::new();
+    let xml = clinvar::fixture_gen::generate(&three_records());
+    // cut within the second VariationArchive
+    let cut = xml.match_indices("<VariationArchive ").nth(1).unwrap().0 + 100;
+    let input = workspace.path("input.xml");
+    fs::write(&input, &xml[..cut]).unwrap();
+    let output = workspace.path("output.vcf.gz");
+
+    let result = workspace.convert(&input, &output, &[]);
+
+    assert_eq!(result.status.code(), Some(3));
+    assert!(!output.exists());
+}
+
+#[test]
+fn counts_records_with_truncated_rcvs() {
+    let workspace = Workspace::new();
+    let mut record = snv(1, 100);
+    record.rcvs = (1..=50)
+        .map(|i| rcv(&format!("RCV{:09}", i), &format!("C{:07}", i), "Pathogenic"))
+        .collect();
+    let input = workspace.write_xml("input.xml", &spec(vec![record, snv(2, 200)]));
+    let output = workspace.path("output.vcf.gz");
+    let stats = workspace.path("stats.json");
+
+    let result = workspace.convert(
+        &input,
+        &output,
+        &["--max-rcvs", "5", "--stats-json", stats.to_str().unwrap()],
+    );
+
+    assert_success(&result);
+    let vcf = read_text(&output);
+    let records = records(&vcf);
+    let info = info(&records[0]);
+    assert_eq!(info["RCV"].split('|').count(), 5);
+    assert_eq!(info["CONDITIONS"].split('|').count(), 5);
+    assert_eq!(info["RCV_TRUNCATED"], "45");
+    assert!(!records[1][7].contains("RCV_TRUNCATED"));
+    assert!(vcf.contains("##INFO=<ID=RCV_TRUNCATED,Number=1,Type=Integer,"));
+    let stats = read_text(&stats);
+    assert!(
+        stats.contains("\"written\":2,\"truncated\":1,"),
+        "{}",
+        stats
+    );
+    assert!(String::from_utf8_lossy(&result.stderr).contains("Records with truncated RCVs: 1"));
+}
+
+#[test]
+fn removed_records_are_kept_by_record_status() {
+    let workspace = Workspace::new();
+    let mut removed = snv(2, 201);
+    removed.record_status = Some("removed".to_string());
+    let input = workspace.write_xml("input.xml", &spec(vec![snv(1, 101), removed]));
+
+    for (args, expected) in [
+        (&[][..], vec!["1"]),
+        (&["--record-status", "removed"][..], vec!["2"]),
+        (&["--record-status", "current,removed"][..], vec!["1", "2"]),
+        (&["--record-status", "CURRENT,Removed"][..], vec!["1", "2"]),
+    ] {
+        let output = workspace.path("output.vcf.gz");
+        let _ = fs::remove_file(&output);
+
+        let result = workspace.convert(&input, &output, args);
+
+        assert_success(&result);
+        let vcf = read_text(&output);
+        let ids = records(&vcf).iter().map(|x| x[2]).collect::<Vec<_>>();
+        assert_eq!(ids, expected, "{:?}", args);
+    }
+}
+
+#[test]
+fn update_base_replaces_records_by_variation_id() {
+    let workspace = Workspace::new();
+    let input = workspace.write_xml(
+        "base.xml",
+        &spec(vec![snv(1, 101), snv(2, 201), snv(3, 301)]),
+    );
+    let base = workspace.path("base.vcf.gz");
+    assert_success(&workspace.convert(&input, &base, &[]));
+
+    // VariationID 2 moves and is reclassified, 3 is removed and 4 is new
+    let mut moved = snv(2, 401);
+    moved.rcvs[0].classification = "Benign".to_string();
+    moved.aggregate.as_mut().unwrap().classification = "Benign".to_string();
+    let mut removed = snv(3, 301);
+    removed.record_status = Some("removed".to_string());
+    let input = workspace.write_xml("update.xml", &spec(vec![moved, removed, snv(4, 501)]));
+    let output = workspace.path("output.vcf.gz");
+
+    let result = workspace.convert(&input, &output, &["--update-base", base.to_str().unwrap()]);
+
+    assert_success(&result);
+    assert!(String::from_utf8_lossy(&result.stderr)
+        .contains("Records of the update base kept: 1, replaced or removed: 2"));
+    let vcf = read_text(&output);
+    assert_eq!(vcf.matches("#CHROM").count(), 1);
+    let records = records(&vcf);
+    let columns = records
+        .iter()
+        .map(|x| (x[1], x[2], info(x)["CLNSIG"].clone()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        columns,
+        vec![
+            ("101", "1", "pathogenic".to_string()),
+            ("401", "2", "benign".to_string()),
+            ("501", "4", "pathogenic".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn resumed_conversion_writes_the_same_output() {
+    let workspace = Workspace::new();
+    let records = (1..=10).map(|i| snv(i, i * 100 + 1)).collect::<Vec<_>>();
+    let complete = clinvar::fixture_gen::generate(&spec(records.clone()));
+    // REF of the 8th record of the same length, not matching the reference
+    let mut broken = records;
+    broken[7].locations[0].reference = "C".to_string();
+    let broken = clinvar::fixture_gen::generate(&spec(broken));
+    assert_eq!(broken.len(), complete.len());
+
+    let input = workspace.path("input.xml");
+    let output = workspace.path("output.vcf.gz");
+    let checkpoint = workspace.path("checkpoint.json");
+    let args = [
+        "--checkpoint",
+        checkpoint.to_str().unwrap(),
+        "--checkpoint-interval",
+        "3",
+        "--on-ref-mismatch",
+        "fail",
+    ];
+
+    // the header has the command line, so the uninterrupted run has the same arguments
+    fs::write(&input, &complete).unwrap();
+    assert_success(&workspace.convert(&input, &output, &args));
+    let expected = fs::read(&output).unwrap();
+    fs::remove_file(&output).unwrap();
+    assert!(!checkpoint.exists());
+
+    // stops after the checkpoint of 6 records
+    fs::write(&input, &broken).unwrap();
+    let result = workspace.convert(&input, &output, &args);
+    assert_eq!(result.status.code(), Some(3));
+    assert!(checkpoint.exists());
+
+    fs::write(&input, &complete).unwrap();
+    let mut resume = args.to_vec();
+    resume.push("--resume");
+    let result = workspace.convert(&input, &output, &resume);
+
+    assert_success(&result);
+    assert!(String::from_utf8_lossy(&result.stderr).contains("Resuming from 6 records"));
+    assert_eq!(fs::read(&output).unwrap(), expected);
+    assert!(!checkpoint.exists());
+}
@@ -0,0 +1,242 @@
+//! Validation of the written INFO values against the `Number` and `Type` declared in the header
+
+mod common;
+
+use clinvar::fixture_gen::{
+    AggregateSpec, CitationSpec, ClassificationKind, ConditionSpec, GeneSpec, RecordSpec, XRefSpec,
+};
+use common::*;
+use std::collections::BTreeMap;
+
+/// `Number` and `Type` of an `##INFO` line
+#[derive(Debug)]
+struct Declaration {
+    number: String,
+    r#type: String,
+}
+
+/// Declarations of the `##INFO` lines, failing on a field declared twice
+fn declarations(vcf: &str) -> BTreeMap<String, Declaration> {
+    let mut declarations = BTreeMap::new();
+    for line in vcf.lines().filter(|x| x.starts_with("##INFO=<")) {
+        let attribute = |key: &str| {
+            let start = line.find(&format!("{}=", key)).expect(line) + key.len() + 1;
+            line[start..].split([',', '>']).next().unwrap().to_string()
+        };
+        let declaration = Declaration {
+            number: attribute("Number"),
+            r#type: attribute("Type"),
+        };
+        assert!(
+            declarations.insert(attribute("ID"), declaration).is_none(),
+            "declared twice: {}",
+            line
+        );
+    }
+
+    declarations
+}
+
+/// Check every INFO value of the VCF against the header (VCF 4.3, section 1.6.1)
+fn validate(vcf: &str) {
+    let declarations = declarations(vcf);
+    let records = records(vcf);
+    assert!(!records.is_empty());
+
+    for record in &records {
+        let alternates = record[4].split(',').count();
+        let mut seen = Vec::new();
+        for entry in record[7].split(';') {
+            let (key, value) = match entry.split_once('=') {
+                Some((k, v)) => (k, Some(v)),
+                None => (entry, None),
+            };
+            assert!(!seen.contains(&key), "{} written twice: {}", key, record[7]);
+            seen.push(key);
+            let declaration = declarations
+                .get(key)
+                .unwrap_or_else(|| panic!("{} is not declared", key));
+
+            if declaration.r#type == "Flag" {
+                assert_eq!(declaration.number, "0", "{}", key);
+                assert_eq!(value, None, "{} is a flag", key);
+                continue;
+            }
+            let value = value.unwrap_or_else(|| panic!("{} has no value", key));
+            assert!(
+                !value.is_empty() && !value.contains([' ', '\t', ';', '=']),
+                "{}={}",
+                key,
+                value
+            );
+
+            let values = value.split(',').collect::<Vec<_>>();
+            let expected = match declaration.number.as_str() {
+                "." => None,
+                "A" => Some(alternates),
+                "R" => Some(alternates + 1),
+                "G" => None,
+                n => Some(n.parse::<usize>().expect("Number")),
+            };
+            if let Some(n) = expected {
+                assert_eq!(values.len(), n, "Number of {}={}", key, value);
+            }
+            for x in values.iter().filter(|x| **x != ".") {
+                let valid = match declaration.r#type.as_str() {
+                    "Integer" => x.parse::<i32>().is_ok(),
+                    "Float" => x.parse::<f32>().is_ok(),
+                    "Character" => x.chars().count() == 1,
+                    "String" => true,
+                    other => panic!("Type {} of {}", other, key),
+                };
+                assert!(valid, "Type of {}={}", key, value);
+            }
+        }
+    }
+}
+
+/// Check that CONDITIONS, RCV and CLNDN have an entry for each other, in the same order
+fn validate_alignment(vcf: &str) {
+    for record in records(vcf) {
+        let info = info(&record);
+        let Some(conditions) = info.get("CONDITIONS") else {
+            continue;
+        };
+        let count = |key: &str| info.get(key).map(|x| x.split('|').count());
+        let rcvs = count("RCV");
+        assert_eq!(
+            Some(conditions.split('|').count()),
+            rcvs,
+            "CONDITIONS and RCV of {}",
+            record[2]
+        );
+        assert_eq!(count("CLNDN"), rcvs, "CLNDN and RCV of {}", record[2]);
+    }
+}
+
+/// Records using the fields of both output formats
+fn records_of_all_fields() -> Vec<RecordSpec> {
+    let mut full = snv(1, 100);
+    full.allele_id = Some(15041);
+    full.variant_type = Some("single nucleotide variant".to_string());
+    full.date_created = Some("2012-08-13".to_string());
+    full.date_last_updated = Some("2024-04-20".to_string());
+    full.genes = vec![GeneSpec {
+        symbol: "BRCA1".to_string(),
+        id: Some(672),
+    }];
+    full.xrefs = vec![
+        XRefSpec {
+            db: "dbSNP".to_string(),
+            id: "80357906".to_string(),
+            r#type: Some("rs".to_string()),
+        },
+        XRefSpec {
+            db: "OMIM".to_string(),
+            id: "113705.0001".to_string(),
+            r#type: Some("Allelic variant".to_string()),
+        },
+    ];
+    let mut germline = rcv("RCV000000001", "C0000001", "Pathogenic/Likely pathogenic");
+    germline.conditions.push(ConditionSpec {
+        db: "MONDO".to_string(),
+        id: "MONDO:0011450".to_string(),
+        name: "Breast-ovarian cancer, familial, susceptibility to, 1".to_string(),
+    });
+    germline.date_last_evaluated = Some("2023-01-05".to_string());
+    germline.citations = vec![CitationSpec {
+        source: "PubMed".to_string(),
+        id: "20104584".to_string(),
+    }];
+    let mut somatic = rcv("RCV000000002", "C0000002", "Tier I - Strong");
+    somatic.kind = ClassificationKind::Somatic;
+    let mut oncogenic = rcv("RCV000000003", "C0000003", "Oncogenic");
+    oncogenic.kind = ClassificationKind::Oncogenicity;
+    oncogenic.submission_count = 2;
+    full.rcvs = vec![germline, somatic, oncogenic];
+
+    let mut conflicting = snv(2, 200);
+    conflicting.rcvs = vec![
+        rcv("RCV000000040", "C0000040", "Pathogenic"),
+        rcv("RCV000000041", "C0000041", "Uncertain significance"),
+        rcv("RCV000000042", "C0000042", "Likely benign"),
+    ];
+    conflicting.aggregate = Some(AggregateSpec {
+        classification: "Conflicting classifications of pathogenicity".to_string(),
+        review_status: Some("criteria provided, conflicting classifications".to_string()),
+        submission_count: 3,
+        date_last_evaluated: None,
+    });
+
+    let mut truncated = snv(3, 300);
+    truncated.rcvs = (10..30)
+        .map(|i| rcv(&format!("RCV{:09}", i), &format!("C{:07}", i), "Benign"))
+        .collect();
+
+    let mut unmapped = snv(4, 400);
+    unmapped.rcvs[0].conditions = vec![ConditionSpec {
+        db: "Orphanet".to_string(),
+        id: "145".to_string(),
+        name: "Hereditary breast and/or ovarian cancer syndrome".to_string(),
+    }];
+
+    vec![full, conflicting, truncated, unmapped]
+}
+
+fn convert(args: &[&str]) -> String {
+    let workspace = Workspace::new();
+    let input = workspace.write_xml("input.xml", &spec(records_of_all_fields()));
+    let output = workspace.path("output.vcf.gz");
+
+    let result = workspace.convert(&input, &output, args);
+
+    assert_success(&result);
+    read_text(&output)
+}
+
+#[test]
+fn togovar_values_match_header() {
+    let vcf = convert(&[
+        "--max-rcvs",
+        "5",
+        "--keep-unmapped-conditions",
+        "--include-scv",
+        "--include-citations",
+        "--condition-db",
+        "MedGen,MONDO",
+    ]);
+
+    validate(&vcf);
+    validate_alignment(&vcf);
+    for key in [
+        "CLNSIGCONF=",
+        "RCV_TRUNCATED=15",
+        "CONDITIONS_UNMAPPED",
+        "ONC=",
+        "SOMATIC_SUBMISSIONS=",
+        "PMIDS=",
+    ] {
+        assert!(vcf.contains(key), "{} is not written", key);
+    }
+}
+
+#[test]
+fn conditions_v2_values_match_header() {
+    let vcf = convert(&["--conditions-format", "v2", "--max-rcvs", "5"]);
+
+    validate(&vcf);
+    let records = records(&vcf);
+    let info = info(&records[0]);
+    assert_eq!(info["CONDITIONS"].split(',').count(), 1);
+}
+
+#[test]
+fn ncbi_values_match_header() {
+    let vcf = convert(&["--format", "ncbi"]);
+
+    validate(&vcf);
+    let records = records(&vcf);
+    let info = info(&records[0]);
+    assert_eq!(info["RS"], "80357906");
+    assert_eq!(info["CLNREVSTAT"], "criteria_provided%2C_single_submitter");
+}